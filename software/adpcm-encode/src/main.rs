@@ -0,0 +1,213 @@
+// IMA ADPCM encoder.
+//
+// Compresses audio to the 4 bit ADPCM that midi-nostd's AdpcmPlayer plays,
+// at half the size of 8 bit PCM:
+//
+//   adpcm-encode [--u8 | --s16le] IN OUT
+//
+// IN is a WAV file (8 or 16 bit PCM, any number of channels, which are
+// mixed down to mono) or, with --u8 or --s16le, raw mono samples like
+// ffmpeg's -f u8 and -f s16le write.  The sample rate isn't changed; set it
+// when decoding, and give the same rate to AdpcmClip.
+//
+
+use midi_nostd::adpcm::AdpcmState;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: adpcm-encode [--u8 | --s16le] IN OUT";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum RawFormat {
+    U8,
+    S16Le,
+}
+
+/// Samples from -0x8000 to 0x7fff, and the sample rate if the input said
+///
+struct Audio {
+    samples: Vec<i32>,
+    sample_rate: Option<u32>,
+}
+
+fn decode_pcm(data: &[u8], bits: u16, channels: u16) -> Vec<i32> {
+    let bytes_per_sample = (bits / 8) as usize;
+    let frame_size = bytes_per_sample * channels as usize;
+    data.chunks_exact(frame_size)
+        .map(|frame| {
+            let sum: i32 = frame
+                .chunks_exact(bytes_per_sample)
+                .map(|sample| match bits {
+                    8 => ((sample[0] as i32) - 0x80) << 8,
+                    _ => i16::from_le_bytes([sample[0], sample[1]]) as i32,
+                })
+                .sum();
+            sum / channels as i32
+        })
+        .collect()
+}
+
+fn read_u16(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn read_wav(data: &[u8]) -> Result<Audio, String> {
+    if data.len() < 12 || &data[0..4] != b"RIFF" || &data[8..12] != b"WAVE" {
+        return Err("not a WAV file; use --u8 or --s16le for raw audio".to_string());
+    }
+    let mut format = None;
+    let mut at = 12;
+    while at + 8 <= data.len() {
+        let id = &data[at..at + 4];
+        let size = read_u32(data, at + 4) as usize;
+        let contents = &data[at + 8..(at + 8 + size).min(data.len())];
+        if id == b"fmt " && contents.len() >= 16 {
+            let (tag, channels, rate, bits) = (
+                read_u16(contents, 0),
+                read_u16(contents, 2),
+                read_u32(contents, 4),
+                read_u16(contents, 14),
+            );
+            if tag != 1 || (bits != 8 && bits != 16) || channels == 0 {
+                return Err("only 8 and 16 bit PCM WAV files are supported".to_string());
+            }
+            format = Some((channels, rate, bits));
+        } else if id == b"data" {
+            let (channels, rate, bits) = format.ok_or("WAV data before its format")?;
+            return Ok(Audio {
+                samples: decode_pcm(contents, bits, channels),
+                sample_rate: Some(rate),
+            });
+        }
+        at += 8 + size + (size & 1);
+    }
+    Err("WAV file has no data".to_string())
+}
+
+fn read_raw(data: &[u8], format: RawFormat) -> Audio {
+    let bits = match format {
+        RawFormat::U8 => 8,
+        RawFormat::S16Le => 16,
+    };
+    Audio {
+        samples: decode_pcm(data, bits, 1),
+        sample_rate: None,
+    }
+}
+
+/// Encode samples to ADPCM nibbles, low nibble first
+///
+fn encode(samples: &[i32]) -> Vec<u8> {
+    let mut state = AdpcmState::new();
+    samples
+        .chunks(2)
+        .map(|pair| {
+            let low = state.encode(pair[0]);
+            let high = match pair.get(1) {
+                Some(sample) => state.encode(*sample),
+                None => 0,
+            };
+            low | (high << 4)
+        })
+        .collect()
+}
+
+fn run() -> Result<(), String> {
+    let mut raw_format = None;
+    let mut files = Vec::new();
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--u8" => raw_format = Some(RawFormat::U8),
+            "--s16le" => raw_format = Some(RawFormat::S16Le),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => files.push(arg),
+        }
+    }
+    if files.len() != 2 {
+        return Err("give an input and an output file".to_string());
+    }
+
+    let input = std::fs::read(&files[0]).map_err(|e| format!("can't read {}: {}", files[0], e))?;
+    let audio = match raw_format {
+        Some(format) => read_raw(&input, format),
+        None => read_wav(&input)?,
+    };
+    let encoded = encode(&audio.samples);
+    std::fs::write(&files[1], &encoded).map_err(|e| format!("can't write {}: {}", files[1], e))?;
+
+    match audio.sample_rate {
+        Some(rate) => println!(
+            "{}: {} samples at {}hz, {} bytes",
+            files[1],
+            audio.samples.len(),
+            rate,
+            encoded.len()
+        ),
+        None => println!(
+            "{}: {} samples, {} bytes",
+            files[1],
+            audio.samples.len(),
+            encoded.len()
+        ),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn wav(channels: u16, bits: u16, data: &[u8]) -> Vec<u8> {
+        let mut fmt = Vec::new();
+        fmt.extend(1u16.to_le_bytes());
+        fmt.extend(channels.to_le_bytes());
+        fmt.extend(24000u32.to_le_bytes());
+        fmt.extend((24000 * (bits / 8 * channels) as u32).to_le_bytes());
+        fmt.extend((bits / 8 * channels).to_le_bytes());
+        fmt.extend(bits.to_le_bytes());
+        let mut wav = b"RIFF\0\0\0\0WAVEfmt ".to_vec();
+        wav.extend((fmt.len() as u32).to_le_bytes());
+        wav.extend(fmt);
+        wav.extend(b"data");
+        wav.extend((data.len() as u32).to_le_bytes());
+        wav.extend(data);
+        wav
+    }
+
+    #[test]
+    fn wav_files_should_mix_down() {
+        let audio = read_wav(&wav(2, 16, &[0x00, 0x40, 0x00, 0x20])).unwrap();
+        assert_eq!(vec![0x3000], audio.samples);
+        assert_eq!(Some(24000), audio.sample_rate);
+        let audio = read_wav(&wav(1, 8, &[0x80, 0xc0])).unwrap();
+        assert_eq!(vec![0, 0x4000], audio.samples);
+        assert!(read_wav(b"not a wav").is_err());
+    }
+
+    #[test]
+    fn encoding_should_pack_low_nibble_first() {
+        let encoded = encode(&[0x7fff, 0x7fff, 0x7fff]);
+        assert_eq!(2, encoded.len());
+        // Full steps up, with a pad nibble on the end
+        assert_eq!(0x77, encoded[0]);
+        assert_eq!(0x07, encoded[1]);
+        assert_eq!(
+            vec![0, 0x100],
+            read_raw(&[0, 0, 0, 1], RawFormat::S16Le).samples
+        );
+    }
+}
@@ -0,0 +1,121 @@
+#!/usr/bin/env python3
+#
+# Writes kalimba.sf2, a one sample SoundFont with a synthesized kalimba on
+# GM program 108, for sf2-convert to turn into midi-nostd's Kalimba:
+#
+#   python3 kalimba_sf2.py /tmp/kalimba.sf2
+#   cd ../sf2-convert && cargo run --release -- /tmp/kalimba.sf2 \
+#       --preset 0:108 --bits 16 --out ../midi-nostd/src
+#
+# The tine is a sine with a bright overtone at 6.27 times the pitch, as on a
+# clamped bar, that dies away in the first few cycles.  After that the
+# sample loops fifteen cycles of the sine, and the volume envelope does the
+# long decay.
+#
+
+import math
+import struct
+import sys
+
+RATE = 24000
+ROOT_KEY = 72
+ATTACK_SAMPLES = 6000
+LOOP_CYCLES = 15
+LOOP_SAMPLES = 688
+# Fifteen cycles in exactly LOOP_SAMPLES, a hair sharp of C5
+FREQUENCY = LOOP_CYCLES * RATE / LOOP_SAMPLES
+OVERTONE = 6.27
+AMPLITUDE = 24000
+
+
+def tine():
+    samples = []
+    for idx in range(ATTACK_SAMPLES + LOOP_SAMPLES):
+        t = idx / RATE
+        # The fundamental settles to a steady level by the loop.
+        level = 1.0 if idx >= ATTACK_SAMPLES else 0.6 + 0.4 * math.exp(-t / 0.05)
+        value = level * math.sin(2 * math.pi * FREQUENCY * t)
+        value += 0.5 * math.exp(-t / 0.015) * math.sin(2 * math.pi * FREQUENCY * OVERTONE * t)
+        samples.append(int(AMPLITUDE * value / 1.5))
+    return samples
+
+
+def timecents(seconds):
+    return round(1200 * math.log2(seconds))
+
+
+def name(text):
+    return text.encode().ljust(20, b"\0")
+
+
+def records(fmt, rows):
+    return b"".join(struct.pack(fmt, *row) for row in rows)
+
+
+def chunk(tag, data):
+    return tag + struct.pack("<I", len(data)) + data
+
+
+def riff_list(tag, chunks):
+    return chunk(b"LIST", tag + b"".join(chunks))
+
+
+def sf2(samples):
+    # 46 zeros after the sample, as the spec asks
+    smpl = records("<h", [(s,) for s in samples + [0] * 46])
+
+    phdr = name("Kalimba") + struct.pack("<HHHIII", 108, 0, 0, 0, 0, 0)
+    phdr += name("EOP") + struct.pack("<HHHIII", 0, 0, 1, 0, 0, 0)
+    pbag = records("<HH", [(0, 0), (1, 0)])
+    pgen = records("<Hh", [(41, 0), (0, 0)])
+
+    inst = name("Kalimba") + struct.pack("<H", 0)
+    inst += name("EOI") + struct.pack("<H", 1)
+    ibag = records("<HH", [(0, 0), (5, 0)])
+    igen = records(
+        "<Hh",
+        [
+            # decayVolEnv, down to silence, and releaseVolEnv
+            (36, timecents(1.5)),
+            (37, 1440),
+            (38, timecents(0.2)),
+            # sampleModes: loop
+            (54, 1),
+            # sampleID
+            (53, 0),
+            (0, 0),
+        ],
+    )
+
+    loop_start = ATTACK_SAMPLES
+    loop_end = ATTACK_SAMPLES + LOOP_SAMPLES
+    shdr = name("Kalimba C5")
+    shdr += struct.pack("<IIIIIBbHH", 0, len(samples), loop_start, loop_end, RATE, ROOT_KEY, 0, 0, 1)
+    shdr += name("EOS") + bytes(26)
+
+    sfbk = b"sfbk" + b"".join(
+        [
+            riff_list(b"INFO", [chunk(b"ifil", struct.pack("<HH", 2, 1)), chunk(b"INAM", b"Kalimba\0")]),
+            riff_list(b"sdta", [chunk(b"smpl", smpl)]),
+            riff_list(
+                b"pdta",
+                [
+                    chunk(b"phdr", phdr),
+                    chunk(b"pbag", pbag),
+                    chunk(b"pmod", bytes(10)),
+                    chunk(b"pgen", pgen),
+                    chunk(b"inst", inst),
+                    chunk(b"ibag", ibag),
+                    chunk(b"imod", bytes(10)),
+                    chunk(b"igen", igen),
+                    chunk(b"shdr", shdr),
+                ],
+            ),
+        ]
+    )
+    return chunk(b"RIFF", sfbk)
+
+
+if __name__ == "__main__":
+    with open(sys.argv[1], "wb") as out:
+        out.write(sf2(tine()))
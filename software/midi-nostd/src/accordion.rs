@@ -0,0 +1,20 @@
+use crate::instrument_low_pass_filters::GenericLowPassCalculator;
+use crate::instrument_template_reed::InstrumentTemplateReed;
+use crate::midi_notes::FREQUENCY_MULTIPLIER;
+use crate::oscillator::OscillatorType;
+use crate::velocity::VelocityCutoff;
+
+pub type Accordion<const P_FREQ: u32, const U_FREQ: u32> = InstrumentTemplateReed<
+    P_FREQ,
+    U_FREQ,
+    30,                                      // Reed pulse width
+    { OscillatorType::PulseWidth as usize }, // Reed wave form
+    14,                                      // Second reed detune, cents
+    { 5 * FREQUENCY_MULTIPLIER },            // Bellows tremolo frequency
+    5,                                       // Bellows tremolo depth
+    60,                                      // A
+    200,                                     // D
+    85,                                      // S
+    120,                                     // R
+    VelocityCutoff<GenericLowPassCalculator<200, 1500>, 40>,
+>;
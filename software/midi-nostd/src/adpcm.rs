@@ -0,0 +1,328 @@
+// IMA ADPCM.
+//
+// IMA (or DVI) ADPCM stores each sample as a 4 bit step up or down from a
+// prediction, where the size of the steps adapts to how loud the audio is.
+// That's a quarter of the size of 16 bit audio and half of 8 bit, and it
+// decodes with a table lookup, a few adds and a clamp per sample.
+//
+// The data is a plain stream of nibbles, low nibble first, with no headers
+// or blocks; decoding starts from silence and the smallest step.  The
+// encoder is here too so the host tool and the decoder can't drift apart.
+//
+
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::OscillatorInterface;
+use crate::sound_source_core::SoundSourceCore;
+
+// Step sizes, from the IMA spec
+const STEP_TABLE: [i32; 89] = [
+    7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 19, 21, 23, 25, 28, 31, 34, 37, 41, 45, 50, 55, 60, 66,
+    73, 80, 88, 97, 107, 118, 130, 143, 157, 173, 190, 209, 230, 253, 279, 307, 337, 371, 408, 449,
+    494, 544, 598, 658, 724, 796, 876, 963, 1060, 1166, 1282, 1411, 1552, 1707, 1878, 2066, 2272,
+    2499, 2749, 3024, 3327, 3660, 4026, 4428, 4871, 5358, 5894, 6484, 7132, 7845, 8630, 9493,
+    10442, 11487, 12635, 13899, 15289, 16818, 18500, 20350, 22385, 24623, 27086, 29794, 32767,
+];
+
+// How far each code moves through the step table
+const INDEX_TABLE: [i32; 16] = [-1, -1, -1, -1, 2, 4, 6, 8, -1, -1, -1, -1, 2, 4, 6, 8];
+
+///
+/// Decoder (and encoder) state
+///
+#[derive(Clone, Copy, Default)]
+pub struct AdpcmState {
+    predictor: i32,
+    step_index: i32,
+}
+
+impl AdpcmState {
+    pub const fn new() -> Self {
+        Self {
+            predictor: 0,
+            step_index: 0,
+        }
+    }
+
+    /// Decode one 4 bit code to a sample, from -0x8000 to 0x7fff
+    ///
+    #[inline]
+    pub fn decode(&mut self, code: u8) -> i32 {
+        let step = STEP_TABLE[self.step_index as usize];
+        let mut diff = step >> 3;
+        if code & 4 != 0 {
+            diff += step;
+        }
+        if code & 2 != 0 {
+            diff += step >> 1;
+        }
+        if code & 1 != 0 {
+            diff += step >> 2;
+        }
+        if code & 8 != 0 {
+            self.predictor -= diff;
+        } else {
+            self.predictor += diff;
+        }
+        self.predictor = self.predictor.clamp(-0x8000, 0x7fff);
+        self.step_index = (self.step_index + INDEX_TABLE[(code & 0xf) as usize]).clamp(0, 88);
+        self.predictor
+    }
+
+    /// Encode one sample, from -0x8000 to 0x7fff, to a 4 bit code
+    ///
+    pub fn encode(&mut self, sample: i32) -> u8 {
+        let mut step = STEP_TABLE[self.step_index as usize];
+        let mut diff = sample - self.predictor;
+        let mut code = 0;
+        if diff < 0 {
+            code = 8;
+            diff = -diff;
+        }
+        for bit in [4, 2, 1] {
+            if diff >= step {
+                code |= bit;
+                diff -= step;
+            }
+            step >>= 1;
+        }
+        // Decode it too, so the prediction follows what the decoder will
+        // actually hear.
+        self.decode(code);
+        code
+    }
+}
+
+///
+/// A clip of IMA ADPCM audio
+///
+#[derive(Clone, Copy, Debug)]
+pub struct AdpcmClip {
+    pub data: &'static [u8],
+    /// Rate it was encoded at, in hz
+    pub sample_rate: u32,
+}
+
+impl AdpcmClip {
+    /// Length, in samples
+    ///
+    pub const fn len(&self) -> u32 {
+        (self.data.len() * 2) as u32
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Length, in ms
+    ///
+    pub const fn duration_ms(&self) -> u32 {
+        ((self.len() as u64) * 1000 / (self.sample_rate as u64)) as u32
+    }
+
+    #[inline]
+    fn code(&self, idx: u32) -> u8 {
+        let byte = self.data[(idx >> 1) as usize];
+        if idx & 1 == 0 {
+            byte & 0xf
+        } else {
+            byte >> 4
+        }
+    }
+}
+
+///
+/// Streaming ADPCM player
+///
+/// Decodes as it plays, so it needs no buffer, and converts the clip's
+/// sample rate to P_FREQ by interpolating between decoded samples.
+///
+pub struct AdpcmPlayer<const P_FREQ: u32, const U_FREQ: u32> {
+    clip: &'static AdpcmClip,
+    state: AdpcmState,
+    /// Next code to decode
+    idx: u32,
+    previous: i32,
+    current: i32,
+    /// Position between previous and current, 16.16
+    fraction: u32,
+    /// Position step per output sample, 16.16
+    fraction_inc: u32,
+    finished: bool,
+    max_amplitude: i32,
+    amplitude: i32,
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32> AdpcmPlayer<P_FREQ, U_FREQ> {
+    fn start(&mut self) {
+        self.state = AdpcmState::new();
+        self.idx = 0;
+        self.previous = 0;
+        self.current = 0;
+        self.fraction = 0;
+        self.finished = self.clip.is_empty();
+        // Fade in from silence to the first sample.
+        if !self.finished {
+            self.current = self.state.decode(self.clip.code(0));
+            self.idx = 1;
+        }
+    }
+
+    /// Stop playing right away
+    ///
+    pub fn stop(&mut self) {
+        self.finished = true;
+    }
+
+    /// How far through the clip the player is, in samples decoded
+    ///
+    pub fn position(&self) -> u32 {
+        self.idx
+    }
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
+    for AdpcmPlayer<P_FREQ, U_FREQ>
+{
+    type InitValuesType = &'static AdpcmClip;
+
+    fn new(clip: Self::InitValuesType) -> Self {
+        let mut rval = Self {
+            clip,
+            state: AdpcmState::new(),
+            idx: 0,
+            previous: 0,
+            current: 0,
+            fraction: 0,
+            fraction_inc: (((clip.sample_rate as u64) << 16) / (P_FREQ as u64)) as u32,
+            finished: true,
+            // Half scale, like the oscillators.
+            max_amplitude: 0x4000,
+            amplitude: 0x4000,
+        };
+        rval.start();
+        rval
+    }
+
+    #[inline]
+    fn get_next(&mut self) -> SoundSampleI32 {
+        if self.finished {
+            return SoundSampleI32::ZERO;
+        }
+        // A full scale step times the fraction needs 33 bits.
+        let step = (self.current - self.previous) as i64;
+        let interpolated = self.previous + ((step * self.fraction as i64) >> 16) as i32;
+
+        self.fraction += self.fraction_inc;
+        while self.fraction >= 0x10000 {
+            self.fraction -= 0x10000;
+            if self.idx >= self.clip.len() {
+                self.finished = true;
+                break;
+            }
+            self.previous = self.current;
+            self.current = self.state.decode(self.clip.code(self.idx));
+            self.idx += 1;
+        }
+
+        SoundSampleI32::new_i32((interpolated * self.amplitude) >> 15)
+    }
+
+    fn update(&mut self) {}
+
+    fn has_next(&self) -> bool {
+        !self.finished
+    }
+
+    fn restart(&mut self, _vel: u8) {
+        self.start();
+    }
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32> OscillatorInterface<P_FREQ, U_FREQ>
+    for AdpcmPlayer<P_FREQ, U_FREQ>
+{
+    fn set_amplitude_adjust(&mut self, adjust: SoundSampleI32) {
+        self.amplitude = (self.max_amplitude * adjust.to_i32()) >> 15;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adpcm::*;
+
+    #[test]
+    fn codes_should_decode_like_the_spec() {
+        let mut state = AdpcmState::new();
+        // 7/8 + 1/2 + 1/4 + 1/8 of the first step, 7
+        assert_eq!(11, state.decode(7));
+        assert_eq!(8, state.step_index);
+        // Down, by 1/8 of the step at index 8, 16
+        assert_eq!(9, state.decode(8));
+        assert_eq!(7, state.step_index);
+    }
+
+    #[test]
+    fn encoding_should_round_trip() {
+        let mut encoder = AdpcmState::new();
+        let mut decoder = AdpcmState::new();
+        let mut worst = 0;
+        for n in 0..2400 {
+            // 440hz at 24khz, most of full scale
+            let sample =
+                (20000.0 * (2.0 * core::f64::consts::PI * 440.0 * n as f64 / 24000.0).sin()) as i32;
+            let decoded = decoder.decode(encoder.encode(sample));
+            if n > 100 {
+                worst = core::cmp::max(worst, (decoded - sample).abs());
+            }
+        }
+        assert!(worst < 1500, "{}", worst);
+    }
+
+    static SILENCE: [u8; 100] = [0; 100];
+    static SILENT_CLIP: AdpcmClip = AdpcmClip {
+        data: &SILENCE,
+        sample_rate: 12000,
+    };
+
+    #[test]
+    fn players_should_convert_the_rate_and_end() {
+        assert_eq!(200, SILENT_CLIP.len());
+        assert_eq!(16, SILENT_CLIP.duration_ms());
+        let mut player = AdpcmPlayer::<24000, 240>::new(&SILENT_CLIP);
+        let mut samples = 0;
+        while player.has_next() {
+            player.get_next();
+            samples += 1;
+        }
+        // Twice as many samples at twice the rate
+        assert!((399..=401).contains(&samples), "{}", samples);
+        assert_eq!(200, player.position());
+
+        player.restart(127);
+        assert!(player.has_next());
+        player.stop();
+        assert!(!player.has_next());
+    }
+
+    // Climbs to the top, then drops to the bottom in one step
+    static CLIFF: [u8; 17] = [
+        0x77, 0x77, 0x77, 0x77, 0x77, 0x77, 0x77, 0x77, 0x77, 0x77, 0x77, 0x77, 0x77, 0x77, 0x77,
+        0x77, 0xff,
+    ];
+    static CLIFF_CLIP: AdpcmClip = AdpcmClip {
+        data: &CLIFF,
+        sample_rate: 7000,
+    };
+
+    #[test]
+    fn full_scale_steps_should_interpolate() {
+        let mut player = AdpcmPlayer::<24000, 240>::new(&CLIFF_CLIP);
+        let mut lowest = 0;
+        while player.has_next() {
+            let sample = player.get_next().to_i32();
+            assert!((-0x4000..=0x4000).contains(&sample), "{}", sample);
+            lowest = core::cmp::min(lowest, sample);
+        }
+        assert!(lowest < -0x3000, "{}", lowest);
+    }
+}
@@ -7,43 +7,202 @@ use crate::sound_source_core::SoundSourceCore;
 const ADSR_FRACTION_DENOMINATOR: i64 = 0x8000000;
 type AdsrFraction = I32Fraction<{ ADSR_FRACTION_DENOMINATOR as i32 }>;
 
+/// Shape of an envelope stage
 ///
-/// ADSR envelope
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(usize)]
+pub enum EnvelopeCurve {
+    /// Straight line
+    Linear,
+    /// Moves quickly at first and slows down as it closes in on the target,
+    /// like a capacitor charging.  Natural sounding decays and releases, and
+    /// punchy attacks.
+    Exponential,
+    /// The mirror image.  Starts slowly and speeds up, for swells.
+    Logarithmic,
+}
+
+impl EnvelopeCurve {
+    const fn from_usize(usize_value: usize) -> Self {
+        match usize_value {
+            0 => Self::Linear,
+            1 => Self::Exponential,
+            2 => Self::Logarithmic,
+            3_usize.. => todo!(),
+        }
+    }
+}
+
+//
+// Curved stages are tracked as a fraction (with 30 bits of precision) that
+// is multiplied by a constant every tick.  A true exponential never gets
+// where it's going, so the curve aims CURVE_OVERSHOOT of the way past the
+// target, which it passes through right on time.
+//
+const CURVE_ONE: u64 = 1 << 30;
+const CURVE_OVERSHOOT: u64 = CURVE_ONE / 64;
+const CURVE_END: u64 = CURVE_OVERSHOOT * CURVE_ONE / (CURVE_ONE + CURVE_OVERSHOOT);
+
+const fn curve_pow(base: u64, exponent: u32) -> u64 {
+    let mut result = CURVE_ONE;
+    let mut square = base;
+    let mut remaining = exponent;
+    while remaining != 0 {
+        if remaining & 1 != 0 {
+            result = (result * square) >> 30;
+        }
+        square = (square * square) >> 30;
+        remaining >>= 1;
+    }
+    result
+}
+
+//
+// Per tick multiplier that takes the curve from 1 to CURVE_END in ticks
+// ticks.  There's no const pow, so binary search for it.
+//
+const fn curve_factor(ticks: i32) -> u64 {
+    if ticks <= 0 {
+        return 0;
+    }
+    let mut low = 0;
+    let mut high = CURVE_ONE;
+    while high - low > 1 {
+        let mid = (low + high) / 2;
+        if curve_pow(mid, ticks as u32) > CURVE_END {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    high
+}
+
 ///
-pub struct CoreAdsr<
+/// Delay, attack, hold, decay, sustain, release envelope
+///
+/// The envelope waits DELAY ms, rises to full volume over A ms, stays there
+/// for HOLD ms, falls to the SUSTAIN_VOLUME percentage over D ms and holds
+/// that until the note is released.  On release it falls to silence over R
+/// ms, starting from wherever it happens to be.  The attack, decay and
+/// release each have their own EnvelopeCurve.
+///
+/// Restarting a note that's still sounding picks the attack up from the
+/// level the note is at now, rather than dropping to silence and clicking.
+///
+/// A_VELOCITY adds up to that many ms to the attack for soft notes, so
+/// quiet notes swell in and loud ones speak right away.  The extra time
+/// shrinks linearly with velocity and is gone at 127.
+///
+pub struct CoreDahdsr<
     const P_FREQ: u32,
     const U_FREQ: u32,
+    const DELAY: i32,
     const A: i32,
+    const HOLD: i32,
     const D: i32,
     const SUSTAIN_VOLUME: u8,
     const R: i32,
+    const A_CURVE: usize,
+    const D_CURVE: usize,
+    const R_CURVE: usize,
+    const A_VELOCITY: i32,
     Source: OscillatorInterface<P_FREQ, U_FREQ>,
 > {
     time_since_state_start: i32, // units are 1/U_FREQ
+    attack_ticks: i32,
+    attack_gain: AdsrFraction,
+    attack_curve_factor: u32,
     last_sound: AdsrFraction,
+    release_gain: AdsrFraction,
+    stage_start_level: i32,
+    curve_position: u32,
     volume: i32,
+    target_volume: i32,
     releasing: bool,
     source: Source,
 }
 
+///
+/// ADSR envelope.  The DAHDSR without the delay and hold, all linear.
+///
+pub type CoreAdsr<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const A: i32,
+    const D: i32,
+    const SUSTAIN_VOLUME: u8,
+    const R: i32,
+    Source,
+> = CoreDahdsr<
+    P_FREQ,
+    U_FREQ,
+    0,
+    A,
+    0,
+    D,
+    SUSTAIN_VOLUME,
+    R,
+    { EnvelopeCurve::Linear as usize },
+    { EnvelopeCurve::Linear as usize },
+    { EnvelopeCurve::Linear as usize },
+    0,
+    Source,
+>;
+
 impl<
         const P_FREQ: u32,
         const U_FREQ: u32,
+        const DELAY: i32,
         const A: i32,
+        const HOLD: i32,
         const D: i32,
         const SUSTAIN_VOLUME: u8,
         const R: i32,
+        const A_CURVE: usize,
+        const D_CURVE: usize,
+        const R_CURVE: usize,
+        const A_VELOCITY: i32,
         Source: OscillatorInterface<P_FREQ, U_FREQ>,
-    > CoreAdsr<P_FREQ, U_FREQ, A, D, SUSTAIN_VOLUME, R, Source>
+    >
+    CoreDahdsr<
+        P_FREQ,
+        U_FREQ,
+        DELAY,
+        A,
+        HOLD,
+        D,
+        SUSTAIN_VOLUME,
+        R,
+        A_CURVE,
+        D_CURVE,
+        R_CURVE,
+        A_VELOCITY,
+        Source,
+    >
 {
     const ATTACK_VOLUME_SCALE: SoundSampleI32 = SoundSampleI32::MAX;
     const SUSTAIN_VOLUME_SCALE: SoundSampleI32 = SoundSampleI32::new_percent(SUSTAIN_VOLUME);
 
+    const A_CURVE_ENUM: EnvelopeCurve = EnvelopeCurve::from_usize(A_CURVE);
+    const D_CURVE_ENUM: EnvelopeCurve = EnvelopeCurve::from_usize(D_CURVE);
+    const R_CURVE_ENUM: EnvelopeCurve = EnvelopeCurve::from_usize(R_CURVE);
+
+    const DELAY_TICKS: i32 = time_to_ticks::<U_FREQ>(DELAY);
     const A_TICKS: i32 = time_to_ticks::<U_FREQ>(A);
+    const HOLD_TICKS: i32 = time_to_ticks::<U_FREQ>(HOLD);
     const D_TICKS: i32 = time_to_ticks::<U_FREQ>(D);
     const R_TICKS: i32 = time_to_ticks::<U_FREQ>(R);
     const RS_TICKS: i32 = time_to_ticks::<U_FREQ>(10);
 
+    // How fast the volume moves when a restart is quieter than the note
+    // it interrupts.  All the way from 0 to 127 takes 50ms.
+    const VOLUME_GLIDE: i32 = (127 << 8) / (time_to_ticks::<U_FREQ>(50) + 1);
+
+    const A_CURVE_FACTOR: u64 = curve_factor(Self::A_TICKS);
+    const D_CURVE_FACTOR: u64 = curve_factor(Self::D_TICKS);
+    const R_CURVE_FACTOR: u64 = curve_factor(Self::R_TICKS);
+
     const A_GAIN: AdsrFraction = if Self::A_TICKS != 0 {
         let a_diff: i64 = Self::ATTACK_VOLUME_SCALE.to_i32() as i64;
         AdsrFraction::new(
@@ -67,136 +226,371 @@ impl<
         AdsrFraction::new(0, 0)
     };
 
-    const R_GAIN: AdsrFraction = if Self::R_TICKS != 0 {
-        let assumed_start_volume = if Self::SUSTAIN_VOLUME_SCALE.to_i32() != 0 {
-            Self::SUSTAIN_VOLUME_SCALE
-        } else {
-            Self::ATTACK_VOLUME_SCALE
-        };
-        let r_diff: i64 = (-assumed_start_volume.to_i32()) as i64;
-        AdsrFraction::new(
-            (r_diff / (Self::R_TICKS as i64)) as i32,
-            ((r_diff) % (Self::R_TICKS as i64) * ADSR_FRACTION_DENOMINATOR / (Self::R_TICKS as i64))
-                as i32,
-        )
-    } else {
-        AdsrFraction::new(0, 0)
-    };
-
-    const A_END: i32 = Self::A_TICKS;
-    const D_END: i32 = Self::A_END + Self::D_TICKS;
+    const DELAY_END: i32 = Self::DELAY_TICKS;
     const R_START: i32 = 0;
     const R_END: i32 = Self::R_START + Self::R_TICKS;
     const R_TERMINATE: i32 = Self::R_END + Self::RS_TICKS;
+
+    //
+    // An attack too short to last a tick is no attack at all.
+    //
+    const fn initial_sound(attack_ticks: i32) -> AdsrFraction {
+        if attack_ticks != 0 {
+            AdsrFraction::new(0, 0)
+        } else if D != 0 {
+            AdsrFraction::new(Self::ATTACK_VOLUME_SCALE.to_i32(), 0)
+        } else {
+            AdsrFraction::new(Self::SUSTAIN_VOLUME_SCALE.to_i32(), 0)
+        }
+    }
+
+    //
+    // Set up the attack for a note played at volume.  Without velocity
+    // to attack routing it's all worked out at compile time.
+    //
+    fn start_attack(&mut self, volume: i32) {
+        if A_VELOCITY == 0 {
+            self.attack_ticks = Self::A_TICKS;
+            self.attack_gain = Self::A_GAIN;
+            self.attack_curve_factor = Self::A_CURVE_FACTOR as u32;
+            return;
+        }
+        let softness = 127 - (volume >> 8).clamp(0, 127);
+        let attack_time = A + A_VELOCITY * softness / 127;
+        self.attack_ticks = time_to_ticks::<U_FREQ>(attack_time);
+        self.attack_gain = if self.attack_ticks != 0 {
+            let a_diff: i64 = Self::ATTACK_VOLUME_SCALE.to_i32() as i64;
+            let ticks = self.attack_ticks as i64;
+            AdsrFraction::new(
+                (a_diff / ticks) as i32,
+                (a_diff % ticks * ADSR_FRACTION_DENOMINATOR / ticks) as i32,
+            )
+        } else {
+            AdsrFraction::new(0, 0)
+        };
+        self.attack_curve_factor = if Self::A_CURVE_ENUM == EnvelopeCurve::Linear {
+            0
+        } else {
+            curve_factor(self.attack_ticks) as u32
+        };
+    }
+
+    //
+    // Where the envelope is right now, as a level at the current volume.
+    //
+    fn current_level(&self) -> i32 {
+        let time = self.time_since_state_start;
+        let silent = if self.releasing {
+            time > Self::R_END
+        } else {
+            time < Self::DELAY_END
+        };
+        if silent {
+            0
+        } else {
+            self.last_sound.int_part.max(0)
+        }
+    }
+
+    /// The source the envelope is shaping
+    ///
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    pub fn source_mut(&mut self) -> &mut Source {
+        &mut self.source
+    }
+
+    fn attack_end(&self) -> i32 {
+        Self::DELAY_END + self.attack_ticks
+    }
+
+    fn hold_end(&self) -> i32 {
+        self.attack_end() + Self::HOLD_TICKS
+    }
+
+    fn decay_end(&self) -> i32 {
+        self.hold_end() + Self::D_TICKS
+    }
+
+    //
+    // Linear gain to get from level to silence in the release time.
+    //
+    const fn release_gain_from(level: i32) -> AdsrFraction {
+        if Self::R_TICKS != 0 {
+            let r_diff: i64 = -(level as i64);
+            AdsrFraction::new(
+                (r_diff / (Self::R_TICKS as i64)) as i32,
+                ((r_diff) % (Self::R_TICKS as i64) * ADSR_FRACTION_DENOMINATOR
+                    / (Self::R_TICKS as i64)) as i32,
+            )
+        } else {
+            AdsrFraction::new(0, 0)
+        }
+    }
+
+    //
+    // Advance one tick through a stage that's stage_ticks long and ends at
+    // target.  Returns the level at the start of the tick.
+    //
+    fn step(
+        &mut self,
+        curve: EnvelopeCurve,
+        curve_factor: u64,
+        gain: &AdsrFraction,
+        target: i32,
+        tick_in_stage: i32,
+        stage_ticks: i32,
+    ) -> i32 {
+        let rval = self.last_sound.int_part;
+        if curve == EnvelopeCurve::Linear {
+            self.last_sound.add(gain);
+            return rval;
+        }
+
+        if tick_in_stage == 0 {
+            self.stage_start_level = rval;
+            self.curve_position = if curve == EnvelopeCurve::Exponential {
+                CURVE_ONE as u32
+            } else {
+                CURVE_END as u32
+            };
+        }
+        let position = if curve == EnvelopeCurve::Exponential {
+            (self.curve_position as u64 * curve_factor) >> 30
+        } else {
+            (self.curve_position as u64 * CURVE_ONE) / curve_factor
+        };
+        self.curve_position = position as u32;
+
+        let progress = if tick_in_stage + 1 >= stage_ticks {
+            CURVE_ONE as i64
+        } else if curve == EnvelopeCurve::Exponential {
+            (((CURVE_ONE + CURVE_OVERSHOOT) * (CURVE_ONE - position.min(CURVE_ONE))) >> 30) as i64
+        } else {
+            ((((CURVE_ONE + CURVE_OVERSHOOT) * position) >> 30) as i64 - CURVE_OVERSHOOT as i64)
+                .clamp(0, CURVE_ONE as i64)
+        };
+        let distance = (target - self.stage_start_level) as i64;
+        let level = self.stage_start_level + ((distance * progress) >> 30) as i32;
+        self.last_sound = AdsrFraction::new(level, 0);
+        rval
+    }
 }
 
 impl<
         const P_FREQ: u32,
         const U_FREQ: u32,
+        const DELAY: i32,
         const A: i32,
+        const HOLD: i32,
         const D: i32,
         const SUSTAIN_VOLUME: u8,
         const R: i32,
+        const A_CURVE: usize,
+        const D_CURVE: usize,
+        const R_CURVE: usize,
+        const A_VELOCITY: i32,
         Source: OscillatorInterface<P_FREQ, U_FREQ>,
     > SoundSourceCore<P_FREQ, U_FREQ>
-    for CoreAdsr<P_FREQ, U_FREQ, A, D, SUSTAIN_VOLUME, R, Source>
+    for CoreDahdsr<
+        P_FREQ,
+        U_FREQ,
+        DELAY,
+        A,
+        HOLD,
+        D,
+        SUSTAIN_VOLUME,
+        R,
+        A_CURVE,
+        D_CURVE,
+        R_CURVE,
+        A_VELOCITY,
+        Source,
+    >
 {
     type InitValuesType = (Source::InitValuesType, i32);
 
     fn new(init_value: Self::InitValuesType) -> Self {
-        let time_since_state_start = 0;
-
-        let last_sound = if A != 0 {
-            AdsrFraction::new(0, 0)
-        } else if D != 0 {
-            AdsrFraction::new(Self::ATTACK_VOLUME_SCALE.to_i32(), 0)
-        } else {
-            AdsrFraction::new(Self::SUSTAIN_VOLUME_SCALE.to_i32(), 0)
-        };
-
-        Self {
-            time_since_state_start,
-            last_sound,
+        let mut rval = Self {
+            time_since_state_start: 0,
+            attack_ticks: 0,
+            attack_gain: AdsrFraction::new(0, 0),
+            attack_curve_factor: 0,
+            last_sound: AdsrFraction::new(0, 0),
+            release_gain: AdsrFraction::new(0, 0),
+            stage_start_level: 0,
+            curve_position: 0,
             volume: init_value.1,
+            target_volume: init_value.1,
             source: Source::new(init_value.0),
             releasing: false,
-        }
+        };
+        rval.start_attack(rval.volume);
+        rval.last_sound = Self::initial_sound(rval.attack_ticks);
+        rval
     }
 
     #[inline]
-    fn get_next(self: &mut Self) -> SoundSampleI32 {
+    fn get_next(&mut self) -> SoundSampleI32 {
         self.source.get_next()
     }
 
-    fn update(self: &mut Self) {
+    fn update(&mut self) {
         self.source.update();
+        let time = self.time_since_state_start;
         let scale: SoundSampleI32 = if !self.releasing {
-            if self.time_since_state_start < Self::A_END {
-                let rval = SoundSampleI32::new_i32(self.last_sound.int_part);
-                self.last_sound.add(&Self::A_GAIN);
-                rval
-            } else if self.time_since_state_start < Self::D_END {
-                let rval = SoundSampleI32::new_i32(self.last_sound.int_part);
-                self.last_sound.add(&Self::D_GAIN);
-                rval
+            if time < Self::DELAY_END {
+                SoundSampleI32::ZERO
+            } else if time < self.attack_end() {
+                let gain =
+                    AdsrFraction::new(self.attack_gain.int_part, self.attack_gain.numerator_part);
+                SoundSampleI32::new_i32(self.step(
+                    Self::A_CURVE_ENUM,
+                    self.attack_curve_factor as u64,
+                    &gain,
+                    Self::ATTACK_VOLUME_SCALE.to_i32(),
+                    time - Self::DELAY_END,
+                    self.attack_ticks,
+                ))
+            } else if time < self.hold_end() {
+                SoundSampleI32::new_i32(self.last_sound.int_part)
+            } else if time < self.decay_end() {
+                let gain = Self::D_GAIN;
+                SoundSampleI32::new_i32(self.step(
+                    Self::D_CURVE_ENUM,
+                    Self::D_CURVE_FACTOR,
+                    &gain,
+                    Self::SUSTAIN_VOLUME_SCALE.to_i32(),
+                    time - self.hold_end(),
+                    Self::D_TICKS,
+                ))
             } else {
                 let rval = SoundSampleI32::new_i32(self.last_sound.int_part);
                 self.last_sound = AdsrFraction::new(Self::SUSTAIN_VOLUME_SCALE.to_i32(), 0);
                 rval
             }
-        } else {
-            if self.time_since_state_start <= Self::R_END {
-                let rval = SoundSampleI32::new_i32(self.last_sound.int_part);
-                self.last_sound.add(&Self::R_GAIN);
-                if self.last_sound.int_part < 0 {
-                    self.time_since_state_start = Self::R_END + 1;
-                }
-                rval
-            } else {
-                SoundSampleI32::ZERO
+        } else if time <= Self::R_END {
+            let gain =
+                AdsrFraction::new(self.release_gain.int_part, self.release_gain.numerator_part);
+            let rval = self.step(
+                Self::R_CURVE_ENUM,
+                Self::R_CURVE_FACTOR,
+                &gain,
+                0,
+                time - Self::R_START,
+                Self::R_TICKS,
+            );
+            if self.last_sound.int_part < 0 {
+                self.last_sound = AdsrFraction::new(0, 0);
+                self.time_since_state_start = Self::R_END + 1;
             }
+            SoundSampleI32::new_i32(rval)
+        } else {
+            SoundSampleI32::ZERO
         };
-        self.time_since_state_start = self.time_since_state_start + 1;
+        self.time_since_state_start += 1;
+        if self.volume != self.target_volume {
+            self.volume = if self.volume > self.target_volume {
+                core::cmp::max(self.volume - Self::VOLUME_GLIDE, self.target_volume)
+            } else {
+                core::cmp::min(self.volume + Self::VOLUME_GLIDE, self.target_volume)
+            };
+        }
         let volume_adjusted_scale = SoundSampleI32::new_i32((self.volume * scale.to_i32()) >> 15);
         self.source
             .set_amplitude_adjust(volume_adjusted_scale.pos_clip());
     }
 
-    fn has_next(self: &Self) -> bool {
+    fn has_next(&self) -> bool {
         !self.releasing || self.time_since_state_start <= Self::R_TERMINATE
     }
 
-    fn trigger_note_off(self: &mut Self) {
+    fn trigger_note_off(&mut self) {
+        //
+        // Release from wherever the envelope is now, which might be part
+        // way through the attack.  Before the delay is over there's
+        // nothing to release.
+        //
+        if !self.releasing && self.time_since_state_start < Self::DELAY_END {
+            self.last_sound = AdsrFraction::new(0, 0);
+        }
+        self.release_gain = Self::release_gain_from(self.last_sound.int_part);
         self.releasing = true;
         self.time_since_state_start = 0;
     }
-    fn restart(self: &mut Self, vel: u8) {
-        self.time_since_state_start = 0;
-        self.last_sound = if A != 0 {
-            AdsrFraction::new(0, 0)
-        } else if D != 0 {
-            AdsrFraction::new(Self::ATTACK_VOLUME_SCALE.to_i32(), 0)
-        } else {
-            AdsrFraction::new(Self::SUSTAIN_VOLUME_SCALE.to_i32(), 0)
-        };
+
+    fn restart(&mut self, vel: u8) {
+        //
+        // Work out what level gives the same output at the new volume, and
+        // attack from there.  If the note is already louder than the new
+        // velocity can reach, attack from the top and glide the volume down
+        // instead.  Retriggers skip the delay.
+        //
+        let new_volume = core::cmp::max((vel as i32) << 8, 1);
+        let output = (self.current_level() as i64) * (self.volume as i64);
+        let max_level = Self::ATTACK_VOLUME_SCALE.to_i32() as i64;
+        let level = core::cmp::min(output / (new_volume as i64), max_level) as i32;
+
         self.releasing = false;
-        self.volume = (vel as i32) << 8;
+        self.target_volume = new_volume;
+        self.volume = core::cmp::max(new_volume, (output / max_level) as i32);
+        self.start_attack(new_volume);
+        self.time_since_state_start = Self::DELAY_END;
+        if self.attack_ticks == 0 {
+            self.last_sound = Self::initial_sound(self.attack_ticks);
+        } else {
+            if Self::A_CURVE_ENUM == EnvelopeCurve::Linear {
+                // Skip the part of the attack that's below level.
+                self.time_since_state_start += ((self.attack_ticks as i64) * (level as i64)
+                    / (Self::ATTACK_VOLUME_SCALE.to_i32() as i64))
+                    as i32;
+            }
+            // Curved attacks run their full length, starting from level.
+            self.last_sound = AdsrFraction::new(level, 0);
+        }
+        self.source.restart(vel);
         self.update();
     }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.source.set_pitch_ratio(ratio)
+    }
 }
 
 impl<
         const P_FREQ: u32,
         const U_FREQ: u32,
+        const DELAY: i32,
         const A: i32,
+        const HOLD: i32,
         const D: i32,
         const SUSTAIN_VOLUME: u8,
         const R: i32,
+        const A_CURVE: usize,
+        const D_CURVE: usize,
+        const R_CURVE: usize,
+        const A_VELOCITY: i32,
         Source: OscillatorInterface<P_FREQ, U_FREQ>,
     > OscillatorInterface<P_FREQ, U_FREQ>
-    for CoreAdsr<P_FREQ, U_FREQ, A, D, SUSTAIN_VOLUME, R, Source>
+    for CoreDahdsr<
+        P_FREQ,
+        U_FREQ,
+        DELAY,
+        A,
+        HOLD,
+        D,
+        SUSTAIN_VOLUME,
+        R,
+        A_CURVE,
+        D_CURVE,
+        R_CURVE,
+        A_VELOCITY,
+        Source,
+    >
 {
-    fn set_amplitude_adjust(self: &mut Self, _adjust: SoundSampleI32) {}
+    fn set_amplitude_adjust(&mut self, _adjust: SoundSampleI32) {}
 }
 
 #[cfg(test)]
@@ -334,4 +728,280 @@ mod tests {
         assert_eq!(false, adsr.has_next());
         */
     }
+
+    const LINEAR: usize = EnvelopeCurve::Linear as usize;
+    const EXPONENTIAL: usize = EnvelopeCurve::Exponential as usize;
+    const LOGARITHMIC: usize = EnvelopeCurve::Logarithmic as usize;
+
+    fn run_for<T: SoundSourceCore<1000, 1000>>(envelope: &mut T, ticks: usize) -> i32 {
+        let mut level = 0;
+        for _ in 0..ticks {
+            envelope.update();
+            level = envelope.get_next().to_i32();
+        }
+        level
+    }
+
+    #[test]
+    fn delay_and_hold_should_stretch_the_envelope() {
+        type TestDahdsr = CoreDahdsr<
+            1000,
+            1000,
+            5,
+            2,
+            3,
+            4,
+            50,
+            8,
+            LINEAR,
+            LINEAR,
+            LINEAR,
+            0,
+            SteadyOne<1000, 1000>,
+        >;
+        let mut envelope = TestDahdsr::new((SoundSourceNoteInit::new(1, 2, 3), 0x8000));
+        assert_eq!(0, run_for(&mut envelope, 5));
+        assert_eq!(0x0000, run_for(&mut envelope, 1));
+        assert_eq!(0x4000, run_for(&mut envelope, 1));
+        assert_eq!(0x8000, run_for(&mut envelope, 3));
+        assert_eq!(0x8000, run_for(&mut envelope, 1));
+        assert_eq!(0x7000, run_for(&mut envelope, 1));
+        assert_eq!(0x4000, run_for(&mut envelope, 3));
+        assert_eq!(0x4000, run_for(&mut envelope, 10));
+    }
+
+    #[test]
+    fn curves_should_bend_the_decay() {
+        type Exponential = CoreDahdsr<
+            1000,
+            1000,
+            0,
+            0,
+            0,
+            400,
+            0,
+            8,
+            LINEAR,
+            EXPONENTIAL,
+            LINEAR,
+            0,
+            SteadyOne<1000, 1000>,
+        >;
+        type Logarithmic = CoreDahdsr<
+            1000,
+            1000,
+            0,
+            0,
+            0,
+            400,
+            0,
+            8,
+            LINEAR,
+            LOGARITHMIC,
+            LINEAR,
+            0,
+            SteadyOne<1000, 1000>,
+        >;
+        let init = (SoundSourceNoteInit::new(1, 2, 3), 0x8000);
+        let mut exponential = Exponential::new(init);
+        let mut logarithmic = Logarithmic::new((SoundSourceNoteInit::new(1, 2, 3), 0x8000));
+
+        // A quarter of the way in, linear would be at 0x6000.
+        let exponential_quarter = run_for(&mut exponential, 100);
+        let logarithmic_quarter = run_for(&mut logarithmic, 100);
+        assert!(exponential_quarter < 0x4000, "{:x}", exponential_quarter);
+        assert!(logarithmic_quarter > 0x7000, "{:x}", logarithmic_quarter);
+
+        // Both should keep heading the same way and land on time.
+        let mut last_exponential = exponential_quarter;
+        let mut last_logarithmic = logarithmic_quarter;
+        for _ in 100..400 {
+            let current_exponential = run_for(&mut exponential, 1);
+            let current_logarithmic = run_for(&mut logarithmic, 1);
+            assert!(current_exponential <= last_exponential);
+            assert!(current_logarithmic <= last_logarithmic);
+            last_exponential = current_exponential;
+            last_logarithmic = current_logarithmic;
+        }
+        assert_eq!(0, run_for(&mut exponential, 1));
+        assert_eq!(0, run_for(&mut logarithmic, 1));
+    }
+
+    //
+    // Let go of a note a quarter of the way through a 100ms attack, and
+    // report the biggest step the envelope takes and where it ends up.
+    //
+    fn release_during_attack<T>() -> (i32, i32)
+    where
+        T: SoundSourceCore<1000, 1000, InitValuesType = (SoundSourceNoteInit, i32)>,
+    {
+        let mut envelope = T::new((SoundSourceNoteInit::new(1, 2, 3), 0x8000));
+        let mut last = 0;
+        let mut biggest_jump = 0;
+        for tick in 0..200 {
+            if tick == 25 {
+                envelope.trigger_note_off();
+            }
+            let current = run_for(&mut envelope, 1);
+            biggest_jump = core::cmp::max(biggest_jump, (current - last).abs());
+            last = current;
+        }
+        (biggest_jump, last)
+    }
+
+    #[test]
+    fn release_should_start_from_the_current_level() {
+        type Linear = CoreDahdsr<
+            1000,
+            1000,
+            0,
+            100,
+            0,
+            100,
+            80,
+            100,
+            LINEAR,
+            LINEAR,
+            LINEAR,
+            0,
+            SteadyOne<1000, 1000>,
+        >;
+        type Exponential = CoreDahdsr<
+            1000,
+            1000,
+            0,
+            100,
+            0,
+            100,
+            80,
+            100,
+            LINEAR,
+            LINEAR,
+            EXPONENTIAL,
+            0,
+            SteadyOne<1000, 1000>,
+        >;
+        let (linear_jump, linear_end) = release_during_attack::<Linear>();
+        assert!(linear_jump < 0x400, "{:x}", linear_jump);
+        assert_eq!(0, linear_end);
+        let (exponential_jump, exponential_end) = release_during_attack::<Exponential>();
+        assert!(exponential_jump < 0x400, "{:x}", exponential_jump);
+        assert_eq!(0, exponential_end);
+    }
+
+    #[test]
+    fn soft_notes_should_attack_more_slowly() {
+        type TestAdsr = CoreDahdsr<
+            1000,
+            1000,
+            0,
+            10,
+            0,
+            0,
+            100,
+            8,
+            LINEAR,
+            LINEAR,
+            LINEAR,
+            100,
+            SteadyOne<1000, 1000>,
+        >;
+        let mut loud = TestAdsr::new((SoundSourceNoteInit::new(1, 2, 127), 127 << 8));
+        let mut soft = TestAdsr::new((SoundSourceNoteInit::new(1, 2, 1), 1 << 8));
+        assert_eq!(127 << 8, run_for(&mut loud, 12));
+        // A soft note takes about 110ms to get all the way up.
+        let soft_level = run_for(&mut soft, 11);
+        assert!(soft_level > 0 && soft_level < 0x20, "{:x}", soft_level);
+        assert_eq!(1 << 8, run_for(&mut soft, 100));
+
+        // A restart picks the attack time again.
+        soft.restart(127);
+        assert_eq!(127 << 8, run_for(&mut soft, 12));
+    }
+
+    //
+    // Play a 440hz sine through the envelope for ticks updates, restart it
+    // and play on.  Returns the biggest sample to sample step seen after the
+    // restart, and the level the envelope settles at.
+    //
+    fn restart_discontinuity<const A_CURVE: usize>(
+        ticks: usize,
+        releasing: bool,
+        vel: u8,
+    ) -> (i32, i32) {
+        use crate::midi_notes::FREQUENCY_MULTIPLIER;
+        use crate::oscillator::CoreOscillator;
+        use crate::oscillator::OscillatorType;
+
+        type Sine = CoreOscillator<24000, 240, 50, 100, { OscillatorType::Sine as usize }>;
+        let mut envelope = CoreDahdsr::<
+            24000,
+            240,
+            0,
+            50,
+            0,
+            100,
+            60,
+            200,
+            A_CURVE,
+            LINEAR,
+            LINEAR,
+            0,
+            Sine,
+        >::new((440 * FREQUENCY_MULTIPLIER, 127 << 8));
+        let mut last = 0;
+        for tick in 0..ticks {
+            if releasing && tick == ticks / 2 {
+                envelope.trigger_note_off();
+            }
+            envelope.update();
+            for _ in 0..100 {
+                last = envelope.get_next().to_i32();
+            }
+        }
+        envelope.restart(vel);
+        let mut biggest_step = 0;
+        let mut peak = 0;
+        for _ in 0..120 {
+            envelope.update();
+            peak = 0;
+            for _ in 0..100 {
+                let current = envelope.get_next().to_i32();
+                biggest_step = core::cmp::max(biggest_step, (current - last).abs());
+                peak = core::cmp::max(peak, current.abs());
+                last = current;
+            }
+        }
+        (biggest_step, peak)
+    }
+
+    #[test]
+    fn restart_should_not_click() {
+        // The oscillator peaks at 0x4000, and a 440hz sine that size moves
+        // at most 0x760 per sample.  The attack adds up to 0x555 per update
+        // on top of that.  Snapping the envelope back to zero from sustain
+        // would be a jump of up to 0x2600.
+        const SMOOTH: i32 = 0xd00;
+        for releasing in [false, true] {
+            for ticks in [5, 20, 60] {
+                for vel in [127, 64, 20] {
+                    let (step, _) = restart_discontinuity::<LINEAR>(ticks, releasing, vel);
+                    assert!(step < SMOOTH, "{} {} {} {:x}", releasing, ticks, vel, step);
+                    let (step, _) = restart_discontinuity::<EXPONENTIAL>(ticks, releasing, vel);
+                    assert!(step < SMOOTH, "{} {} {} {:x}", releasing, ticks, vel, step);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn restart_should_end_up_at_the_new_velocity() {
+        let (_, loud) = restart_discontinuity::<LINEAR>(60, false, 127);
+        let (_, soft) = restart_discontinuity::<LINEAR>(60, false, 64);
+        let (_, from_release) = restart_discontinuity::<LINEAR>(60, true, 127);
+        // Sustain is at 60%.
+        assert!((0x2500..=0x2700).contains(&loud), "{:x}", loud);
+        assert!((0x1280..=0x1380).contains(&soft), "{:x}", soft);
+        assert_eq!(loud, from_release);
+    }
 }
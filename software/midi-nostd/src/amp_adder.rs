@@ -4,6 +4,12 @@ use crate::note::SoundSourceNoteInit;
 use crate::sound_sample::SoundSampleI32;
 use crate::sound_source_core::SoundSourceCore;
 
+/// Send bus for the reverb
+pub const REVERB_SEND: usize = 0;
+/// Send bus for the chorus
+pub const CHORUS_SEND: usize = 1;
+pub const NUM_SENDS: usize = 2;
+
 ///
 /// Amp Adder
 ///
@@ -18,6 +24,10 @@ pub struct AmpAdder<
     active_channel_list: [usize; NUM_CHANNELS],
     num_active_channels: usize,
     scale: SoundSampleI32,
+    /// Send levels of each note, from 0 to 127
+    sends: [[u8; NUM_SENDS]; NUM_CHANNELS],
+    /// The notes mixed by their send levels, from the last get_next
+    send_outputs: [SoundSampleI32; NUM_SENDS],
 }
 
 impl<const P_FREQ: u32, const U_FREQ: u32, const NUM_CHANNELS: usize, const NO_SCALEDOWN: bool>
@@ -39,6 +49,22 @@ impl<const P_FREQ: u32, const U_FREQ: u32, const NUM_CHANNELS: usize, const NO_S
         self.channels[element].restart(vel);
     }
 
+    /// Set how much of a note goes to a send bus, from 0 to 127
+    ///
+    pub fn set_send_at(&mut self, element: usize, send: usize, level: u8) {
+        self.sends[element][send] = level;
+    }
+
+    /// A send bus, for the sample get_next last returned
+    ///
+    pub fn get_send(&self, send: usize) -> SoundSampleI32 {
+        self.send_outputs[send]
+    }
+
+    pub fn set_pitch_ratio_at(&mut self, element: usize, ratio: u32) -> bool {
+        self.channels[element].set_pitch_ratio(ratio)
+    }
+
     pub fn get_current_num_mixed_notes(self: &mut Self) -> u32 {
         return self.num_active_channels as u32;
     }
@@ -61,6 +87,8 @@ impl<const P_FREQ: u32, const U_FREQ: u32, const NUM_CHANNELS: usize, const NO_S
             channels: { core::array::from_fn(|_idx| Note::<P_FREQ, U_FREQ>::default()) },
             num_active_channels: 0,
             scale,
+            sends: [[0; NUM_SENDS]; NUM_CHANNELS],
+            send_outputs: [SoundSampleI32::ZERO; NUM_SENDS],
             active_channel_list: { core::array::from_fn(|_idx| 0) },
         }
     }
@@ -68,13 +96,24 @@ impl<const P_FREQ: u32, const U_FREQ: u32, const NUM_CHANNELS: usize, const NO_S
     #[inline(never)]
     fn get_next(self: &mut Self) -> SoundSampleI32 {
         let mut output: SoundSampleI32 = SoundSampleI32::ZERO;
+        let mut sends: [i32; NUM_SENDS] = [0; NUM_SENDS];
 
         let active_channels = &self.active_channel_list[0..self.num_active_channels];
 
         for i in active_channels {
-            output = output + self.channels[*i].get_next();
+            let sample = self.channels[*i].get_next();
+            for (send, level) in sends.iter_mut().zip(self.sends[*i]) {
+                *send += sample.to_i32() * (level as i32);
+            }
+            output = output + sample;
         }
 
+        for (send_output, send) in self.send_outputs.iter_mut().zip(sends) {
+            *send_output = SoundSampleI32::new_i32(send >> 7);
+            if !NO_SCALEDOWN {
+                *send_output = *send_output * self.scale;
+            }
+        }
         if NO_SCALEDOWN {
             output
         } else {
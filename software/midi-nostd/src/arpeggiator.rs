@@ -0,0 +1,723 @@
+// Arpeggiator and chord memory.
+//
+// Sits between the keys and a LiveSynth.  Chord memory turns each key into
+// a stored chord built on it, so one finger plays a triad.  The
+// arpeggiator then plays whatever's held one note at a time, in turn, at a
+// rate tied to the tempo, across one or more octaves.
+//
+// Arpeggiator is just the timing and the order of the notes: it's ticked
+// once a sample and says which note to let go and which to start.
+// ArpPlayer puts the two together with a LiveSynth, and takes commands
+// from another core through a CommandQueue, like SequencerPlayer.
+//
+
+use crate::command_queue::CommandQueue;
+use crate::live_synth::LiveSynth;
+use crate::sound_sample::SoundSampleI32;
+
+/// Most notes the arpeggiator holds at once, chords included
+pub const MAX_HELD_NOTES: usize = 16;
+/// Most notes in a chord
+pub const MAX_CHORD_NOTES: usize = 6;
+pub const MAX_OCTAVES: u8 = 4;
+
+// One arpeggio note at a time, and chords of a few notes without it
+const LOUD_NOTES: i32 = 2;
+
+/// Order the held notes are played in
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArpMode {
+    Up,
+    Down,
+    /// Up then down, without playing the top and bottom notes twice
+    UpDown,
+    Random,
+}
+
+/// Length of each arpeggio note
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ArpRate {
+    Quarter,
+    Eighth,
+    EighthTriplet,
+    Sixteenth,
+    SixteenthTriplet,
+    ThirtySecond,
+}
+
+impl ArpRate {
+    pub const fn notes_per_beat(self) -> u32 {
+        match self {
+            ArpRate::Quarter => 1,
+            ArpRate::Eighth => 2,
+            ArpRate::EighthTriplet => 3,
+            ArpRate::Sixteenth => 4,
+            ArpRate::SixteenthTriplet => 6,
+            ArpRate::ThirtySecond => 8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ArpSettings {
+    pub mode: ArpMode,
+    /// Octaves the arpeggio runs over, from 1 to MAX_OCTAVES
+    pub octaves: u8,
+    pub rate: ArpRate,
+    /// How much of each note is held, from 1 to 100
+    pub gate_percent: u8,
+    /// Keep playing the last keys after they're let go, until new ones
+    /// are pressed
+    pub latch: bool,
+}
+
+impl ArpSettings {
+    pub const DEFAULT: Self = Self {
+        mode: ArpMode::Up,
+        octaves: 1,
+        rate: ArpRate::Sixteenth,
+        gate_percent: 50,
+        latch: false,
+    };
+}
+
+///
+/// A chord, as semitones up from the key that plays it
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Chord {
+    intervals: [u8; MAX_CHORD_NOTES],
+    len: u8,
+}
+
+impl Chord {
+    pub const MAJOR: Self = Self::new(&[0, 4, 7]);
+    pub const MINOR: Self = Self::new(&[0, 3, 7]);
+    pub const SEVENTH: Self = Self::new(&[0, 4, 7, 10]);
+    /// Root, fifth and octave
+    pub const POWER: Self = Self::new(&[0, 7, 12]);
+
+    /// Chord from intervals, lowest first.  Past MAX_CHORD_NOTES they're
+    /// dropped.
+    ///
+    pub const fn new(intervals: &[u8]) -> Self {
+        let mut rval = Self {
+            intervals: [0; MAX_CHORD_NOTES],
+            len: 0,
+        };
+        while (rval.len as usize) < intervals.len() && (rval.len as usize) < MAX_CHORD_NOTES {
+            rval.intervals[rval.len as usize] = intervals[rval.len as usize];
+            rval.len += 1;
+        }
+        rval
+    }
+
+    /// Remember the chord keys make, counted up from the lowest.  None if
+    /// there aren't any keys.
+    ///
+    pub fn learn(keys: &[u8]) -> Option<Self> {
+        let lowest = *keys.iter().min()?;
+        let mut rval = Self::new(&[]);
+        // Every key once, lowest first
+        let mut last = None;
+        while let Some(key) = keys
+            .iter()
+            .copied()
+            .filter(|key| last.map_or(true, |last| *key > last))
+            .min()
+        {
+            if rval.len as usize == MAX_CHORD_NOTES {
+                break;
+            }
+            rval.intervals[rval.len as usize] = key - lowest;
+            rval.len += 1;
+            last = Some(key);
+        }
+        Some(rval)
+    }
+
+    pub fn intervals(&self) -> &[u8] {
+        &self.intervals[..self.len as usize]
+    }
+
+    /// The keys of the chord played from key, leaving out any off the top
+    /// of the keyboard
+    ///
+    pub fn keys(&self, key: u8) -> impl Iterator<Item = u8> + '_ {
+        self.intervals()
+            .iter()
+            .map(move |interval| key as u16 + *interval as u16)
+            .filter(|key| *key < 128)
+            .map(|key| key as u8)
+    }
+}
+
+/// What to play after a tick
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ArpOutput {
+    pub note_off: Option<u8>,
+    /// Key and velocity
+    pub note_on: Option<(u8, u8)>,
+}
+
+#[derive(Clone, Copy)]
+struct HeldNote {
+    key: u8,
+    velocity: u8,
+    /// The key that was pressed for it, which differs with chord memory
+    pressed_key: u8,
+}
+
+///
+/// Plays held notes one at a time, at FREQ ticks a second
+///
+pub struct Arpeggiator<const FREQ: u32> {
+    settings: ArpSettings,
+    bpm: u16,
+    chord: Option<Chord>,
+    /// Notes to play, lowest first
+    notes: [HeldNote; MAX_HELD_NOTES],
+    num_notes: usize,
+    /// Keys down right now, a bit per key
+    keys_down: u128,
+    /// Notes are only there because of latch, so new keys replace them
+    latched: bool,
+    /// Ticks into the note, scaled so a note is PERIOD whatever the tempo
+    phase: u32,
+    /// Notes played since the arpeggio started
+    position: usize,
+    playing_key: Option<u8>,
+    random: u32,
+}
+
+impl<const FREQ: u32> Arpeggiator<FREQ> {
+    const PERIOD: u32 = FREQ * 60;
+
+    pub const fn new(settings: ArpSettings, bpm: u16) -> Self {
+        Self {
+            settings,
+            bpm,
+            chord: None,
+            notes: [HeldNote {
+                key: 0,
+                velocity: 0,
+                pressed_key: 0,
+            }; MAX_HELD_NOTES],
+            num_notes: 0,
+            keys_down: 0,
+            latched: false,
+            phase: Self::PERIOD,
+            position: 0,
+            playing_key: None,
+            random: 0x1234_5678,
+        }
+    }
+
+    pub fn settings(&self) -> &ArpSettings {
+        &self.settings
+    }
+
+    /// Change the settings.  Turning latch off lets go of the keys that
+    /// aren't down.
+    ///
+    pub fn set_settings(&mut self, settings: ArpSettings) {
+        self.settings = settings;
+        self.settings.octaves = settings.octaves.clamp(1, MAX_OCTAVES);
+        self.settings.gate_percent = settings.gate_percent.clamp(1, 100);
+        if !settings.latch {
+            let keys_down = self.keys_down;
+            self.remove_notes(|note| keys_down & (1 << note.pressed_key) == 0);
+            self.latched = false;
+        }
+    }
+
+    /// Change the tempo the rate follows
+    ///
+    pub fn set_bpm(&mut self, bpm: u16) {
+        self.bpm = bpm;
+    }
+
+    /// Play each key as this chord from now on, or as itself with None.
+    /// Notes already held stay as they are.
+    ///
+    pub fn set_chord(&mut self, chord: Option<Chord>) {
+        self.chord = chord;
+    }
+
+    pub fn key_down(&mut self, key: u8, velocity: u8) {
+        let key = key & 0x7f;
+        if self.latched {
+            self.num_notes = 0;
+            self.latched = false;
+        }
+        if self.num_notes == 0 {
+            // Start the arpeggio on the next tick
+            self.phase = Self::PERIOD;
+            self.position = 0;
+        }
+        self.keys_down |= 1 << key;
+
+        let chord = self.chord.unwrap_or(Chord::new(&[0]));
+        for chord_key in chord.keys(key) {
+            if self.num_notes == MAX_HELD_NOTES {
+                break;
+            }
+            let at = self.notes[..self.num_notes]
+                .iter()
+                .position(|note| note.key > chord_key)
+                .unwrap_or(self.num_notes);
+            self.notes.copy_within(at..self.num_notes, at + 1);
+            self.notes[at] = HeldNote {
+                key: chord_key,
+                velocity,
+                pressed_key: key,
+            };
+            self.num_notes += 1;
+        }
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        let key = key & 0x7f;
+        self.keys_down &= !(1 << key);
+        if self.settings.latch {
+            self.latched = self.keys_down == 0;
+        } else {
+            self.remove_notes(|note| note.pressed_key == key);
+        }
+    }
+
+    /// Let go of everything, latched notes included.  Returns the key
+    /// that was sounding.
+    ///
+    pub fn stop(&mut self) -> Option<u8> {
+        self.num_notes = 0;
+        self.keys_down = 0;
+        self.latched = false;
+        self.playing_key.take()
+    }
+
+    fn remove_notes(&mut self, remove: impl Fn(&HeldNote) -> bool) {
+        let mut kept = 0;
+        for idx in 0..self.num_notes {
+            if !remove(&self.notes[idx]) {
+                self.notes[kept] = self.notes[idx];
+                kept += 1;
+            }
+        }
+        self.num_notes = kept;
+    }
+
+    /// True while there's something to play
+    ///
+    pub fn is_running(&self) -> bool {
+        self.num_notes > 0
+    }
+
+    // Next random number, from a xorshift
+    fn next_random(&mut self) -> u32 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        self.random
+    }
+
+    // The next note of the arpeggio, as (key, velocity)
+    fn next_note(&mut self) -> Option<(u8, u8)> {
+        let total = self.num_notes * self.settings.octaves as usize;
+        let position = self.position;
+        self.position += 1;
+        let idx = match self.settings.mode {
+            ArpMode::Up => position % total,
+            ArpMode::Down => total - 1 - position % total,
+            ArpMode::UpDown if total == 1 => 0,
+            ArpMode::UpDown => {
+                let cycle = 2 * total - 2;
+                let idx = position % cycle;
+                if idx < total {
+                    idx
+                } else {
+                    cycle - idx
+                }
+            }
+            ArpMode::Random => self.next_random() as usize % total,
+        };
+        let note = self.notes[idx % self.num_notes];
+        let key = note.key as usize + 12 * (idx / self.num_notes);
+        // Octaves off the top of the keyboard are rests.
+        (key < 128).then_some((key as u8, note.velocity))
+    }
+
+    /// Move on a tick
+    ///
+    pub fn tick(&mut self) -> ArpOutput {
+        let mut output = ArpOutput::default();
+        if self.num_notes == 0 {
+            // Start on the next key down, which resets the phase
+            output.note_off = self.playing_key.take();
+            return output;
+        }
+        if self.phase >= Self::PERIOD {
+            self.phase -= Self::PERIOD;
+            output.note_off = self.playing_key.take();
+            output.note_on = self.next_note();
+            self.playing_key = output.note_on.map(|(key, _)| key);
+        } else if self.playing_key.is_some()
+            && (self.phase as u64) * 100
+                >= (Self::PERIOD as u64) * (self.settings.gate_percent as u64)
+        {
+            output.note_off = self.playing_key.take();
+        }
+        self.phase += self.bpm as u32 * self.settings.rate.notes_per_beat();
+        output
+    }
+}
+
+///
+/// How an ArpPlayer plays keys
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LiveSettings {
+    /// None to play keys as they come
+    pub arp: Option<ArpSettings>,
+    /// None to play each key on its own
+    pub chord: Option<Chord>,
+    pub bpm: u16,
+    pub program: u8,
+}
+
+impl LiveSettings {
+    pub const DEFAULT: Self = Self {
+        arp: Some(ArpSettings::DEFAULT),
+        chord: None,
+        bpm: 120,
+        program: 80,
+    };
+}
+
+/// Commands for an ArpPlayer on another core
+///
+#[derive(Clone, Copy, Debug)]
+pub enum ArpCommand {
+    KeyDown { key: u8, velocity: u8 },
+    KeyUp { key: u8 },
+    Settings(LiveSettings),
+}
+
+///
+/// Plays keys through chord memory and the arpeggiator, on channel 0
+///
+pub struct ArpPlayer<const P_FREQ: u32, const U_FREQ: u32, const MAX_NOTES: usize> {
+    synth: LiveSynth<P_FREQ, U_FREQ, MAX_NOTES>,
+    arp: Arpeggiator<P_FREQ>,
+    settings: LiveSettings,
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, const MAX_NOTES: usize>
+    ArpPlayer<P_FREQ, U_FREQ, MAX_NOTES>
+{
+    const CHANNEL: u8 = 0;
+
+    pub fn new(settings: &LiveSettings) -> Self {
+        let mut rval = Self {
+            synth: LiveSynth::new(LOUD_NOTES),
+            arp: Arpeggiator::new(ArpSettings::DEFAULT, settings.bpm),
+            settings: *settings,
+        };
+        rval.set_settings(settings);
+        rval
+    }
+
+    pub fn settings(&self) -> &LiveSettings {
+        &self.settings
+    }
+
+    /// Change how keys are played.  Switching the arpeggiator on or off
+    /// lets go of everything.
+    ///
+    pub fn set_settings(&mut self, settings: &LiveSettings) {
+        if settings.arp.is_some() != self.settings.arp.is_some() {
+            self.all_notes_off();
+        }
+        self.settings = *settings;
+        if let Some(arp_settings) = settings.arp {
+            self.arp.set_settings(arp_settings);
+        }
+        self.arp.set_bpm(settings.bpm);
+        self.arp.set_chord(settings.chord);
+        self.synth.set_program(Self::CHANNEL, settings.program);
+    }
+
+    fn all_notes_off(&mut self) {
+        self.arp.stop();
+        self.synth.all_notes_off(Self::CHANNEL);
+    }
+
+    pub fn key_down(&mut self, key: u8, velocity: u8) {
+        if self.settings.arp.is_some() {
+            self.arp.key_down(key, velocity);
+            return;
+        }
+        let chord = self.settings.chord.unwrap_or(Chord::new(&[0]));
+        for chord_key in chord.keys(key) {
+            self.synth.note_on(Self::CHANNEL, chord_key, velocity);
+        }
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        if self.settings.arp.is_some() {
+            self.arp.key_up(key);
+            return;
+        }
+        let chord = self.settings.chord.unwrap_or(Chord::new(&[0]));
+        for chord_key in chord.keys(key) {
+            self.synth.note_off(Self::CHANNEL, chord_key);
+        }
+    }
+
+    pub fn run_commands<const N: usize>(&mut self, queue: &CommandQueue<ArpCommand, N>) {
+        while let Some(command) = queue.pop() {
+            match command {
+                ArpCommand::KeyDown { key, velocity } => self.key_down(key, velocity),
+                ArpCommand::KeyUp { key } => self.key_up(key),
+                ArpCommand::Settings(settings) => self.set_settings(&settings),
+            }
+        }
+    }
+
+    pub fn get_next(&mut self) -> SoundSampleI32 {
+        if self.settings.arp.is_some() {
+            let output = self.arp.tick();
+            if let Some(key) = output.note_off {
+                self.synth.note_off(Self::CHANNEL, key);
+            }
+            if let Some((key, velocity)) = output.note_on {
+                self.synth.note_on(Self::CHANNEL, key, velocity);
+            }
+        }
+        self.synth.get_next()
+    }
+
+    /// True if key is sounding, not counting releases
+    ///
+    pub fn is_key_down(&self, key: u8) -> bool {
+        self.synth.is_key_down(Self::CHANNEL, key)
+    }
+
+    /// Change the master volume, from 0 to 100.  The change is ramped, so
+    /// it's safe to call while playing.
+    ///
+    pub fn set_volume(&mut self, volume: u8) {
+        self.synth.set_volume(volume);
+    }
+
+    pub fn get_volume(&self) -> u8 {
+        self.synth.get_volume()
+    }
+
+    /// Go down to silence over time_in_ms.  Check is_faded_out to know
+    /// when it's safe to stop.
+    ///
+    pub fn fade_out(&mut self, time_in_ms: i32) {
+        self.synth.fade_out(time_in_ms);
+    }
+
+    pub fn is_faded_out(&self) -> bool {
+        self.synth.is_faded_out()
+    }
+
+    /// Keys can come at any time, so it never ends on its own
+    ///
+    pub fn has_next(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arpeggiator::*;
+
+    // At 1kHz and 120bpm, a sixteenth is 125 ticks.
+    type TestArp = Arpeggiator<1000>;
+
+    fn settings(mode: ArpMode, octaves: u8) -> ArpSettings {
+        ArpSettings {
+            mode,
+            octaves,
+            ..ArpSettings::DEFAULT
+        }
+    }
+
+    // The keys started over ticks, in order
+    fn run(arp: &mut TestArp, ticks: usize) -> Vec<u8> {
+        (0..ticks)
+            .filter_map(|_| arp.tick().note_on.map(|(key, _)| key))
+            .collect()
+    }
+
+    #[test]
+    fn modes_should_play_in_order() {
+        let mut arp = TestArp::new(settings(ArpMode::Up, 1), 120);
+        arp.key_down(64, 100);
+        arp.key_down(60, 100);
+        arp.key_down(67, 100);
+        assert_eq!(vec![60, 64, 67, 60, 64], run(&mut arp, 125 * 5));
+
+        arp.set_settings(settings(ArpMode::Down, 1));
+        arp.key_up(60);
+        assert_eq!(vec![64, 67, 64], run(&mut arp, 125 * 3));
+
+        arp.key_down(60, 100);
+        arp.set_settings(settings(ArpMode::UpDown, 2));
+        let notes = run(&mut arp, 125 * 22);
+        let start = notes.iter().position(|key| *key == 60).unwrap();
+        assert_eq!(
+            vec![60, 64, 67, 72, 76, 79, 76, 72, 67, 64, 60],
+            notes[start..start + 11]
+        );
+
+        arp.set_settings(settings(ArpMode::Random, 2));
+        let notes = run(&mut arp, 125 * 100);
+        for key in [60, 64, 67, 72, 76, 79] {
+            assert!(notes.contains(&key));
+        }
+        assert!(notes
+            .iter()
+            .all(|key| [60, 64, 67, 72, 76, 79].contains(key)));
+    }
+
+    #[test]
+    fn notes_should_follow_rate_and_gate() {
+        let mut arp = TestArp::new(ArpSettings::DEFAULT, 120);
+        assert_eq!(ArpOutput::default(), arp.tick());
+
+        arp.key_down(60, 90);
+        assert_eq!(Some((60, 90)), arp.tick().note_on);
+        // Let go half way through
+        let offs: Vec<usize> = (1..125).filter(|_| arp.tick().note_off.is_some()).collect();
+        assert_eq!(vec![63], offs);
+        // Twice as fast
+        arp.set_bpm(240);
+        assert_eq!(Some((60, 90)), arp.tick().note_on);
+        assert_eq!(16, run(&mut arp, 1000).len());
+
+        // Triplets
+        let mut triplets = ArpSettings::DEFAULT;
+        triplets.rate = ArpRate::EighthTriplet;
+        arp.set_settings(triplets);
+        arp.set_bpm(120);
+        assert_eq!(6, run(&mut arp, 1000).len());
+
+        arp.key_up(60);
+        assert_eq!(
+            ArpOutput {
+                note_off: Some(60),
+                note_on: None
+            },
+            arp.tick()
+        );
+        assert!(!arp.is_running());
+    }
+
+    #[test]
+    fn latch_should_hold_until_new_keys() {
+        let mut latch = ArpSettings::DEFAULT;
+        latch.latch = true;
+        let mut arp = TestArp::new(latch, 120);
+        arp.key_down(60, 100);
+        arp.key_down(64, 100);
+        arp.key_up(60);
+        arp.key_up(64);
+        assert_eq!(vec![60, 64, 60], run(&mut arp, 125 * 3));
+
+        // All the keys were let go, so a new one starts over
+        arp.key_down(62, 100);
+        assert_eq!(vec![62, 62], run(&mut arp, 125 * 2));
+        // While it's down, more keys add to it
+        arp.key_down(65, 100);
+        assert_eq!(vec![62, 65], run(&mut arp, 125 * 2));
+
+        // Latch off drops the keys that are up
+        arp.key_up(62);
+        arp.set_settings(ArpSettings::DEFAULT);
+        assert_eq!(vec![65, 65], run(&mut arp, 125 * 2));
+        assert_eq!(Some((65, 100)), arp.tick().note_on);
+        assert_eq!(Some(65), arp.stop());
+        assert!(!arp.is_running());
+    }
+
+    #[test]
+    fn chords_should_expand_keys() {
+        assert_eq!(&[0, 4, 7], Chord::MAJOR.intervals());
+        assert_eq!(
+            vec![120, 124, 127],
+            Chord::MAJOR.keys(120).collect::<Vec<u8>>()
+        );
+        assert_eq!(
+            vec![125],
+            Chord::new(&[0, 4, 7]).keys(125).collect::<Vec<u8>>()
+        );
+        assert_eq!(Some(Chord::MINOR), Chord::learn(&[67, 60, 63, 60]));
+        assert_eq!(None, Chord::learn(&[]));
+        assert_eq!(
+            MAX_CHORD_NOTES,
+            Chord::learn(&[1, 2, 3, 4, 5, 6, 7, 8])
+                .unwrap()
+                .intervals()
+                .len()
+        );
+
+        let mut arp = TestArp::new(settings(ArpMode::Up, 1), 120);
+        arp.set_chord(Some(Chord::SEVENTH));
+        arp.key_down(48, 100);
+        assert_eq!(vec![48, 52, 55, 58], run(&mut arp, 125 * 4));
+        arp.key_up(48);
+        assert!(!arp.is_running());
+    }
+
+    #[test]
+    fn the_player_should_play_commands() {
+        type TestPlayer = ArpPlayer<24000, 1000, 8>;
+        let queue = CommandQueue::<ArpCommand, 4>::new();
+        let mut settings = LiveSettings::DEFAULT;
+        settings.arp = None;
+        settings.chord = Some(Chord::MAJOR);
+        let mut player = TestPlayer::new(&settings);
+
+        // Straight chords
+        queue
+            .push(ArpCommand::KeyDown {
+                key: 60,
+                velocity: 100,
+            })
+            .unwrap();
+        player.run_commands(&queue);
+        player.get_next();
+        assert!(player.is_key_down(60) && player.is_key_down(64) && player.is_key_down(67));
+        queue.push(ArpCommand::KeyUp { key: 60 }).unwrap();
+        player.run_commands(&queue);
+        assert!(!player.is_key_down(64));
+
+        // Arpeggiated, with the last settings kept
+        settings.arp = Some(ArpSettings::DEFAULT);
+        queue.push(ArpCommand::Settings(settings)).unwrap();
+        queue
+            .push(ArpCommand::KeyDown {
+                key: 60,
+                velocity: 100,
+            })
+            .unwrap();
+        player.run_commands(&queue);
+        assert_eq!(&settings, player.settings());
+        player.get_next();
+        assert!(player.is_key_down(60) && !player.is_key_down(64));
+        // A sixteenth at 120bpm is 3000 samples
+        for _ in 0..3000 {
+            player.get_next();
+        }
+        assert!(!player.is_key_down(60) && player.is_key_down(64));
+        assert!(player.has_next());
+    }
+}
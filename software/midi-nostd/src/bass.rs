@@ -2,7 +2,6 @@ use crate::adsr::CoreAdsr;
 use crate::double_oscillator::DoubleOscillator;
 use crate::filter::Filter;
 use crate::lfo_amplitude::LfoAmplitude;
-use crate::midi_notes::midi_note_to_freq;
 use crate::midi_notes::FREQUENCY_MULTIPLIER;
 use crate::note::SoundSourceNoteInit;
 use crate::oscillator::CoreOscillator;
@@ -55,8 +54,8 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
     }
 
     fn new(init_values: Self::InitValuesType) -> Self {
-        let frequency_1 = midi_note_to_freq(init_values.key - 12);
-        let frequency_2 = midi_note_to_freq(init_values.key - 12);
+        let frequency_1 = init_values.frequency(-12);
+        let frequency_2 = init_values.frequency(-12);
         let adsr_init = (init_values.velocity as i32) << 8;
         let core =
             BassFiltered::<P_FREQ, U_FREQ>::new((((frequency_1, frequency_2), adsr_init), 2000));
@@ -70,4 +69,8 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
     fn restart(self: &mut Self, vel: u8) {
         self.core.restart(vel);
     }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.core.set_pitch_ratio(ratio)
+    }
 }
@@ -0,0 +1,21 @@
+use crate::instrument_template_fm::InstrumentTemplateFm;
+
+//
+// The 3.5:1 modulator puts the partials in between the harmonics, which is
+// what makes this sound like metal instead of a string.
+//
+pub type Bells<const P_FREQ: u32, const U_FREQ: u32> = InstrumentTemplateFm<
+    P_FREQ,
+    U_FREQ,
+    350,  // Modulator frequency ratio (3.5:1)
+    0,    // Modulator A
+    2000, // Modulator D
+    0,    // Modulator S
+    1500, // Modulator R
+    100,  // Carrier frequency ratio (1:1)
+    30,   // Peak modulation index (3.0)
+    0,    // A
+    3500, // D
+    0,    // S
+    2000, // R
+>;
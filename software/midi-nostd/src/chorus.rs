@@ -0,0 +1,160 @@
+// Chorus.
+//
+// A chorus mixes in copies of the signal delayed by a few milliseconds,
+// with the delays slowly swept by an LFO.  The sweep bends each copy's
+// pitch a little sharp and then a little flat, which is what a section of
+// players (or a pair of detuned oscillators) sounds like.  Doing it once on
+// a shared bus costs the same however many notes are playing, where
+// detuning every voice doubles its oscillators.
+//
+// Two taps are read from one delay line, a quarter of an LFO cycle apart,
+// so one copy is always moving while the other turns around.
+//
+
+use crate::delay_line::mul_q15;
+use crate::delay_line::DelayLine;
+use crate::sound_sample::SoundSampleI32;
+use crate::wave_tables::SINE_WAVE;
+
+///
+/// Chorus, for the CC93 send bus
+///
+pub struct Chorus<const P_FREQ: u32, const BUFFER_SIZE: usize> {
+    line: DelayLine<BUFFER_SIZE>,
+    lfo_phase: u32,
+    lfo_phase_inc: u32,
+    /// Delay at the middle of the sweep, 16.16 samples
+    center_delay: u32,
+    /// How far the delay sweeps either side of center_delay, 16.16 samples
+    depth: u32,
+    /// Output level, 0x8000 for 1
+    level: i32,
+}
+
+impl<const P_FREQ: u32, const BUFFER_SIZE: usize> Chorus<P_FREQ, BUFFER_SIZE> {
+    /// RAM used by the delay line, in bytes.  The default 12ms +/- 4ms
+    /// sweep needs P_FREQ * 16 / 1000 samples.
+    pub const MEMORY_BYTES: usize = DelayLine::<BUFFER_SIZE>::MEMORY_BYTES;
+
+    /// False if BUFFER_SIZE is too small to delay anything
+    pub const ENABLED: bool = BUFFER_SIZE >= 2;
+
+    /// Most the chorus puts out for its send.  It's an average of two
+    /// delayed copies, so no more than the send.
+    pub const PEAK_GAIN: i32 = 1;
+
+    pub fn new() -> Self {
+        let mut rval = Self {
+            line: DelayLine::new(),
+            lfo_phase: 0,
+            lfo_phase_inc: 0,
+            center_delay: 0,
+            depth: 0,
+            level: 0,
+        };
+        rval.set_rate(80);
+        rval.set_delay(12, 4);
+        rval.set_level(100);
+        rval
+    }
+
+    fn ms_to_delay(time_in_ms: u32) -> u64 {
+        (((time_in_ms as u64) * (P_FREQ as u64)) << 16) / 1000
+    }
+
+    /// LFO rate, in hundredths of a hz
+    ///
+    pub fn set_rate(&mut self, rate: u32) {
+        self.lfo_phase_inc = (((rate as u64) << 32) / ((P_FREQ as u64) * 100)) as u32;
+    }
+
+    /// Delay at the middle of the sweep and how far either side of it the
+    /// sweep goes, in ms.  Both shrink to fit the buffer.
+    ///
+    pub fn set_delay(&mut self, center_in_ms: u32, depth_in_ms: u32) {
+        let max_delay = (DelayLine::<BUFFER_SIZE>::MAX_DELAY as u64) << 16;
+        let depth = core::cmp::min(
+            Self::ms_to_delay(depth_in_ms),
+            max_delay.saturating_sub(0x10000) / 2,
+        );
+        let center = core::cmp::min(
+            core::cmp::max(Self::ms_to_delay(center_in_ms), depth + 0x10000),
+            max_delay.saturating_sub(depth),
+        );
+        self.center_delay = center as u32;
+        self.depth = depth as u32;
+    }
+
+    /// Output level, as a percentage
+    ///
+    pub fn set_level(&mut self, percent: u8) {
+        self.level = 0x8000 * (core::cmp::min(percent, 100) as i32) / 100;
+    }
+
+    pub fn reset(&mut self) {
+        self.line.reset();
+    }
+
+    #[inline]
+    fn tap(&self, phase: u32) -> i32 {
+        let sweep = SINE_WAVE[(phase >> 22) as usize] as i64;
+        let delay = (self.center_delay as i64) + (((self.depth as i64) * sweep) >> 15);
+        self.line.read(delay as u32)
+    }
+
+    ///
+    /// Take one sample of the chorus send and return one sample of chorus.
+    /// Only the delayed copies are returned; add them to the dry signal.
+    ///
+    #[inline]
+    pub fn process(&mut self, input: SoundSampleI32) -> SoundSampleI32 {
+        if !Self::ENABLED {
+            return SoundSampleI32::ZERO;
+        }
+        let output =
+            (self.tap(self.lfo_phase) + self.tap(self.lfo_phase.wrapping_add(1 << 30))) / 2;
+        self.line.push(input.to_i32());
+        self.lfo_phase = self.lfo_phase.wrapping_add(self.lfo_phase_inc);
+        SoundSampleI32::new_i32(mul_q15(output, self.level))
+    }
+}
+
+impl<const P_FREQ: u32, const BUFFER_SIZE: usize> Default for Chorus<P_FREQ, BUFFER_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chorus::*;
+
+    #[test]
+    fn chorus_should_delay_the_send() {
+        let mut chorus = Chorus::<24000, 512>::new();
+        let impulse = SoundSampleI32::new_i32(0x4000);
+        let mut first_output = None;
+        for sample in 0..512 {
+            let input = if sample == 0 {
+                impulse
+            } else {
+                SoundSampleI32::ZERO
+            };
+            if chorus.process(input).to_i32() != 0 && first_output.is_none() {
+                first_output = Some(sample);
+            }
+        }
+        // Somewhere between 8ms and 16ms.
+        let first_output = first_output.unwrap();
+        assert!((190..=385).contains(&first_output), "{}", first_output);
+    }
+
+    #[test]
+    fn tiny_buffers_should_still_fit() {
+        let chorus = Chorus::<24000, 64>::new();
+        assert!(chorus.center_delay + chorus.depth <= 63 << 16);
+        assert!(chorus.center_delay >= chorus.depth + 0x10000);
+        let mut disabled = Chorus::<24000, 0>::new();
+        assert_eq!(0, disabled.process(SoundSampleI32::MAX).to_i32());
+    }
+}
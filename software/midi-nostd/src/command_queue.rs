@@ -0,0 +1,131 @@
+// Command queue.
+//
+// A fixed size, lock free queue for passing small commands from one task
+// (or core) to another, like a menu asking the audio core to play a click.
+// It only needs atomic loads and stores, which the RP2040's M0+ cores have,
+// and no compare and swap, which they don't.
+//
+// That only works with one producer and one consumer: one task pushes and
+// one task pops.  Pushing from two tasks at once can lose commands.
+//
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+///
+/// Single producer, single consumer queue of up to N - 1 commands
+///
+pub struct CommandQueue<T: Copy, const N: usize> {
+    buffer: UnsafeCell<[MaybeUninit<T>; N]>,
+    /// Next slot to pop.  Only the consumer writes it.
+    head: AtomicUsize,
+    /// Next slot to push.  Only the producer writes it.
+    tail: AtomicUsize,
+}
+
+// The producer and consumer never touch the same slot at the same time; a
+// slot is handed over by the Release store of tail (or head) after it's
+// written (or read).
+unsafe impl<T: Copy + Send, const N: usize> Sync for CommandQueue<T, N> {}
+
+impl<T: Copy, const N: usize> CommandQueue<T, N> {
+    /// Most commands the queue holds at once
+    pub const CAPACITY: usize = N - 1;
+
+    pub const fn new() -> Self {
+        Self {
+            buffer: UnsafeCell::new([const { MaybeUninit::uninit() }; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    fn slot(&self, idx: usize) -> *mut MaybeUninit<T> {
+        // Pointer to one slot, without making a reference to the whole
+        // buffer that the other side might be using.
+        unsafe { (self.buffer.get() as *mut MaybeUninit<T>).add(idx) }
+    }
+
+    /// Add a command.  Returns it back if the queue is full.  Producer
+    /// side only.
+    ///
+    pub fn push(&self, command: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % N;
+        if next == self.head.load(Ordering::Acquire) {
+            return Err(command);
+        }
+        unsafe { self.slot(tail).write(MaybeUninit::new(command)) };
+        self.tail.store(next, Ordering::Release);
+        Ok(())
+    }
+
+    /// Take the oldest command, if there is one.  Consumer side only.
+    ///
+    pub fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let command = unsafe { self.slot(head).read().assume_init() };
+        self.head.store((head + 1) % N, Ordering::Release);
+        Some(command)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+    }
+}
+
+impl<T: Copy, const N: usize> Default for CommandQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::command_queue::*;
+
+    #[test]
+    fn commands_should_come_out_in_order() {
+        let queue = CommandQueue::<u32, 4>::new();
+        assert!(queue.is_empty());
+        for command in 0..3 {
+            assert_eq!(Ok(()), queue.push(command));
+        }
+        assert_eq!(Err(3), queue.push(3));
+        assert_eq!(Some(0), queue.pop());
+        assert_eq!(Ok(()), queue.push(3));
+        assert_eq!(Some(1), queue.pop());
+        assert_eq!(Some(2), queue.pop());
+        assert_eq!(Some(3), queue.pop());
+        assert_eq!(None, queue.pop());
+        assert_eq!(3, CommandQueue::<u32, 4>::CAPACITY);
+    }
+
+    #[test]
+    fn commands_should_cross_threads() {
+        static QUEUE: CommandQueue<u32, 8> = CommandQueue::new();
+        let producer = std::thread::spawn(|| {
+            for command in 0..10000 {
+                while QUEUE.push(command).is_err() {
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < 10000 {
+            match QUEUE.pop() {
+                Some(command) => {
+                    assert_eq!(expected, command);
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+    }
+}
@@ -0,0 +1,241 @@
+// Delay.
+//
+// A feedback delay (an echo) on the whole mix.  The delay time can be set
+// in ms or as a fraction of a quarter note, in which case it follows the
+// song's tempo.  The repeats go through a gentle low pass, so each one is
+// a little darker than the last, like a tape echo.
+//
+// Changing the delay time doesn't jump straight to the new time; the read
+// point slides there, which bends the pitch of the repeats briefly instead
+// of clicking.
+//
+
+use crate::delay_line::mul_q15;
+use crate::delay_line::DelayLine;
+use crate::sound_sample::SoundSampleI32;
+
+///
+/// Feedback delay with tempo sync
+///
+pub struct Delay<const P_FREQ: u32, const BUFFER_SIZE: usize> {
+    line: DelayLine<BUFFER_SIZE>,
+    /// Current delay, 16.16 samples
+    delay: u32,
+    /// Delay being slid towards, 16.16 samples
+    target_delay: u32,
+    /// Tempo sync, as a fraction of a quarter note, or None for a fixed time
+    sync: Option<(u32, u32)>,
+    /// Last tempo seen by set_tempo, in microseconds per quarter note
+    tempo: u32,
+    /// Feedback, 0x8000 for 1
+    feedback: i32,
+    /// Output level, 0x8000 for 1
+    level: i32,
+    damping_state: i32,
+}
+
+impl<const P_FREQ: u32, const BUFFER_SIZE: usize> Delay<P_FREQ, BUFFER_SIZE> {
+    /// RAM used by the delay line, in bytes.  The longest delay is
+    /// BUFFER_SIZE / P_FREQ seconds.
+    pub const MEMORY_BYTES: usize = DelayLine::<BUFFER_SIZE>::MEMORY_BYTES;
+
+    /// False if BUFFER_SIZE is too small to delay anything
+    pub const ENABLED: bool = BUFFER_SIZE >= 2;
+
+    /// Feedback new starts with, as a percentage
+    pub const DEFAULT_FEEDBACK: u8 = 35;
+
+    /// Most the echoes add up to, as a percentage of the mix, at full level
+    /// and the default feedback.  More feedback than that can clip.
+    pub const PEAK_GAIN_PERCENT: i32 = 100 * 100 / (100 - Self::DEFAULT_FEEDBACK as i32);
+
+    // Fastest the read point slides, in 16.16 samples per sample
+    const SLIDE_RATE: u32 = 0x1000;
+
+    // Low pass in the feedback loop, 0x8000 for none
+    const DAMPING: i32 = 0x6000;
+
+    const MAX_DELAY: u64 = (DelayLine::<BUFFER_SIZE>::MAX_DELAY as u64) << 16;
+
+    pub fn new() -> Self {
+        let mut rval = Self {
+            line: DelayLine::new(),
+            delay: 0,
+            target_delay: 0,
+            sync: None,
+            tempo: 500000,
+            feedback: 0,
+            level: 0,
+            damping_state: 0,
+        };
+        rval.set_time_ms(250);
+        rval.delay = rval.target_delay;
+        rval.set_feedback(Self::DEFAULT_FEEDBACK);
+        rval
+    }
+
+    fn set_target(&mut self, delay: u64) {
+        self.target_delay = delay.clamp(0x10000, core::cmp::max(Self::MAX_DELAY, 0x10000)) as u32;
+    }
+
+    /// Fixed delay time, in ms.  Turns tempo sync off.
+    ///
+    pub fn set_time_ms(&mut self, time_in_ms: u32) {
+        self.sync = None;
+        self.set_target((((time_in_ms as u64) * (P_FREQ as u64)) << 16) / 1000);
+    }
+
+    /// Delay by numerator / denominator quarter notes at the current tempo;
+    /// 1/2 for eighth notes, 3/4 for dotted eighths.  If the result is
+    /// longer than the buffer it's cut down to fit.
+    ///
+    pub fn set_tempo_sync(&mut self, numerator: u32, denominator: u32) {
+        self.sync = Some((numerator, core::cmp::max(denominator, 1)));
+        self.sync_to_tempo();
+    }
+
+    /// Tell the delay the tempo, in microseconds per quarter note.  Only
+    /// changes anything with tempo sync on.
+    ///
+    pub fn set_tempo(&mut self, tempo: u32) {
+        if tempo != self.tempo {
+            self.tempo = tempo;
+            self.sync_to_tempo();
+        }
+    }
+
+    fn sync_to_tempo(&mut self) {
+        if let Some((numerator, denominator)) = self.sync {
+            let samples_per_quarter_note =
+                (((self.tempo as u64) * (P_FREQ as u64)) << 16) / 1000000;
+            self.set_target(samples_per_quarter_note * (numerator as u64) / (denominator as u64));
+        }
+    }
+
+    /// How much of each repeat is fed back, as a percentage.  Capped at 95
+    /// so the echoes always die away.
+    ///
+    pub fn set_feedback(&mut self, percent: u8) {
+        self.feedback = 0x8000 * (core::cmp::min(percent, 95) as i32) / 100;
+    }
+
+    /// Output level, as a percentage.  The delay starts off at 0.
+    ///
+    pub fn set_level(&mut self, percent: u8) {
+        self.level = 0x8000 * (core::cmp::min(percent, 100) as i32) / 100;
+    }
+
+    /// Current delay time, in whole samples
+    ///
+    pub fn get_delay_samples(&self) -> u32 {
+        self.delay >> 16
+    }
+
+    pub fn reset(&mut self) {
+        self.line.reset();
+        self.damping_state = 0;
+    }
+
+    ///
+    /// Take one sample of the mix and return one sample of echoes.  Only
+    /// the echoes are returned; add them to the dry signal.
+    ///
+    #[inline]
+    pub fn process(&mut self, input: SoundSampleI32) -> SoundSampleI32 {
+        if !Self::ENABLED || self.level == 0 {
+            return SoundSampleI32::ZERO;
+        }
+        if self.delay < self.target_delay {
+            self.delay = core::cmp::min(self.delay + Self::SLIDE_RATE, self.target_delay);
+        } else if self.delay > self.target_delay {
+            self.delay = core::cmp::max(self.delay - Self::SLIDE_RATE, self.target_delay);
+        }
+
+        let delayed = self.line.read(self.delay);
+        self.damping_state =
+            mul_q15(delayed, Self::DAMPING) + mul_q15(self.damping_state, 0x8000 - Self::DAMPING);
+        self.line
+            .push(input.to_i32() + mul_q15(self.damping_state, self.feedback));
+        SoundSampleI32::new_i32(mul_q15(delayed, self.level))
+    }
+}
+
+impl<const P_FREQ: u32, const BUFFER_SIZE: usize> Default for Delay<P_FREQ, BUFFER_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::delay::*;
+
+    //
+    // Times, in samples, that the delay output an echo louder than 0x100.
+    //
+    fn echo_times<const BUFFER_SIZE: usize>(
+        delay: &mut Delay<1000, BUFFER_SIZE>,
+        samples: u32,
+    ) -> [u32; 3] {
+        let mut times = [0; 3];
+        let mut found = 0;
+        let mut last_loud = false;
+        for sample in 0..samples {
+            let input = if sample == 0 {
+                SoundSampleI32::new_i32(0x4000)
+            } else {
+                SoundSampleI32::ZERO
+            };
+            let loud = delay.process(input).to_i32().abs() > 0x100;
+            if loud && !last_loud && found < 3 {
+                times[found] = sample;
+                found += 1;
+            }
+            last_loud = loud;
+        }
+        times
+    }
+
+    #[test]
+    fn echoes_should_repeat_at_the_delay_time() {
+        let mut delay = Delay::<1000, 1000>::new();
+        delay.set_level(100);
+        delay.set_feedback(80);
+        delay.set_time_ms(100);
+        delay.delay = delay.target_delay;
+        assert_eq!([100, 200, 300], echo_times(&mut delay, 400));
+    }
+
+    #[test]
+    fn tempo_sync_should_follow_the_tempo() {
+        let mut delay = Delay::<1000, 1000>::new();
+        // Eighth notes at 120 bpm.
+        delay.set_tempo_sync(1, 2);
+        assert_eq!(250 << 16, delay.target_delay);
+        // 150 bpm.
+        delay.set_tempo(400000);
+        assert_eq!(200 << 16, delay.target_delay);
+        // Too long for the buffer.
+        delay.set_tempo_sync(4, 1);
+        assert_eq!(999 << 16, delay.target_delay);
+        // A fixed time ignores the tempo.
+        delay.set_time_ms(100);
+        delay.set_tempo(500000);
+        assert_eq!(100 << 16, delay.target_delay);
+    }
+
+    #[test]
+    fn delay_time_should_slide() {
+        let mut delay = Delay::<1000, 1000>::new();
+        delay.set_level(100);
+        delay.set_time_ms(100);
+        delay.delay = delay.target_delay;
+        delay.set_time_ms(101);
+        for _ in 0..15 {
+            delay.process(SoundSampleI32::ZERO);
+        }
+        assert_eq!(100, delay.get_delay_samples());
+        delay.process(SoundSampleI32::ZERO);
+        assert_eq!(101, delay.get_delay_samples());
+    }
+}
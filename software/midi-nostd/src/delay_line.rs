@@ -0,0 +1,124 @@
+// Delay line.
+//
+// A circular buffer that can be read back at any delay, including
+// fractions of a sample.  Fractional reads interpolate linearly between
+// the two nearest samples, so a delay that moves smoothly (as it does in a
+// chorus) changes pitch smoothly instead of clicking from sample to sample.
+//
+
+///
+/// Multiply by a 0x8000 for 1 factor, rounding towards zero.  Rounding down
+/// would leave -1s circulating in a feedback loop forever.
+///
+#[inline]
+pub const fn mul_q15(value: i32, factor: i32) -> i32 {
+    let product = value * factor;
+    (product + ((product >> 31) & 0x7fff)) >> 15
+}
+
+///
+/// Circular delay line, read at a 16.16 fixed point delay in samples
+///
+pub struct DelayLine<const SIZE: usize> {
+    buffer: [i16; SIZE],
+    write_idx: usize,
+}
+
+impl<const SIZE: usize> DelayLine<SIZE> {
+    /// Longest delay that can be read, in samples
+    pub const MAX_DELAY: usize = SIZE.saturating_sub(1);
+
+    /// RAM used by the buffer, in bytes
+    pub const MEMORY_BYTES: usize = SIZE * core::mem::size_of::<i16>();
+
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; SIZE],
+            write_idx: 0,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.buffer = [0; SIZE];
+    }
+
+    /// Add a sample to the line.  Samples are clipped to 16 bits.
+    ///
+    #[inline]
+    pub fn push(&mut self, sample: i32) {
+        if SIZE == 0 {
+            return;
+        }
+        self.buffer[self.write_idx] = sample.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        self.write_idx += 1;
+        if self.write_idx == SIZE {
+            self.write_idx = 0;
+        }
+    }
+
+    #[inline]
+    fn at(&self, delay: usize) -> i32 {
+        // The newest sample is a delay of 1, as it's read before the next push.
+        let idx = if delay <= self.write_idx {
+            self.write_idx - delay
+        } else {
+            self.write_idx + SIZE - delay
+        };
+        self.buffer[idx] as i32
+    }
+
+    /// Read the sample pushed delay samples ago, where delay is 16.16 fixed
+    /// point and is clamped to between 1 and MAX_DELAY.
+    ///
+    #[inline]
+    pub fn read(&self, delay: u32) -> i32 {
+        if SIZE < 2 {
+            return 0;
+        }
+        let whole = core::cmp::min((delay >> 16) as usize, Self::MAX_DELAY);
+        if whole < 1 {
+            return self.at(1);
+        }
+        let fraction = (delay & 0xffff) as i32;
+        let nearer = self.at(whole);
+        if fraction == 0 || whole == Self::MAX_DELAY {
+            return nearer;
+        }
+        let further = self.at(whole + 1);
+        nearer + (((further - nearer) * fraction) >> 16)
+    }
+}
+
+impl<const SIZE: usize> Default for DelayLine<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::delay_line::*;
+
+    #[test]
+    fn whole_delays_should_return_old_samples() {
+        let mut line = DelayLine::<8>::new();
+        for sample in 1..=10 {
+            line.push(sample * 100);
+        }
+        assert_eq!(1000, line.read(1 << 16));
+        assert_eq!(800, line.read(3 << 16));
+        assert_eq!(400, line.read(7 << 16));
+        // Too long a delay is clamped.
+        assert_eq!(400, line.read(20 << 16));
+    }
+
+    #[test]
+    fn fractional_delays_should_interpolate() {
+        let mut line = DelayLine::<8>::new();
+        for sample in 1..=10 {
+            line.push(sample * 100);
+        }
+        assert_eq!(950, line.read(0x18000));
+        assert_eq!(875, line.read(0x24000));
+    }
+}
@@ -59,7 +59,16 @@ impl<
         self.source_1.trigger_note_off();
     }
 
-    fn restart(self: &mut Self, _vel: u8) {}
+    fn restart(self: &mut Self, vel: u8) {
+        self.source_0.restart(vel);
+        self.source_1.restart(vel);
+    }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        let followed_0 = self.source_0.set_pitch_ratio(ratio);
+        let followed_1 = self.source_1.set_pitch_ratio(ratio);
+        followed_0 && followed_1
+    }
 }
 
 impl<
@@ -0,0 +1,426 @@
+// Additive drawbar (tonewheel) organ.
+//
+// A Hammond style organ mixes nine sine waves per key, each on its own
+// drawbar, at the footages 16', 5 1/3', 8', 4', 2 2/3', 2', 1 3/5', 1 1/3'
+// and 1'.  Relative to the 16' drawbar those are harmonics 1, 3, 2, 4, 6,
+// 8, 10, 12 and 16, so a single phase accumulator running at the 16' pitch
+// is enough.  Each partial's phase is just a multiple of it.
+//
+
+use crate::adsr::CoreAdsr;
+use crate::midi_notes::FREQUENCY_MULTIPLIER;
+use crate::note::SoundSourceNoteInit;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::OscillatorInterface;
+use crate::sound_source_core::SoundSourceCore;
+use crate::wave_tables::SINE_WAVE;
+
+/// Number of drawbars
+///
+pub const NUM_DRAWBARS: usize = 9;
+
+// Harmonic of the 16' drawbar each drawbar plays, in drawbar order.
+const DRAWBAR_HARMONICS: [u32; NUM_DRAWBARS] = [1, 3, 2, 4, 6, 8, 10, 12, 16];
+
+// Drawbar position (0 to 8) to level, out of 256.  Each step is about 3db.
+const DRAWBAR_LEVELS: [u32; 9] = [0, 23, 32, 45, 64, 91, 128, 181, 256];
+
+/// Hammond style percussion
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(usize)]
+pub enum Percussion {
+    Off,
+    /// Percussion on the 4' (second harmonic) drawbar
+    Second,
+    /// Percussion on the 2 2/3' (third harmonic) drawbar
+    Third,
+}
+
+impl Percussion {
+    const fn from_usize(usize_value: usize) -> Self {
+        match usize_value {
+            0 => Self::Off,
+            1 => Self::Second,
+            2 => Self::Third,
+            3_usize.. => todo!(),
+        }
+    }
+}
+
+//
+// Turn a registration, one decimal digit per drawbar like the "888000000"
+// organists use, into mix levels.  The levels are scaled so they add up to
+// no more than 256, so a full registration can't clip.
+//
+const fn drawbar_mix(drawbars: u32) -> [i32; NUM_DRAWBARS] {
+    let mut positions = [0u32; NUM_DRAWBARS];
+    let mut remaining = drawbars;
+    let mut idx = NUM_DRAWBARS;
+    while idx > 0 {
+        idx -= 1;
+        let position = remaining % 10;
+        assert!(position <= 8);
+        positions[idx] = position;
+        remaining /= 10;
+    }
+
+    let mut total = 0;
+    idx = 0;
+    while idx < NUM_DRAWBARS {
+        total += DRAWBAR_LEVELS[positions[idx] as usize];
+        idx += 1;
+    }
+    if total < 256 {
+        total = 256;
+    }
+
+    let mut mix = [0i32; NUM_DRAWBARS];
+    idx = 0;
+    while idx < NUM_DRAWBARS {
+        mix[idx] = (DRAWBAR_LEVELS[positions[idx] as usize] * 256 / total) as i32;
+        idx += 1;
+    }
+    mix
+}
+
+///
+/// The tone generator.  Nine drawbars worth of sine waves.
+///
+/// DRAWBARS is the registration, one decimal digit (0 to 8) per drawbar
+/// starting with the 16', so 888000000 is the classic jazz sound.
+/// PERCUSSION adds a decaying second or third harmonic to the start of each
+/// note, and CLICK adds the short burst of noise a tonewheel organ's key
+/// contacts make.
+///
+pub struct Tonewheels<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const DRAWBARS: u32,
+    const PERCUSSION: usize,
+    const CLICK: bool,
+> {
+    table_idx: u32,
+    table_idx_inc: u32,
+    base_table_idx_inc: u32,
+    harmonics: [u8; NUM_DRAWBARS],
+    click_remaining: u16,
+    percussion_level: i32,
+    amplitude: SoundSampleI32,
+    seed: u32,
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        const DRAWBARS: u32,
+        const PERCUSSION: usize,
+        const CLICK: bool,
+    > Tonewheels<P_FREQ, U_FREQ, DRAWBARS, PERCUSSION, CLICK>
+{
+    const MIX: [i32; NUM_DRAWBARS] = drawbar_mix(DRAWBARS);
+    const PERCUSSION_ENUM: Percussion = Percussion::from_usize(PERCUSSION);
+
+    // Percussion starts at about half the level of a full drawbar and dies
+    // away with a time constant of 200ms.
+    const PERCUSSION_START: i32 = 0x4000;
+    const PERCUSSION_DECAY: i32 = 0x8000 - (0x8000 * 1000 / (200 * U_FREQ)) as i32;
+
+    // Key click, 4ms of noise at about a quarter of full scale.
+    const CLICK_SAMPLES: u16 = (P_FREQ / 250) as u16;
+    const CLICK_LEVEL: i32 = 0x2000;
+
+    // Partials above this are folded back down an octave, like the top
+    // tonewheels of a real organ, rather than aliasing.
+    const FOLDBACK_FREQUENCY: u64 = (P_FREQ as u64) * (FREQUENCY_MULTIPLIER as u64) * 4 / 10;
+
+    fn trigger_percussion_and_click(&mut self) {
+        if Self::PERCUSSION_ENUM != Percussion::Off {
+            self.percussion_level = Self::PERCUSSION_START;
+        }
+        if CLICK {
+            self.click_remaining = Self::CLICK_SAMPLES;
+        }
+    }
+
+    fn random(&mut self) -> i32 {
+        // xorshift32.  Good enough for noise.
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        (self.seed >> 16) as i32 - 0x8000
+    }
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        const DRAWBARS: u32,
+        const PERCUSSION: usize,
+        const CLICK: bool,
+    > SoundSourceCore<P_FREQ, U_FREQ> for Tonewheels<P_FREQ, U_FREQ, DRAWBARS, PERCUSSION, CLICK>
+{
+    type InitValuesType = u32;
+
+    fn new(frequency: Self::InitValuesType) -> Self {
+        // The accumulator runs at the 16' pitch, an octave below the note.
+        let sub_frequency = (frequency / 2) as u64;
+        let table_idx_inc = (((1u64 << 32) * sub_frequency)
+            / ((FREQUENCY_MULTIPLIER as u64) * (P_FREQ as u64))) as u32;
+
+        let mut harmonics = [0u8; NUM_DRAWBARS];
+        for (harmonic, base) in harmonics.iter_mut().zip(DRAWBAR_HARMONICS) {
+            let mut folded = base;
+            while folded > 2 && sub_frequency * (folded as u64) > Self::FOLDBACK_FREQUENCY {
+                folded /= 2;
+            }
+            *harmonic = folded as u8;
+        }
+
+        let mut rval = Self {
+            table_idx: 0,
+            table_idx_inc,
+            base_table_idx_inc: table_idx_inc,
+            harmonics,
+            click_remaining: 0,
+            percussion_level: 0,
+            amplitude: SoundSampleI32::ZERO,
+            seed: 0x2545f491 ^ frequency,
+        };
+        rval.trigger_percussion_and_click();
+        rval
+    }
+
+    #[inline]
+    fn get_next(&mut self) -> SoundSampleI32 {
+        self.table_idx = self.table_idx.wrapping_add(self.table_idx_inc);
+
+        let mut sum: i32 = 0;
+        for drawbar in 0..NUM_DRAWBARS {
+            let level = Self::MIX[drawbar];
+            if level != 0 {
+                let idx = self.table_idx.wrapping_mul(self.harmonics[drawbar] as u32);
+                sum += SINE_WAVE[(idx >> 22) as usize] * level;
+            }
+        }
+        let mut out = sum >> 8;
+
+        let percussion_drawbar = match Self::PERCUSSION_ENUM {
+            Percussion::Off => None,
+            Percussion::Second => Some(3),
+            Percussion::Third => Some(4),
+        };
+        if let Some(drawbar) = percussion_drawbar {
+            let idx = self.table_idx.wrapping_mul(self.harmonics[drawbar] as u32);
+            out += (SINE_WAVE[(idx >> 22) as usize] * (self.percussion_level >> 3)) >> 12;
+        }
+
+        if CLICK && self.click_remaining != 0 {
+            let level =
+                Self::CLICK_LEVEL * (self.click_remaining as i32) / (Self::CLICK_SAMPLES as i32);
+            out += (self.random() * level) >> 15;
+            self.click_remaining -= 1;
+        }
+
+        (SoundSampleI32::new_i32(out) * self.amplitude).clip()
+    }
+
+    fn update(&mut self) {
+        self.percussion_level = (self.percussion_level * Self::PERCUSSION_DECAY) >> 15;
+    }
+
+    fn has_next(&self) -> bool {
+        true
+    }
+
+    fn reset_oscillator(&mut self) {
+        self.table_idx = 0;
+    }
+
+    fn restart(&mut self, _vel: u8) {
+        self.trigger_percussion_and_click();
+    }
+
+    // The foldback was worked out for the note's own pitch, which is close
+    // enough for a glide.
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.table_idx_inc = (((self.base_table_idx_inc as u64) * (ratio as u64)) >> 16) as u32;
+        true
+    }
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        const DRAWBARS: u32,
+        const PERCUSSION: usize,
+        const CLICK: bool,
+    > OscillatorInterface<P_FREQ, U_FREQ>
+    for Tonewheels<P_FREQ, U_FREQ, DRAWBARS, PERCUSSION, CLICK>
+{
+    fn set_amplitude_adjust(&mut self, adjust: SoundSampleI32) {
+        self.amplitude = adjust;
+    }
+
+    fn get_table_idx(&self) -> u32 {
+        self.table_idx
+    }
+}
+
+///
+/// Drawbar organ.  The tone generator with an organ's on/off envelope.
+///
+pub struct DrawbarOrgan<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const DRAWBARS: u32,
+    const PERCUSSION: usize,
+    const CLICK: bool,
+> {
+    core: CoreAdsr<
+        P_FREQ,
+        U_FREQ,
+        5,
+        0,
+        100,
+        60,
+        Tonewheels<P_FREQ, U_FREQ, DRAWBARS, PERCUSSION, CLICK>,
+    >,
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        const DRAWBARS: u32,
+        const PERCUSSION: usize,
+        const CLICK: bool,
+    > SoundSourceCore<P_FREQ, U_FREQ>
+    for DrawbarOrgan<P_FREQ, U_FREQ, DRAWBARS, PERCUSSION, CLICK>
+{
+    type InitValuesType = SoundSourceNoteInit;
+
+    fn get_next(&mut self) -> SoundSampleI32 {
+        self.core.get_next()
+    }
+
+    fn update(&mut self) {
+        self.core.update()
+    }
+
+    fn has_next(&self) -> bool {
+        self.core.has_next()
+    }
+
+    fn trigger_note_off(&mut self) {
+        self.core.trigger_note_off();
+    }
+
+    fn restart(&mut self, vel: u8) {
+        self.core.restart(vel);
+    }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.core.set_pitch_ratio(ratio)
+    }
+
+    fn new(init_values: Self::InitValuesType) -> Self {
+        let frequency = init_values.frequency(0);
+        let adsr_init = (init_values.velocity as i32) << 8;
+        let core = CoreAdsr::new((frequency, adsr_init));
+        Self { core }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::drawbar_organ::*;
+
+    //
+    // Count zero crossings.  A sine at f crosses 2f times a second.
+    //
+    fn count_transitions<T>(source: &mut T) -> u32
+    where
+        T: SoundSourceCore<24000, 24000>,
+    {
+        let mut last = source.get_next();
+        let mut transitions: u32 = 0;
+        for _ in 1..24000 {
+            let current = source.get_next();
+            if (last.to_i32() > 0) != (current.to_i32() > 0) {
+                transitions += 1;
+            }
+            last = current;
+        }
+        transitions
+    }
+
+    #[test]
+    fn registration_should_be_normalized() {
+        assert_eq!([0, 0, 256, 0, 0, 0, 0, 0, 0], drawbar_mix(8000000));
+        assert_eq!([0, 0, 128, 0, 0, 0, 0, 0, 0], drawbar_mix(6000000));
+        let full = drawbar_mix(888888888);
+        assert!(full.iter().all(|level| *level == 28));
+        let jazz = drawbar_mix(888000000);
+        assert!(jazz[0..3].iter().all(|level| *level == 85));
+    }
+
+    #[test]
+    fn single_drawbar_should_be_a_sine_at_its_footage() {
+        let freq = 200 * FREQUENCY_MULTIPLIER;
+        let mut eight_foot = Tonewheels::<24000, 24000, 8000000, 0, false>::new(freq);
+        let mut sixteen_foot = Tonewheels::<24000, 24000, 800000000, 0, false>::new(freq);
+        let mut four_foot = Tonewheels::<24000, 24000, 800000, 0, false>::new(freq);
+        eight_foot.set_amplitude_adjust(SoundSampleI32::MAX);
+        sixteen_foot.set_amplitude_adjust(SoundSampleI32::MAX);
+        four_foot.set_amplitude_adjust(SoundSampleI32::MAX);
+        assert_eq!(200 * 2 - 1, count_transitions(&mut eight_foot));
+        assert_eq!(100 * 2 - 1, count_transitions(&mut sixteen_foot));
+        assert_eq!(400 * 2 - 1, count_transitions(&mut four_foot));
+    }
+
+    #[test]
+    fn high_partials_should_fold_back() {
+        // 1' on a 4000hz note would be 32000hz, way past nyquist.
+        let mut wheels = Tonewheels::<24000, 24000, 8, 0, false>::new(4000 * FREQUENCY_MULTIPLIER);
+        wheels.set_amplitude_adjust(SoundSampleI32::MAX);
+        assert!(count_transitions(&mut wheels) < 9600 * 2);
+    }
+
+    #[test]
+    fn percussion_should_decay() {
+        let freq = 200 * FREQUENCY_MULTIPLIER;
+        let mut wheels = Tonewheels::<24000, 240, 6000000, 2, false>::new(freq);
+        wheels.set_amplitude_adjust(SoundSampleI32::MAX);
+
+        let mut peak_at_start = 0;
+        for _ in 0..240 {
+            peak_at_start = core::cmp::max(peak_at_start, wheels.get_next().to_i32());
+        }
+        for _ in 0..240 {
+            wheels.update();
+        }
+        let mut peak_later = 0;
+        for _ in 0..240 {
+            peak_later = core::cmp::max(peak_later, wheels.get_next().to_i32());
+        }
+        assert!(peak_at_start > peak_later + 0x1000);
+        assert!(peak_later > 0x3800);
+    }
+
+    #[test]
+    fn organ_should_stop_after_release() {
+        type TestOrgan = DrawbarOrgan<24000, 24000, 888000000, 2, true>;
+        let mut organ = TestOrgan::new(SoundSourceNoteInit::new(60, 0, 127));
+        for _ in 0..100 {
+            organ.update();
+        }
+        assert!(organ.has_next());
+        organ.trigger_note_off();
+        let mut updates = 0;
+        while organ.has_next() {
+            organ.update();
+            updates += 1;
+        }
+        assert!(updates < 24000 / 10);
+    }
+}
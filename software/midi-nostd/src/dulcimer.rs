@@ -0,0 +1,10 @@
+use crate::plucked_string::Excitation;
+use crate::plucked_string::PluckedString;
+
+pub type Dulcimer<const P_FREQ: u32, const U_FREQ: u32> = PluckedString<
+    P_FREQ,
+    U_FREQ,
+    { Excitation::Noise as usize }, // Excitation
+    997,                            // Damping
+    990,                            // Release damping
+>;
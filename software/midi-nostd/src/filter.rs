@@ -247,6 +247,10 @@ impl<const P_FREQ: u32, const U_FREQ: u32, Source: OscillatorInterface<P_FREQ, U
         self.source.restart(vel);
     }
 
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.source.set_pitch_ratio(ratio)
+    }
+
     fn has_next(self: &Self) -> bool {
         self.source.has_next()
     }
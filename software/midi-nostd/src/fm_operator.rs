@@ -0,0 +1,257 @@
+use crate::midi_notes::FREQUENCY_MULTIPLIER;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::OscillatorInterface;
+use crate::sound_source_core::SoundSourceCore;
+use crate::wave_tables::SINE_WAVE;
+
+///
+/// FM (really phase modulation) operator.
+///
+/// A sine oscillator running at RATIO / 100 times the note frequency, so a
+/// RATIO of 100 is the note itself, 200 is an octave up and 350 gives the
+/// inharmonic 3.5:1 partial bells like.  VOLUME is a percentage of full scale.
+///
+/// The phase of the operator can be pushed around by another operator using
+/// get_next_modulated, which is the whole trick behind DX style synthesis.
+///
+pub struct FmOperator<const P_FREQ: u32, const U_FREQ: u32, const RATIO: u32, const VOLUME: u8> {
+    table_idx: u32,
+    table_idx_inc: u32,
+    base_table_idx_inc: u32,
+    max_amplitude: SoundSampleI32,
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, const RATIO: u32, const VOLUME: u8>
+    FmOperator<P_FREQ, U_FREQ, RATIO, VOLUME>
+{
+    const VOLUME_SCALE: SoundSampleI32 = SoundSampleI32::new_percent(VOLUME);
+    const INC_DENOMINATOR: u64 = (FREQUENCY_MULTIPLIER as u64) * (P_FREQ as u64) * 100;
+
+    ///
+    /// Draw a sample with the phase offset by phase_offset.
+    ///
+    /// The offset is in table units, so 1 << 32 is one full cycle.
+    ///
+    #[inline]
+    pub fn get_next_modulated(&mut self, phase_offset: u32) -> SoundSampleI32 {
+        self.table_idx = self.table_idx.wrapping_add(self.table_idx_inc);
+        let idx = self.table_idx.wrapping_add(phase_offset);
+        SoundSampleI32::new_i32(SINE_WAVE[(idx >> 22) as usize]) * self.max_amplitude
+    }
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, const RATIO: u32, const VOLUME: u8>
+    SoundSourceCore<P_FREQ, U_FREQ> for FmOperator<P_FREQ, U_FREQ, RATIO, VOLUME>
+{
+    type InitValuesType = u32;
+
+    fn new(frequency: Self::InitValuesType) -> Self {
+        let table_idx_inc =
+            (((1u64 << 32) * (frequency as u64) * (RATIO as u64)) / Self::INC_DENOMINATOR) as u32;
+        Self {
+            table_idx: 0,
+            table_idx_inc,
+            base_table_idx_inc: table_idx_inc,
+            max_amplitude: Self::VOLUME_SCALE,
+        }
+    }
+
+    #[inline]
+    fn has_next(&self) -> bool {
+        true
+    }
+
+    #[inline]
+    fn get_next(&mut self) -> SoundSampleI32 {
+        self.get_next_modulated(0)
+    }
+
+    fn update(&mut self) {}
+
+    fn reset_oscillator(&mut self) {
+        self.table_idx = 0;
+    }
+
+    fn restart(&mut self, _vel: u8) {}
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.table_idx_inc = (((self.base_table_idx_inc as u64) * (ratio as u64)) >> 16) as u32;
+        true
+    }
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, const RATIO: u32, const VOLUME: u8>
+    OscillatorInterface<P_FREQ, U_FREQ> for FmOperator<P_FREQ, U_FREQ, RATIO, VOLUME>
+{
+    fn set_amplitude_adjust(&mut self, adjust: SoundSampleI32) {
+        self.max_amplitude = Self::VOLUME_SCALE * adjust;
+    }
+    fn get_table_idx(&self) -> u32 {
+        self.table_idx
+    }
+}
+
+///
+/// Two operator FM voice.  The modulator's output is used to bend the phase
+/// of the carrier.
+///
+/// The modulator is normally an operator wrapped in its own CoreAdsr, so the
+/// brightness of the note can decay independently of its volume.  The carrier
+/// envelope comes from whoever owns the pair, through set_amplitude_adjust.
+///
+/// INDEX_TENTHS is the peak modulation index in tenths, so 15 means a full
+/// scale modulator swings the carrier's phase by +/- 1.5 radians.
+///
+pub struct FmPair<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    Modulator: OscillatorInterface<P_FREQ, U_FREQ>,
+    const CARRIER_RATIO: u32,
+    const INDEX_TENTHS: u32,
+> {
+    modulator: Modulator,
+    carrier: FmOperator<P_FREQ, U_FREQ, CARRIER_RATIO, 100>,
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        Modulator: OscillatorInterface<P_FREQ, U_FREQ>,
+        const CARRIER_RATIO: u32,
+        const INDEX_TENTHS: u32,
+    > FmPair<P_FREQ, U_FREQ, Modulator, CARRIER_RATIO, INDEX_TENTHS>
+{
+    //
+    // A full scale (0x8000) modulator sample should move the phase by INDEX
+    // radians.  One radian is (1 << 32) / 2pi table units, so the multiplier
+    // is that divided by 0x8000 and scaled by the index.
+    //
+    const PHASE_MULTIPLIER: u32 =
+        ((1u64 << 32) * (INDEX_TENTHS as u64) * 10000 / 62832 / 10 / 0x8000) as u32;
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        Modulator: OscillatorInterface<P_FREQ, U_FREQ>,
+        const CARRIER_RATIO: u32,
+        const INDEX_TENTHS: u32,
+    > SoundSourceCore<P_FREQ, U_FREQ>
+    for FmPair<P_FREQ, U_FREQ, Modulator, CARRIER_RATIO, INDEX_TENTHS>
+{
+    type InitValuesType = (Modulator::InitValuesType, u32);
+
+    fn new(init_values: Self::InitValuesType) -> Self {
+        Self {
+            modulator: Modulator::new(init_values.0),
+            carrier: FmOperator::new(init_values.1),
+        }
+    }
+
+    #[inline]
+    fn get_next(&mut self) -> SoundSampleI32 {
+        let modulation = self.modulator.get_next().to_i32();
+        // Wrapping is fine here, the phase is modulo 1 << 32 anyway.
+        let phase_offset = (modulation as u32).wrapping_mul(Self::PHASE_MULTIPLIER);
+        self.carrier.get_next_modulated(phase_offset)
+    }
+
+    fn update(&mut self) {
+        self.modulator.update();
+    }
+
+    fn has_next(&self) -> bool {
+        self.carrier.has_next()
+    }
+
+    fn trigger_note_off(&mut self) {
+        self.modulator.trigger_note_off();
+    }
+
+    fn restart(&mut self, vel: u8) {
+        self.modulator.restart(vel);
+    }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        let modulator_followed = self.modulator.set_pitch_ratio(ratio);
+        let carrier_followed = self.carrier.set_pitch_ratio(ratio);
+        modulator_followed && carrier_followed
+    }
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        Modulator: OscillatorInterface<P_FREQ, U_FREQ>,
+        const CARRIER_RATIO: u32,
+        const INDEX_TENTHS: u32,
+    > OscillatorInterface<P_FREQ, U_FREQ>
+    for FmPair<P_FREQ, U_FREQ, Modulator, CARRIER_RATIO, INDEX_TENTHS>
+{
+    fn set_amplitude_adjust(&mut self, adjust: SoundSampleI32) {
+        self.carrier.set_amplitude_adjust(adjust);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fm_operator::*;
+
+    type Modulator = FmOperator<24000, 24000, 100, 100>;
+
+    //
+    // Count zero crossings.  More harmonics means more crossings.
+    //
+    fn count_transitions<T>(source: &mut T) -> u32
+    where
+        T: SoundSourceCore<24000, 24000>,
+    {
+        let mut last = source.get_next();
+        let mut transitions: u32 = 0;
+        for _ in 1..24000 {
+            let current = source.get_next();
+            if (last.to_i32() > 0) != (current.to_i32() > 0) {
+                transitions += 1;
+            }
+            last = current;
+        }
+        transitions
+    }
+
+    #[test]
+    fn operator_ratio_should_scale_frequency() {
+        let mut unison = FmOperator::<24000, 24000, 100, 100>::new(100 * FREQUENCY_MULTIPLIER);
+        let mut octave = FmOperator::<24000, 24000, 200, 100>::new(100 * FREQUENCY_MULTIPLIER);
+        assert_eq!(100 * 2 - 1, count_transitions(&mut unison));
+        assert_eq!(200 * 2 - 1, count_transitions(&mut octave));
+    }
+
+    #[test]
+    fn zero_index_should_be_a_plain_sine() {
+        let freq = 100 * FREQUENCY_MULTIPLIER;
+        let mut pair = FmPair::<24000, 24000, Modulator, 100, 0>::new((freq, freq));
+        let mut sine = FmOperator::<24000, 24000, 100, 100>::new(freq);
+        for _ in 0..1000 {
+            assert_eq!(sine.get_next(), pair.get_next());
+        }
+    }
+
+    #[test]
+    fn modulation_should_add_harmonics() {
+        let freq = 100 * FREQUENCY_MULTIPLIER;
+        let mut plain = FmPair::<24000, 24000, Modulator, 100, 0>::new((freq, freq));
+        let mut bright = FmPair::<24000, 24000, Modulator, 100, 50>::new((freq, freq));
+        let plain_transitions = count_transitions(&mut plain);
+        let bright_transitions = count_transitions(&mut bright);
+        assert_eq!(100 * 2 - 1, plain_transitions);
+        assert!(bright_transitions > plain_transitions * 2);
+    }
+
+    #[test]
+    fn modulator_amplitude_should_control_brightness() {
+        let freq = 100 * FREQUENCY_MULTIPLIER;
+        let mut pair = FmPair::<24000, 24000, Modulator, 100, 50>::new((freq, freq));
+        pair.modulator.set_amplitude_adjust(SoundSampleI32::ZERO);
+        assert_eq!(100 * 2 - 1, count_transitions(&mut pair));
+    }
+}
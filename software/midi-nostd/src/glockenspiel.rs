@@ -0,0 +1,35 @@
+use crate::modal_percussion::ModalPatch;
+use crate::modal_percussion::ModalPercussion;
+use crate::modal_percussion::Mode;
+use crate::modal_percussion::MAX_MODES;
+
+//
+// A free steel bar.  The modes of a bar are far from harmonic, 2.76 and
+// 5.40 times the fundamental, which is where the glassy ring comes from.
+//
+pub struct GlockenspielModes {}
+
+impl ModalPatch for GlockenspielModes {
+    const MODES: [Mode; MAX_MODES] = [
+        Mode {
+            ratio: 1000,
+            level: 50,
+            decay: 2500,
+        },
+        Mode {
+            ratio: 2756,
+            level: 30,
+            decay: 1200,
+        },
+        Mode {
+            ratio: 5404,
+            level: 20,
+            decay: 500,
+        },
+        Mode::UNUSED,
+    ];
+    const RELEASE: u32 = 1500;
+}
+
+pub type Glockenspiel<const P_FREQ: u32, const U_FREQ: u32> =
+    ModalPercussion<P_FREQ, U_FREQ, GlockenspielModes>;
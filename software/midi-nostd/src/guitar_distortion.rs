@@ -0,0 +1,19 @@
+use crate::plucked_string::Excitation;
+use crate::plucked_string::PluckedString;
+use crate::waveshaper::ShapeCurve;
+use crate::waveshaper::Waveshaper;
+
+pub type GuitarDistortion<const P_FREQ: u32, const U_FREQ: u32> = Waveshaper<
+    P_FREQ,
+    U_FREQ,
+    PluckedString<
+        P_FREQ,
+        U_FREQ,
+        { Excitation::Noise as usize }, // Excitation
+        999,                            // Damping, the distortion does the sustaining
+        960,                            // Release damping
+    >,
+    { ShapeCurve::SoftClip as usize }, // Curve
+    1500,                              // Drive, percent
+    55,                                // Output, percent
+>;
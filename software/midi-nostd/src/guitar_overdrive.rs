@@ -0,0 +1,19 @@
+use crate::plucked_string::Excitation;
+use crate::plucked_string::PluckedString;
+use crate::waveshaper::ShapeCurve;
+use crate::waveshaper::Waveshaper;
+
+pub type GuitarOverdrive<const P_FREQ: u32, const U_FREQ: u32> = Waveshaper<
+    P_FREQ,
+    U_FREQ,
+    PluckedString<
+        P_FREQ,
+        U_FREQ,
+        { Excitation::Noise as usize }, // Excitation
+        998,                            // Damping
+        960,                            // Release damping
+    >,
+    { ShapeCurve::Tube as usize }, // Curve
+    400,                           // Drive, percent
+    70,                            // Output, percent
+>;
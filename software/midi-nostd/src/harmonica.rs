@@ -0,0 +1,20 @@
+use crate::instrument_low_pass_filters::GenericLowPassCalculator;
+use crate::instrument_template_reed::InstrumentTemplateReed;
+use crate::midi_notes::FREQUENCY_MULTIPLIER;
+use crate::oscillator::OscillatorType;
+use crate::velocity::VelocityCutoff;
+
+pub type Harmonica<const P_FREQ: u32, const U_FREQ: u32> = InstrumentTemplateReed<
+    P_FREQ,
+    U_FREQ,
+    20,                                      // Reed pulse width
+    { OscillatorType::PulseWidth as usize }, // Reed wave form
+    4,                                       // Second reed detune, cents
+    { 6 * FREQUENCY_MULTIPLIER },            // Bellows tremolo frequency
+    8,                                       // Bellows tremolo depth
+    40,                                      // A
+    300,                                     // D
+    80,                                      // S
+    80,                                      // R
+    VelocityCutoff<GenericLowPassCalculator<250, 1800>, 50>,
+>;
@@ -0,0 +1,10 @@
+use crate::plucked_string::Excitation;
+use crate::plucked_string::PluckedString;
+
+pub type Harp<const P_FREQ: u32, const U_FREQ: u32> = PluckedString<
+    P_FREQ,
+    U_FREQ,
+    { Excitation::Pulse as usize }, // Excitation
+    998,                            // Damping
+    995,                            // Release damping, harp strings ring on
+>;
@@ -3,7 +3,6 @@ use crate::double_oscillator::DoubleOscillator;
 use crate::filter::Filter;
 use crate::instrument_low_pass_filters::FrequencyCalculator;
 use crate::lfo_amplitude::LfoAmplitude;
-use crate::midi_notes::midi_note_to_freq;
 use crate::note::SoundSourceNoteInit;
 use crate::oscillator::CoreOscillator;
 use crate::sound_sample::SoundSampleI32;
@@ -137,9 +136,13 @@ impl<
         self.core.restart(vel);
     }
 
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.core.set_pitch_ratio(ratio)
+    }
+
     fn new(init_values: Self::InitValuesType) -> Self {
-        let frequency_1 = midi_note_to_freq(((init_values.key as i8) + OSC_0_TUNE) as u8);
-        let frequency_2 = midi_note_to_freq(((init_values.key as i8) + OSC_1_TUNE) as u8);
+        let frequency_1 = init_values.frequency(OSC_0_TUNE);
+        let frequency_2 = init_values.frequency(OSC_1_TUNE);
         let cutoff_frequency = CutoffFrequencyCalculator::get_cutoff_frequency(&init_values);
         let adsr_init = (init_values.velocity as i32) << 8;
         let core = CoreAdsr::new((((frequency_1, frequency_2), cutoff_frequency), adsr_init));
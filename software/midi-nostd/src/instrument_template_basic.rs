@@ -1,8 +1,8 @@
-use crate::adsr::CoreAdsr;
+use crate::adsr::CoreDahdsr;
+use crate::adsr::EnvelopeCurve;
 use crate::double_oscillator::DoubleOscillator;
 use crate::filter::Filter;
 use crate::instrument_low_pass_filters::FrequencyCalculator;
-use crate::midi_notes::midi_note_to_freq;
 use crate::note::SoundSourceNoteInit;
 use crate::oscillator::CoreOscillator;
 use crate::sound_sample::SoundSampleI32;
@@ -25,15 +25,23 @@ pub struct InstrumentTemplateBasic<
     const D: i32,
     const S: u8,
     const R: i32,
+    const D_CURVE: usize,
+    const R_CURVE: usize,
     CutoffFrequencyCalculator: FrequencyCalculator,
 > {
-    core: CoreAdsr<
+    core: CoreDahdsr<
         P_FREQ,
         U_FREQ,
+        0,
         A,
+        0,
         D,
         S,
         R,
+        { EnvelopeCurve::Linear as usize },
+        D_CURVE,
+        R_CURVE,
+        0,
         Filter<
             P_FREQ,
             U_FREQ,
@@ -65,6 +73,8 @@ impl<
         const D: i32,
         const S: u8,
         const R: i32,
+        const D_CURVE: usize,
+        const R_CURVE: usize,
         CutoffFrequencyCalculator: FrequencyCalculator,
     > SoundSourceCore<P_FREQ, U_FREQ>
     for InstrumentTemplateBasic<
@@ -83,6 +93,8 @@ impl<
         D,
         S,
         R,
+        D_CURVE,
+        R_CURVE,
         CutoffFrequencyCalculator,
     >
 {
@@ -108,12 +120,16 @@ impl<
         self.core.restart(vel);
     }
 
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.core.set_pitch_ratio(ratio)
+    }
+
     fn new(init_values: Self::InitValuesType) -> Self {
-        let frequency_1 = midi_note_to_freq(((init_values.key as i8) + OSC_0_TUNE) as u8);
-        let frequency_2 = midi_note_to_freq(((init_values.key as i8) + OSC_1_TUNE) as u8);
+        let frequency_1 = init_values.frequency(OSC_0_TUNE);
+        let frequency_2 = init_values.frequency(OSC_1_TUNE);
         let cutoff_frequency = CutoffFrequencyCalculator::get_cutoff_frequency(&init_values);
         let adsr_init = (init_values.velocity as i32) << 8;
-        let core = CoreAdsr::new((((frequency_1, frequency_2), cutoff_frequency), adsr_init));
+        let core = CoreDahdsr::new((((frequency_1, frequency_2), cutoff_frequency), adsr_init));
         Self {
             core,
             _marker: PhantomData,
@@ -0,0 +1,112 @@
+use crate::adsr::CoreAdsr;
+use crate::fm_operator::FmOperator;
+use crate::fm_operator::FmPair;
+use crate::note::SoundSourceNoteInit;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::SoundSourceCore;
+
+pub struct InstrumentTemplateFm<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const MOD_RATIO: u32,
+    const MOD_A: i32,
+    const MOD_D: i32,
+    const MOD_S: u8,
+    const MOD_R: i32,
+    const CARRIER_RATIO: u32,
+    const INDEX_TENTHS: u32,
+    const A: i32,
+    const D: i32,
+    const S: u8,
+    const R: i32,
+> {
+    core: CoreAdsr<
+        P_FREQ,
+        U_FREQ,
+        A,
+        D,
+        S,
+        R,
+        FmPair<
+            P_FREQ,
+            U_FREQ,
+            CoreAdsr<
+                P_FREQ,
+                U_FREQ,
+                MOD_A,
+                MOD_D,
+                MOD_S,
+                MOD_R,
+                FmOperator<P_FREQ, U_FREQ, MOD_RATIO, 100>,
+            >,
+            CARRIER_RATIO,
+            INDEX_TENTHS,
+        >,
+    >,
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        const MOD_RATIO: u32,
+        const MOD_A: i32,
+        const MOD_D: i32,
+        const MOD_S: u8,
+        const MOD_R: i32,
+        const CARRIER_RATIO: u32,
+        const INDEX_TENTHS: u32,
+        const A: i32,
+        const D: i32,
+        const S: u8,
+        const R: i32,
+    > SoundSourceCore<P_FREQ, U_FREQ>
+    for InstrumentTemplateFm<
+        P_FREQ,
+        U_FREQ,
+        MOD_RATIO,
+        MOD_A,
+        MOD_D,
+        MOD_S,
+        MOD_R,
+        CARRIER_RATIO,
+        INDEX_TENTHS,
+        A,
+        D,
+        S,
+        R,
+    >
+{
+    type InitValuesType = SoundSourceNoteInit;
+
+    fn get_next(&mut self) -> SoundSampleI32 {
+        self.core.get_next()
+    }
+
+    fn update(&mut self) {
+        self.core.update()
+    }
+
+    fn has_next(&self) -> bool {
+        self.core.has_next()
+    }
+
+    fn trigger_note_off(&mut self) {
+        self.core.trigger_note_off();
+    }
+
+    fn restart(&mut self, vel: u8) {
+        self.core.restart(vel);
+    }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.core.set_pitch_ratio(ratio)
+    }
+
+    fn new(init_values: Self::InitValuesType) -> Self {
+        let frequency = init_values.frequency(0);
+        // Velocity drives both the loudness and the brightness of the note.
+        let adsr_init = (init_values.velocity as i32) << 8;
+        let core = CoreAdsr::new((((frequency, adsr_init), frequency), adsr_init));
+        Self { core }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::adsr::CoreAdsr;
+use crate::double_oscillator::DoubleOscillator;
+use crate::filter::Filter;
+use crate::instrument_low_pass_filters::FrequencyCalculator;
+use crate::lfo_amplitude::LfoAmplitude;
+use crate::midi_notes::detune_cents;
+use crate::note::SoundSourceNoteInit;
+use crate::oscillator::CoreOscillator;
+use crate::oscillator::OscillatorType;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::SoundSourceCore;
+use core::marker::PhantomData;
+
+///
+/// Free reed instruments, like the accordion and harmonica.
+///
+/// Two identical reeds per note, the second tuned DETUNE_CENTS sharp.  The
+/// beating between them is the "wet" musette sound of an accordion; a few
+/// cents is a dry tango accordion, fifteen or so a French musette.  A slow
+/// attack and a little bellows tremolo stand in for the breath.
+///
+pub struct InstrumentTemplateReed<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const REED_PULSE_WIDTH: u8,
+    const REED_WAVE_FORM: usize,
+    const DETUNE_CENTS: i32,
+    const TREMOLO_FREQ: u32,
+    const TREMOLO_DEPTH: u8,
+    const A: i32,
+    const D: i32,
+    const S: u8,
+    const R: i32,
+    CutoffFrequencyCalculator: FrequencyCalculator,
+> {
+    core: CoreAdsr<
+        P_FREQ,
+        U_FREQ,
+        A,
+        D,
+        S,
+        R,
+        Filter<
+            P_FREQ,
+            U_FREQ,
+            LfoAmplitude<
+                P_FREQ,
+                U_FREQ,
+                DoubleOscillator<
+                    P_FREQ,
+                    U_FREQ,
+                    CoreOscillator<P_FREQ, U_FREQ, REED_PULSE_WIDTH, 60, REED_WAVE_FORM>,
+                    CoreOscillator<P_FREQ, U_FREQ, REED_PULSE_WIDTH, 60, REED_WAVE_FORM>,
+                    false,
+                >,
+                { OscillatorType::Sine as usize },
+                TREMOLO_FREQ,
+                TREMOLO_DEPTH,
+            >,
+        >,
+    >,
+    _marker: PhantomData<CutoffFrequencyCalculator>,
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        const REED_PULSE_WIDTH: u8,
+        const REED_WAVE_FORM: usize,
+        const DETUNE_CENTS: i32,
+        const TREMOLO_FREQ: u32,
+        const TREMOLO_DEPTH: u8,
+        const A: i32,
+        const D: i32,
+        const S: u8,
+        const R: i32,
+        CutoffFrequencyCalculator: FrequencyCalculator,
+    > SoundSourceCore<P_FREQ, U_FREQ>
+    for InstrumentTemplateReed<
+        P_FREQ,
+        U_FREQ,
+        REED_PULSE_WIDTH,
+        REED_WAVE_FORM,
+        DETUNE_CENTS,
+        TREMOLO_FREQ,
+        TREMOLO_DEPTH,
+        A,
+        D,
+        S,
+        R,
+        CutoffFrequencyCalculator,
+    >
+{
+    type InitValuesType = SoundSourceNoteInit;
+
+    fn get_next(&mut self) -> SoundSampleI32 {
+        self.core.get_next()
+    }
+
+    fn update(&mut self) {
+        self.core.update()
+    }
+
+    fn has_next(&self) -> bool {
+        self.core.has_next()
+    }
+
+    fn trigger_note_off(&mut self) {
+        self.core.trigger_note_off();
+    }
+
+    fn restart(&mut self, vel: u8) {
+        self.core.restart(vel);
+    }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.core.set_pitch_ratio(ratio)
+    }
+
+    fn new(init_values: Self::InitValuesType) -> Self {
+        let frequency_1 = init_values.frequency(0);
+        let frequency_2 = detune_cents(frequency_1, DETUNE_CENTS);
+        let cutoff_frequency = CutoffFrequencyCalculator::get_cutoff_frequency(&init_values);
+        let adsr_init = (init_values.velocity as i32) << 8;
+        let core = CoreAdsr::new((((frequency_1, frequency_2), cutoff_frequency), adsr_init));
+        Self {
+            core,
+            _marker: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,46 @@
+// Generated by sf2-convert from kalimba.sf2, preset "Kalimba" (0:108).
+// Run sf2-convert again instead of editing.
+//
+
+use crate::sample_data::LoopMode;
+use crate::sample_data::Sample;
+use crate::sample_data::SampleFormat;
+use crate::sample_data::SamplePatch;
+use crate::sample_data::Zone;
+use crate::sample_player::SampledInstrument;
+
+static SAMPLE_0: Sample = Sample {
+    format: SampleFormat::I16Le,
+    data: include_bytes!("kalimba_0.bin"),
+    sample_rate: 24000,
+    loop_mode: LoopMode::Continuous,
+    loop_start: 6000,
+    loop_end: 6688,
+};
+
+pub struct KalimbaZones {}
+
+impl SamplePatch for KalimbaZones {
+    const ZONES: &'static [Zone] = &[Zone {
+        low_key: 0,
+        high_key: 127,
+        low_velocity: 0,
+        high_velocity: 127,
+        root_key: 72,
+        fine_tune: 0,
+        volume: 100,
+        sample: &SAMPLE_0,
+    }];
+}
+
+pub type Kalimba<const P_FREQ: u32, const U_FREQ: u32> = SampledInstrument<
+    P_FREQ,
+    U_FREQ,
+    KalimbaZones,
+    0,    // Delay
+    0,    // A
+    0,    // Hold
+    1500, // D
+    0,    // S
+    200,  // R
+>;
@@ -71,7 +71,14 @@ impl<
         self.source.trigger_note_off();
     }
 
-    fn restart(self: &mut Self, _vel: u8) {}
+    // The LFO carries on where it was, so the tremolo doesn't jump.
+    fn restart(self: &mut Self, vel: u8) {
+        self.source.restart(vel);
+    }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.source.set_pitch_ratio(ratio)
+    }
 }
 
 impl<
@@ -1,35 +1,84 @@
 #![cfg_attr(not(test), no_std)]
 
+pub mod accordion;
+pub mod adpcm;
 pub mod adsr;
 pub mod amp_adder;
 pub mod amp_mixer;
+pub mod arpeggiator;
 pub mod bass;
+pub mod bells;
 pub mod cello;
 pub mod choir;
+pub mod chorus;
+pub mod command_queue;
+pub mod delay;
+pub mod delay_line;
 pub mod double_oscillator;
+pub mod drawbar_organ;
+pub mod dulcimer;
 pub mod electric_piano;
 pub mod filter;
+pub mod fm_operator;
 pub mod free_list;
 pub mod french_horn;
+pub mod glockenspiel;
 pub mod guitar_acoustic;
+pub mod guitar_distortion;
+pub mod guitar_overdrive;
+pub mod harmonica;
+pub mod harp;
 pub mod instrument_low_pass_filters;
 pub mod instrument_template_amp_lfo;
 pub mod instrument_template_basic;
+pub mod instrument_template_fm;
+pub mod instrument_template_reed;
+pub mod kalimba;
 pub mod lfo_amplitude;
+pub mod live_synth;
+pub mod marimba;
+pub mod master_volume;
 pub mod midi;
 pub mod midi_channels;
 pub mod midi_events;
 pub mod midi_notes;
+pub mod midi_recorder;
 pub mod midi_time;
 pub mod midi_track;
+pub mod modal_percussion;
+pub mod music_box;
 pub mod note;
 pub mod oboe;
+pub mod organ;
+pub mod organ_church;
+pub mod organ_percussive;
 pub mod oscillator;
 pub mod piano;
+pub mod pizzicato_strings;
+pub mod playback_settings;
+pub mod plucked_string;
+pub mod protracker;
+pub mod reverb;
+pub mod rhythm_game;
+pub mod rtttl;
+pub mod sample_data;
+pub mod sample_player;
+pub mod sampled;
 pub mod sax;
+pub mod sfx_mixer;
 pub mod silence;
+pub mod smf_writer;
 pub mod sound_sample;
 pub mod sound_source_core;
 pub mod steady_one;
+pub mod step_sequencer;
+pub mod synth_lead;
+pub mod tango_accordion;
+pub mod timpani;
+pub mod tuning;
+pub mod velocity;
+pub mod vibraphone;
 pub mod violin;
 mod wave_tables;
+pub mod waveshaper;
+pub mod xylophone;
@@ -0,0 +1,232 @@
+// Live synth.
+//
+// The synth without a file: channel messages go in as they happen, from
+// buttons, a sequencer or anything else, and samples come out.  It's the
+// same AmpAdder and event handling Midi uses, so every program in NoteEnum
+// and the channel controllers work the same way.
+//
+// There's no end to it, so has_next is always true.  Whoever owns it
+// decides when to stop, usually with a fade out.
+//
+
+use crate::amp_adder::AmpAdder;
+use crate::master_volume::MasterVolume;
+use crate::midi_channels::Channel;
+use crate::midi_channels::Channels;
+use crate::midi_events::handle_midi_event;
+use crate::midi_events::update_glides;
+use crate::playback_settings::PlaybackSettings;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::SoundSourceCore;
+use midly::num::u7;
+use midly::MidiMessage;
+
+///
+/// Plays channel messages as they come in
+///
+pub struct LiveSynth<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const MAX_NOTES: usize,
+    const NO_SCALEDOWN: bool = false,
+> {
+    amp_adder: AmpAdder<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>,
+    channels: Channels,
+    settings: PlaybackSettings,
+    volume: MasterVolume<P_FREQ>,
+    skip_count: u32,
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, const MAX_NOTES: usize, const NO_SCALEDOWN: bool>
+    LiveSynth<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>
+{
+    const SKIP: u32 = P_FREQ / U_FREQ;
+
+    /// A synth that can play about loud_notes notes at full velocity before
+    /// it clips.  Nothing plays through it ahead of time, so unlike Midi it
+    /// can't measure how loud it gets.
+    ///
+    pub fn new(loud_notes: i32) -> Self {
+        assert_eq!(0, (P_FREQ % U_FREQ));
+        Self {
+            amp_adder: AmpAdder::new(core::cmp::max(1, loud_notes)),
+            channels: Channels::default(),
+            settings: PlaybackSettings::DEFAULT,
+            volume: MasterVolume::new(),
+            skip_count: 0,
+        }
+    }
+
+    /// Play a channel message
+    ///
+    pub fn send(&mut self, channel: u8, message: &MidiMessage) {
+        handle_midi_event(
+            message,
+            channel & 0xf,
+            &mut self.amp_adder,
+            &mut self.channels,
+            &self.settings,
+        );
+    }
+
+    pub fn note_on(&mut self, channel: u8, key: u8, velocity: u8) {
+        self.send(
+            channel,
+            &MidiMessage::NoteOn {
+                key: u7::new(key & 0x7f),
+                vel: u7::new(velocity & 0x7f),
+            },
+        );
+    }
+
+    pub fn note_off(&mut self, channel: u8, key: u8) {
+        self.send(
+            channel,
+            &MidiMessage::NoteOff {
+                key: u7::new(key & 0x7f),
+                vel: u7::new(0),
+            },
+        );
+    }
+
+    /// Let go of everything on channel.  The notes' releases still play.
+    ///
+    pub fn all_notes_off(&mut self, channel: u8) {
+        // Mono mode off, which lets everything go and leaves it off
+        self.send(
+            channel,
+            &MidiMessage::Controller {
+                controller: u7::new(127),
+                value: u7::new(0),
+            },
+        );
+    }
+
+    /// Play new notes on channel with program
+    ///
+    pub fn set_program(&mut self, channel: u8, program: u8) {
+        self.settings.channel_programs[(channel & 0xf) as usize] = program as i32;
+    }
+
+    /// Move new notes up or down by semitones
+    ///
+    pub fn set_transpose(&mut self, semitones: i8) {
+        self.settings.transpose = semitones;
+    }
+
+    /// Change the master volume, from 0 to 100.  The change is ramped, so
+    /// it's safe to call while playing.
+    ///
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume.set_volume(volume);
+    }
+
+    pub fn get_volume(&self) -> u8 {
+        self.volume.get_volume()
+    }
+
+    /// Come up from silence to the master volume over time_in_ms
+    ///
+    pub fn fade_in(&mut self, time_in_ms: i32) {
+        self.volume.fade_in(time_in_ms);
+    }
+
+    /// Go down to silence over time_in_ms.  Notes carry on, so check
+    /// is_faded_out to know when it's safe to stop.
+    ///
+    pub fn fade_out(&mut self, time_in_ms: i32) {
+        self.volume.fade_out(time_in_ms);
+    }
+
+    pub fn is_faded_out(&self) -> bool {
+        self.volume.is_silent()
+    }
+
+    pub fn update(&mut self) {
+        update_glides(&mut self.amp_adder, &mut self.channels);
+        self.amp_adder.update();
+    }
+
+    pub fn get_next(&mut self) -> SoundSampleI32 {
+        if self.skip_count == 0 {
+            self.update();
+        }
+        self.skip_count += 1;
+        if self.skip_count == Self::SKIP {
+            self.skip_count = 0;
+        }
+        self.volume.apply(self.amp_adder.get_next())
+    }
+
+    pub fn get_note_state(&self, note_volume: &mut [u8; 128]) {
+        note_volume.fill(0);
+        self.channels.get_note_state(note_volume);
+    }
+
+    /// True if key is held down on channel
+    ///
+    pub fn is_key_down(&self, channel: u8, key: u8) -> bool {
+        self.channels.channels[(channel & 0xf) as usize].playing_notes[(key & 0x7f) as usize]
+            != Channel::UNUSED
+    }
+
+    /// Notes sounding, releases included
+    ///
+    pub fn num_playing_notes(&mut self) -> u32 {
+        self.amp_adder.get_current_num_mixed_notes()
+    }
+
+    pub fn has_next(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::live_synth::*;
+
+    type TestSynth = LiveSynth<24000, 1000, 8>;
+
+    fn loudest(synth: &mut TestSynth, samples: usize) -> i32 {
+        (0..samples)
+            .map(|_| synth.get_next().to_i32().abs())
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn notes_should_play_until_released() {
+        let mut synth = TestSynth::new(1);
+        assert_eq!(0, loudest(&mut synth, 240));
+
+        synth.set_program(2, 80);
+        synth.note_on(2, 69, 100);
+        assert!(loudest(&mut synth, 2400) > 0);
+        assert!(synth.is_key_down(2, 69));
+        assert!(!synth.is_key_down(0, 69));
+
+        synth.note_off(2, 69);
+        // Past the release
+        loudest(&mut synth, 24000);
+        assert_eq!(0, synth.num_playing_notes());
+        assert_eq!(0, loudest(&mut synth, 240));
+    }
+
+    #[test]
+    fn all_notes_off_should_release_a_channel() {
+        let mut synth = TestSynth::new(4);
+        synth.set_program(0, 80);
+        synth.set_program(1, 80);
+        for key in [60, 64, 67] {
+            synth.note_on(0, key, 100);
+        }
+        synth.note_on(1, 48, 100);
+        loudest(&mut synth, 240);
+        assert_eq!(4, synth.num_playing_notes());
+
+        synth.all_notes_off(0);
+        loudest(&mut synth, 24000);
+        assert_eq!(1, synth.num_playing_notes());
+        assert!(synth.has_next());
+    }
+}
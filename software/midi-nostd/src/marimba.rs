@@ -0,0 +1,35 @@
+use crate::modal_percussion::ModalPatch;
+use crate::modal_percussion::ModalPercussion;
+use crate::modal_percussion::Mode;
+use crate::modal_percussion::MAX_MODES;
+
+//
+// Rosewood bars tuned 1:4:10 like the vibraphone, but wood damps the
+// overtones fast and the fundamental isn't far behind.
+//
+pub struct MarimbaModes {}
+
+impl ModalPatch for MarimbaModes {
+    const MODES: [Mode; MAX_MODES] = [
+        Mode {
+            ratio: 1000,
+            level: 70,
+            decay: 600,
+        },
+        Mode {
+            ratio: 3984,
+            level: 20,
+            decay: 100,
+        },
+        Mode {
+            ratio: 9723,
+            level: 10,
+            decay: 40,
+        },
+        Mode::UNUSED,
+    ];
+    const RELEASE: u32 = 300;
+}
+
+pub type Marimba<const P_FREQ: u32, const U_FREQ: u32> =
+    ModalPercussion<P_FREQ, U_FREQ, MarimbaModes>;
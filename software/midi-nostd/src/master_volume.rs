@@ -0,0 +1,166 @@
+// Master volume.
+//
+// A gain applied to the final mix.  Jumping straight to a new gain makes
+// an audible step (and a run of steps, as someone holds a volume button,
+// sounds like a zipper), so every change is a ramp, moved along one
+// sample at a time.  Fades are just longer ramps.
+//
+
+use crate::sound_sample::time_to_ticks;
+use crate::sound_sample::SoundSampleI32;
+
+///
+/// Master volume, ramped per sample
+///
+pub struct MasterVolume<const P_FREQ: u32> {
+    /// The volume set by the user, from 0 to 100
+    volume: u8,
+    /// Current gain, 0x8000 for unity, with 8 extra fractional bits
+    gain: i32,
+    target_gain: i32,
+    step: i32,
+    samples_left: i32,
+}
+
+impl<const P_FREQ: u32> MasterVolume<P_FREQ> {
+    /// Time to move to a new volume, in ms
+    pub const SMOOTHING_TIME: i32 = 20;
+
+    const FRACTION_BITS: u32 = 8;
+
+    pub const fn new() -> Self {
+        Self {
+            volume: 100,
+            gain: Self::volume_to_gain(100),
+            target_gain: Self::volume_to_gain(100),
+            step: 0,
+            samples_left: 0,
+        }
+    }
+
+    const fn volume_to_gain(volume: u8) -> i32 {
+        let volume = if volume > 100 { 100 } else { volume };
+        ((volume as i32) * 0x8000 / 100) << Self::FRACTION_BITS
+    }
+
+    fn ramp_to(&mut self, target_gain: i32, time_in_ms: i32) {
+        self.target_gain = target_gain;
+        self.samples_left = time_to_ticks::<P_FREQ>(time_in_ms);
+        if self.samples_left <= 0 {
+            self.gain = target_gain;
+            self.step = 0;
+            self.samples_left = 0;
+        } else {
+            self.step = (target_gain - self.gain) / self.samples_left;
+        }
+    }
+
+    /// Change the volume, from 0 to 100
+    ///
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = core::cmp::min(volume, 100);
+        self.ramp_to(Self::volume_to_gain(self.volume), Self::SMOOTHING_TIME);
+    }
+
+    pub fn get_volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// Start from silence and come up to the volume over time_in_ms
+    ///
+    pub fn fade_in(&mut self, time_in_ms: i32) {
+        self.gain = 0;
+        self.ramp_to(Self::volume_to_gain(self.volume), time_in_ms);
+    }
+
+    /// Go down to silence over time_in_ms.  The volume setting is kept, so
+    /// fade_in or set_volume brings the sound back.
+    ///
+    pub fn fade_out(&mut self, time_in_ms: i32) {
+        self.ramp_to(0, time_in_ms);
+    }
+
+    /// True once a fade out (or a volume of 0) has reached silence
+    ///
+    pub fn is_silent(&self) -> bool {
+        self.gain == 0 && self.target_gain == 0
+    }
+
+    /// True while the gain is still moving
+    ///
+    pub fn is_ramping(&self) -> bool {
+        self.samples_left != 0
+    }
+
+    /// Apply the gain to a sample and move the ramp along
+    ///
+    #[inline]
+    pub fn apply(&mut self, sample: SoundSampleI32) -> SoundSampleI32 {
+        if self.samples_left != 0 {
+            self.samples_left -= 1;
+            self.gain = if self.samples_left == 0 {
+                self.target_gain
+            } else {
+                self.gain + self.step
+            };
+        }
+        let gain = self.gain >> Self::FRACTION_BITS;
+        SoundSampleI32::new_i32(((sample.to_i32() as i64 * gain as i64) >> 15) as i32)
+    }
+}
+
+impl<const P_FREQ: u32> Default for MasterVolume<P_FREQ> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::master_volume::*;
+
+    const FULL: SoundSampleI32 = SoundSampleI32::new_i32(0x4000);
+
+    #[test]
+    fn full_volume_should_leave_samples_alone() {
+        let mut volume = MasterVolume::<1000>::new();
+        assert_eq!(0x4000, volume.apply(FULL).to_i32());
+    }
+
+    #[test]
+    fn volume_changes_should_ramp() {
+        let mut volume = MasterVolume::<1000>::new();
+        volume.set_volume(50);
+        let mut last = volume.apply(FULL).to_i32();
+        assert!(last > 0x2000);
+        for _ in 1..MasterVolume::<1000>::SMOOTHING_TIME {
+            let current = volume.apply(FULL).to_i32();
+            assert!(current <= last);
+            assert!(last - current < 0x200);
+            last = current;
+        }
+        assert_eq!(0x2000, last);
+        assert!(!volume.is_ramping());
+    }
+
+    #[test]
+    fn fades_should_reach_silence_and_come_back() {
+        let mut volume = MasterVolume::<1000>::new();
+        volume.set_volume(80);
+        volume.fade_out(500);
+        for _ in 0..499 {
+            volume.apply(FULL);
+        }
+        assert!(!volume.is_silent());
+        volume.apply(FULL);
+        assert!(volume.is_silent());
+        assert_eq!(0, volume.apply(FULL).to_i32());
+
+        volume.fade_in(100);
+        assert!(volume.apply(FULL).to_i32() < 0x100);
+        for _ in 0..99 {
+            volume.apply(FULL);
+        }
+        assert_eq!(0x4000 * 80 / 100, volume.apply(FULL).to_i32());
+    }
+}
@@ -1,10 +1,20 @@
 use crate::amp_adder::AmpAdder;
+use crate::amp_adder::CHORUS_SEND;
+use crate::amp_adder::REVERB_SEND;
+use crate::chorus::Chorus;
+use crate::delay::Delay;
+use crate::master_volume::MasterVolume;
 use crate::midi_channels::Channels;
+use crate::midi_events::release_inaudible_channels;
+use crate::midi_events::update_glides;
 use crate::midi_time::MidiTime;
 use crate::midi_track::MidiTrack;
+use crate::playback_settings::PlaybackSettings;
+use crate::reverb::Reverb;
 use crate::sound_sample::SoundSampleI32;
 use crate::sound_source_core::SoundSourceCore;
-use midly::Timing;
+use crate::tuning::Tuning;
+use crate::velocity::VelocityCurve;
 
 pub struct Midi<
     'a,
@@ -13,6 +23,9 @@ pub struct Midi<
     const MAX_NOTES: usize,
     const MAX_TRACKS: usize,
     const NO_SCALEDOWN: bool = false,
+    const REVERB_BUFFER: usize = 0,
+    const CHORUS_BUFFER: usize = 0,
+    const DELAY_BUFFER: usize = 0,
 > {
     num_tracks: usize,
     tracks: [Option<MidiTrack<'a, P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>>; MAX_TRACKS],
@@ -21,7 +34,11 @@ pub struct Midi<
     tempo: MidiTime<P_FREQ, U_FREQ>,
     skip_count: u32,
     tracks_still_playing: bool,
-    program_override: i32,
+    settings: PlaybackSettings,
+    volume: MasterVolume<P_FREQ>,
+    reverb: Reverb<P_FREQ, REVERB_BUFFER>,
+    chorus: Chorus<P_FREQ, CHORUS_BUFFER>,
+    delay: Delay<P_FREQ, DELAY_BUFFER>,
 }
 
 impl<
@@ -31,7 +48,21 @@ impl<
         const MAX_NOTES: usize,
         const MAX_TRACKS: usize,
         const NO_SCALEDOWN: bool,
-    > Midi<'a, P_FREQ, U_FREQ, MAX_NOTES, MAX_TRACKS, NO_SCALEDOWN>
+        const REVERB_BUFFER: usize,
+        const CHORUS_BUFFER: usize,
+        const DELAY_BUFFER: usize,
+    >
+    Midi<
+        'a,
+        P_FREQ,
+        U_FREQ,
+        MAX_NOTES,
+        MAX_TRACKS,
+        NO_SCALEDOWN,
+        REVERB_BUFFER,
+        CHORUS_BUFFER,
+        DELAY_BUFFER,
+    >
 {
     const SKIP: u32 = P_FREQ / U_FREQ;
 
@@ -39,6 +70,7 @@ impl<
         header: &midly::Header,
         mut track_iter: midly::TrackIter<'a>,
         divider: i32,
+        settings: PlaybackSettings,
     ) -> Self {
         assert_eq!(0, (P_FREQ % U_FREQ));
         let num_tracks = track_iter.clone().count();
@@ -57,12 +89,8 @@ impl<
 
         let amp_adder = AmpAdder::<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>::new(divider);
 
-        let tpqn_midly = match header.timing {
-            Timing::Metrical(ticks) => ticks,
-            Timing::Timecode(_, _) => todo!(),
-        };
-        let tpqn_u16: u16 = tpqn_midly.into();
-        let tempo = MidiTime::new(500000, tpqn_u16 as u32);
+        let mut tempo = MidiTime::for_timing(header.timing);
+        tempo.set_tempo_percent(settings.tempo_percent);
 
         Self {
             num_tracks,
@@ -72,22 +100,145 @@ impl<
             tempo,
             skip_count: 0,
             tracks_still_playing: true,
-            program_override: -1,
+            settings,
+            volume: MasterVolume::new(),
+            reverb: Reverb::new(),
+            chorus: Chorus::new(),
+            delay: Delay::new(),
         }
     }
 
+    /// Play every channel with one program.  -1 to go back to the
+    /// programs in the file.
+    ///
     pub fn set_program_override(self: &mut Self, program_override: i32) {
-        self.program_override = program_override;
+        self.settings.channel_programs = [program_override; 16];
+    }
+
+    /// Play channel with program.  -1 to go back to the program in the file.
+    /// Only the bottom four bits of channel are used, as in a MIDI message.
+    ///
+    pub fn set_channel_program(&mut self, channel: usize, program: i32) {
+        self.settings.channel_programs[channel & 0xf] = program;
+    }
+
+    /// Move new notes up or down by semitones
+    ///
+    pub fn set_transpose(&mut self, semitones: i8) {
+        self.settings.transpose = semitones;
+    }
+
+    /// Play at tempo_percent of the file's tempo.  50 for slow practice.
+    ///
+    pub fn set_tempo_percent(&mut self, tempo_percent: u32) {
+        self.settings.tempo_percent = tempo_percent;
+        self.tempo.set_tempo_percent(tempo_percent);
+    }
+
+    /// Silence channel.  Only the bottom four bits of channel are used.
+    ///
+    pub fn set_channel_muted(&mut self, channel: usize, muted: bool) {
+        Self::set_channel_bit(&mut self.settings.muted, channel, muted);
+        release_inaudible_channels(&mut self.amp_adder, &mut self.channels, &self.settings);
+    }
+
+    /// Solo channel.  While any channel is soloed, only soloed channels play.
+    ///
+    pub fn set_channel_solo(&mut self, channel: usize, solo: bool) {
+        Self::set_channel_bit(&mut self.settings.soloed, channel, solo);
+        release_inaudible_channels(&mut self.amp_adder, &mut self.channels, &self.settings);
+    }
+
+    fn set_channel_bit(bits: &mut u16, channel: usize, value: bool) {
+        if value {
+            *bits |= 1 << (channel & 0xf);
+        } else {
+            *bits &= !(1 << (channel & 0xf));
+        }
+    }
+
+    /// Change the velocity curve.  The output level was worked out in new
+    /// with the old curve, so a curve that makes notes louder may clip; use
+    /// new_with_settings to avoid that.
+    ///
+    pub fn set_velocity_curve(&mut self, velocity_curve: VelocityCurve) {
+        self.settings.velocity_curve = velocity_curve;
+    }
+
+    /// Change the master volume, from 0 to 100.  The change is ramped, so
+    /// it's safe to call while playing.
+    ///
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume.set_volume(volume);
+    }
+
+    pub fn get_volume(&self) -> u8 {
+        self.volume.get_volume()
+    }
+
+    /// Come up from silence to the master volume over time_in_ms
+    ///
+    pub fn fade_in(&mut self, time_in_ms: i32) {
+        self.volume.fade_in(time_in_ms);
     }
 
-    pub fn get_loudest_sample(header: &midly::Header, track_iter: midly::TrackIter<'a>) -> i32 {
+    /// Go down to silence over time_in_ms.  Playback carries on, so check
+    /// is_faded_out to know when it's safe to stop.
+    ///
+    pub fn fade_out(&mut self, time_in_ms: i32) {
+        self.volume.fade_out(time_in_ms);
+    }
+
+    pub fn is_faded_out(&self) -> bool {
+        self.volume.is_silent()
+    }
+
+    /// The reverb, for changing its room size, damping and level.  It's
+    /// only there if REVERB_BUFFER is big enough; see Reverb::ENABLED.
+    ///
+    pub fn reverb(&mut self) -> &mut Reverb<P_FREQ, REVERB_BUFFER> {
+        &mut self.reverb
+    }
+
+    /// The chorus, fed by each channel's CC93 send.  It's only there if
+    /// CHORUS_BUFFER is big enough; see Chorus::ENABLED.
+    ///
+    pub fn chorus(&mut self) -> &mut Chorus<P_FREQ, CHORUS_BUFFER> {
+        &mut self.chorus
+    }
+
+    /// The delay, on the whole mix.  It starts with its level at 0, and is
+    /// only there if DELAY_BUFFER is big enough; see Delay::ENABLED.
+    ///
+    pub fn delay(&mut self) -> &mut Delay<P_FREQ, DELAY_BUFFER> {
+        &mut self.delay
+    }
+
+    /// Change the tuning.  SysEx tuning messages in the file are applied on
+    /// top of it as they're played.
+    ///
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.channels.tuning = tuning;
+    }
+
+    /// Loudest sample the song plays, with room for whichever of the
+    /// reverb, chorus and delay are enabled at their full levels
+    ///
+    pub fn get_loudest_sample(
+        header: &midly::Header,
+        track_iter: midly::TrackIter<'a>,
+        settings: PlaybackSettings,
+    ) -> i32 {
         let mut fast_forward_midi_player =
             Midi::<240, 240, MAX_NOTES, MAX_TRACKS, true>::new_internal(
                 header,
                 track_iter.clone(),
                 0, // not used
+                settings,
             );
         let mut loudest: i32 = 0;
+        let mut loudest_reverb_send: i32 = 0;
+        let mut loudest_chorus_send: i32 = 0;
         while fast_forward_midi_player.has_next() {
             let sample = fast_forward_midi_player.get_next().to_i32();
             let abs_sample = if sample < 0 { -sample } else { sample };
@@ -96,22 +247,50 @@ impl<
             } else {
                 loudest
             };
+            let amp_adder = &fast_forward_midi_player.amp_adder;
+            let reverb_send = amp_adder.get_send(REVERB_SEND).to_i32().abs();
+            loudest_reverb_send = core::cmp::max(loudest_reverb_send, reverb_send);
+            let chorus_send = amp_adder.get_send(CHORUS_SEND).to_i32().abs();
+            loudest_chorus_send = core::cmp::max(loudest_chorus_send, chorus_send);
+        }
+
+        // The effects don't run at the fast forward rate, so leave room for
+        // the most they can add.
+        if Reverb::<P_FREQ, REVERB_BUFFER>::ENABLED {
+            loudest += loudest_reverb_send * Reverb::<P_FREQ, REVERB_BUFFER>::PEAK_GAIN;
+        }
+        if Chorus::<P_FREQ, CHORUS_BUFFER>::ENABLED {
+            loudest += loudest_chorus_send * Chorus::<P_FREQ, CHORUS_BUFFER>::PEAK_GAIN;
+        }
+        if Delay::<P_FREQ, DELAY_BUFFER>::ENABLED {
+            loudest += loudest * Delay::<P_FREQ, DELAY_BUFFER>::PEAK_GAIN_PERCENT / 100;
         }
         loudest
     }
 
     pub fn new(header: &midly::Header, track_iter: midly::TrackIter<'a>) -> Self {
+        Self::new_with_settings(header, track_iter, PlaybackSettings::DEFAULT)
+    }
+
+    pub fn new_with_settings(
+        header: &midly::Header,
+        track_iter: midly::TrackIter<'a>,
+        settings: PlaybackSettings,
+    ) -> Self {
         //
         // Limit to 255 (not 256) notes to save space in the midi data
         // structure.  On embedded platforms memory is often limited
         //
         assert!(MAX_NOTES < 0xff);
-        let loudest = Self::get_loudest_sample(header, track_iter.clone());
-        Midi::<P_FREQ, U_FREQ, MAX_NOTES, MAX_TRACKS, NO_SCALEDOWN>::new_internal(
-            header,
-            track_iter.clone(),
-            loudest / 0x8000 + 1,
-        )
+        let loudest = Self::get_loudest_sample(header, track_iter.clone(), settings);
+        Self::new_internal(header, track_iter.clone(), loudest / 0x8000 + 1, settings)
+    }
+
+    /// How far into the song playback is, in ticks.  Tempo changes make
+    /// ticks longer or shorter, but the notes stay on them.
+    ///
+    pub fn current_tick(&self) -> u32 {
+        self.tempo.get_current_time()
     }
 
     pub fn get_current_num_mixed_notes(self: &mut Self) -> u32 {
@@ -126,10 +305,12 @@ impl<
                     &mut self.amp_adder,
                     &mut self.channels,
                     &mut self.tempo,
-                    self.program_override,
+                    &self.settings,
                 );
             }
         }
+        update_glides(&mut self.amp_adder, &mut self.channels);
+        self.delay.set_tempo(self.tempo.get_ms_per_quarter_note());
         self.amp_adder.update();
         self.tracks_still_playing = false;
         for i in 0..self.num_tracks {
@@ -149,7 +330,12 @@ impl<
         if self.skip_count == Self::SKIP {
             self.skip_count = 0;
         }
-        self.amp_adder.get_next()
+        let dry = self.amp_adder.get_next();
+        let wet = self.reverb.process(self.amp_adder.get_send(REVERB_SEND))
+            + self.chorus.process(self.amp_adder.get_send(CHORUS_SEND));
+        let mix = dry + wet;
+        let echoes = self.delay.process(mix);
+        self.volume.apply(mix + echoes)
     }
     pub fn get_note_state(self: &Self, note_volume: &mut [u8; 128]) {
         for item in note_volume.iter_mut() {
@@ -167,16 +353,188 @@ impl<
 mod tests {
 
     use crate::midi::Midi;
+    use crate::playback_settings::PlaybackSettings;
+    use crate::smf_writer::SmfFormat;
+    use crate::smf_writer::SmfWriter;
+    use crate::velocity::VelocityCurve;
+    use midly::MidiMessage;
 
     #[test]
     fn basic_midi_test() {
         let (header, tracks) = midly::parse(include_bytes!("../assets/twinkle.mid"))
             .expect("It's inlined data, so it better work, gosh darn it");
-        let mut midi = Midi::<24000, 24000, 32, 16>::new_internal(&header, tracks, 1);
+        let mut midi = Midi::<24000, 24000, 32, 16>::new_internal(
+            &header,
+            tracks,
+            1,
+            PlaybackSettings::DEFAULT,
+        );
 
         assert_eq!(11, midi.get_next().to_i32());
         //assert_eq!(8192, midi.get_next(&smf).to_i32());
         //assert_eq!(8719, midi.get_next(&smf).to_i32());
         //assert_eq!(9246, midi.get_next(&smf).to_i32());
     }
+
+    #[test]
+    fn current_tick_should_follow_playback() {
+        let (header, tracks) = midly::parse(include_bytes!("../assets/twinkle.mid"))
+            .expect("It's inlined data, so it better work, gosh darn it");
+        let mut midi = Midi::<24000, 1000, 32, 16>::new_internal(
+            &header,
+            tracks,
+            1,
+            PlaybackSettings::DEFAULT,
+        );
+        assert_eq!(0, midi.current_tick());
+        // The file's 95 bpm, at 384 ticks per quarter note
+        for _ in 0..24000 {
+            midi.get_next();
+        }
+        assert!((607..=608).contains(&midi.current_tick()));
+    }
+
+    fn loudest_with_curve(velocity_curve: VelocityCurve) -> i32 {
+        let (header, tracks) = midly::parse(include_bytes!("../assets/twinkle.mid"))
+            .expect("It's inlined data, so it better work, gosh darn it");
+        let settings = PlaybackSettings {
+            velocity_curve,
+            ..PlaybackSettings::DEFAULT
+        };
+        let mut midi = Midi::<24000, 24000, 32, 16>::new_internal(&header, tracks, 1, settings);
+        let mut loudest = 0;
+        for _ in 0..24000 {
+            loudest = core::cmp::max(loudest, midi.get_next().to_i32().abs());
+        }
+        loudest
+    }
+
+    #[test]
+    fn channel_settings_should_wrap_channels() {
+        let (header, tracks) = midly::parse(include_bytes!("../assets/twinkle.mid"))
+            .expect("It's inlined data, so it better work, gosh darn it");
+        let mut midi = Midi::<24000, 24000, 32, 16>::new(&header, tracks);
+        midi.set_channel_program(16, 40);
+        midi.set_channel_muted(17, true);
+        midi.set_channel_solo(255, true);
+        assert_eq!(40, midi.settings.channel_programs[0]);
+        assert!(!midi.settings.is_audible(1));
+        assert!(midi.settings.is_audible(15));
+        assert!(!midi.settings.is_audible(0));
+    }
+
+    // Returns a single track file of a chord of n organ notes, held for a
+    // second, with full reverb and chorus sends
+    fn loud_chord(buffer: &mut [u8], n: u8) -> &[u8] {
+        let mut writer = SmfWriter::new(buffer, SmfFormat::SingleTrack, 96).unwrap();
+        writer.start_track().unwrap();
+        let setup = [
+            MidiMessage::ProgramChange { program: 16.into() },
+            MidiMessage::Controller {
+                controller: 91.into(),
+                value: 127.into(),
+            },
+            MidiMessage::Controller {
+                controller: 93.into(),
+                value: 127.into(),
+            },
+        ];
+        for message in setup {
+            writer.midi_event(0, 0, &message).unwrap();
+        }
+        for idx in 0..n {
+            let message = MidiMessage::NoteOn {
+                key: (48 + idx * 4).into(),
+                vel: 127.into(),
+            };
+            writer.midi_event(0, 0, &message).unwrap();
+        }
+        for idx in 0..n {
+            let message = MidiMessage::NoteOff {
+                key: (48 + idx * 4).into(),
+                vel: 0.into(),
+            };
+            writer
+                .midi_event(if idx == 0 { 192 } else { 0 }, 0, &message)
+                .unwrap();
+        }
+        writer.end_track(96).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn effects_should_not_clip() {
+        for n in [1, 4, 8] {
+            let mut buffer = [0u8; 256];
+            let (header, tracks) = midly::parse(loud_chord(&mut buffer, n)).unwrap();
+            let mut midi =
+                Midi::<24000, 240, 32, 16, false, 8192, 1024, 12000>::new(&header, tracks);
+            midi.reverb().set_room_size(100);
+            midi.delay().set_level(100);
+            let mut loudest = 0;
+            while midi.has_next() {
+                loudest = core::cmp::max(loudest, midi.get_next().to_i32().abs());
+            }
+            assert!(loudest > 0x1000 && loudest < 0x8000, "{} {:x}", n, loudest);
+        }
+    }
+
+    #[test]
+    fn velocity_curve_should_change_the_level() {
+        let loud = loudest_with_curve(VelocityCurve::Fixed(127));
+        let soft = loudest_with_curve(VelocityCurve::Fixed(16));
+        assert!(soft > 0);
+        assert!(soft * 4 < loud, "{} {}", soft, loud);
+    }
+
+    #[test]
+    fn sampled_programs_should_play() {
+        // A half second C5 on the kalimba, at 120 bpm
+        let mut buffer = [0u8; 64];
+        let mut writer = SmfWriter::new(&mut buffer, SmfFormat::SingleTrack, 96).unwrap();
+        writer.start_track().unwrap();
+        for (time, message) in [
+            (
+                0,
+                MidiMessage::ProgramChange {
+                    program: 108.into(),
+                },
+            ),
+            (
+                0,
+                MidiMessage::NoteOn {
+                    key: 72.into(),
+                    vel: 100.into(),
+                },
+            ),
+            (
+                96,
+                MidiMessage::NoteOff {
+                    key: 72.into(),
+                    vel: 0.into(),
+                },
+            ),
+        ] {
+            writer.midi_event(time, 0, &message).unwrap();
+        }
+        writer.end_track(96).unwrap();
+        let (header, tracks) = midly::parse(writer.finish().unwrap()).unwrap();
+
+        let mut midi = Midi::<24000, 24000, 32, 16>::new_internal(
+            &header,
+            tracks,
+            1,
+            PlaybackSettings::DEFAULT,
+        );
+        let mut loudest = 0;
+        for _ in 0..12000 {
+            loudest = core::cmp::max(loudest, midi.get_next().to_i32().abs());
+        }
+        assert!(loudest > 1000, "{}", loudest);
+        // Then the release dies away.
+        for _ in 0..12000 {
+            midi.get_next();
+        }
+        assert_eq!(0, midi.get_next().to_i32());
+    }
 }
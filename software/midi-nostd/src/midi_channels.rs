@@ -2,15 +2,126 @@
 // Binds (channel, note) to an array element in the AmpAdder data structure;
 // these entries are stored as a u8 because space is at a premium.
 //
+// In mono mode (CC126) every held key on a channel is bound to the same
+// voice, and new keys change that voice's pitch instead of starting a new
+// one.  With portamento (CC65) on, the pitch glides there over a time set
+// by CC5.
+//
+// Every channel shares one Tuning, which sets the pitch of each key.
+//
+
+use crate::midi_notes::cents_to_ratio;
+use crate::tuning::Tuning;
 
 pub struct Channel {
     pub current_program: u8,
     pub playing_notes: [u8; 128],
+    pub mono: bool,
+    pub portamento: bool,
+    pub portamento_time: u8,
+    /// Reverb send (CC91), from 0 to 127
+    pub reverb_send: u8,
+    /// Chorus send (CC93), from 0 to 127
+    pub chorus_send: u8,
+    /// The voice playing in mono mode, or UNUSED
+    pub mono_voice: u8,
+    /// Key the mono voice was started with, after transposing.  Its pitch
+    /// is relative to this.
+    pub mono_key: u8,
+    /// Key the mono voice is playing, or gliding to, after transposing
+    pub target_key: u8,
+    /// Last key played on the channel, after transposing, where portamento
+    /// glides from
+    pub last_key: u8,
+    glide_cents: i32,
+    glide_target_cents: i32,
+    glide_step: i32,
 }
 
 impl Channel {
     pub const UNUSED: u8 = 0xff;
 
+    /// General MIDI's reverb send for a channel that hasn't set one
+    pub const DEFAULT_REVERB_SEND: u8 = 40;
+
+    /// Portamento time for a CC5 value, in ms.  The curve gives fine
+    /// control over short glides and still reaches a couple of seconds.
+    ///
+    pub const fn portamento_time_ms(value: u8) -> i32 {
+        (value as i32) * (value as i32) / 8
+    }
+
+    /// Pitch of the mono voice, as a 16.16 ratio of mono_key's frequency
+    ///
+    pub fn glide_ratio(&self) -> u32 {
+        cents_to_ratio(self.glide_cents)
+    }
+
+    //
+    // Distance from one key to another in cents, with tuning.
+    //
+    fn interval_cents(from_key: u8, to_key: u8, tuning: &Tuning) -> i32 {
+        ((to_key as i32) - (from_key as i32)) * 100 + (tuning.cents(to_key) as i32)
+            - (tuning.cents(from_key) as i32)
+    }
+
+    /// Bind a new mono voice, started with key, and put its pitch at
+    /// from_key.  start_glide takes it from there.
+    ///
+    pub fn start_mono_voice(&mut self, voice: u8, key: u8, from_key: u8, tuning: &Tuning) {
+        self.mono_voice = voice;
+        self.mono_key = key;
+        self.target_key = key;
+        self.glide_cents = Self::interval_cents(key, from_key, tuning);
+        self.glide_target_cents = self.glide_cents;
+        self.glide_step = 0;
+    }
+
+    /// Head for key from wherever the pitch is now, over updates updates.
+    /// An updates of 0 jumps straight there.
+    ///
+    pub fn start_glide(&mut self, key: u8, updates: i32, tuning: &Tuning) {
+        self.target_key = key;
+        self.glide_target_cents = Self::interval_cents(self.mono_key, key, tuning);
+        if updates <= 0 {
+            self.glide_cents = self.glide_target_cents;
+            self.glide_step = 0;
+        } else {
+            // Round the step away from zero, so the glide is never late.
+            let distance = self.glide_target_cents - self.glide_cents;
+            let step = (distance.abs() + updates - 1) / updates;
+            self.glide_step = if distance < 0 { -step } else { step };
+        }
+    }
+
+    /// Move one update along the glide.  Returns true if the pitch changed.
+    ///
+    pub fn advance_glide(&mut self) -> bool {
+        if self.glide_cents == self.glide_target_cents {
+            return false;
+        }
+        let next = self.glide_cents + self.glide_step;
+        let passed = if self.glide_step > 0 {
+            next >= self.glide_target_cents
+        } else {
+            next <= self.glide_target_cents
+        };
+        self.glide_cents = if passed {
+            self.glide_target_cents
+        } else {
+            next
+        };
+        true
+    }
+
+    /// The highest held key other than skip_key
+    ///
+    pub fn highest_held_key(&self, skip_key: u8) -> Option<u8> {
+        (0..128u8)
+            .rev()
+            .find(|key| *key != skip_key && self.playing_notes[*key as usize] != Self::UNUSED)
+    }
+
     fn get_note_state(self: &Self, note_volume: &mut [u8; 128]) {
         for idx in 0..128 {
             if self.playing_notes[idx] != Self::UNUSED && self.playing_notes[idx] > note_volume[idx]
@@ -26,12 +137,25 @@ impl Default for Channel {
         Self {
             current_program: 0,
             playing_notes: [Self::UNUSED; 128],
+            mono: false,
+            portamento: false,
+            portamento_time: 0,
+            reverb_send: Self::DEFAULT_REVERB_SEND,
+            chorus_send: 0,
+            mono_voice: Self::UNUSED,
+            mono_key: 0,
+            target_key: 0,
+            last_key: Self::UNUSED,
+            glide_cents: 0,
+            glide_target_cents: 0,
+            glide_step: 0,
         }
     }
 }
 
 pub struct Channels {
     pub channels: [Channel; 16],
+    pub tuning: Tuning,
 }
 
 impl Channels {
@@ -46,6 +170,7 @@ impl Default for Channels {
     fn default() -> Self {
         Self {
             channels: core::array::from_fn(|_idx| Channel::default()),
+            tuning: Tuning::EQUAL,
         }
     }
 }
@@ -1,8 +1,124 @@
 use crate::amp_adder::AmpAdder;
+use crate::amp_adder::CHORUS_SEND;
+use crate::amp_adder::REVERB_SEND;
 use crate::midi_channels::Channel;
 use crate::midi_channels::Channels;
 use crate::midi_time::MidiTime;
 use crate::note::SoundSourceNoteInit;
+use crate::playback_settings::PlaybackSettings;
+use crate::sound_sample::time_to_ticks;
+use crate::tuning::Tuning;
+
+//
+// Release key on channel.  In mono mode the voice carries on if other keys
+// are still held, and goes back to one of them if key was the one playing.
+// The mono voice's keys are transposed, so settings maps key onto them.
+//
+fn note_off<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const MAX_NOTES: usize,
+    const NO_SCALEDOWN: bool,
+>(
+    key: u8,
+    channel: &mut Channel,
+    tuning: &Tuning,
+    notes: &mut AmpAdder<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>,
+    settings: &PlaybackSettings,
+) {
+    let playing_note = channel.playing_notes[key as usize];
+    if playing_note == Channel::UNUSED {
+        return;
+    }
+    channel.playing_notes[key as usize] = Channel::UNUSED;
+
+    if playing_note == channel.mono_voice {
+        match channel.highest_held_key(key) {
+            Some(held_key) => {
+                if settings.transpose_key(key) == Some(channel.target_key) {
+                    if let Some(held_pitch_key) = settings.transpose_key(held_key) {
+                        let updates = glide_updates::<U_FREQ>(channel);
+                        channel.start_glide(held_pitch_key, updates, tuning);
+                        notes.set_pitch_ratio_at(playing_note as usize, channel.glide_ratio());
+                    }
+                }
+                return;
+            }
+            None => channel.mono_voice = Channel::UNUSED,
+        }
+    }
+    notes.trigger_note_off_at(playing_note as usize);
+}
+
+//
+// Release everything on a channel, for the mono and poly mode messages.
+//
+fn all_notes_off<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const MAX_NOTES: usize,
+    const NO_SCALEDOWN: bool,
+>(
+    channel: &mut Channel,
+    notes: &mut AmpAdder<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>,
+) {
+    for key in 0..128 {
+        let playing_note = channel.playing_notes[key];
+        if playing_note != Channel::UNUSED && playing_note != channel.mono_voice {
+            notes.trigger_note_off_at(playing_note as usize);
+        }
+        channel.playing_notes[key] = Channel::UNUSED;
+    }
+    if channel.mono_voice != Channel::UNUSED {
+        notes.trigger_note_off_at(channel.mono_voice as usize);
+        channel.mono_voice = Channel::UNUSED;
+    }
+}
+
+fn glide_updates<const U_FREQ: u32>(channel: &Channel) -> i32 {
+    if channel.portamento {
+        time_to_ticks::<U_FREQ>(Channel::portamento_time_ms(channel.portamento_time))
+    } else {
+        0
+    }
+}
+
+//
+// Mono mode note on, for file_key in the file.  note_init has the transposed
+// key the voice plays.  Returns false if the note needs a voice of its own.
+//
+fn mono_note_on<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const MAX_NOTES: usize,
+    const NO_SCALEDOWN: bool,
+>(
+    file_key: u8,
+    note_init: &SoundSourceNoteInit,
+    channel: &mut Channel,
+    tuning: &Tuning,
+    notes: &mut AmpAdder<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>,
+) -> bool {
+    let voice = channel.mono_voice;
+    if voice == Channel::UNUSED {
+        return false;
+    }
+    let key = note_init.key;
+    if key == channel.target_key {
+        notes.restart_note_at(voice as usize, note_init.velocity);
+    } else {
+        // Legato.  The envelope carries on, only the pitch moves.
+        let updates = glide_updates::<U_FREQ>(channel);
+        channel.start_glide(key, updates, tuning);
+        if !notes.set_pitch_ratio_at(voice as usize, channel.glide_ratio()) {
+            // This instrument can't change pitch, so cut the old note off.
+            all_notes_off(channel, notes);
+            return false;
+        }
+    }
+    channel.playing_notes[file_key as usize] = voice;
+    true
+}
 
 pub fn handle_midi_event<
     const P_FREQ: u32,
@@ -14,54 +130,144 @@ pub fn handle_midi_event<
     channel_u8: u8,
     notes: &mut AmpAdder<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>,
     channels: &mut Channels,
-    program_override: i32,
+    settings: &PlaybackSettings,
 ) {
     let channel: usize = channel_u8 as usize;
     match midi_event {
         midly::MidiMessage::NoteOn { key, vel } => {
             let key_as_u32: u8 = (*key).into();
 
-            if *vel == 0 {
-                let playing_note = channels.channels[channel].playing_notes[key_as_u32 as usize];
-
-                if playing_note != Channel::UNUSED {
-                    notes.trigger_note_off_at(playing_note as usize);
-                    channels.channels[channel].playing_notes[key_as_u32 as usize] = Channel::UNUSED;
-                }
+            if *vel == 0 || !settings.is_audible(channel) {
+                note_off(
+                    key_as_u32,
+                    &mut channels.channels[channel],
+                    &channels.tuning,
+                    notes,
+                    settings,
+                );
             } else {
-                let mut instrument: u8 = channels.channels[channel].current_program;
-                if program_override != -1 {
-                    instrument = program_override as u8;
+                let instrument =
+                    settings.program(channel, channels.channels[channel].current_program);
+
+                // The channel keeps track of notes by the key in the file,
+                // so a transpose change can't leave notes stuck on.
+                let Some(pitch_key) = settings.transpose_key(key_as_u32) else {
+                    return;
+                };
+                let velocity = settings.velocity_curve.apply((*vel).into());
+                let tuning = &channels.tuning;
+                let note_init = SoundSourceNoteInit::new_tuned(
+                    pitch_key,
+                    instrument,
+                    velocity,
+                    tuning.cents(pitch_key),
+                );
+                let channel = &mut channels.channels[channel];
+                let last_key = channel.last_key;
+                channel.last_key = pitch_key;
+                if channel.mono && mono_note_on(key_as_u32, &note_init, channel, tuning, notes) {
+                    return;
                 }
 
-                let note_init = SoundSourceNoteInit::new((*key).into(), instrument, (*vel).into());
-                let playing_note_u8 = channels.channels[channel].playing_notes[key_as_u32 as usize];
+                let playing_note_u8 = channel.playing_notes[key_as_u32 as usize];
                 if playing_note_u8 != Channel::UNUSED {
                     let playing_note = playing_note_u8 as usize;
                     notes.restart_note_at(playing_note, note_init.velocity);
                 } else {
                     let new_note = notes.alloc();
                     notes.new_note_at(new_note, note_init);
-                    channels.channels[channel].playing_notes[key_as_u32 as usize] = new_note as u8;
+                    notes.set_send_at(new_note, REVERB_SEND, channel.reverb_send);
+                    notes.set_send_at(new_note, CHORUS_SEND, channel.chorus_send);
+                    channel.playing_notes[key_as_u32 as usize] = new_note as u8;
+                    if channel.mono {
+                        // Portamento starts from the last note, even if it's over.
+                        let updates = glide_updates::<U_FREQ>(channel);
+                        let from_key = if last_key != Channel::UNUSED && updates != 0 {
+                            last_key
+                        } else {
+                            pitch_key
+                        };
+                        channel.start_mono_voice(new_note as u8, pitch_key, from_key, tuning);
+                        channel.start_glide(pitch_key, updates, tuning);
+                        notes.set_pitch_ratio_at(new_note, channel.glide_ratio());
+                    }
                 }
             }
         }
         midly::MidiMessage::NoteOff { key, vel: _ } => {
-            let key_as_u32: u8 = (*key).into();
-            let playing_note = channels.channels[channel].playing_notes[key_as_u32 as usize];
-
-            if playing_note != Channel::UNUSED {
-                notes.trigger_note_off_at(playing_note as usize);
-                channels.channels[channel].playing_notes[key_as_u32 as usize] = Channel::UNUSED;
-            }
+            note_off(
+                (*key).into(),
+                &mut channels.channels[channel],
+                &channels.tuning,
+                notes,
+                settings,
+            );
         }
         midly::MidiMessage::ProgramChange { program } => {
             channels.channels[channel].current_program = (*program).into();
         }
+        midly::MidiMessage::Controller { controller, value } => {
+            let channel = &mut channels.channels[channel];
+            let value: u8 = (*value).into();
+            match (*controller).into() {
+                5u8 => channel.portamento_time = value,
+                65u8 => channel.portamento = value >= 64,
+                91u8 => channel.reverb_send = value,
+                93u8 => channel.chorus_send = value,
+                126u8 => {
+                    all_notes_off(channel, notes);
+                    channel.mono = true;
+                }
+                127u8 => {
+                    all_notes_off(channel, notes);
+                    channel.mono = false;
+                }
+                _ => {}
+            }
+        }
         _ => {}
     }
 }
 
+///
+/// Release every note on channels that mute and solo have silenced
+///
+pub fn release_inaudible_channels<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const MAX_NOTES: usize,
+    const NO_SCALEDOWN: bool,
+>(
+    notes: &mut AmpAdder<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>,
+    channels: &mut Channels,
+    settings: &PlaybackSettings,
+) {
+    for (idx, channel) in channels.channels.iter_mut().enumerate() {
+        if !settings.is_audible(idx) {
+            all_notes_off(channel, notes);
+        }
+    }
+}
+
+///
+/// Move any portamento glides along.  Call once per update.
+///
+pub fn update_glides<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const MAX_NOTES: usize,
+    const NO_SCALEDOWN: bool,
+>(
+    notes: &mut AmpAdder<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>,
+    channels: &mut Channels,
+) {
+    for channel in channels.channels.iter_mut() {
+        if channel.mono_voice != Channel::UNUSED && channel.advance_glide() {
+            notes.set_pitch_ratio_at(channel.mono_voice as usize, channel.glide_ratio());
+        }
+    }
+}
+
 pub fn handle_track_event<
     const P_FREQ: u32,
     const U_FREQ: u32,
@@ -72,14 +278,14 @@ pub fn handle_track_event<
     notes: &mut AmpAdder<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>,
     channels: &mut Channels,
     tempo: &mut MidiTime<P_FREQ, U_FREQ>,
-    program_override: i32,
+    settings: &PlaybackSettings,
 ) -> bool {
     match track_event.kind {
         midly::TrackEventKind::Midi { message, channel } => {
             if channel == 10 {
                 return true;
             }
-            handle_midi_event(&message, channel.into(), notes, channels, program_override)
+            handle_midi_event(&message, channel.into(), notes, channels, settings)
         }
         midly::TrackEventKind::Meta(message) => match message {
             midly::MetaMessage::Tempo(ms_per_qn_midly) => {
@@ -91,7 +297,286 @@ pub fn handle_track_event<
             }
             _ => {}
         },
+        midly::TrackEventKind::SysEx(data) => {
+            channels.tuning.handle_sysex(data);
+        }
         _ => {}
     }
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::amp_adder::AmpAdder;
+    use crate::midi_channels::Channel;
+    use crate::midi_channels::Channels;
+    use crate::midi_events::*;
+    use crate::midi_notes::cents_to_ratio;
+    use crate::sound_source_core::SoundSourceCore;
+    use midly::num::u7;
+
+    type Notes = AmpAdder<24000, 240, 8, true>;
+
+    fn send(notes: &mut Notes, channels: &mut Channels, message: midly::MidiMessage) {
+        handle_midi_event(&message, 0, notes, channels, &PlaybackSettings::DEFAULT);
+    }
+
+    fn note_on(notes: &mut Notes, channels: &mut Channels, key: u8) {
+        let message = midly::MidiMessage::NoteOn {
+            key: u7::new(key),
+            vel: u7::new(100),
+        };
+        send(notes, channels, message);
+    }
+
+    fn note_off(notes: &mut Notes, channels: &mut Channels, key: u8) {
+        let message = midly::MidiMessage::NoteOff {
+            key: u7::new(key),
+            vel: u7::new(0),
+        };
+        send(notes, channels, message);
+    }
+
+    fn controller(notes: &mut Notes, channels: &mut Channels, controller: u8, value: u8) {
+        let message = midly::MidiMessage::Controller {
+            controller: u7::new(controller),
+            value: u7::new(value),
+        };
+        send(notes, channels, message);
+    }
+
+    fn setup() -> (Notes, Channels) {
+        let mut notes = Notes::new(0);
+        let mut channels = Channels::default();
+        send(
+            &mut notes,
+            &mut channels,
+            midly::MidiMessage::ProgramChange {
+                program: u7::new(80),
+            },
+        );
+        (notes, channels)
+    }
+
+    fn update(notes: &mut Notes, channels: &mut Channels, updates: usize) {
+        for _ in 0..updates {
+            update_glides(notes, channels);
+            notes.update();
+        }
+    }
+
+    //
+    // Count zero crossings over a tenth of a second.
+    //
+    fn count_transitions(notes: &mut Notes, channels: &mut Channels) -> u32 {
+        let mut last = 0;
+        let mut transitions = 0;
+        for _ in 0..24 {
+            update(notes, channels, 1);
+            for _ in 0..100 {
+                let current = notes.get_next().to_i32();
+                if (last > 0) != (current > 0) {
+                    transitions += 1;
+                }
+                last = current;
+            }
+        }
+        transitions
+    }
+
+    #[test]
+    fn poly_mode_should_use_a_voice_per_key() {
+        let (mut notes, mut channels) = setup();
+        note_on(&mut notes, &mut channels, 60);
+        note_on(&mut notes, &mut channels, 64);
+        update(&mut notes, &mut channels, 1);
+        assert_eq!(2, notes.get_current_num_mixed_notes());
+    }
+
+    #[test]
+    fn mono_mode_should_move_one_voice() {
+        let (mut notes, mut channels) = setup();
+        controller(&mut notes, &mut channels, 126, 1);
+        note_on(&mut notes, &mut channels, 57);
+        update(&mut notes, &mut channels, 1);
+        let a3 = count_transitions(&mut notes, &mut channels);
+
+        note_on(&mut notes, &mut channels, 69);
+        update(&mut notes, &mut channels, 1);
+        assert_eq!(1, notes.get_current_num_mixed_notes());
+        let a4 = count_transitions(&mut notes, &mut channels);
+        assert!((42..=46).contains(&a3), "{}", a3);
+        assert!((86..=90).contains(&a4), "{}", a4);
+
+        // Letting go of the new key goes back to the one still held...
+        note_off(&mut notes, &mut channels, 69);
+        assert_eq!(0x10000, channels.channels[0].glide_ratio());
+        assert_ne!(Channel::UNUSED, channels.channels[0].mono_voice);
+
+        // ... and letting go of that one ends the note.
+        note_off(&mut notes, &mut channels, 57);
+        assert_eq!(Channel::UNUSED, channels.channels[0].mono_voice);
+    }
+
+    #[test]
+    fn portamento_should_glide_over_the_portamento_time() {
+        let (mut notes, mut channels) = setup();
+        controller(&mut notes, &mut channels, 126, 1);
+        controller(&mut notes, &mut channels, 65, 127);
+        // 800ms
+        controller(&mut notes, &mut channels, 5, 80);
+        note_on(&mut notes, &mut channels, 60);
+        note_on(&mut notes, &mut channels, 72);
+
+        update(&mut notes, &mut channels, 96);
+        let halfway = channels.channels[0].glide_ratio();
+        assert!(
+            (cents_to_ratio(550)..=cents_to_ratio(700)).contains(&halfway),
+            "{:x}",
+            halfway
+        );
+        update(&mut notes, &mut channels, 96);
+        assert_eq!(cents_to_ratio(1200), channels.channels[0].glide_ratio());
+        update(&mut notes, &mut channels, 10);
+        assert_eq!(cents_to_ratio(1200), channels.channels[0].glide_ratio());
+    }
+
+    #[test]
+    fn tuning_should_move_the_pitch() {
+        let (mut notes, mut channels) = setup();
+        channels
+            .tuning
+            .set_reference_pitch(220 * crate::midi_notes::FREQUENCY_MULTIPLIER);
+        note_on(&mut notes, &mut channels, 69);
+        update(&mut notes, &mut channels, 1);
+        let a4_at_a220 = count_transitions(&mut notes, &mut channels);
+        assert!((42..=46).contains(&a4_at_a220), "{}", a4_at_a220);
+    }
+
+    fn note_on_with(notes: &mut Notes, channels: &mut Channels, settings: &PlaybackSettings) {
+        let message = midly::MidiMessage::NoteOn {
+            key: u7::new(57),
+            vel: u7::new(100),
+        };
+        handle_midi_event(&message, 0, notes, channels, settings);
+    }
+
+    #[test]
+    fn transpose_should_move_the_pitch_but_not_the_key() {
+        let (mut notes, mut channels) = setup();
+        let settings = PlaybackSettings {
+            transpose: 12,
+            ..PlaybackSettings::DEFAULT
+        };
+        note_on_with(&mut notes, &mut channels, &settings);
+        update(&mut notes, &mut channels, 1);
+        let a4 = count_transitions(&mut notes, &mut channels);
+        assert!((86..=90).contains(&a4), "{}", a4);
+        assert_ne!(Channel::UNUSED, channels.channels[0].playing_notes[57]);
+    }
+
+    #[test]
+    fn transpose_should_keep_mono_intervals() {
+        let (mut notes, mut channels) = setup();
+        let settings = PlaybackSettings {
+            transpose: 12,
+            ..PlaybackSettings::DEFAULT
+        };
+        let key_message = |key: u8, vel: u8| midly::MidiMessage::NoteOn {
+            key: u7::new(key),
+            vel: u7::new(vel),
+        };
+        controller(&mut notes, &mut channels, 126, 1);
+        handle_midi_event(
+            &key_message(57, 100),
+            0,
+            &mut notes,
+            &mut channels,
+            &settings,
+        );
+        handle_midi_event(
+            &key_message(60, 100),
+            0,
+            &mut notes,
+            &mut channels,
+            &settings,
+        );
+        assert_eq!(cents_to_ratio(300), channels.channels[0].glide_ratio());
+        assert_ne!(Channel::UNUSED, channels.channels[0].playing_notes[60]);
+
+        // Letting go of the new key goes back to the one still held.
+        handle_midi_event(&key_message(60, 0), 0, &mut notes, &mut channels, &settings);
+        assert_eq!(0x10000, channels.channels[0].glide_ratio());
+        assert_ne!(Channel::UNUSED, channels.channels[0].mono_voice);
+    }
+
+    #[test]
+    fn mute_and_solo_should_silence_channels() {
+        let (mut notes, mut channels) = setup();
+        let mut settings = PlaybackSettings {
+            muted: 1,
+            ..PlaybackSettings::DEFAULT
+        };
+        note_on_with(&mut notes, &mut channels, &settings);
+        assert_eq!(Channel::UNUSED, channels.channels[0].playing_notes[57]);
+
+        settings.muted = 0;
+        settings.soloed = 2;
+        note_on_with(&mut notes, &mut channels, &settings);
+        assert_eq!(Channel::UNUSED, channels.channels[0].playing_notes[57]);
+
+        settings.soloed = 3;
+        note_on_with(&mut notes, &mut channels, &settings);
+        assert_ne!(Channel::UNUSED, channels.channels[0].playing_notes[57]);
+
+        settings.muted = 1;
+        release_inaudible_channels(&mut notes, &mut channels, &settings);
+        assert_eq!(Channel::UNUSED, channels.channels[0].playing_notes[57]);
+    }
+
+    #[test]
+    fn channel_programs_should_replace_the_channel_program() {
+        let settings = PlaybackSettings {
+            channel_programs: [
+                -1, 40, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1, -1,
+            ],
+            ..PlaybackSettings::DEFAULT
+        };
+        assert_eq!(80, settings.program(0, 80));
+        assert_eq!(40, settings.program(1, 80));
+    }
+
+    fn send_level(reverb_send: u8) -> i32 {
+        let (mut notes, mut channels) = setup();
+        controller(&mut notes, &mut channels, 91, reverb_send);
+        note_on(&mut notes, &mut channels, 69);
+        let mut level = 0;
+        for _ in 0..24 {
+            update(&mut notes, &mut channels, 1);
+            for _ in 0..100 {
+                notes.get_next();
+                level += notes.get_send(REVERB_SEND).to_i32().abs();
+            }
+        }
+        level
+    }
+
+    #[test]
+    fn reverb_send_should_follow_cc91() {
+        assert_eq!(0, send_level(0));
+        assert!(send_level(40) > 0);
+        assert!(send_level(127) > send_level(40) * 3);
+    }
+
+    #[test]
+    fn poly_mode_message_should_release_mono_notes() {
+        let (mut notes, mut channels) = setup();
+        controller(&mut notes, &mut channels, 126, 1);
+        note_on(&mut notes, &mut channels, 60);
+        note_on(&mut notes, &mut channels, 62);
+        controller(&mut notes, &mut channels, 127, 0);
+        assert_eq!(Channel::UNUSED, channels.channels[0].mono_voice);
+        assert_eq!(Channel::UNUSED, channels.channels[0].playing_notes[60]);
+        assert_eq!(Channel::UNUSED, channels.channels[0].playing_notes[62]);
+    }
+}
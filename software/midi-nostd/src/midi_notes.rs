@@ -26,11 +26,80 @@ pub fn midi_note_to_freq(midi_note: u8) -> u32 {
     freq
 }
 
+// 2^(n/12) for each semitone in an octave, in 16.16 fixed point.
+const SEMITONE_RATIOS: [u32; 12] = [
+    65536, 69433, 73562, 77936, 82570, 87480, 92682, 98193, 104032, 110218, 116772, 123715,
+];
+
+// ln(2) / 1200, the natural log of one cent, with 30 bits of fraction.
+const LN_CENT: i64 = 620218;
+
+///
+/// Frequency ratio for a pitch change in cents, in 16.16 fixed point.
+///
+/// Whole octaves are shifts and whole semitones come from a table.  What's
+/// left is under a semitone, where a few terms of the series for e^x are
+/// plenty accurate.
+///
+pub const fn cents_to_ratio(cents: i32) -> u32 {
+    let octaves = cents.div_euclid(1200);
+    let within_octave = cents.rem_euclid(1200);
+    let semitone = (within_octave / 100) as usize;
+    let x = (within_octave % 100) as i64 * LN_CENT;
+    let x_squared = (x * x) >> 30;
+    let x_cubed = (x_squared * x) >> 30;
+    let fine = (1 << 30) + x + x_squared / 2 + x_cubed / 6;
+    let ratio = ((SEMITONE_RATIOS[semitone] as i64) * fine) >> 30;
+    if octaves >= 0 {
+        (ratio << octaves) as u32
+    } else {
+        (ratio >> -octaves) as u32
+    }
+}
+
+///
+/// Move a frequency (already multiplied by FREQUENCY_MULTIPLIER) by some
+/// number of cents.  100 cents is a semitone.
+///
+pub fn detune_cents(frequency: u32, cents: i32) -> u32 {
+    (((frequency as u64) * (cents_to_ratio(cents) as u64)) >> 16) as u32
+}
+
+///
+/// The inverse of cents_to_ratio.  Pitch change in cents for a 16.16
+/// frequency ratio, to the nearest cent.
+///
+pub const fn ratio_to_cents(ratio: u32) -> i32 {
+    if ratio == 0 {
+        return i32::MIN;
+    }
+    // cents_to_ratio only goes up, so search for the closest cent.  Past
+    // +/- 16 octaves the ratio doesn't fit in 16.16 anyway.
+    let mut low: i32 = -16 * 1200;
+    let mut high: i32 = 15 * 1200;
+    while high - low > 1 {
+        let mid = (low + high) / 2;
+        if cents_to_ratio(mid) > ratio {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    if ratio - cents_to_ratio(low) > cents_to_ratio(high) - ratio {
+        high
+    } else {
+        low
+    }
+}
+
 #[cfg(test)]
 
 mod tests {
 
+    use crate::midi_notes::cents_to_ratio;
+    use crate::midi_notes::detune_cents;
     use crate::midi_notes::midi_note_to_freq;
+    use crate::midi_notes::ratio_to_cents;
     use crate::midi_notes::FREQUENCY_MULTIPLIER;
 
     #[test]
@@ -45,4 +114,41 @@ mod tests {
         assert_eq!(2750, midi_note_to_freq(21)); // A0 (27.5 Hz * 100)
         assert_eq!(98777, midi_note_to_freq(83)); // B5 (987.77 * 100)
     }
+
+    #[test]
+    fn cents_should_scale_frequencies() {
+        assert_eq!(0x10000, cents_to_ratio(0));
+        assert_eq!(0x20000, cents_to_ratio(1200));
+        assert_eq!(0x8000, cents_to_ratio(-1200));
+        assert_eq!(44000, detune_cents(44000, 0));
+        assert_eq!(88000, detune_cents(44000, 1200));
+
+        // A semitone up or down should land on the neighbouring notes.
+        for key in 1..127 {
+            let up = detune_cents(midi_note_to_freq(key), 100) as i32;
+            let down = detune_cents(midi_note_to_freq(key), -100) as i32;
+            let above = midi_note_to_freq(key + 1) as i32;
+            let below = midi_note_to_freq(key - 1) as i32;
+            assert!((up - above).abs() <= above / 1000 + 1, "{} {}", up, above);
+            assert!(
+                (down - below).abs() <= below / 1000 + 1,
+                "{} {}",
+                down,
+                below
+            );
+        }
+
+        // Fifty cents is halfway, on a log scale.
+        let quarter_tone = detune_cents(44000, 50);
+        assert!((45280..=45300).contains(&quarter_tone), "{}", quarter_tone);
+    }
+
+    #[test]
+    fn ratios_should_convert_back_to_cents() {
+        for cents in (-2400..2400).step_by(7) {
+            assert_eq!(cents, ratio_to_cents(cents_to_ratio(cents)));
+        }
+        // A4 at 415hz is a little under a semitone flat.
+        assert_eq!(-101, ratio_to_cents(41500 * 0x10000 / 44000));
+    }
 }
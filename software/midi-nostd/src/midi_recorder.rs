@@ -0,0 +1,327 @@
+// MIDI recorder.
+//
+// Keeps the channel messages fed to the synth, from buttons or anywhere
+// else, along with when they came, so they can be saved as a Standard MIDI
+// File.  Events go into a slice the caller owns, so a recording takes no
+// more memory than the caller sets aside for it.
+//
+// Times are in ms from whatever clock the caller has; they're turned into
+// ticks when the file is written, at the tempo the caller picks.
+//
+
+use crate::smf_writer::SmfError;
+use crate::smf_writer::SmfFormat;
+use crate::smf_writer::SmfWriter;
+use midly::num::u7;
+use midly::MidiMessage;
+
+/// Ticks per quarter note in written files
+pub const TICKS_PER_QUARTER_NOTE: u16 = 480;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RecordedEvent {
+    /// Since the recording started
+    pub time_ms: u32,
+    pub channel: u8,
+    pub message: MidiMessage,
+}
+
+impl RecordedEvent {
+    /// For filling the buffer before recording
+    pub const EMPTY: Self = Self {
+        time_ms: 0,
+        channel: 0,
+        message: MidiMessage::NoteOff {
+            key: u7::new(0),
+            vel: u7::new(0),
+        },
+    };
+}
+
+///
+/// Records channel messages into a buffer of events
+///
+pub struct MidiRecorder<'buf> {
+    events: &'buf mut [RecordedEvent],
+    len: usize,
+    start_ms: u32,
+    /// Length of a stopped recording
+    duration_ms: u32,
+    recording: bool,
+}
+
+impl<'buf> MidiRecorder<'buf> {
+    pub fn new(events: &'buf mut [RecordedEvent]) -> Self {
+        Self {
+            events,
+            len: 0,
+            start_ms: 0,
+            duration_ms: 0,
+            recording: false,
+        }
+    }
+
+    /// Throw away what's recorded and record from now_ms
+    ///
+    pub fn start(&mut self, now_ms: u32) {
+        self.len = 0;
+        self.start_ms = now_ms;
+        self.duration_ms = 0;
+        self.recording = true;
+    }
+
+    pub fn stop(&mut self, now_ms: u32) {
+        if self.recording {
+            self.duration_ms = now_ms.wrapping_sub(self.start_ms);
+            self.recording = false;
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Keep message, if recording.  Once the buffer's full the rest of the
+    /// recording is dropped, but what's there can still be written.
+    ///
+    pub fn record(
+        &mut self,
+        now_ms: u32,
+        channel: u8,
+        message: &MidiMessage,
+    ) -> Result<(), SmfError> {
+        if !self.recording {
+            return Ok(());
+        }
+        let event = self.events.get_mut(self.len).ok_or(SmfError::BufferFull)?;
+        *event = RecordedEvent {
+            time_ms: now_ms.wrapping_sub(self.start_ms),
+            channel: channel & 0xf,
+            message: *message,
+        };
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn events(&self) -> &[RecordedEvent] {
+        &self.events[..self.len]
+    }
+
+    /// Length of the recording, in ms.  Up to the last event if it's still
+    /// going.
+    ///
+    pub fn duration_ms(&self) -> u32 {
+        let last_event_ms = self.events().last().map_or(0, |event| event.time_ms);
+        core::cmp::max(self.duration_ms, last_event_ms)
+    }
+
+    /// Write the recording to out as an SMF, at us_per_quarter_note.  A
+    /// MultiTrack file gets a tempo track and then a track per channel.
+    /// Notes still down at the end are let go there.  The tempo is kept to
+    /// what an SMF can hold, 1 to 0xffffff.
+    ///
+    pub fn write_smf<'out>(
+        &self,
+        out: &'out mut [u8],
+        format: SmfFormat,
+        us_per_quarter_note: u32,
+    ) -> Result<&'out [u8], SmfError> {
+        let us_per_quarter_note = us_per_quarter_note.clamp(1, 0xff_ffff);
+        let to_ticks = |time_ms: u32| {
+            ((time_ms as u64) * 1000 * (TICKS_PER_QUARTER_NOTE as u64)
+                / (us_per_quarter_note as u64))
+                .min(u32::MAX as u64) as u32
+        };
+        let end = to_ticks(self.duration_ms());
+
+        let mut writer = SmfWriter::new(out, format, TICKS_PER_QUARTER_NOTE)?;
+        writer.start_track()?;
+        writer.tempo(0, us_per_quarter_note)?;
+        match format {
+            SmfFormat::SingleTrack => {
+                self.write_events(&mut writer, None, to_ticks, end)?;
+            }
+            SmfFormat::MultiTrack => {
+                writer.end_track(end)?;
+                let mut used_channels = 0u16;
+                for event in self.events() {
+                    used_channels |= 1 << event.channel;
+                }
+                for channel in (0..16).filter(|channel| used_channels & (1 << channel) != 0) {
+                    writer.start_track()?;
+                    self.write_events(&mut writer, Some(channel), to_ticks, end)?;
+                }
+            }
+        }
+        writer.finish()
+    }
+
+    // Write the events on channel, or on every channel, and end the track
+    fn write_events(
+        &self,
+        writer: &mut SmfWriter,
+        channel: Option<u8>,
+        to_ticks: impl Fn(u32) -> u32,
+        end: u32,
+    ) -> Result<(), SmfError> {
+        // Keys down, a bit per key per channel
+        let mut held = [0u128; 16];
+        let events = self
+            .events()
+            .iter()
+            .filter(|event| channel.map_or(true, |channel| channel == event.channel));
+        for event in events {
+            let keys = &mut held[event.channel as usize];
+            match event.message {
+                MidiMessage::NoteOn { key, vel } if vel.as_int() > 0 => *keys |= 1 << key.as_int(),
+                MidiMessage::NoteOn { key, .. } | MidiMessage::NoteOff { key, .. } => {
+                    *keys &= !(1 << key.as_int())
+                }
+                _ => {}
+            }
+            writer.midi_event(to_ticks(event.time_ms), event.channel, &event.message)?;
+        }
+
+        for (channel, keys) in held.iter().enumerate() {
+            for key in (0..128).filter(|key| keys & (1 << key) != 0) {
+                let note_off = MidiMessage::NoteOff {
+                    key: u7::new(key),
+                    vel: u7::new(0),
+                };
+                writer.midi_event(end, channel as u8, &note_off)?;
+            }
+        }
+        writer.end_track(end)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::midi_recorder::*;
+    use midly::TrackEventKind;
+
+    // 480 ticks a quarter note at 125bpm is a tick a ms.
+    const TEMPO: u32 = 480000;
+
+    fn note(key: u8, vel: u8) -> MidiMessage {
+        MidiMessage::NoteOn {
+            key: u7::new(key),
+            vel: u7::new(vel),
+        }
+    }
+
+    // Each track's channel messages, as (time in ticks, channel, message)
+    fn parse(file: &[u8]) -> Vec<Vec<(u32, u8, MidiMessage)>> {
+        let (_, tracks) = midly::parse(file).unwrap();
+        tracks
+            .map(|track| {
+                let mut time = 0;
+                let mut messages = Vec::new();
+                for event in track.unwrap() {
+                    let event = event.unwrap();
+                    time += event.delta.as_int();
+                    if let TrackEventKind::Midi { channel, message } = event.kind {
+                        messages.push((time, channel.as_int(), message));
+                    }
+                }
+                messages
+            })
+            .collect()
+    }
+
+    #[test]
+    fn recordings_should_write_one_track() {
+        let mut events = [RecordedEvent::EMPTY; 8];
+        let mut recorder = MidiRecorder::new(&mut events);
+        recorder.record(0, 0, &note(1, 1)).unwrap();
+        assert!(recorder.is_empty());
+
+        recorder.start(5000);
+        let program = MidiMessage::ProgramChange {
+            program: u7::new(10),
+        };
+        recorder.record(5000, 0, &program).unwrap();
+        recorder.record(5100, 0, &note(60, 100)).unwrap();
+        recorder.record(5400, 0, &note(60, 0)).unwrap();
+        recorder.record(5500, 0, &note(64, 100)).unwrap();
+        recorder.stop(6000);
+        assert_eq!(1000, recorder.duration_ms());
+
+        let mut buffer = [0u8; 128];
+        let file = recorder
+            .write_smf(&mut buffer, SmfFormat::SingleTrack, TEMPO)
+            .unwrap();
+        let tracks = parse(file);
+        assert_eq!(
+            vec![vec![
+                (0, 0, program),
+                (100, 0, note(60, 100)),
+                (400, 0, note(60, 0)),
+                (500, 0, note(64, 100)),
+                // Still down at the end
+                (
+                    1000,
+                    0,
+                    MidiMessage::NoteOff {
+                        key: u7::new(64),
+                        vel: u7::new(0)
+                    }
+                ),
+            ]],
+            tracks
+        );
+
+        // Far too quick, but no divide by zero
+        let file = recorder
+            .write_smf(&mut buffer, SmfFormat::SingleTrack, 0)
+            .unwrap();
+        assert_eq!(5, parse(file)[0].len());
+    }
+
+    #[test]
+    fn channels_should_get_their_own_tracks() {
+        let mut events = [RecordedEvent::EMPTY; 3];
+        let mut recorder = MidiRecorder::new(&mut events);
+        recorder.start(0);
+        recorder.record(10, 9, &note(36, 100)).unwrap();
+        recorder.record(20, 2, &note(60, 100)).unwrap();
+        recorder.record(30, 2, &note(60, 0)).unwrap();
+        assert_eq!(
+            Err(SmfError::BufferFull),
+            recorder.record(40, 2, &note(62, 100))
+        );
+        assert_eq!(3, recorder.len());
+
+        let mut buffer = [0u8; 128];
+        let file = recorder
+            .write_smf(&mut buffer, SmfFormat::MultiTrack, TEMPO * 2)
+            .unwrap();
+        let tracks = parse(file);
+        assert_eq!(3, tracks.len());
+        // The tempo track
+        assert!(tracks[0].is_empty());
+        // Half the ticks at half the tempo
+        assert_eq!(
+            vec![(10, 2, note(60, 100)), (15, 2, note(60, 0))],
+            tracks[1]
+        );
+        assert_eq!((5, 9, note(36, 100)), tracks[2][0]);
+        assert_eq!(15, tracks[2][1].0);
+
+        let mut small_buffer = [0u8; 40];
+        assert_eq!(
+            Err(SmfError::BufferFull),
+            recorder
+                .write_smf(&mut small_buffer, SmfFormat::MultiTrack, TEMPO)
+                .map(|_| ())
+        );
+    }
+}
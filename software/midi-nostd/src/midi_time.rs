@@ -1,8 +1,27 @@
 use crate::sound_sample::U32Fraction;
+use midly::Timing;
+
+/// Microseconds per quarter note that SMPTE timed files play at
+pub const SMPTE_US_PER_QUARTER_NOTE: u32 = 1000000;
+
+/// Ticks per quarter note for a file's timing.  SMPTE timed files count
+/// frames and subframes instead of beats, so for them a quarter note is
+/// taken to be a second.
+///
+pub fn ticks_per_quarter_note(timing: Timing) -> u32 {
+    let ticks = match timing {
+        Timing::Metrical(ticks) => ticks.as_int() as u32,
+        Timing::Timecode(fps, subframes) => (fps.as_int() as u32) * (subframes as u32),
+    };
+    core::cmp::max(ticks, 1)
+}
 
 pub struct MidiTime<const P_FREQ: u32, const U_FREQ: u32> {
     current_ms_per_quarter_note: u32,
     ticks_per_quarter_note: u32,
+    /// SMPTE timing, where tempo changes don't apply
+    fixed_tempo: bool,
+    tempo_percent: u32,
     midi_event_update_rate: U32Fraction<U_FREQ>,
     current_time: U32Fraction<U_FREQ>,
 }
@@ -20,9 +39,11 @@ impl<const P_FREQ: u32, const U_FREQ: u32> MidiTime<P_FREQ, U_FREQ> {
         // than the midi playback, so I can fast forward through the track to get
         // a good maximum output voltage.
         //
-        let midi_events_per_second: u32 = (1000000u64 * (self.ticks_per_quarter_note as u64)
-            / (self.current_ms_per_quarter_note as u64))
-            as u32;
+        // Tempo scaling speeds that up or slows it down by tempo_percent / 100.
+        //
+        let midi_events_per_second: u32 =
+            (1000000u64 * (self.ticks_per_quarter_note as u64) * (self.tempo_percent as u64)
+                / ((self.current_ms_per_quarter_note as u64) * 100)) as u32;
         let midi_events_per_sample = midi_events_per_second / U_FREQ;
         let midi_events_per_sample_remainder = midi_events_per_second % U_FREQ;
 
@@ -30,14 +51,33 @@ impl<const P_FREQ: u32, const U_FREQ: u32> MidiTime<P_FREQ, U_FREQ> {
             U32Fraction::new(midi_events_per_sample, midi_events_per_sample_remainder);
     }
     pub fn set_ms_per_quarter_note(self: &mut Self, current_ms_per_quarter_note: u32) {
+        if self.fixed_tempo {
+            return;
+        }
         self.current_ms_per_quarter_note = current_ms_per_quarter_note;
         self.compute_midi_events_per_second();
     }
 
+    /// Play at tempo_percent of the file's tempo
+    ///
+    pub fn set_tempo_percent(&mut self, tempo_percent: u32) {
+        self.tempo_percent = core::cmp::max(tempo_percent, 1);
+        self.compute_midi_events_per_second();
+    }
+
+    /// Microseconds per quarter note (despite the name), with the tempo
+    /// percentage applied
+    ///
+    pub fn get_ms_per_quarter_note(&self) -> u32 {
+        ((self.current_ms_per_quarter_note as u64) * 100 / (self.tempo_percent as u64)) as u32
+    }
+
     pub fn new(current_ms_per_quarter_note: u32, ticks_per_quarter_note: u32) -> Self {
         let mut rval = Self {
             current_ms_per_quarter_note,
             ticks_per_quarter_note,
+            fixed_tempo: false,
+            tempo_percent: 100,
             midi_event_update_rate: U32Fraction::new(0, 0),
             current_time: U32Fraction::new(0, 0),
         };
@@ -45,6 +85,20 @@ impl<const P_FREQ: u32, const U_FREQ: u32> MidiTime<P_FREQ, U_FREQ> {
         rval
     }
 
+    /// Timing for a file, starting at 120 bpm if it's counted in beats
+    ///
+    pub fn for_timing(timing: Timing) -> Self {
+        let ticks_per_quarter_note = ticks_per_quarter_note(timing);
+        match timing {
+            Timing::Metrical(_) => Self::new(500000, ticks_per_quarter_note),
+            Timing::Timecode(_, _) => {
+                let mut rval = Self::new(SMPTE_US_PER_QUARTER_NOTE, ticks_per_quarter_note);
+                rval.fixed_tempo = true;
+                rval
+            }
+        }
+    }
+
     pub fn advance_time(self: &mut Self) {
         self.current_time.add(&self.midi_event_update_rate);
     }
@@ -53,3 +107,38 @@ impl<const P_FREQ: u32, const U_FREQ: u32> MidiTime<P_FREQ, U_FREQ> {
         self.current_time.int_part
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::midi_time::*;
+
+    #[test]
+    fn tempo_percent_should_scale_the_tempo() {
+        // 120 bpm, 100 ticks per quarter note: 200 ticks a second.
+        let mut tempo = MidiTime::<1000, 1000>::new(500000, 100);
+        for _ in 0..1000 {
+            tempo.advance_time();
+        }
+        assert!((199..=200).contains(&tempo.get_current_time()));
+
+        tempo.set_tempo_percent(50);
+        for _ in 0..1000 {
+            tempo.advance_time();
+        }
+        assert!((299..=300).contains(&tempo.get_current_time()));
+    }
+
+    #[test]
+    fn smpte_timing_should_count_subframes() {
+        // 25 frames of 40 subframes: 1000 ticks a second
+        let timing = Timing::Timecode(midly::Fps::Fps25, 40);
+        assert_eq!(1000, ticks_per_quarter_note(timing));
+        let mut tempo = MidiTime::<1000, 1000>::for_timing(timing);
+        // Tempo events don't apply.
+        tempo.set_ms_per_quarter_note(250000);
+        for _ in 0..1000 {
+            tempo.advance_time();
+        }
+        assert!((999..=1000).contains(&tempo.get_current_time()));
+    }
+}
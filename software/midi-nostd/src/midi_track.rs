@@ -2,6 +2,7 @@ use crate::amp_adder::AmpAdder;
 use crate::midi_channels::Channels;
 use crate::midi_events::*;
 use crate::midi_time::MidiTime;
+use crate::playback_settings::PlaybackSettings;
 
 pub struct MidiTrack<
     'a,
@@ -42,20 +43,58 @@ impl<
         return !self.last_event.is_none();
     }
 
+    /// Events still to come, soonest first, with the time each one is due
+    /// in ticks.  Looking ahead doesn't move the track on.
+    ///
+    pub fn upcoming_events(&self) -> impl Iterator<Item = (u32, midly::TrackEventKind<'a>)> + 'a {
+        let first = match &self.last_event {
+            Some(Ok(event)) => Some((self.next_event_time, event.kind)),
+            _ => None,
+        };
+        let mut time = self.next_event_time;
+        let rest = self
+            .event_iter
+            .clone()
+            .map_while(|event| event.ok())
+            .map(move |event| {
+                time += u32::from(event.delta);
+                (time, event.kind)
+            });
+        // Nothing's left after the end of the track, or a bad event.
+        let rest = first.is_some().then_some(rest);
+        first.into_iter().chain(rest.into_iter().flatten())
+    }
+
+    /// Move past the events before time without playing them
+    ///
+    pub fn skip_to(&mut self, time: u32) {
+        while self.next_event_time < time {
+            match self.event_iter.next() {
+                Some(Ok(event)) => {
+                    self.next_event_time += u32::from(event.delta);
+                    self.last_event = Some(Ok(event));
+                }
+                _ => {
+                    self.last_event = None;
+                    return;
+                }
+            }
+        }
+    }
+
     pub fn update(
         self: &mut Self,
         notes: &mut AmpAdder<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>,
         channels: &mut Channels,
         tempo: &mut MidiTime<P_FREQ, U_FREQ>,
-        program_override: i32,
+        settings: &PlaybackSettings,
     ) {
         if !self.has_next() {
             return;
         }
         while tempo.get_current_time() >= self.next_event_time {
             let track_event = self.last_event.as_ref().unwrap().as_ref().unwrap();
-            let end_of_track =
-                handle_track_event(&track_event, notes, channels, tempo, program_override);
+            let end_of_track = handle_track_event(&track_event, notes, channels, tempo, settings);
 
             if !end_of_track {
                 self.last_event = self.event_iter.next();
@@ -72,3 +111,43 @@ impl<
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::midi_track::*;
+
+    type TestTrack<'a> = MidiTrack<'a, 1, 1, 1, false>;
+
+    fn note_on_times(track: &TestTrack) -> Vec<u32> {
+        track
+            .upcoming_events()
+            .filter(|(_, kind)| {
+                matches!(
+                    kind,
+                    midly::TrackEventKind::Midi {
+                        message: midly::MidiMessage::NoteOn { .. },
+                        ..
+                    }
+                )
+            })
+            .map(|(time, _)| time)
+            .collect()
+    }
+
+    #[test]
+    fn looking_ahead_should_not_move_the_track() {
+        let (_, mut tracks) = midly::parse(include_bytes!("../assets/twinkle.mid"))
+            .expect("It's inlined data, so it better work, gosh darn it");
+        let mut track = TestTrack::new(tracks.next().unwrap().unwrap());
+        let times = note_on_times(&track);
+        assert_eq!(84, times.len());
+        assert_eq!(&[0, 0, 384, 384], &times[..4]);
+        assert_eq!(times, note_on_times(&track));
+
+        track.skip_to(384);
+        assert_eq!(&[384, 384, 768], &note_on_times(&track)[..3]);
+        track.skip_to(u32::MAX);
+        assert!(!track.has_next());
+        assert_eq!(0, track.upcoming_events().count());
+    }
+}
@@ -0,0 +1,327 @@
+// Modal synthesis for tuned percussion.
+//
+// Anything that's struck, a bar, a tine or a drum head, rings at a handful
+// of resonant frequencies (modes) that generally aren't harmonics of each
+// other.  Each mode is a sine wave with its own level and its own
+// exponential decay.  The higher modes usually die first, so the note starts
+// bright and mellows out.
+//
+// The modes themselves are patch data, supplied by a type implementing
+// ModalPatch.
+//
+
+use crate::midi_notes::cents_to_ratio;
+use crate::midi_notes::FREQUENCY_MULTIPLIER;
+use crate::note::SoundSourceNoteInit;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::SoundSourceCore;
+use crate::wave_tables::SINE_WAVE;
+use core::marker::PhantomData;
+
+/// Most modes an instrument can have
+///
+pub const MAX_MODES: usize = 4;
+
+/// One resonant mode
+///
+#[derive(Clone, Copy)]
+pub struct Mode {
+    /// Frequency relative to the note, in 1/1000ths.  1000 is the note itself.
+    pub ratio: u32,
+    /// Level, as a percent of full scale.  0 for an unused mode.
+    pub level: u8,
+    /// Time for the mode to fall to about a third of its level, in ms
+    pub decay: u32,
+}
+
+impl Mode {
+    pub const UNUSED: Self = Self {
+        ratio: 1000,
+        level: 0,
+        decay: 1,
+    };
+}
+
+/// Patch data for a modal percussion instrument
+///
+pub trait ModalPatch {
+    /// The modes, fundamental first.  Levels should add up to 100 or less.
+    const MODES: [Mode; MAX_MODES];
+
+    /// How far sharp the note starts, in cents.  Drum heads are tighter
+    /// while they're moving a lot, so the pitch drops as the note dies away.
+    const PITCH_DROP_CENTS: i32 = 0;
+
+    /// Time constant for the pitch drop, in ms
+    const PITCH_DROP_TIME: u32 = 1;
+
+    /// Time constant for every mode once the note is released, in ms.  Long
+    /// decays with a short release are mallets over a damper.
+    const RELEASE: u32;
+}
+
+//
+// Per update multiplier, in 1/0x8000ths, for an exponential decay with a
+// time constant of time_in_ms.
+//
+const fn decay_factor(time_in_ms: u32, update_frequency: u32) -> i32 {
+    let updates = (time_in_ms as i64) * (update_frequency as i64) / 1000;
+    if updates <= 1 {
+        0
+    } else {
+        (0x8000 - 0x8000 / updates) as i32
+    }
+}
+
+///
+/// Modal percussion voice
+///
+/// Velocity sets the volume and, by favouring the upper modes on harder
+/// hits, the brightness.
+///
+pub struct ModalPercussion<const P_FREQ: u32, const U_FREQ: u32, Patch: ModalPatch> {
+    table_idx: [u32; MAX_MODES],
+    base_table_idx_inc: [u32; MAX_MODES],
+    table_idx_inc: [u32; MAX_MODES],
+    levels: [i32; MAX_MODES],
+    bend: i32,
+    releasing: bool,
+    _marker: PhantomData<Patch>,
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, Patch: ModalPatch>
+    ModalPercussion<P_FREQ, U_FREQ, Patch>
+{
+    const DECAYS: [i32; MAX_MODES] = {
+        let mut decays = [0; MAX_MODES];
+        let mut idx = 0;
+        while idx < MAX_MODES {
+            decays[idx] = decay_factor(Patch::MODES[idx].decay, U_FREQ);
+            idx += 1;
+        }
+        decays
+    };
+    const RELEASE_DECAY: i32 = decay_factor(Patch::RELEASE, U_FREQ);
+    const BEND_DECAY: i32 = decay_factor(Patch::PITCH_DROP_TIME, U_FREQ);
+
+    // Starting pitch bend, as a fraction of the frequency in 1/0x10000ths.
+    const BEND_START: i32 = (cents_to_ratio(Patch::PITCH_DROP_CENTS) as i32) - 0x10000;
+
+    // Modes this close to nyquist alias, so they're dropped.
+    const MAX_MODE_FREQUENCY: u64 = (P_FREQ as u64) * (FREQUENCY_MULTIPLIER as u64) * 45 / 100;
+
+    // The voice is done when every mode is below this.
+    const QUIET_LEVEL: i32 = 0x10;
+
+    fn strike(&mut self, vel: u8) {
+        let volume = (vel as i32) << 8;
+        let mut brightness = 0x8000;
+        for mode in 0..MAX_MODES {
+            let level = if self.base_table_idx_inc[mode] != 0 {
+                (Patch::MODES[mode].level as i32) * 0x8000 / 100
+            } else {
+                0
+            };
+            self.levels[mode] = (((level * volume) >> 15) * brightness) >> 15;
+            // Soft hits lose their upper modes quickly.
+            brightness = (brightness * volume) >> 15;
+        }
+        self.bend = Self::BEND_START;
+        self.releasing = false;
+        self.apply_bend();
+    }
+
+    fn apply_bend(&mut self) {
+        for mode in 0..MAX_MODES {
+            let base = self.base_table_idx_inc[mode] as i64;
+            self.table_idx_inc[mode] = (base + ((base * (self.bend as i64)) >> 16)) as u32;
+        }
+    }
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, Patch: ModalPatch> SoundSourceCore<P_FREQ, U_FREQ>
+    for ModalPercussion<P_FREQ, U_FREQ, Patch>
+{
+    type InitValuesType = SoundSourceNoteInit;
+
+    fn new(init_values: Self::InitValuesType) -> Self {
+        let frequency = init_values.frequency(0) as u64;
+        let mut base_table_idx_inc = [0; MAX_MODES];
+        for (inc, mode) in base_table_idx_inc.iter_mut().zip(Patch::MODES) {
+            let mode_frequency = frequency * (mode.ratio as u64) / 1000;
+            if mode.level != 0 && mode_frequency < Self::MAX_MODE_FREQUENCY {
+                *inc = (((1u64 << 32) * mode_frequency)
+                    / ((FREQUENCY_MULTIPLIER as u64) * (P_FREQ as u64)))
+                    as u32;
+            }
+        }
+        let mut rval = Self {
+            table_idx: [0; MAX_MODES],
+            base_table_idx_inc,
+            table_idx_inc: base_table_idx_inc,
+            levels: [0; MAX_MODES],
+            bend: 0,
+            releasing: false,
+            _marker: PhantomData,
+        };
+        rval.strike(init_values.velocity);
+        rval
+    }
+
+    #[inline]
+    fn get_next(&mut self) -> SoundSampleI32 {
+        let mut sum: i32 = 0;
+        for mode in 0..MAX_MODES {
+            if self.levels[mode] != 0 {
+                self.table_idx[mode] = self.table_idx[mode].wrapping_add(self.table_idx_inc[mode]);
+                sum += (SINE_WAVE[(self.table_idx[mode] >> 22) as usize] * self.levels[mode]) >> 15;
+            }
+        }
+        SoundSampleI32::new_i32(sum).clip()
+    }
+
+    fn update(&mut self) {
+        for mode in 0..MAX_MODES {
+            let decay = if self.releasing {
+                core::cmp::min(Self::DECAYS[mode], Self::RELEASE_DECAY)
+            } else {
+                Self::DECAYS[mode]
+            };
+            self.levels[mode] = (self.levels[mode] * decay) >> 15;
+        }
+        if self.bend != 0 {
+            self.bend = (self.bend * Self::BEND_DECAY) >> 15;
+            self.apply_bend();
+        }
+    }
+
+    fn has_next(&self) -> bool {
+        self.levels.iter().any(|level| *level > Self::QUIET_LEVEL)
+    }
+
+    fn trigger_note_off(&mut self) {
+        self.releasing = true;
+    }
+
+    fn reset_oscillator(&mut self) {
+        self.table_idx = [0; MAX_MODES];
+    }
+
+    fn restart(&mut self, vel: u8) {
+        self.strike(vel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::modal_percussion::*;
+
+    struct TestBar {}
+
+    impl ModalPatch for TestBar {
+        const MODES: [Mode; MAX_MODES] = [
+            Mode {
+                ratio: 1000,
+                level: 50,
+                decay: 1000,
+            },
+            Mode {
+                ratio: 4000,
+                level: 50,
+                decay: 100,
+            },
+            Mode::UNUSED,
+            Mode::UNUSED,
+        ];
+        const RELEASE: u32 = 50;
+    }
+
+    struct TestDrum {}
+
+    impl ModalPatch for TestDrum {
+        const MODES: [Mode; MAX_MODES] = [
+            Mode {
+                ratio: 1000,
+                level: 100,
+                decay: 2000,
+            },
+            Mode::UNUSED,
+            Mode::UNUSED,
+            Mode::UNUSED,
+        ];
+        const PITCH_DROP_CENTS: i32 = 1200;
+        const PITCH_DROP_TIME: u32 = 100;
+        const RELEASE: u32 = 2000;
+    }
+
+    type Bar = ModalPercussion<24000, 240, TestBar>;
+    type Drum = ModalPercussion<24000, 240, TestDrum>;
+
+    //
+    // Count zero crossings over a tenth of a second.
+    //
+    fn count_transitions<T>(source: &mut T) -> u32
+    where
+        T: SoundSourceCore<24000, 240>,
+    {
+        let mut last = source.get_next();
+        let mut transitions: u32 = 0;
+        for _ in 1..2400 {
+            let current = source.get_next();
+            if (last.to_i32() > 0) != (current.to_i32() > 0) {
+                transitions += 1;
+            }
+            last = current;
+        }
+        transitions
+    }
+
+    #[test]
+    fn upper_modes_should_die_first() {
+        // A4, with a 1760hz upper mode.
+        let mut bar = Bar::new(SoundSourceNoteInit::new(69, 0, 127));
+        let bright = count_transitions(&mut bar);
+        for _ in 0..120 {
+            bar.update();
+        }
+        let mellow = count_transitions(&mut bar);
+        assert!(bright > 200, "{}", bright);
+        assert!((86..=90).contains(&mellow), "{}", mellow);
+    }
+
+    #[test]
+    fn soft_hits_should_be_darker() {
+        let loud = Bar::new(SoundSourceNoteInit::new(69, 0, 127));
+        let soft = Bar::new(SoundSourceNoteInit::new(69, 0, 32));
+        let loud_ratio = loud.levels[1] * 100 / loud.levels[0];
+        let soft_ratio = soft.levels[1] * 100 / soft.levels[0];
+        assert!(loud_ratio > 95);
+        assert!(soft_ratio < 30);
+    }
+
+    #[test]
+    fn pitch_should_drop_after_the_strike() {
+        let mut drum = Drum::new(SoundSourceNoteInit::new(57, 0, 127));
+        assert_eq!(drum.base_table_idx_inc[0] * 2, drum.table_idx_inc[0]);
+        for _ in 0..120 {
+            drum.update();
+        }
+        let settled = count_transitions(&mut drum);
+        assert!((43..=45).contains(&settled), "{}", settled);
+    }
+
+    #[test]
+    fn note_should_finish_sooner_after_release() {
+        let mut held = Bar::new(SoundSourceNoteInit::new(69, 0, 127));
+        let mut released = Bar::new(SoundSourceNoteInit::new(69, 0, 127));
+        released.trigger_note_off();
+        let mut updates = 0;
+        while released.has_next() {
+            released.update();
+            held.update();
+            updates += 1;
+        }
+        assert!(held.has_next());
+        assert!(updates < 240);
+    }
+}
@@ -0,0 +1,31 @@
+use crate::modal_percussion::ModalPatch;
+use crate::modal_percussion::ModalPercussion;
+use crate::modal_percussion::Mode;
+use crate::modal_percussion::MAX_MODES;
+
+//
+// A comb tine is a bar clamped at one end, with its first overtone up at
+// 6.27 times the fundamental.
+//
+pub struct MusicBoxModes {}
+
+impl ModalPatch for MusicBoxModes {
+    const MODES: [Mode; MAX_MODES] = [
+        Mode {
+            ratio: 1000,
+            level: 70,
+            decay: 1200,
+        },
+        Mode {
+            ratio: 6267,
+            level: 30,
+            decay: 150,
+        },
+        Mode::UNUSED,
+        Mode::UNUSED,
+    ];
+    const RELEASE: u32 = 1200;
+}
+
+pub type MusicBox<const P_FREQ: u32, const U_FREQ: u32> =
+    ModalPercussion<P_FREQ, U_FREQ, MusicBoxModes>;
@@ -1,22 +1,46 @@
+use crate::accordion::Accordion;
 use crate::bass::Bass;
+use crate::bells::Bells;
 use crate::cello::Cello;
 use crate::choir::Choir;
+use crate::dulcimer::Dulcimer;
 use crate::electric_piano::ElectricPiano;
 use crate::french_horn::FrenchHorn;
+use crate::glockenspiel::Glockenspiel;
 use crate::guitar_acoustic::GuitarAcoustic;
+use crate::guitar_distortion::GuitarDistortion;
+use crate::guitar_overdrive::GuitarOverdrive;
+use crate::harmonica::Harmonica;
+use crate::harp::Harp;
+use crate::marimba::Marimba;
+use crate::midi_notes::detune_cents;
+use crate::midi_notes::midi_note_to_freq;
+use crate::music_box::MusicBox;
 use crate::oboe::Oboe;
+use crate::organ::Organ;
+use crate::organ_church::OrganChurch;
+use crate::organ_percussive::OrganPercussive;
 use crate::piano::Piano;
+use crate::pizzicato_strings::PizzicatoStrings;
+use crate::sampled::Sampled;
 use crate::sax::Sax;
 use crate::silence::Silence;
 use crate::sound_sample::SoundSampleI32;
 use crate::sound_source_core::SoundSourceCore;
+use crate::synth_lead::SynthLead;
+use crate::tango_accordion::TangoAccordion;
+use crate::timpani::Timpani;
+use crate::vibraphone::Vibraphone;
 use crate::violin::Violin;
+use crate::xylophone::Xylophone;
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct SoundSourceNoteInit {
     pub key: u8,
     pub instrument: u8,
     pub velocity: u8,
+    /// Tuning offset from equal temperament at A440, in cents
+    pub cents: i16,
 }
 
 impl SoundSourceNoteInit {
@@ -25,8 +49,26 @@ impl SoundSourceNoteInit {
             key,
             instrument,
             velocity,
+            cents: 0,
         };
     }
+
+    pub fn new_tuned(key: u8, instrument: u8, velocity: u8, cents: i16) -> Self {
+        Self {
+            key,
+            instrument,
+            velocity,
+            cents,
+        }
+    }
+
+    /// Frequency, with tuning, of the key semitones above (or below) this
+    /// one.  Multiplied by FREQUENCY_MULTIPLIER, like midi_note_to_freq.
+    ///
+    pub fn frequency(&self, semitones: i8) -> u32 {
+        let key = ((self.key as i16) + (semitones as i16)).clamp(0, 127) as u8;
+        detune_cents(midi_note_to_freq(key), self.cents as i32)
+    }
 }
 
 pub enum NoteEnum<const P_FREQ: u32, const U_FREQ: u32> {
@@ -63,6 +105,66 @@ pub enum NoteEnum<const P_FREQ: u32, const U_FREQ: u32> {
     OboeEnum {
         pcore: Oboe<P_FREQ, U_FREQ>,
     },
+    BellsEnum {
+        pcore: Bells<P_FREQ, U_FREQ>,
+    },
+    MarimbaEnum {
+        pcore: Marimba<P_FREQ, U_FREQ>,
+    },
+    HarpEnum {
+        pcore: Harp<P_FREQ, U_FREQ>,
+    },
+    DulcimerEnum {
+        pcore: Dulcimer<P_FREQ, U_FREQ>,
+    },
+    PizzicatoStringsEnum {
+        pcore: PizzicatoStrings<P_FREQ, U_FREQ>,
+    },
+    GuitarOverdriveEnum {
+        pcore: GuitarOverdrive<P_FREQ, U_FREQ>,
+    },
+    GuitarDistortionEnum {
+        pcore: GuitarDistortion<P_FREQ, U_FREQ>,
+    },
+    SynthLeadEnum {
+        pcore: SynthLead<P_FREQ, U_FREQ>,
+    },
+    OrganEnum {
+        pcore: Organ<P_FREQ, U_FREQ>,
+    },
+    OrganPercussiveEnum {
+        pcore: OrganPercussive<P_FREQ, U_FREQ>,
+    },
+    OrganChurchEnum {
+        pcore: OrganChurch<P_FREQ, U_FREQ>,
+    },
+    AccordionEnum {
+        pcore: Accordion<P_FREQ, U_FREQ>,
+    },
+    HarmonicaEnum {
+        pcore: Harmonica<P_FREQ, U_FREQ>,
+    },
+    TangoAccordionEnum {
+        pcore: TangoAccordion<P_FREQ, U_FREQ>,
+    },
+    GlockenspielEnum {
+        pcore: Glockenspiel<P_FREQ, U_FREQ>,
+    },
+    MusicBoxEnum {
+        pcore: MusicBox<P_FREQ, U_FREQ>,
+    },
+    VibraphoneEnum {
+        pcore: Vibraphone<P_FREQ, U_FREQ>,
+    },
+    XylophoneEnum {
+        pcore: Xylophone<P_FREQ, U_FREQ>,
+    },
+    TimpaniEnum {
+        pcore: Timpani<P_FREQ, U_FREQ>,
+    },
+    SampledEnum {
+        pcore: Sampled<P_FREQ, U_FREQ>,
+    },
     Unassigned,
 }
 
@@ -99,6 +201,26 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
             NoteEnum::BassEnum { pcore } => pcore.get_next(),
             NoteEnum::SaxEnum { pcore } => pcore.get_next(),
             NoteEnum::OboeEnum { pcore } => pcore.get_next(),
+            NoteEnum::BellsEnum { pcore } => pcore.get_next(),
+            NoteEnum::MarimbaEnum { pcore } => pcore.get_next(),
+            NoteEnum::HarpEnum { pcore } => pcore.get_next(),
+            NoteEnum::DulcimerEnum { pcore } => pcore.get_next(),
+            NoteEnum::PizzicatoStringsEnum { pcore } => pcore.get_next(),
+            NoteEnum::GuitarOverdriveEnum { pcore } => pcore.get_next(),
+            NoteEnum::GuitarDistortionEnum { pcore } => pcore.get_next(),
+            NoteEnum::SynthLeadEnum { pcore } => pcore.get_next(),
+            NoteEnum::OrganEnum { pcore } => pcore.get_next(),
+            NoteEnum::OrganPercussiveEnum { pcore } => pcore.get_next(),
+            NoteEnum::OrganChurchEnum { pcore } => pcore.get_next(),
+            NoteEnum::AccordionEnum { pcore } => pcore.get_next(),
+            NoteEnum::HarmonicaEnum { pcore } => pcore.get_next(),
+            NoteEnum::TangoAccordionEnum { pcore } => pcore.get_next(),
+            NoteEnum::GlockenspielEnum { pcore } => pcore.get_next(),
+            NoteEnum::MusicBoxEnum { pcore } => pcore.get_next(),
+            NoteEnum::VibraphoneEnum { pcore } => pcore.get_next(),
+            NoteEnum::XylophoneEnum { pcore } => pcore.get_next(),
+            NoteEnum::TimpaniEnum { pcore } => pcore.get_next(),
+            NoteEnum::SampledEnum { pcore } => pcore.get_next(),
             NoteEnum::Unassigned => SoundSampleI32::ZERO,
         }
     }
@@ -116,6 +238,26 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
             NoteEnum::BassEnum { pcore } => pcore.update(),
             NoteEnum::SaxEnum { pcore } => pcore.update(),
             NoteEnum::OboeEnum { pcore } => pcore.update(),
+            NoteEnum::BellsEnum { pcore } => pcore.update(),
+            NoteEnum::MarimbaEnum { pcore } => pcore.update(),
+            NoteEnum::HarpEnum { pcore } => pcore.update(),
+            NoteEnum::DulcimerEnum { pcore } => pcore.update(),
+            NoteEnum::PizzicatoStringsEnum { pcore } => pcore.update(),
+            NoteEnum::GuitarOverdriveEnum { pcore } => pcore.update(),
+            NoteEnum::GuitarDistortionEnum { pcore } => pcore.update(),
+            NoteEnum::SynthLeadEnum { pcore } => pcore.update(),
+            NoteEnum::OrganEnum { pcore } => pcore.update(),
+            NoteEnum::OrganPercussiveEnum { pcore } => pcore.update(),
+            NoteEnum::OrganChurchEnum { pcore } => pcore.update(),
+            NoteEnum::AccordionEnum { pcore } => pcore.update(),
+            NoteEnum::HarmonicaEnum { pcore } => pcore.update(),
+            NoteEnum::TangoAccordionEnum { pcore } => pcore.update(),
+            NoteEnum::GlockenspielEnum { pcore } => pcore.update(),
+            NoteEnum::MusicBoxEnum { pcore } => pcore.update(),
+            NoteEnum::VibraphoneEnum { pcore } => pcore.update(),
+            NoteEnum::XylophoneEnum { pcore } => pcore.update(),
+            NoteEnum::TimpaniEnum { pcore } => pcore.update(),
+            NoteEnum::SampledEnum { pcore } => pcore.update(),
             NoteEnum::Unassigned => {}
         }
     }
@@ -133,6 +275,26 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
             NoteEnum::BassEnum { pcore } => pcore.has_next(),
             NoteEnum::SaxEnum { pcore } => pcore.has_next(),
             NoteEnum::OboeEnum { pcore } => pcore.has_next(),
+            NoteEnum::BellsEnum { pcore } => pcore.has_next(),
+            NoteEnum::MarimbaEnum { pcore } => pcore.has_next(),
+            NoteEnum::HarpEnum { pcore } => pcore.has_next(),
+            NoteEnum::DulcimerEnum { pcore } => pcore.has_next(),
+            NoteEnum::PizzicatoStringsEnum { pcore } => pcore.has_next(),
+            NoteEnum::GuitarOverdriveEnum { pcore } => pcore.has_next(),
+            NoteEnum::GuitarDistortionEnum { pcore } => pcore.has_next(),
+            NoteEnum::SynthLeadEnum { pcore } => pcore.has_next(),
+            NoteEnum::OrganEnum { pcore } => pcore.has_next(),
+            NoteEnum::OrganPercussiveEnum { pcore } => pcore.has_next(),
+            NoteEnum::OrganChurchEnum { pcore } => pcore.has_next(),
+            NoteEnum::AccordionEnum { pcore } => pcore.has_next(),
+            NoteEnum::HarmonicaEnum { pcore } => pcore.has_next(),
+            NoteEnum::TangoAccordionEnum { pcore } => pcore.has_next(),
+            NoteEnum::GlockenspielEnum { pcore } => pcore.has_next(),
+            NoteEnum::MusicBoxEnum { pcore } => pcore.has_next(),
+            NoteEnum::VibraphoneEnum { pcore } => pcore.has_next(),
+            NoteEnum::XylophoneEnum { pcore } => pcore.has_next(),
+            NoteEnum::TimpaniEnum { pcore } => pcore.has_next(),
+            NoteEnum::SampledEnum { pcore } => pcore.has_next(),
             NoteEnum::Unassigned => false,
         }
     }
@@ -150,6 +312,26 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
             NoteEnum::BassEnum { pcore } => pcore.trigger_note_off(),
             NoteEnum::SaxEnum { pcore } => pcore.trigger_note_off(),
             NoteEnum::OboeEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::BellsEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::MarimbaEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::HarpEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::DulcimerEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::PizzicatoStringsEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::GuitarOverdriveEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::GuitarDistortionEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::SynthLeadEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::OrganEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::OrganPercussiveEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::OrganChurchEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::AccordionEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::HarmonicaEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::TangoAccordionEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::GlockenspielEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::MusicBoxEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::VibraphoneEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::XylophoneEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::TimpaniEnum { pcore } => pcore.trigger_note_off(),
+            NoteEnum::SampledEnum { pcore } => pcore.trigger_note_off(),
             NoteEnum::Unassigned => {}
         }
     }
@@ -167,10 +349,67 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
             NoteEnum::BassEnum { pcore } => pcore.restart(vel),
             NoteEnum::SaxEnum { pcore } => pcore.restart(vel),
             NoteEnum::OboeEnum { pcore } => pcore.restart(vel),
+            NoteEnum::BellsEnum { pcore } => pcore.restart(vel),
+            NoteEnum::MarimbaEnum { pcore } => pcore.restart(vel),
+            NoteEnum::HarpEnum { pcore } => pcore.restart(vel),
+            NoteEnum::DulcimerEnum { pcore } => pcore.restart(vel),
+            NoteEnum::PizzicatoStringsEnum { pcore } => pcore.restart(vel),
+            NoteEnum::GuitarOverdriveEnum { pcore } => pcore.restart(vel),
+            NoteEnum::GuitarDistortionEnum { pcore } => pcore.restart(vel),
+            NoteEnum::SynthLeadEnum { pcore } => pcore.restart(vel),
+            NoteEnum::OrganEnum { pcore } => pcore.restart(vel),
+            NoteEnum::OrganPercussiveEnum { pcore } => pcore.restart(vel),
+            NoteEnum::OrganChurchEnum { pcore } => pcore.restart(vel),
+            NoteEnum::AccordionEnum { pcore } => pcore.restart(vel),
+            NoteEnum::HarmonicaEnum { pcore } => pcore.restart(vel),
+            NoteEnum::TangoAccordionEnum { pcore } => pcore.restart(vel),
+            NoteEnum::GlockenspielEnum { pcore } => pcore.restart(vel),
+            NoteEnum::MusicBoxEnum { pcore } => pcore.restart(vel),
+            NoteEnum::VibraphoneEnum { pcore } => pcore.restart(vel),
+            NoteEnum::XylophoneEnum { pcore } => pcore.restart(vel),
+            NoteEnum::TimpaniEnum { pcore } => pcore.restart(vel),
+            NoteEnum::SampledEnum { pcore } => pcore.restart(vel),
             NoteEnum::Unassigned => {}
         }
     }
 
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        match &mut self.core {
+            NoteEnum::PianoEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::ElectricPianoEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::GuitarAcousticEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::SilenceEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::CelloEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::ViolinEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::ChoirEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::FrenchHornEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::BassEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::SaxEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::OboeEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::BellsEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::MarimbaEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::HarpEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::DulcimerEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::PizzicatoStringsEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::GuitarOverdriveEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::GuitarDistortionEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::SynthLeadEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::OrganEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::OrganPercussiveEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::OrganChurchEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::AccordionEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::HarmonicaEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::TangoAccordionEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::GlockenspielEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::MusicBoxEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::VibraphoneEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::XylophoneEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::TimpaniEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::SampledEnum { pcore } => pcore.set_pitch_ratio(ratio),
+            NoteEnum::Unassigned => false,
+        }
+    }
+
     fn new(init_values: Self::InitValuesType) -> Self {
         let instrument = init_values.instrument;
 
@@ -184,24 +423,91 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
                 let pcore = Piano::<P_FREQ, U_FREQ>::new(init_values);
                 NoteEnum::<P_FREQ, U_FREQ>::PianoEnum { pcore }
             }
-            6 => {
+            4..=6 => {
+                // Electric Piano 1 and 2.  6 is the Harpsichord, which the
+                // electric piano has been standing in for.
                 let pcore = ElectricPiano::<P_FREQ, U_FREQ>::new(init_values);
                 NoteEnum::<P_FREQ, U_FREQ>::ElectricPianoEnum { pcore }
             }
-            16 => {
+            8 | 14 => {
+                // Celesta, Tubular Bells
+                let pcore = Bells::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::BellsEnum { pcore }
+            }
+            9 => {
+                // Glockenspiel
+                let pcore = Glockenspiel::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::GlockenspielEnum { pcore }
+            }
+            10 => {
+                // Music Box
+                let pcore = MusicBox::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::MusicBoxEnum { pcore }
+            }
+            11 => {
+                // Vibraphone
+                let pcore = Vibraphone::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::VibraphoneEnum { pcore }
+            }
+            12 => {
+                // Marimba
+                let pcore = Marimba::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::MarimbaEnum { pcore }
+            }
+            13 => {
+                // Xylophone
+                let pcore = Xylophone::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::XylophoneEnum { pcore }
+            }
+            15 => {
                 // Dulcimer
-                let pcore = Silence::<P_FREQ, U_FREQ>::new(init_values);
-                NoteEnum::<P_FREQ, U_FREQ>::SilenceEnum { pcore }
+                let pcore = Dulcimer::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::DulcimerEnum { pcore }
+            }
+            16 | 18 => {
+                // Drawbar and Rock Organ
+                let pcore = Organ::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::OrganEnum { pcore }
+            }
+            17 => {
+                // Percussive Organ
+                let pcore = OrganPercussive::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::OrganPercussiveEnum { pcore }
+            }
+            19 | 20 => {
+                // Church and Reed Organ
+                let pcore = OrganChurch::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::OrganChurchEnum { pcore }
             }
-            24 => {
-                // "Tango Accordian".  TODO
+            21 => {
+                // Accordion
+                let pcore = Accordion::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::AccordionEnum { pcore }
+            }
+            22 => {
+                // Harmonica
+                let pcore = Harmonica::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::HarmonicaEnum { pcore }
+            }
+            23 => {
+                // Tango Accordion
+                let pcore = TangoAccordion::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::TangoAccordionEnum { pcore }
+            }
+            24 | 25 => {
+                // Nylon and Steel String Acoustic Guitar
                 let pcore = GuitarAcoustic::<P_FREQ, U_FREQ>::new(init_values);
                 NoteEnum::<P_FREQ, U_FREQ>::GuitarAcousticEnum { pcore }
             }
+            29 => {
+                // Overdriven Guitar
+                let pcore = GuitarOverdrive::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::GuitarOverdriveEnum { pcore }
+            }
             30 => {
-                // Distortion Guitar.  TODO
-                let pcore = GuitarAcoustic::<P_FREQ, U_FREQ>::new(init_values);
-                NoteEnum::<P_FREQ, U_FREQ>::GuitarAcousticEnum { pcore }
+                // Distortion Guitar
+                let pcore = GuitarDistortion::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::GuitarDistortionEnum { pcore }
             }
             33 => {
                 // Bass
@@ -218,10 +524,25 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
                 let pcore = Cello::<P_FREQ, U_FREQ>::new(init_values);
                 NoteEnum::<P_FREQ, U_FREQ>::CelloEnum { pcore }
             }
-            48 => {
+            45 => {
+                // Pizzicato Strings
+                let pcore = PizzicatoStrings::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::PizzicatoStringsEnum { pcore }
+            }
+            46 => {
+                // Orchestral Harp
+                let pcore = Harp::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::HarpEnum { pcore }
+            }
+            47 => {
                 // Timpani
-                let pcore = Silence::<P_FREQ, U_FREQ>::new(init_values);
-                NoteEnum::<P_FREQ, U_FREQ>::SilenceEnum { pcore }
+                let pcore = Timpani::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::TimpaniEnum { pcore }
+            }
+            48 => {
+                // String Ensemble.  This was labelled Timpani, which is 47.
+                let pcore = Violin::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::ViolinEnum { pcore }
             }
             52 => {
                 //Choir
@@ -253,8 +574,24 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
                 let pcore = Oboe::<P_FREQ, U_FREQ>::new(init_values);
                 NoteEnum::<P_FREQ, U_FREQ>::OboeEnum { pcore }
             }
+            80 | 81 => {
+                // Square and Sawtooth Lead
+                let pcore = SynthLead::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::SynthLeadEnum { pcore }
+            }
+            _ if Sampled::<P_FREQ, U_FREQ>::plays(instrument) => {
+                // Converted from SoundFonts
+                let pcore = Sampled::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::SampledEnum { pcore }
+            }
+            _ if instrument <= 127 => {
+                // No instrument for this program yet, so play it on the
+                // piano, General MIDI's first program.
+                let pcore = Piano::<P_FREQ, U_FREQ>::new(init_values);
+                NoteEnum::<P_FREQ, U_FREQ>::PianoEnum { pcore }
+            }
             _ => {
-                assert_eq!(0, instrument);
+                // Not a General MIDI program
                 let pcore = Silence::<P_FREQ, U_FREQ>::new(init_values);
                 NoteEnum::<P_FREQ, U_FREQ>::SilenceEnum { pcore }
             }
@@ -0,0 +1,21 @@
+use crate::drawbar_organ::DrawbarOrgan;
+use crate::drawbar_organ::Percussion;
+use crate::velocity::VelocityResponse;
+use crate::velocity::VelocityShape;
+
+//
+// Organ keys are switches, so velocity is ignored.
+//
+pub type OrganChurch<const P_FREQ: u32, const U_FREQ: u32> = VelocityResponse<
+    P_FREQ,
+    U_FREQ,
+    DrawbarOrgan<
+        P_FREQ,
+        U_FREQ,
+        868868446,                    // Drawbars, 16' to 1'.  Full, pipe organ like
+        { Percussion::Off as usize }, // Percussion
+        false,                        // Key click, pipes don't have one
+    >,
+    { VelocityShape::Linear as usize }, // Velocity shape
+    0,                                  // Velocity sensitivity
+>;
@@ -44,6 +44,7 @@ pub struct CoreOscillator<
 > {
     table_idx: u32,
     table_idx_inc: u32,
+    base_table_idx_inc: u32,
     max_amplitude: SoundSampleI32,
 }
 
@@ -73,9 +74,11 @@ impl<
     type InitValuesType = u32;
 
     fn new(frequency: Self::InitValuesType) -> Self {
+        let table_idx_inc = (((1u64 << 32) * (frequency as u64)) / Self::INC_DENOMINATOR) as u32;
         Self {
             table_idx: 0,
-            table_idx_inc: (((1u64 << 32) * (frequency as u64)) / Self::INC_DENOMINATOR) as u32,
+            table_idx_inc,
+            base_table_idx_inc: table_idx_inc,
             max_amplitude: Self::VOLUME_SCALE,
         }
     }
@@ -110,6 +113,11 @@ impl<
     }
 
     fn restart(self: &mut Self, _vel: u8) {}
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.table_idx_inc = (((self.base_table_idx_inc as u64) * (ratio as u64)) >> 16) as u32;
+        true
+    }
 }
 
 impl<
@@ -1,3 +1,4 @@
+use crate::adsr::EnvelopeCurve;
 use crate::instrument_low_pass_filters::PianoLowPassCalculator;
 use crate::instrument_template_basic::InstrumentTemplateBasic;
 use crate::oscillator::OscillatorType;
@@ -18,5 +19,7 @@ pub type Piano<const P_FREQ: u32, const U_FREQ: u32> = InstrumentTemplateBasic<
     300,                                     // D
     20,                                      // S
     500,                                     // R
+    { EnvelopeCurve::Exponential as usize }, // D curve
+    { EnvelopeCurve::Exponential as usize }, // R curve
     PianoLowPassCalculator,
 >;
@@ -0,0 +1,10 @@
+use crate::plucked_string::Excitation;
+use crate::plucked_string::PluckedString;
+
+pub type PizzicatoStrings<const P_FREQ: u32, const U_FREQ: u32> = PluckedString<
+    P_FREQ,
+    U_FREQ,
+    { Excitation::Pulse as usize }, // Excitation
+    985,                            // Damping
+    900,                            // Release damping
+>;
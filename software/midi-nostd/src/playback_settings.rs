@@ -0,0 +1,66 @@
+use crate::velocity::VelocityCurve;
+
+///
+/// Player wide settings that change how events are turned into notes
+///
+#[derive(Clone, Copy, Debug)]
+pub struct PlaybackSettings {
+    /// Play every note on a channel with this program instead of the
+    /// channel's own.  -1 for none.
+    pub channel_programs: [i32; 16],
+    /// Applied to the velocity of every note on
+    pub velocity_curve: VelocityCurve,
+    /// Semitones to move every note by
+    pub transpose: i8,
+    /// Playback speed, as a percentage of the file's tempo
+    pub tempo_percent: u32,
+    /// Channels that aren't played, one bit per channel
+    pub muted: u16,
+    /// If any bits are set, only these channels are played
+    pub soloed: u16,
+}
+
+impl PlaybackSettings {
+    pub const DEFAULT: Self = Self {
+        channel_programs: [-1; 16],
+        velocity_curve: VelocityCurve::LINEAR,
+        transpose: 0,
+        tempo_percent: 100,
+        muted: 0,
+        soloed: 0,
+    };
+
+    /// Program to play notes on channel with, given the channel's current one
+    ///
+    pub fn program(&self, channel: usize, current_program: u8) -> u8 {
+        match self.channel_programs[channel] {
+            -1 => current_program,
+            program => program as u8,
+        }
+    }
+
+    /// Should notes on channel be heard, with mute and solo applied
+    ///
+    pub fn is_audible(&self, channel: usize) -> bool {
+        let bit = 1u16 << channel;
+        (self.muted & bit) == 0 && (self.soloed == 0 || (self.soloed & bit) != 0)
+    }
+
+    /// Key to play for key, with transpose applied.  None if it's off the
+    /// end of the keyboard.
+    ///
+    pub fn transpose_key(&self, key: u8) -> Option<u8> {
+        let transposed = (key as i16) + (self.transpose as i16);
+        if (0..128).contains(&transposed) {
+            Some(transposed as u8)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
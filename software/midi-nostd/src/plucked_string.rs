@@ -0,0 +1,434 @@
+// Karplus-Strong plucked string.
+//
+// A short delay line is filled with a burst of energy (the pluck) and then
+// played back over and over, running each pass through an averaging filter
+// that takes a little off the top.  The high harmonics die first, just like
+// a real string.
+//
+// Memory is the problem.  Every note in the AmpAdder is as big as the biggest
+// instrument in NoteEnum, so a delay line long enough for a low E (about 250
+// samples at 20292hz) would cost 500 bytes in every one of the 64 voices.
+// Instead the delay line is capped at PLUCKED_STRING_LENGTH entries and low
+// notes run the string at a divided down sample rate, interpolating the
+// output back up to the playback rate.  We lose some top end on the low notes,
+// which is roughly what a guitar does anyway.
+//
+
+use crate::midi_notes::midi_note_to_freq;
+use crate::midi_notes::FREQUENCY_MULTIPLIER;
+use crate::note::SoundSourceNoteInit;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::SoundSourceCore;
+
+/// Length of the delay line, in samples.  128 bytes per voice.
+///
+pub const PLUCKED_STRING_LENGTH: usize = 64;
+
+/// How the string gets its initial energy
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(usize)]
+pub enum Excitation {
+    /// White noise, softened for quiet notes.  Bright, guitar like.
+    Noise,
+    /// A triangle shaped pluck.  Rounder, harp or pizzicato like.
+    Pulse,
+}
+
+impl Excitation {
+    const fn from_usize(usize_value: usize) -> Self {
+        match usize_value {
+            0 => Self::Noise,
+            1 => Self::Pulse,
+            2_usize.. => todo!(),
+        }
+    }
+}
+
+///
+/// Plucked string voice
+///
+/// DAMPING is the loop gain in 1/1000ths for a string tuned to middle C.  996
+/// rings for a second or two.  Other notes have their loop gain adjusted so
+/// they lose about the same amount per second, otherwise the short strings
+/// that go round the loop more often would die almost instantly.
+/// RELEASE_DAMPING replaces DAMPING once the key is released, so 900 mutes
+/// the string almost immediately and something close to DAMPING lets it ring
+/// out.
+///
+pub struct PluckedString<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const EXCITATION: usize,
+    const DAMPING: u32,
+    const RELEASE_DAMPING: u32,
+> {
+    delay_line: [i16; PLUCKED_STRING_LENGTH],
+    delay_line_len: u8,
+    pos: u8,
+    divider: u8,
+    divider_count: u8,
+    quiet_updates: u8,
+    key: u8,
+    allpass_coefficient: i16,
+    stretch: i16,
+    allpass_last_in: i32,
+    allpass_last_out: i32,
+    last_tuned: i32,
+    loop_gain: i32,
+    output: i32,
+    output_step: i32,
+    peak: i32,
+    seed: u32,
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        const EXCITATION: usize,
+        const DAMPING: u32,
+        const RELEASE_DAMPING: u32,
+    > PluckedString<P_FREQ, U_FREQ, EXCITATION, DAMPING, RELEASE_DAMPING>
+{
+    const EXCITATION_ENUM: Excitation = Excitation::from_usize(EXCITATION);
+    // Middle C, the note DAMPING and RELEASE_DAMPING are given for.
+    const REFERENCE_FREQUENCY: u32 = 26163;
+
+    // A string that stays below this level for QUIET_UPDATES updates is done.
+    const QUIET_LEVEL: i32 = 0x20;
+    const QUIET_UPDATES: u8 = 4;
+
+    //
+    // Loop gain for the string, in 1/0x8000ths.  The loss per trip round the
+    // loop scales with the period of the note, so every note decays at
+    // roughly the same rate.
+    //
+    fn loop_gain(damping: u32, key: u8) -> i32 {
+        let loss = (1000 - damping.min(1000)) * 0x8000 / 1000;
+        let scaled_loss = (loss as u64) * (Self::REFERENCE_FREQUENCY as u64)
+            / (midi_note_to_freq(key) as u64).max(1);
+        0x8000 - (scaled_loss.min(0x8000) as i32)
+    }
+
+    fn random(&mut self) -> i32 {
+        // xorshift32.  Good enough for noise.
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        (self.seed >> 16) as i32 - 0x8000
+    }
+
+    //
+    // Add a pluck into the delay line.  Adding rather than replacing means a
+    // restrike on a ringing string behaves like a real one.
+    //
+    fn excite(&mut self, vel: u8) {
+        let amplitude = (vel as i32) << 8;
+        let len = self.delay_line_len as i32;
+        // Softer notes get a smoother (darker) burst of noise.
+        let smoothing = 0x8000 - ((vel as i32) << 8);
+        let mut smoothed = 0;
+        let mut total = 0;
+
+        for idx in 0..len {
+            let shape = match Self::EXCITATION_ENUM {
+                Excitation::Noise => {
+                    let noise = self.random();
+                    smoothed = noise + (((smoothed - noise) * smoothing) >> 15);
+                    smoothed
+                }
+                Excitation::Pulse => {
+                    // Plucked a quarter of the way along the string.
+                    let peak = len / 4 + 1;
+                    let triangle = if idx < peak {
+                        0xffff * idx / peak
+                    } else {
+                        0xffff * (len - idx) / (len - peak)
+                    };
+                    triangle - 0x7fff
+                }
+            };
+            let pluck = (shape * amplitude) >> 15;
+            total += pluck;
+            let entry = &mut self.delay_line[idx as usize];
+            *entry = ((*entry as i32) + pluck).clamp(-0x7fff, 0x7fff) as i16;
+        }
+
+        //
+        // Take out any DC offset the pluck added.  The damping filter
+        // doesn't touch DC, so it would hang around long after the note has
+        // died, keeping the voice busy and the speaker pushed out.
+        //
+        let offset = total / len;
+        for entry in self.delay_line[0..len as usize].iter_mut() {
+            *entry = ((*entry as i32) - offset).clamp(-0x7fff, 0x7fff) as i16;
+        }
+    }
+
+    //
+    // Advance the string by one of its own samples.
+    //
+    #[inline]
+    fn tick(&mut self) -> i32 {
+        let delayed = self.delay_line[self.pos as usize] as i32;
+
+        //
+        // First order all pass filter for the fractional part of the delay.
+        // Unlike linear interpolation it doesn't dull the sound, which matters
+        // on the short high strings that go round the loop a thousand times
+        // a second.
+        //
+        let tuned = ((self.allpass_coefficient as i32) * (delayed - self.allpass_last_out))
+            / 0x8000
+            + self.allpass_last_in;
+        self.allpass_last_in = delayed;
+        self.allpass_last_out = tuned;
+
+        // The damping filter, a weighted average of this sample and the last.
+        let filtered = tuned + ((self.last_tuned - tuned) * (self.stretch as i32)) / 0x8000;
+        self.last_tuned = tuned;
+
+        //
+        // Dividing rounds towards zero, so the string always dies out instead
+        // of getting stuck on a small DC offset.
+        //
+        let out = ((filtered * self.loop_gain) / 0x8000).clamp(-0x7fff, 0x7fff);
+        self.delay_line[self.pos as usize] = out as i16;
+        self.pos += 1;
+        if self.pos == self.delay_line_len {
+            self.pos = 0;
+        }
+        out
+    }
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        const EXCITATION: usize,
+        const DAMPING: u32,
+        const RELEASE_DAMPING: u32,
+    > SoundSourceCore<P_FREQ, U_FREQ>
+    for PluckedString<P_FREQ, U_FREQ, EXCITATION, DAMPING, RELEASE_DAMPING>
+{
+    type InitValuesType = SoundSourceNoteInit;
+
+    fn new(init_values: Self::InitValuesType) -> Self {
+        let frequency = init_values.frequency(0) as u64;
+
+        //
+        // Period of the note in samples, 16.16 fixed point.  If that doesn't
+        // fit in the delay line, slow the string down until it does.
+        //
+        let period = (((P_FREQ as u64) * (FREQUENCY_MULTIPLIER as u64)) << 16) / frequency;
+        let max_period = (PLUCKED_STRING_LENGTH as u64) << 16;
+        let divider = period.div_ceil(max_period).max(1);
+
+        //
+        // A plain average of the last two samples (a stretch of one half) is
+        // the classic damping filter.  How much it takes off the fundamental
+        // each second goes up with the cube of the note's frequency, so
+        // strings shorter than 48 samples get a lighter filter, scaled by the
+        // cube of their length, to keep the high notes ringing.
+        //
+        let string_period = period / divider;
+        let length_ratio = core::cmp::min(0x8000, string_period * 0x8000 / (48 << 16));
+        let stretch = ((((length_ratio * length_ratio) >> 15) * length_ratio) >> 15) >> 1;
+
+        //
+        // The damping filter delays the loop by the stretch, the delay line
+        // and all pass filter do the rest.  The all pass behaves best with a
+        // delay between 0.1 and 1.1 samples.  A delay line of at least one
+        // keeps the silly cases (like the 240hz loudness pre-scan) from
+        // falling over.
+        //
+        let delay = string_period.saturating_sub(stretch << 1);
+        let mut whole = (delay >> 16) as usize;
+        let mut fraction = delay & 0xffff;
+        if fraction < 0x1999 && whole > 1 {
+            whole -= 1;
+            fraction += 0x10000;
+        }
+        let whole = whole.clamp(1, PLUCKED_STRING_LENGTH);
+        let allpass_coefficient =
+            (((0x10000 - fraction as i64) << 15) / (0x10000 + fraction as i64)) as i32;
+
+        let mut rval = Self {
+            delay_line: [0; PLUCKED_STRING_LENGTH],
+            delay_line_len: whole as u8,
+            pos: 0,
+            divider: divider as u8,
+            divider_count: 0,
+            quiet_updates: 0,
+            key: init_values.key,
+            allpass_coefficient: allpass_coefficient as i16,
+            stretch: stretch as i16,
+            allpass_last_in: 0,
+            allpass_last_out: 0,
+            last_tuned: 0,
+            loop_gain: Self::loop_gain(DAMPING, init_values.key),
+            output: 0,
+            output_step: 0,
+            peak: 0,
+            seed: 0x9e3779b9 ^ (init_values.key as u32),
+        };
+        rval.excite(init_values.velocity);
+        rval
+    }
+
+    #[inline]
+    fn get_next(&mut self) -> SoundSampleI32 {
+        if self.divider == 1 {
+            self.output = self.tick();
+        } else {
+            if self.divider_count == 0 {
+                let target = self.tick();
+                self.output_step = (target - self.output) / (self.divider as i32);
+            }
+            self.divider_count += 1;
+            if self.divider_count == self.divider {
+                self.divider_count = 0;
+            }
+            self.output += self.output_step;
+        }
+        let abs_output = self.output.abs();
+        if abs_output > self.peak {
+            self.peak = abs_output;
+        }
+        SoundSampleI32::new_i32(self.output)
+    }
+
+    fn update(&mut self) {
+        if self.peak < Self::QUIET_LEVEL {
+            self.quiet_updates = self.quiet_updates.saturating_add(1);
+        } else {
+            self.quiet_updates = 0;
+        }
+        self.peak = 0;
+    }
+
+    fn has_next(&self) -> bool {
+        self.quiet_updates < Self::QUIET_UPDATES
+    }
+
+    fn trigger_note_off(&mut self) {
+        self.loop_gain = Self::loop_gain(RELEASE_DAMPING, self.key);
+    }
+
+    fn restart(&mut self, vel: u8) {
+        self.loop_gain = Self::loop_gain(DAMPING, self.key);
+        self.quiet_updates = 0;
+        self.excite(vel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::plucked_string::*;
+
+    type TestString = PluckedString<24000, 24000, { Excitation::Noise as usize }, 996, 900>;
+    type TestPulseString = PluckedString<24000, 24000, { Excitation::Pulse as usize }, 996, 900>;
+
+    //
+    // Estimate the pitch by counting upward zero crossings over a tenth of a
+    // second, once the pluck has had a chance to settle into the string's
+    // fundamental.
+    //
+    fn measure_frequency<T>(string: &mut T) -> i32
+    where
+        T: SoundSourceCore<24000, 24000>,
+    {
+        for _ in 0..240 {
+            string.get_next();
+        }
+        let mut last = string.get_next().to_i32();
+        let mut crossings = 0;
+        for _ in 0..2400 {
+            let current = string.get_next().to_i32();
+            if last < 0 && current >= 0 {
+                crossings += 1;
+            }
+            last = current;
+        }
+        crossings * 10
+    }
+
+    #[test]
+    fn string_should_play_in_tune() {
+        // A4, short enough that the delay line is used directly
+        let mut a4 = TestPulseString::new(SoundSourceNoteInit::new(69, 0, 127));
+        let a4_freq = measure_frequency(&mut a4);
+        assert!((430..=450).contains(&a4_freq), "{}", a4_freq);
+
+        // A5, where the fractional part of the delay matters most
+        let mut a5 = TestPulseString::new(SoundSourceNoteInit::new(81, 0, 127));
+        let a5_freq = measure_frequency(&mut a5);
+        assert!((870..=890).contains(&a5_freq), "{}", a5_freq);
+
+        // A2, which needs the divided down string
+        let mut a2 = TestPulseString::new(SoundSourceNoteInit::new(45, 0, 127));
+        let a2_freq = measure_frequency(&mut a2);
+        assert!((100..=120).contains(&a2_freq), "{}", a2_freq);
+    }
+
+    #[test]
+    fn high_strings_should_keep_ringing() {
+        let mut a6 = TestString::new(SoundSourceNoteInit::new(93, 0, 127));
+        for _ in 0..7200 {
+            a6.get_next();
+        }
+        let mut peak = 0;
+        for _ in 0..240 {
+            peak = core::cmp::max(peak, a6.get_next().to_i32().abs());
+        }
+        assert!(peak > 0x800, "{}", peak);
+        let a6_freq = measure_frequency(&mut a6);
+        assert!((1720..=1800).contains(&a6_freq), "{}", a6_freq);
+    }
+
+    #[test]
+    fn string_should_decay_and_finish_after_note_off() {
+        let mut string = TestString::new(SoundSourceNoteInit::new(60, 0, 127));
+        for _ in 0..240 {
+            string.get_next();
+        }
+        string.update();
+        assert!(string.has_next());
+
+        string.trigger_note_off();
+        let mut updates = 0;
+        while string.has_next() {
+            for _ in 0..240 {
+                string.get_next();
+            }
+            string.update();
+            updates += 1;
+            assert!(updates < 100);
+        }
+    }
+
+    #[test]
+    fn restart_should_pluck_again() {
+        let mut string = TestString::new(SoundSourceNoteInit::new(60, 0, 127));
+        string.trigger_note_off();
+        while string.has_next() {
+            for _ in 0..240 {
+                string.get_next();
+            }
+            string.update();
+        }
+        string.restart(100);
+        let mut peak = 0;
+        for _ in 0..240 {
+            peak = core::cmp::max(peak, string.get_next().to_i32().abs());
+        }
+        string.update();
+        assert!(string.has_next());
+        assert!(peak > 0x1000);
+    }
+
+    #[test]
+    fn string_should_be_small_enough_for_64_voices() {
+        assert!(core::mem::size_of::<TestString>() <= 176);
+    }
+}
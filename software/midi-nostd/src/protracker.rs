@@ -0,0 +1,787 @@
+// ProTracker modules.
+//
+// A MOD file is a song for the Amiga's four sample channels: 31 instruments
+// of 8 bit samples, and patterns of 64 rows that say, for each channel, which
+// note to play with which instrument and an effect to run while it plays.
+// The order table lists the patterns in the order the song plays them.
+//
+// The song moves on in ticks, 50 a second at the default tempo of 125, and
+// each row lasts speed ticks, 6 to start with.  Notes start on a row's first
+// tick; effects like slides and vibrato move them along on the others.
+//
+// Pitches are Amiga periods, the number of clock cycles between samples, so
+// lower periods play higher.  ModPlayer steps through the samples at the
+// rate each period gives and mixes the four channels with the same fixed
+// point samples as the rest of the crate.
+//
+// Only four channel files tagged M.K., M!K!, FLT4 or 4CHN are played.
+//
+
+use crate::master_volume::MasterVolume;
+use crate::sound_sample::SoundSampleI32;
+
+pub const NUM_SAMPLES: usize = 31;
+pub const NUM_CHANNELS: usize = 4;
+pub const ROWS_PER_PATTERN: usize = 64;
+
+const TITLE_LEN: usize = 20;
+const SAMPLE_HEADER_LEN: usize = 30;
+const SONG_LENGTH_AT: usize = 950;
+const ORDERS_AT: usize = 952;
+const NUM_ORDERS: usize = 128;
+const TAG_AT: usize = 1080;
+const PATTERNS_AT: usize = 1084;
+const CELL_LEN: usize = 4;
+const PATTERN_LEN: usize = ROWS_PER_PATTERN * NUM_CHANNELS * CELL_LEN;
+
+// Paula's clock on a PAL Amiga, halved: samples a second is this / period.
+const AMIGA_CLOCK: u64 = 3546895;
+
+// Limits of ProTracker's period table, which slides are held to
+const MIN_PERIOD: u16 = 113;
+const MAX_PERIOD: u16 = 856;
+
+const MAX_VOLUME: u8 = 64;
+const DEFAULT_SPEED: u8 = 6;
+const DEFAULT_TEMPO: u8 = 125;
+
+// 2^(n/12), 16.16, for arpeggios
+const SEMITONE_RATIOS: [u32; 16] = [
+    65536, 69433, 73562, 77936, 82570, 87480, 92682, 98193, 104032, 110218, 116772, 123715, 131072,
+    138866, 147123, 155872,
+];
+
+// 2^(finetune/96), 16.16, for finetunes 0 to 7 then -8 to -1
+const FINETUNE_RATIOS: [u32; 16] = [
+    65536, 66011, 66489, 66971, 67456, 67945, 68438, 68933, 61858, 62306, 62757, 63212, 63670,
+    64132, 64596, 65065,
+];
+
+// Half a sine wave, from ProTracker's vibrato
+const VIBRATO_TABLE: [u16; 32] = [
+    0, 24, 49, 74, 97, 120, 141, 161, 180, 197, 212, 224, 235, 244, 250, 253, 255, 253, 250, 244,
+    235, 224, 212, 197, 180, 161, 141, 120, 97, 74, 49, 24,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ModError {
+    /// The file ends before its patterns do
+    TooShort,
+    /// Not a four channel, 31 instrument module
+    UnknownFormat,
+}
+
+///
+/// One instrument
+///
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ModSample<'a> {
+    pub name: &'a [u8],
+    /// Signed 8 bit samples
+    pub data: &'a [u8],
+    /// In 1/8ths of a semitone, from -8 to 7
+    pub finetune: i8,
+    /// From 0 to 64
+    pub volume: u8,
+    /// Loop, as indexes into data.  loop_end is 0 if there's no loop.
+    pub loop_start: u32,
+    pub loop_end: u32,
+}
+
+impl ModSample<'_> {
+    pub fn is_looped(&self) -> bool {
+        self.loop_end > self.loop_start
+    }
+
+    #[inline]
+    fn at(&self, idx: u32) -> i32 {
+        match self.data.get(idx as usize) {
+            Some(sample) => (*sample as i8) as i32,
+            None => 0,
+        }
+    }
+}
+
+///
+/// One channel of one row of a pattern
+///
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Cell {
+    /// Instrument, from 1 to 31, or 0 to keep the last one
+    pub sample: u8,
+    /// Amiga period, or 0 for no new note
+    pub period: u16,
+    pub effect: u8,
+    pub param: u8,
+}
+
+///
+/// A parsed module.  Patterns and sample data are read from the file as
+/// they're played.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct ModFile<'a> {
+    pub title: &'a [u8],
+    pub samples: [ModSample<'a>; NUM_SAMPLES],
+    /// Number of entries in orders that are played
+    pub song_length: usize,
+    pub orders: &'a [u8],
+    patterns: &'a [u8],
+}
+
+fn read_u16_be(data: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([data[at], data[at + 1]])
+}
+
+impl<'a> ModFile<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, ModError> {
+        if data.len() < PATTERNS_AT {
+            return Err(ModError::TooShort);
+        }
+        if !matches!(
+            &data[TAG_AT..PATTERNS_AT],
+            b"M.K." | b"M!K!" | b"FLT4" | b"4CHN"
+        ) {
+            return Err(ModError::UnknownFormat);
+        }
+
+        let orders = &data[ORDERS_AT..ORDERS_AT + NUM_ORDERS];
+        let song_length = (data[SONG_LENGTH_AT] as usize).clamp(1, NUM_ORDERS);
+        // Every order counts here, even past song_length, like ProTracker.
+        let num_patterns = orders
+            .iter()
+            .map(|pattern| *pattern as usize + 1)
+            .max()
+            .unwrap_or(1);
+        let samples_at = PATTERNS_AT + num_patterns * PATTERN_LEN;
+        if data.len() < samples_at {
+            return Err(ModError::TooShort);
+        }
+
+        // The sample data comes one after the other at the end.  Lots of
+        // files in the wild are cut short, so the last samples may be too.
+        let mut next_sample_at = samples_at;
+        let samples = core::array::from_fn(|idx| {
+            let header = &data[TITLE_LEN + idx * SAMPLE_HEADER_LEN..][..SAMPLE_HEADER_LEN];
+            let length = 2 * read_u16_be(header, 22) as usize;
+            let start = core::cmp::min(next_sample_at, data.len());
+            let end = core::cmp::min(next_sample_at + length, data.len());
+            next_sample_at += length;
+
+            let loop_start = 2 * read_u16_be(header, 26) as u32;
+            let loop_length = 2 * read_u16_be(header, 28) as u32;
+            let loop_end = core::cmp::min(loop_start + loop_length, (end - start) as u32);
+            ModSample {
+                name: &header[0..22],
+                data: &data[start..end],
+                // A signed nibble
+                finetune: (((header[24] & 0xf) << 4) as i8) >> 4,
+                volume: core::cmp::min(header[25], MAX_VOLUME),
+                loop_start,
+                // A loop of one word is how the format says "no loop".
+                loop_end: if loop_length > 2 && loop_end > loop_start {
+                    loop_end
+                } else {
+                    0
+                },
+            }
+        });
+
+        Ok(Self {
+            title: &data[0..TITLE_LEN],
+            samples,
+            song_length,
+            orders,
+            patterns: &data[PATTERNS_AT..samples_at],
+        })
+    }
+
+    pub fn num_patterns(&self) -> usize {
+        self.patterns.len() / PATTERN_LEN
+    }
+
+    pub fn cell(&self, pattern: usize, row: usize, channel: usize) -> Cell {
+        let at = ((pattern * ROWS_PER_PATTERN + row) * NUM_CHANNELS + channel) * CELL_LEN;
+        let bytes = &self.patterns[at..at + CELL_LEN];
+        Cell {
+            sample: (bytes[0] & 0xf0) | (bytes[2] >> 4),
+            period: (((bytes[0] & 0x0f) as u16) << 8) | bytes[1] as u16,
+            effect: bytes[2] & 0x0f,
+            param: bytes[3],
+        }
+    }
+}
+
+//
+// What one channel is playing
+//
+#[derive(Clone, Copy, Default)]
+struct Channel {
+    /// Instrument, from 1 to 31, or 0 for none yet
+    sample: u8,
+    playing: bool,
+    /// Position in the sample, and the step per output sample, 16.16
+    position: u32,
+    fraction: u32,
+    step: u32,
+    /// Period of the note, with slides applied
+    period: u16,
+    finetune: i8,
+    volume: u8,
+    /// Where tone portamento is sliding to, and how fast
+    target_period: u16,
+    portamento_speed: u8,
+    vibrato_pos: u8,
+    vibrato_speed: u8,
+    vibrato_depth: u8,
+    /// Effect on the current row
+    effect: u8,
+    param: u8,
+}
+
+impl Channel {
+    fn slide_volume(&mut self) {
+        let (up, down) = (self.param >> 4, self.param & 0xf);
+        self.volume = if up != 0 {
+            core::cmp::min(self.volume + up, MAX_VOLUME)
+        } else {
+            self.volume.saturating_sub(down)
+        };
+    }
+
+    fn slide_to_target(&mut self) {
+        let speed = self.portamento_speed as u16;
+        if self.target_period == 0 {
+            return;
+        }
+        self.period = if self.period < self.target_period {
+            core::cmp::min(self.period + speed, self.target_period)
+        } else {
+            core::cmp::max(self.period.saturating_sub(speed), self.target_period)
+        };
+    }
+
+    fn vibrato_offset(&self) -> i32 {
+        let offset = (VIBRATO_TABLE[(self.vibrato_pos & 31) as usize] as i32
+            * self.vibrato_depth as i32)
+            >> 7;
+        if self.vibrato_pos & 32 != 0 {
+            -offset
+        } else {
+            offset
+        }
+    }
+}
+
+///
+/// Plays a module through to the end of its order table
+///
+/// Songs that jump back to loop forever are stopped at the jump, so
+/// has_next goes false like it does at the end of a MIDI file.  All four
+/// channels at full volume give a full scale output.
+///
+pub struct ModPlayer<'a, const P_FREQ: u32> {
+    song: &'a ModFile<'a>,
+    channels: [Channel; NUM_CHANNELS],
+    /// Position in the order table and the pattern
+    order: usize,
+    row: usize,
+    tick: u8,
+    speed: u8,
+    tempo: u8,
+    /// Output samples until the next tick, and what's left over from
+    /// dividing them up
+    samples_left: u32,
+    tick_remainder: u32,
+    /// Where a jump or break on this row goes once it's over
+    next_order: Option<usize>,
+    next_row: usize,
+    /// Orders already played, one bit each, to spot loops
+    played_orders: u128,
+    finished: bool,
+    volume: MasterVolume<P_FREQ>,
+}
+
+impl<'a, const P_FREQ: u32> ModPlayer<'a, P_FREQ> {
+    pub fn new(song: &'a ModFile<'a>) -> Self {
+        let mut rval = Self {
+            song,
+            channels: [Channel::default(); NUM_CHANNELS],
+            order: 0,
+            row: 0,
+            tick: 0,
+            speed: DEFAULT_SPEED,
+            tempo: DEFAULT_TEMPO,
+            samples_left: 0,
+            tick_remainder: 0,
+            next_order: None,
+            next_row: 0,
+            played_orders: 1,
+            finished: false,
+            volume: MasterVolume::new(),
+        };
+        rval.finished = rval.pattern() >= song.num_patterns();
+        rval
+    }
+
+    /// Change the master volume, from 0 to 100.  The change is ramped, so
+    /// it's safe to call while playing.
+    ///
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume.set_volume(volume);
+    }
+
+    pub fn get_volume(&self) -> u8 {
+        self.volume.get_volume()
+    }
+
+    /// Come up from silence to the master volume over time_in_ms
+    ///
+    pub fn fade_in(&mut self, time_in_ms: i32) {
+        self.volume.fade_in(time_in_ms);
+    }
+
+    /// Go down to silence over time_in_ms.  Playback carries on, so check
+    /// is_faded_out to know when it's safe to stop.
+    ///
+    pub fn fade_out(&mut self, time_in_ms: i32) {
+        self.volume.fade_out(time_in_ms);
+    }
+
+    pub fn is_faded_out(&self) -> bool {
+        self.volume.is_silent()
+    }
+
+    /// Where the song is, as a position in the order table and a row
+    ///
+    pub fn position(&self) -> (usize, usize) {
+        (self.order, self.row)
+    }
+
+    fn pattern(&self) -> usize {
+        self.song.orders[self.order] as usize
+    }
+
+    fn start_row(&mut self) {
+        let pattern = self.pattern();
+        for (idx, channel) in self.channels.iter_mut().enumerate() {
+            let cell = self.song.cell(pattern, self.row, idx);
+            channel.effect = cell.effect;
+            channel.param = cell.param;
+
+            if cell.sample != 0 && (cell.sample as usize) <= NUM_SAMPLES {
+                let sample = &self.song.samples[cell.sample as usize - 1];
+                channel.sample = cell.sample;
+                channel.volume = sample.volume;
+                channel.finetune = sample.finetune;
+            }
+            let portamento = matches!(cell.effect, 0x3 | 0x5);
+            if cell.period != 0 {
+                if portamento {
+                    // Slide to the new note rather than playing it.
+                    channel.target_period = cell.period;
+                } else {
+                    channel.period = cell.period;
+                    channel.playing = channel.sample != 0;
+                    channel.position = 0;
+                    channel.fraction = 0;
+                    channel.vibrato_pos = 0;
+                    if cell.effect == 0x9 {
+                        channel.position = (cell.param as u32) << 8;
+                    }
+                }
+            }
+
+            let (x, y) = (cell.param >> 4, cell.param & 0xf);
+            match cell.effect {
+                0x3 if cell.param != 0 => channel.portamento_speed = cell.param,
+                0x4 => {
+                    if x != 0 {
+                        channel.vibrato_speed = x;
+                    }
+                    if y != 0 {
+                        channel.vibrato_depth = y;
+                    }
+                }
+                0xb => {
+                    self.next_order = Some(cell.param as usize);
+                    self.next_row = 0;
+                }
+                0xc => channel.volume = core::cmp::min(cell.param, MAX_VOLUME),
+                0xd => {
+                    // The row is in decimal, one digit a nibble.
+                    if self.next_order.is_none() {
+                        self.next_order = Some(self.order + 1);
+                    }
+                    self.next_row = core::cmp::min(x as usize * 10 + y as usize, 63);
+                }
+                0xe => match x {
+                    0x1 => {
+                        channel.period =
+                            core::cmp::max(channel.period.saturating_sub(y as u16), MIN_PERIOD)
+                    }
+                    0x2 => channel.period = core::cmp::min(channel.period + y as u16, MAX_PERIOD),
+                    0xa => channel.volume = core::cmp::min(channel.volume + y, MAX_VOLUME),
+                    0xb => channel.volume = channel.volume.saturating_sub(y),
+                    0xc if y == 0 => channel.volume = 0,
+                    _ => {}
+                },
+                // Speed 0 stops some players; here it's ignored.
+                0xf if cell.param == 0 => {}
+                0xf if cell.param < 32 => self.speed = cell.param,
+                0xf => self.tempo = cell.param,
+                _ => {}
+            }
+        }
+    }
+
+    fn run_effects(&mut self) {
+        let tick = self.tick;
+        for channel in self.channels.iter_mut() {
+            let (x, y) = (channel.param >> 4, channel.param & 0xf);
+            match channel.effect {
+                0x1 => {
+                    channel.period = core::cmp::max(
+                        channel.period.saturating_sub(channel.param as u16),
+                        MIN_PERIOD,
+                    )
+                }
+                0x2 => {
+                    channel.period =
+                        core::cmp::min(channel.period + channel.param as u16, MAX_PERIOD)
+                }
+                0x3 => channel.slide_to_target(),
+                0x4 => channel.vibrato_pos = (channel.vibrato_pos + channel.vibrato_speed) & 63,
+                0x5 => {
+                    channel.slide_to_target();
+                    channel.slide_volume();
+                }
+                0x6 => {
+                    channel.vibrato_pos = (channel.vibrato_pos + channel.vibrato_speed) & 63;
+                    channel.slide_volume();
+                }
+                0xa => channel.slide_volume(),
+                0xe if x == 0xc && y == tick => channel.volume = 0,
+                _ => {}
+            }
+        }
+    }
+
+    // Work out each channel's step through its sample for this tick
+    fn set_steps(&mut self) {
+        let tick = self.tick;
+        for channel in self.channels.iter_mut() {
+            if !channel.playing || channel.period == 0 {
+                continue;
+            }
+            let period = match channel.effect {
+                0x4 | 0x6 => channel.period as i32 + channel.vibrato_offset(),
+                _ => channel.period as i32,
+            };
+            let mut ratio = FINETUNE_RATIOS[(channel.finetune & 0xf) as usize] as u64;
+            if channel.effect == 0x0 && channel.param != 0 {
+                // Arpeggio: the note, then up x semitones, then up y.
+                let semitones = match tick % 3 {
+                    0 => 0,
+                    1 => channel.param >> 4,
+                    _ => channel.param & 0xf,
+                };
+                ratio = (ratio * SEMITONE_RATIOS[semitones as usize] as u64) >> 16;
+            }
+            let rate = (AMIGA_CLOCK << 16) / (core::cmp::max(period, 1) as u64 * P_FREQ as u64);
+            channel.step = ((rate * ratio) >> 16) as u32;
+        }
+    }
+
+    fn next_row(&mut self) {
+        let (order, row) = match self.next_order.take() {
+            Some(order) => (order, self.next_row),
+            None if self.row + 1 < ROWS_PER_PATTERN => (self.order, self.row + 1),
+            None => (self.order + 1, 0),
+        };
+        if order != self.order {
+            // Going back to an order that's been played means the song
+            // loops, so that's the end.
+            if order >= self.song.song_length || self.played_orders & (1 << order) != 0 {
+                self.finished = true;
+                return;
+            }
+            self.played_orders |= 1 << order;
+        }
+        self.order = order;
+        self.row = row;
+        if self.pattern() >= self.song.num_patterns() {
+            self.finished = true;
+        }
+    }
+
+    fn run_tick(&mut self) {
+        if self.tick == 0 {
+            self.start_row();
+        } else {
+            self.run_effects();
+        }
+        self.set_steps();
+
+        // A tick is 2.5 / tempo seconds.
+        let total = 5 * P_FREQ + self.tick_remainder;
+        let divisor = 2 * self.tempo as u32;
+        self.samples_left = total / divisor;
+        self.tick_remainder = total % divisor;
+
+        self.tick += 1;
+        if self.tick >= self.speed {
+            self.tick = 0;
+            self.next_row();
+        }
+    }
+
+    #[inline]
+    fn mix_channel(channel: &mut Channel, sample: &ModSample) -> i32 {
+        let current = sample.at(channel.position);
+        let next_position = if sample.is_looped() && channel.position + 1 >= sample.loop_end {
+            sample.loop_start
+        } else {
+            channel.position + 1
+        };
+        let next = sample.at(next_position);
+        let interpolated = current + (((next - current) * channel.fraction as i32) >> 16);
+
+        channel.fraction += channel.step;
+        channel.position += channel.fraction >> 16;
+        channel.fraction &= 0xffff;
+        if sample.is_looped() {
+            while channel.position >= sample.loop_end {
+                channel.position -= sample.loop_end - sample.loop_start;
+            }
+        } else if channel.position as usize >= sample.data.len() {
+            channel.playing = false;
+        }
+
+        interpolated * channel.volume as i32
+    }
+
+    pub fn get_next(&mut self) -> SoundSampleI32 {
+        if self.samples_left == 0 {
+            // The last tick is played out before stopping.
+            if self.finished {
+                return SoundSampleI32::ZERO;
+            }
+            self.run_tick();
+        }
+        self.samples_left -= 1;
+
+        let mut mix = 0;
+        for channel in self.channels.iter_mut() {
+            if channel.playing {
+                let sample = &self.song.samples[channel.sample as usize - 1];
+                mix += Self::mix_channel(channel, sample);
+            }
+        }
+        // Each channel is up to 8 bits of sample times 64 of volume.
+        self.volume.apply(SoundSampleI32::new_i32(mix))
+    }
+
+    pub fn has_next(&self) -> bool {
+        !self.finished || self.samples_left > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protracker::*;
+
+    // A module with a looped square wave as sample 1, a one shot ramp as
+    // sample 2, and one pattern per entry of rows, given as (row, channel,
+    // cell).
+    fn test_mod(orders: &[u8], rows: &[&[(usize, usize, Cell)]]) -> Vec<u8> {
+        let mut data = vec![0u8; PATTERNS_AT];
+        data[0..4].copy_from_slice(b"test");
+        let square: Vec<u8> = (0..64).map(|i| if i < 32 { 100 } else { 156 }).collect();
+        let ramp: Vec<u8> = (0..100).map(|i| i as u8).collect();
+        for (idx, (len, volume, finetune, loop_words)) in
+            [(64, 64, 0, 32), (100, 32, 0xf, 1)].iter().enumerate()
+        {
+            let header = &mut data[TITLE_LEN + idx * SAMPLE_HEADER_LEN..];
+            header[22..24].copy_from_slice(&((len / 2) as u16).to_be_bytes());
+            header[24] = *finetune;
+            header[25] = *volume;
+            header[28..30].copy_from_slice(&(*loop_words as u16).to_be_bytes());
+        }
+        data[SONG_LENGTH_AT] = orders.len() as u8;
+        data[ORDERS_AT..ORDERS_AT + orders.len()].copy_from_slice(orders);
+        data[TAG_AT..PATTERNS_AT].copy_from_slice(b"M.K.");
+        for pattern in rows {
+            let mut cells = vec![0u8; PATTERN_LEN];
+            for (row, channel, cell) in pattern.iter() {
+                let at = ((row * NUM_CHANNELS) + channel) * CELL_LEN;
+                cells[at] = (cell.sample & 0xf0) | (cell.period >> 8) as u8;
+                cells[at + 1] = cell.period as u8;
+                cells[at + 2] = (cell.sample << 4) | cell.effect;
+                cells[at + 3] = cell.param;
+            }
+            data.extend(cells);
+        }
+        data.extend(square);
+        data.extend(ramp);
+        data
+    }
+
+    fn cell(sample: u8, period: u16, effect: u8, param: u8) -> Cell {
+        Cell {
+            sample,
+            period,
+            effect,
+            param,
+        }
+    }
+
+    // 10khz gives 200 samples a tick at the default tempo.
+    type Player<'a> = ModPlayer<'a, 10000>;
+
+    fn length_in_ticks(data: &[u8]) -> u32 {
+        let song = ModFile::parse(data).unwrap();
+        let mut player = Player::new(&song);
+        let mut samples = 0;
+        while player.has_next() {
+            player.get_next();
+            samples += 1;
+        }
+        assert_eq!(0, samples % 200);
+        samples / 200
+    }
+
+    #[test]
+    fn modules_should_parse() {
+        let data = test_mod(&[0, 1, 0], &[&[(0, 2, cell(17, 428, 0xc, 0x20))], &[]]);
+        let song = ModFile::parse(&data).unwrap();
+        assert_eq!(b"test", &song.title[0..4]);
+        assert_eq!(3, song.song_length);
+        assert_eq!(2, song.num_patterns());
+        assert_eq!(cell(17, 428, 0xc, 0x20), song.cell(0, 0, 2));
+        assert_eq!(Cell::default(), song.cell(1, 0, 2));
+
+        assert_eq!(64, song.samples[0].data.len());
+        assert_eq!(
+            (0, 64),
+            (song.samples[0].loop_start, song.samples[0].loop_end)
+        );
+        assert!(song.samples[0].is_looped());
+        assert_eq!(100, song.samples[1].data.len());
+        assert_eq!(99, song.samples[1].data[99]);
+        assert_eq!(-1, song.samples[1].finetune);
+        assert_eq!(32, song.samples[1].volume);
+        assert!(!song.samples[1].is_looped());
+
+        assert_eq!(
+            Err(ModError::TooShort),
+            ModFile::parse(&data[0..2000]).map(|_| ())
+        );
+        let mut data = data;
+        data[TAG_AT..PATTERNS_AT].copy_from_slice(b"8CHN");
+        assert_eq!(
+            Err(ModError::UnknownFormat),
+            ModFile::parse(&data).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn songs_should_follow_speed_and_breaks() {
+        // Two patterns of 64 rows at 6 ticks a row
+        assert_eq!(2 * 64 * 6, length_in_ticks(&test_mod(&[0, 0], &[&[]])));
+        // Speed 3, then a break to row 60 of the next order after row 1
+        let rows = [(0, 0, cell(0, 0, 0xf, 3)), (1, 3, cell(0, 0, 0xd, 0x60))];
+        assert_eq!((2 + 4) * 3, length_in_ticks(&test_mod(&[0, 0], &[&rows])));
+        // Tempo 250 halves the ticks, so it's 100 samples each.
+        let rows = [(0, 0, cell(0, 0, 0xf, 250)), (0, 1, cell(0, 0, 0xd, 0))];
+        assert_eq!(
+            64 * 6 / 2 + 3,
+            length_in_ticks(&test_mod(&[0, 1], &[&rows, &[]]))
+        );
+    }
+
+    #[test]
+    fn loops_should_end_the_song() {
+        // Jump back to the start from the second order's first row
+        let data = test_mod(&[0, 1], &[&[], &[(0, 0, cell(0, 0, 0xb, 0))]]);
+        assert_eq!(65 * 6, length_in_ticks(&data));
+    }
+
+    #[test]
+    fn effects_should_change_channels() {
+        let rows = [
+            (0, 0, cell(1, 400, 0x1, 2)),
+            (0, 1, cell(1, 400, 0xa, 0x03)),
+            (0, 2, cell(2, 400, 0x0, 0x47)),
+            (1, 0, cell(0, 300, 0x3, 10)),
+            (1, 1, cell(0, 0, 0xc, 70)),
+            (1, 2, cell(0, 0, 0x4, 0x48)),
+        ];
+        let data = test_mod(&[0], &[&rows]);
+        let song = ModFile::parse(&data).unwrap();
+        let mut player = Player::new(&song);
+
+        // Arpeggio: the note, up 4 semitones, then up 7
+        let mut steps = [0; 3];
+        for step in steps.iter_mut() {
+            player.run_tick();
+            *step = player.channels[2].step as u64;
+        }
+        assert!((steps[1] * 65536 / 82570).abs_diff(steps[0]) <= 2);
+        assert!((steps[2] * 65536 / 98193).abs_diff(steps[0]) <= 2);
+        for _ in 3..6 {
+            player.run_tick();
+        }
+        assert_eq!(400 - 5 * 2, player.channels[0].period);
+        assert_eq!(64 - 5 * 3, player.channels[1].volume);
+        assert_eq!((0, 1), player.position());
+
+        player.run_tick();
+        // The slide's target is set, but the period isn't changed yet.
+        assert_eq!(390, player.channels[0].period);
+        assert_eq!(64, player.channels[1].volume);
+        player.run_tick();
+        assert_eq!(380, player.channels[0].period);
+        assert_ne!(0, player.channels[2].vibrato_offset());
+        for _ in 2..6 {
+            player.run_tick();
+        }
+        assert_eq!(340, player.channels[0].period);
+        // Slides only run on their own row.
+        player.run_tick();
+        player.run_tick();
+        assert_eq!(340, player.channels[0].period);
+    }
+
+    #[test]
+    fn samples_should_play_at_their_pitch() {
+        // Period 428 plays 8287 samples a second, so the 64 sample
+        // square is a 129hz tone; about 77 output samples per cycle.
+        let rows = [(0, 0, cell(1, 428, 0, 0)), (0, 1, cell(2, 428, 0, 0))];
+        let data = test_mod(&[0], &[&rows]);
+        let song = ModFile::parse(&data).unwrap();
+        let mut player = Player::new(&song);
+        let mut last = 0;
+        let mut crossings = 0;
+        let mut loudest = 0;
+        for _ in 0..10000 {
+            let sample = player.get_next().to_i32();
+            if (sample < 0) != (last < 0) {
+                crossings += 1;
+            }
+            loudest = core::cmp::max(loudest, sample.abs());
+            last = sample;
+        }
+        // 129 cycles, two crossings each
+        assert!((255..=262).contains(&crossings), "{}", crossings);
+        // Both channels together
+        assert!(
+            loudest > 100 * 64 && loudest <= 100 * 64 + 99 * 32,
+            "{}",
+            loudest
+        );
+        // The ramp is one shot.
+        assert!(!player.channels[1].playing);
+        assert!(player.channels[0].playing);
+    }
+}
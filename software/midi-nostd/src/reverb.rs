@@ -0,0 +1,247 @@
+// Reverb.
+//
+// A small Schroeder reverb, laid out like Freeverb: four feedback comb
+// filters in parallel, each with a one pole low pass in the loop so the
+// high end dies away first, and then two allpass filters in series to
+// smear the echoes together.  Freeverb runs eight combs per side at
+// 44.1khz in floating point; this is one side, half the combs, and all
+// integer math.
+//
+// Every delay line lives in one shared buffer of BUFFER_SIZE samples, which
+// is the RAM budget.  The Freeverb delay lengths are scaled to the playback
+// frequency and then, if they don't fit, shrunk until they do.  Shorter
+// delays sound more like a small room, and below a few hundred samples more
+// like a spring.  A BUFFER_SIZE of 0 turns the reverb off.
+//
+
+use crate::delay_line::mul_q15;
+use crate::sound_sample::SoundSampleI32;
+
+const NUM_COMBS: usize = 4;
+const NUM_ALLPASSES: usize = 2;
+const NUM_DELAYS: usize = NUM_COMBS + NUM_ALLPASSES;
+
+// Freeverb's delay lengths, in samples at 44.1khz
+const FREEVERB_FREQUENCY: u64 = 44100;
+const FREEVERB_DELAYS: [u64; NUM_DELAYS] = [1116, 1188, 1277, 1356, 556, 441];
+
+///
+/// Fixed point reverb
+///
+pub struct Reverb<const P_FREQ: u32, const BUFFER_SIZE: usize> {
+    buffer: [i16; BUFFER_SIZE],
+    /// Read/write position in each delay line
+    positions: [usize; NUM_DELAYS],
+    /// Low pass filter state for each comb
+    damping_state: [i32; NUM_COMBS],
+    /// Comb feedback, 0x8000 for 1
+    feedback: i32,
+    /// Comb low pass, 0 for none and 0x8000 for everything
+    damping: i32,
+    /// Output level, 0x8000 for 1
+    wet: i32,
+}
+
+impl<const P_FREQ: u32, const BUFFER_SIZE: usize> Reverb<P_FREQ, BUFFER_SIZE> {
+    /// RAM used by the delay lines, in bytes
+    pub const MEMORY_BYTES: usize = BUFFER_SIZE * core::mem::size_of::<i16>();
+
+    /// Multiplies per output sample.  Everything else is adds, shifts and
+    /// buffer accesses, so on a Cortex-M0+ a sample costs roughly 150
+    /// cycles; budget about P_FREQ * 150 cycles a second.
+    pub const MULTIPLIES_PER_SAMPLE: usize = NUM_COMBS * 3 + 1;
+
+    /// False if BUFFER_SIZE is too small to hold the delay lines
+    pub const ENABLED: bool = BUFFER_SIZE >= NUM_DELAYS;
+
+    /// Most the reverb puts out for its send, at full level and any room
+    /// size.  Full scale square waves measure up to 2.7 times.
+    pub const PEAK_GAIN: i32 = 3;
+
+    /// Length of each delay line, combs first
+    pub const DELAY_LENGTHS: [usize; NUM_DELAYS] = {
+        let mut lengths = [0usize; NUM_DELAYS];
+        let mut total: u64 = 0;
+        let mut idx = 0;
+        while idx < NUM_DELAYS {
+            let length = FREEVERB_DELAYS[idx] * (P_FREQ as u64) / FREEVERB_FREQUENCY;
+            lengths[idx] = if length < 1 { 1 } else { length as usize };
+            total += lengths[idx] as u64;
+            idx += 1;
+        }
+        if total > BUFFER_SIZE as u64 {
+            let mut idx = 0;
+            while idx < NUM_DELAYS {
+                let length = (lengths[idx] as u64) * (BUFFER_SIZE as u64) / total;
+                lengths[idx] = if length < 1 { 1 } else { length as usize };
+                idx += 1;
+            }
+        }
+        lengths
+    };
+
+    // Where each delay line starts in the buffer
+    const DELAY_STARTS: [usize; NUM_DELAYS] = {
+        let mut starts = [0usize; NUM_DELAYS];
+        let mut idx = 1;
+        while idx < NUM_DELAYS {
+            starts[idx] = starts[idx - 1] + Self::DELAY_LENGTHS[idx - 1];
+            idx += 1;
+        }
+        starts
+    };
+
+    // The combs add up to four times their input, or more with feedback.
+    const INPUT_SHIFT: u32 = 3;
+
+    pub fn new() -> Self {
+        let mut rval = Self {
+            buffer: [0; BUFFER_SIZE],
+            positions: [0; NUM_DELAYS],
+            damping_state: [0; NUM_COMBS],
+            feedback: 0,
+            damping: 0,
+            wet: 0,
+        };
+        rval.set_room_size(70);
+        rval.set_damping(50);
+        rval.set_level(100);
+        rval
+    }
+
+    /// How long the tail is, as a percentage.  Freeverb's feedback range,
+    /// 0.7 to 0.98, is mapped onto 0 to 100.
+    ///
+    pub fn set_room_size(&mut self, percent: u8) {
+        let percent = core::cmp::min(percent, 100) as i32;
+        self.feedback = 0x8000 * 70 / 100 + 0x8000 * 28 / 100 * percent / 100;
+    }
+
+    /// How quickly the high end of the tail dies, as a percentage
+    ///
+    pub fn set_damping(&mut self, percent: u8) {
+        let percent = core::cmp::min(percent, 100) as i32;
+        self.damping = 0x8000 * 40 / 100 * percent / 100;
+    }
+
+    /// Output level, as a percentage
+    ///
+    pub fn set_level(&mut self, percent: u8) {
+        self.wet = 0x8000 * (core::cmp::min(percent, 100) as i32) / 100;
+    }
+
+    /// Clear the tail
+    ///
+    pub fn reset(&mut self) {
+        self.buffer = [0; BUFFER_SIZE];
+        self.damping_state = [0; NUM_COMBS];
+    }
+
+    #[inline]
+    fn read(&self, delay: usize) -> i32 {
+        self.buffer[Self::DELAY_STARTS[delay] + self.positions[delay]] as i32
+    }
+
+    #[inline]
+    fn write_and_advance(&mut self, delay: usize, value: i32) {
+        let value = value.clamp(i16::MIN as i32, i16::MAX as i32);
+        self.buffer[Self::DELAY_STARTS[delay] + self.positions[delay]] = value as i16;
+        self.positions[delay] += 1;
+        if self.positions[delay] == Self::DELAY_LENGTHS[delay] {
+            self.positions[delay] = 0;
+        }
+    }
+
+    ///
+    /// Take one sample of the reverb send and return one sample of reverb.
+    /// Only the reverb is returned; add it to the dry signal.
+    ///
+    #[inline]
+    pub fn process(&mut self, input: SoundSampleI32) -> SoundSampleI32 {
+        if !Self::ENABLED {
+            return SoundSampleI32::ZERO;
+        }
+        let input = input.to_i32() >> Self::INPUT_SHIFT;
+
+        let mut output: i32 = 0;
+        for comb in 0..NUM_COMBS {
+            let delayed = self.read(comb);
+            output += delayed;
+            let state = self.damping_state[comb];
+            let filtered = mul_q15(delayed, 0x8000 - self.damping) + mul_q15(state, self.damping);
+            self.damping_state[comb] = filtered;
+            self.write_and_advance(comb, input + mul_q15(filtered, self.feedback));
+        }
+
+        for allpass in NUM_COMBS..NUM_DELAYS {
+            let delayed = self.read(allpass);
+            // Halved with a divide, not a shift, for the same reason as mul_q15.
+            self.write_and_advance(allpass, output + delayed / 2);
+            output = delayed - output;
+        }
+
+        // Loud sends can take the all-passes past 16 bits.
+        SoundSampleI32::new_i32(((output as i64 * self.wet as i64) >> 15) as i32)
+    }
+}
+
+impl<const P_FREQ: u32, const BUFFER_SIZE: usize> Default for Reverb<P_FREQ, BUFFER_SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::reverb::*;
+
+    const IMPULSE: SoundSampleI32 = SoundSampleI32::new_i32(0x4000);
+
+    fn energy<const P_FREQ: u32, const BUFFER_SIZE: usize>(
+        reverb: &mut Reverb<P_FREQ, BUFFER_SIZE>,
+        samples: usize,
+    ) -> i64 {
+        (0..samples)
+            .map(|_| reverb.process(SoundSampleI32::ZERO).to_i32().abs() as i64)
+            .sum()
+    }
+
+    #[test]
+    fn delay_lines_should_fit_the_budget() {
+        type Full = Reverb<24000, 4096>;
+        type Small = Reverb<24000, 1024>;
+        assert_eq!(1116 * 24000 / 44100, Full::DELAY_LENGTHS[0]);
+        assert!(Full::DELAY_LENGTHS.iter().sum::<usize>() <= 4096);
+        assert!(Small::DELAY_LENGTHS.iter().sum::<usize>() <= 1024);
+        assert!(Small::DELAY_LENGTHS.iter().all(|length| *length > 0));
+        assert_eq!(2048, Small::MEMORY_BYTES);
+    }
+
+    #[test]
+    fn impulse_should_leave_a_decaying_tail() {
+        // A quarter of a second at a time.
+        let mut reverb = Reverb::<24000, 4096>::new();
+        reverb.process(IMPULSE);
+        let early = energy(&mut reverb, 6000);
+        let late = energy(&mut reverb, 6000);
+        assert!(late > 0);
+        assert!(late < early / 2, "{} {}", early, late);
+
+        let mut small_room = Reverb::<24000, 4096>::new();
+        small_room.set_room_size(0);
+        small_room.process(IMPULSE);
+        energy(&mut small_room, 6000);
+        let small_late = energy(&mut small_room, 6000);
+        assert!(small_late < late / 2, "{} {}", small_late, late);
+
+        // And no rounding error left going round the loops.
+        energy(&mut reverb, 48000);
+        assert_eq!(0, energy(&mut reverb, 6000));
+    }
+
+    #[test]
+    fn disabled_reverb_should_be_silent() {
+        let mut reverb = Reverb::<24000, 0>::new();
+        assert_eq!(0, reverb.process(IMPULSE).to_i32());
+    }
+}
@@ -0,0 +1,559 @@
+// Rhythm game.
+//
+// The parts of a play-along game that don't need a screen or buttons.  A
+// Chart reads one channel's notes out of a MIDI file a little ahead of the
+// song, using MidiTrack's look ahead, and puts each note in one of three
+// lanes by pitch.  Notes too close together to press one after the other
+// are left out.  RhythmGame keeps the notes that are on their way, judges
+// each press by how far it was from its note, and keeps the score.
+//
+// Time is in the file's ticks throughout, the same as Midi::current_tick,
+// so the player on the other core only has to share that one number.  The
+// judging windows are fractions of a beat, which makes them wider for
+// slower songs.  An SMPTE timed file's beat is a second, as it is to
+// MidiTime.
+//
+
+use crate::midi_time;
+use crate::midi_track::MidiTrack;
+use midly::MidiMessage;
+use midly::TrackEventKind;
+
+/// Beats of notes on their way at once
+pub const LOOK_AHEAD_BEATS: u32 = 4;
+/// Most notes on their way at once.  Past that they're left out.
+pub const MAX_UPCOMING_NOTES: usize = 32;
+
+// Most notes read in one go, before thinning out
+const MAX_READ_NOTES: usize = 64;
+// Perfect within a 32nd note, good within a 16th
+const PERFECT_PER_BEAT: u32 = 8;
+const GOOD_PER_BEAT: u32 = 4;
+// No closer than a 16th
+const GAPS_PER_BEAT: u32 = 4;
+const MAX_MULTIPLIER: u32 = 4;
+const COMBO_PER_MULTIPLIER: u32 = 10;
+
+// A chart never plays its tracks, so the playback parameters don't matter.
+type ChartTrack<'a> = MidiTrack<'a, 1, 1, 1, false>;
+
+/// Where a note comes down, by pitch.  Top to bottom on the screen.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Lane {
+    High,
+    Middle,
+    Low,
+}
+
+impl Lane {
+    pub const ALL: [Lane; 3] = [Lane::High, Lane::Middle, Lane::Low];
+
+    /// The lane for key, splitting lowest to highest into thirds
+    ///
+    pub fn for_key(key: u8, lowest: u8, highest: u8) -> Self {
+        let span = highest.saturating_sub(lowest) as u32 + 1;
+        match key.saturating_sub(lowest) as u32 * 3 / span {
+            0 => Lane::Low,
+            1 => Lane::Middle,
+            _ => Lane::High,
+        }
+    }
+
+    /// 0 for the top lane
+    ///
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Judgement {
+    Perfect,
+    Good,
+    Miss,
+}
+
+impl Judgement {
+    /// Points before the combo multiplier
+    ///
+    pub const fn points(self) -> u32 {
+        match self {
+            Judgement::Perfect => 100,
+            Judgement::Good => 50,
+            Judgement::Miss => 0,
+        }
+    }
+
+    /// Judge a press offset ticks early or late.  None if it's too far
+    /// from the note to count.
+    ///
+    pub fn for_offset(offset: u32, ticks_per_quarter_note: u32) -> Option<Self> {
+        if offset <= ticks_per_quarter_note / PERFECT_PER_BEAT {
+            Some(Judgement::Perfect)
+        } else if offset <= ticks_per_quarter_note / GOOD_PER_BEAT {
+            Some(Judgement::Good)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ChartNote {
+    pub tick: u32,
+    pub key: u8,
+    pub lane: Lane,
+}
+
+///
+/// One channel's notes, read out a little at a time
+///
+pub struct Chart<'a, const MAX_TRACKS: usize> {
+    tracks: [Option<ChartTrack<'a>>; MAX_TRACKS],
+    channel: u8,
+    lowest: u8,
+    highest: u8,
+    ticks_per_quarter_note: u32,
+    last_tick: Option<u32>,
+    num_notes: usize,
+}
+
+impl<'a, const MAX_TRACKS: usize> Chart<'a, MAX_TRACKS> {
+    /// Chart of channel, from the start of the song
+    ///
+    pub fn new(header: &midly::Header, track_iter: midly::TrackIter<'a>, channel: u8) -> Self {
+        let mut rval = Self::new_internal(header, track_iter.clone(), channel);
+        // Read a copy through to count what's left after thinning out.
+        let mut counter = Self::new_internal(header, track_iter, channel);
+        let mut until = 0;
+        while !counter.is_finished() {
+            until += counter.ticks_per_quarter_note;
+            counter.read(until, |_| rval.num_notes += 1);
+        }
+        rval
+    }
+
+    fn new_internal(
+        header: &midly::Header,
+        mut track_iter: midly::TrackIter<'a>,
+        channel: u8,
+    ) -> Self {
+        let tracks: [Option<ChartTrack<'a>>; MAX_TRACKS] = core::array::from_fn(|_idx| {
+            track_iter
+                .next()
+                .and_then(|track| track.ok())
+                .map(ChartTrack::new)
+        });
+        let mut rval = Self {
+            tracks,
+            channel: channel & 0xf,
+            lowest: 0x7f,
+            highest: 0,
+            ticks_per_quarter_note: midi_time::ticks_per_quarter_note(header.timing),
+            last_tick: None,
+            num_notes: 0,
+        };
+        for track in rval.tracks.iter().flatten() {
+            for (_, key) in Self::notes(track, rval.channel, u32::MAX) {
+                rval.lowest = core::cmp::min(rval.lowest, key);
+                rval.highest = core::cmp::max(rval.highest, key);
+            }
+        }
+        rval
+    }
+
+    // The note ons for channel on track before until, as (tick, key)
+    fn notes(
+        track: &ChartTrack<'a>,
+        channel: u8,
+        until: u32,
+    ) -> impl Iterator<Item = (u32, u8)> + 'a {
+        track
+            .upcoming_events()
+            .take_while(move |(tick, _)| *tick < until)
+            .filter_map(move |(tick, kind)| match kind {
+                TrackEventKind::Midi {
+                    channel: event_channel,
+                    message: MidiMessage::NoteOn { key, vel },
+                } if event_channel.as_int() == channel && vel.as_int() > 0 => {
+                    Some((tick, key.as_int()))
+                }
+                _ => None,
+            })
+    }
+
+    /// Hand the notes from where the last call left off up to until to
+    /// add, soonest first.  If there are too many to read at once it stops
+    /// short, and the next call carries on.
+    ///
+    pub fn read(&mut self, until: u32, mut add: impl FnMut(ChartNote)) {
+        let mut read = [(0u32, 0u8); MAX_READ_NOTES];
+        let mut num_read = 0;
+        let mut read_until = until;
+        for track in self.tracks.iter().flatten() {
+            for note in Self::notes(track, self.channel, until) {
+                if num_read == MAX_READ_NOTES {
+                    // Full, so only the soonest notes are kept.  The rest of
+                    // the window is read next time.
+                    let (last_tick, _) = read[num_read - 1];
+                    if note.0 >= last_tick {
+                        break;
+                    }
+                    num_read -= 1;
+                }
+                // Tracks are merged by time.
+                let at = read[..num_read]
+                    .iter()
+                    .position(|(tick, _)| *tick > note.0)
+                    .unwrap_or(num_read);
+                read.copy_within(at..num_read, at + 1);
+                read[at] = note;
+                num_read += 1;
+            }
+        }
+        if num_read == MAX_READ_NOTES {
+            // Stop just after the last note taken.  Any others on the same
+            // tick would have been thinned out.
+            read_until = core::cmp::min(read[num_read - 1].0 + 1, until);
+        }
+        for track in self.tracks.iter_mut().flatten() {
+            track.skip_to(read_until);
+        }
+
+        let gap = self.ticks_per_quarter_note / GAPS_PER_BEAT;
+        for (tick, key) in read[..num_read].iter().copied() {
+            if self.last_tick.map_or(true, |last| tick >= last + gap) {
+                self.last_tick = Some(tick);
+                add(ChartNote {
+                    tick,
+                    key,
+                    lane: Lane::for_key(key, self.lowest, self.highest),
+                });
+            }
+        }
+    }
+
+    /// True when every note's been read
+    ///
+    pub fn is_finished(&self) -> bool {
+        self.tracks
+            .iter()
+            .flatten()
+            .all(|track| Self::notes(track, self.channel, u32::MAX).next().is_none())
+    }
+
+    /// Notes in the whole chart
+    ///
+    pub fn num_notes(&self) -> usize {
+        self.num_notes
+    }
+
+    pub fn ticks_per_quarter_note(&self) -> u32 {
+        self.ticks_per_quarter_note
+    }
+}
+
+///
+/// Notes on their way, judgement and score
+///
+pub struct RhythmGame {
+    notes: [ChartNote; MAX_UPCOMING_NOTES],
+    num_notes: usize,
+    ticks_per_quarter_note: u32,
+    score: u32,
+    combo: u32,
+    best_combo: u32,
+    counts: [u32; 3],
+    last_judgement: Option<Judgement>,
+}
+
+impl RhythmGame {
+    pub fn new(ticks_per_quarter_note: u32) -> Self {
+        Self {
+            notes: [ChartNote {
+                tick: 0,
+                key: 0,
+                lane: Lane::Middle,
+            }; MAX_UPCOMING_NOTES],
+            num_notes: 0,
+            ticks_per_quarter_note: core::cmp::max(ticks_per_quarter_note, 1),
+            score: 0,
+            combo: 0,
+            best_combo: 0,
+            counts: [0; 3],
+            last_judgement: None,
+        }
+    }
+
+    /// Ticks from a note appearing to it being due
+    ///
+    pub fn look_ahead(&self) -> u32 {
+        self.ticks_per_quarter_note * LOOK_AHEAD_BEATS
+    }
+
+    fn good_window(&self) -> u32 {
+        self.ticks_per_quarter_note / GOOD_PER_BEAT
+    }
+
+    /// Catch up with the song at tick now: notes too late to press are
+    /// missed, and the notes coming into view are read from chart.
+    ///
+    pub fn update<const MAX_TRACKS: usize>(&mut self, chart: &mut Chart<'_, MAX_TRACKS>, now: u32) {
+        let window = self.good_window();
+        while self.num_notes > 0 && self.notes[0].tick.saturating_add(window) < now {
+            self.remove(0);
+            self.judged(Judgement::Miss);
+        }
+        chart.read(now.saturating_add(self.look_ahead()), |note| {
+            if self.num_notes < MAX_UPCOMING_NOTES {
+                self.notes[self.num_notes] = note;
+                self.num_notes += 1;
+            }
+        });
+    }
+
+    /// A press on lane at tick now.  Judges the closest note in the lane,
+    /// or returns None if there isn't one close enough; there's no penalty
+    /// for pressing when nothing's due.
+    ///
+    pub fn press(&mut self, lane: Lane, now: u32) -> Option<Judgement> {
+        let (idx, offset) = self.notes[..self.num_notes]
+            .iter()
+            .enumerate()
+            .filter(|(_, note)| note.lane == lane)
+            .map(|(idx, note)| (idx, note.tick.abs_diff(now)))
+            .min_by_key(|(_, offset)| *offset)?;
+        let judgement = Judgement::for_offset(offset, self.ticks_per_quarter_note)?;
+        self.remove(idx);
+        self.judged(judgement);
+        Some(judgement)
+    }
+
+    fn remove(&mut self, idx: usize) {
+        self.notes.copy_within(idx + 1..self.num_notes, idx);
+        self.num_notes -= 1;
+    }
+
+    fn judged(&mut self, judgement: Judgement) {
+        self.counts[judgement as usize] += 1;
+        self.last_judgement = Some(judgement);
+        if judgement == Judgement::Miss {
+            self.combo = 0;
+            return;
+        }
+        self.score += judgement.points() * self.multiplier();
+        self.combo += 1;
+        self.best_combo = core::cmp::max(self.best_combo, self.combo);
+    }
+
+    /// What points are multiplied by, going up with the combo
+    ///
+    pub fn multiplier(&self) -> u32 {
+        core::cmp::min(1 + self.combo / COMBO_PER_MULTIPLIER, MAX_MULTIPLIER)
+    }
+
+    /// Notes on their way, soonest first
+    ///
+    pub fn notes(&self) -> &[ChartNote] {
+        &self.notes[..self.num_notes]
+    }
+
+    /// How far along to draw note at tick now, from 0 when it's due to
+    /// distance when it first appears.  Negative once it's late.
+    ///
+    pub fn position(&self, note: &ChartNote, now: u32, distance: i32) -> i32 {
+        let ahead = note.tick as i64 - now as i64;
+        (ahead * distance as i64 / self.look_ahead() as i64) as i32
+    }
+
+    /// True when the chart's been read and every note judged
+    ///
+    pub fn is_finished<const MAX_TRACKS: usize>(&self, chart: &Chart<'_, MAX_TRACKS>) -> bool {
+        self.num_notes == 0 && chart.is_finished()
+    }
+
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    pub fn combo(&self) -> u32 {
+        self.combo
+    }
+
+    pub fn best_combo(&self) -> u32 {
+        self.best_combo
+    }
+
+    /// Notes judged as judgement so far
+    ///
+    pub fn count(&self, judgement: Judgement) -> u32 {
+        self.counts[judgement as usize]
+    }
+
+    pub fn last_judgement(&self) -> Option<Judgement> {
+        self.last_judgement
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rhythm_game::*;
+    use crate::smf_writer::SmfFormat;
+    use crate::smf_writer::SmfWriter;
+
+    // One track, channel 0, 384 ticks per quarter note.  The first verse's
+    // notes are in twice, and the tune's between C4 and A4.
+    fn twinkle() -> (midly::Header, midly::TrackIter<'static>) {
+        midly::parse(include_bytes!("../assets/twinkle.mid"))
+            .expect("It's inlined data, so it better work, gosh darn it")
+    }
+
+    #[test]
+    fn lanes_should_split_by_pitch() {
+        assert_eq!(Lane::Low, Lane::for_key(60, 60, 71));
+        assert_eq!(Lane::Low, Lane::for_key(63, 60, 71));
+        assert_eq!(Lane::Middle, Lane::for_key(64, 60, 71));
+        assert_eq!(Lane::High, Lane::for_key(71, 60, 71));
+        // Out of range goes to the nearest lane
+        assert_eq!(Lane::Low, Lane::for_key(10, 60, 71));
+        assert_eq!(Lane::High, Lane::for_key(100, 60, 71));
+        // A one note tune
+        assert_eq!(Lane::Low, Lane::for_key(60, 60, 60));
+        assert_eq!(0, Lane::High.index());
+    }
+
+    #[test]
+    fn presses_should_be_judged_by_offset() {
+        assert_eq!(Some(Judgement::Perfect), Judgement::for_offset(0, 384));
+        assert_eq!(Some(Judgement::Perfect), Judgement::for_offset(48, 384));
+        assert_eq!(Some(Judgement::Good), Judgement::for_offset(49, 384));
+        assert_eq!(Some(Judgement::Good), Judgement::for_offset(96, 384));
+        assert_eq!(None, Judgement::for_offset(97, 384));
+    }
+
+    #[test]
+    fn charts_should_read_ahead_in_order() {
+        let (header, tracks) = twinkle();
+        let mut chart = Chart::<4>::new(&header, tracks, 0);
+        assert_eq!(384, chart.ticks_per_quarter_note());
+        // The doubled notes are thinned out
+        assert_eq!(70, chart.num_notes());
+
+        let mut notes = vec![];
+        chart.read(800, |note| notes.push(note));
+        assert_eq!(
+            vec![
+                (0, 60, Lane::Low),
+                (384, 60, Lane::Low),
+                (768, 67, Lane::High)
+            ],
+            notes
+                .iter()
+                .map(|note| (note.tick, note.key, note.lane))
+                .collect::<Vec<_>>()
+        );
+        // Carries on from where it left off
+        notes.clear();
+        chart.read(1200, |note| notes.push(note));
+        assert_eq!(
+            vec![1152],
+            notes.iter().map(|note| note.tick).collect::<Vec<_>>()
+        );
+        assert!(!chart.is_finished());
+        // More than can be read in one go, but the rest come next time
+        chart.read(u32::MAX, |_| {});
+        assert!(!chart.is_finished());
+        chart.read(u32::MAX, |_| {});
+        assert!(chart.is_finished());
+
+        // Nothing on other channels
+        let (header, tracks) = twinkle();
+        let chart = Chart::<4>::new(&header, tracks, 3);
+        assert_eq!(0, chart.num_notes());
+        assert!(chart.is_finished());
+    }
+
+    #[test]
+    fn dense_chords_should_all_be_read() {
+        // Four note chords a 16th apart for eight beats, more than fit in
+        // one read
+        let mut buffer = [0u8; 2048];
+        let mut writer = SmfWriter::new(&mut buffer, SmfFormat::SingleTrack, 96).unwrap();
+        writer.start_track().unwrap();
+        for chord in 0..32 {
+            for key in [60, 64, 67, 72] {
+                let message = MidiMessage::NoteOn {
+                    key: key.into(),
+                    vel: 100.into(),
+                };
+                writer.midi_event(chord * 24, 0, &message).unwrap();
+            }
+        }
+        writer.end_track(8 * 96).unwrap();
+        let (header, tracks) = midly::parse(writer.finish().unwrap()).unwrap();
+
+        // One note from each chord
+        let mut chart = Chart::<1>::new(&header, tracks, 0);
+        assert_eq!(32, chart.num_notes());
+        let mut ticks = vec![];
+        while !chart.is_finished() {
+            chart.read(8 * 96, |note| ticks.push(note.tick));
+        }
+        assert_eq!((0..32).map(|chord| chord * 24).collect::<Vec<_>>(), ticks);
+    }
+
+    #[test]
+    fn the_game_should_keep_score() {
+        let (header, tracks) = twinkle();
+        let mut chart = Chart::<4>::new(&header, tracks, 0);
+        let mut game = RhythmGame::new(chart.ticks_per_quarter_note());
+        game.update(&mut chart, 0);
+        // Four beats of quarter notes
+        assert_eq!(4, game.notes().len());
+        assert_eq!(1152, game.position(&game.notes()[3], 0, 384 * 4));
+
+        // Nothing in the lane
+        assert_eq!(None, game.press(Lane::High, 0));
+        assert_eq!(Some(Judgement::Perfect), game.press(Lane::Low, 10));
+        assert_eq!(100, game.score());
+        // Too early for the next one
+        assert_eq!(None, game.press(Lane::Low, 200));
+        game.update(&mut chart, 300);
+        assert_eq!(Some(Judgement::Good), game.press(Lane::Low, 300));
+        assert_eq!(150, game.score());
+        assert_eq!(2, game.combo());
+
+        // The note at 768 goes by
+        game.update(&mut chart, 768 + 97);
+        assert_eq!(Some(Judgement::Miss), game.last_judgement());
+        assert_eq!(0, game.combo());
+        assert_eq!(2, game.best_combo());
+        assert_eq!(1, game.count(Judgement::Perfect));
+        assert_eq!(1, game.count(Judgement::Good));
+        assert_eq!(1, game.count(Judgement::Miss));
+        assert!(game.position(&game.notes()[0], 768 + 97, 100) > 0);
+    }
+
+    #[test]
+    fn combos_should_multiply_points() {
+        let (header, tracks) = twinkle();
+        let mut chart = Chart::<4>::new(&header, tracks, 0);
+        let mut game = RhythmGame::new(chart.ticks_per_quarter_note());
+        let mut now = 0;
+        while !game.is_finished(&chart) {
+            game.update(&mut chart, now);
+            if let Some(note) = game.notes().first().copied() {
+                if note.tick == now {
+                    assert_eq!(Some(Judgement::Perfect), game.press(note.lane, now));
+                }
+            }
+            now += 1;
+        }
+        assert_eq!(70, game.count(Judgement::Perfect));
+        assert_eq!(70, game.best_combo());
+        assert_eq!(MAX_MULTIPLIER, game.multiplier());
+        // 10 at each multiplier up to 4, then the rest at 4
+        assert_eq!(100 * (10 + 2 * 10 + 3 * 10 + 4 * 40), game.score());
+    }
+}
@@ -0,0 +1,536 @@
+// RTTTL ringtones.
+//
+// RTTTL (the Nokia ringtone format) fits a whole tune on one line:
+//
+//   Twinkle:d=4,o=5,b=120:c,c,g,g,a,a,2g,f,f,e,e,d,d,2c
+//
+// A name, then default duration, octave and tempo, then the notes.  Each
+// note is [duration]letter[#][.][octave][.], where the duration is 1 for a
+// whole note up to 32 for a thirty-second, the dot makes it half as long
+// again and p is a pause.  Anything left out takes the default.
+//
+// RtttlPlayer turns the notes into note on and note off messages on channel
+// 0 and plays them through the same AmpAdder and event handling as Midi, so
+// a tune can use any program in NoteEnum.
+//
+
+use crate::amp_adder::AmpAdder;
+use crate::master_volume::MasterVolume;
+use crate::midi_channels::Channels;
+use crate::midi_events::handle_midi_event;
+use crate::midi_events::update_glides;
+use crate::midi_time::MidiTime;
+use crate::playback_settings::PlaybackSettings;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::SoundSourceCore;
+use midly::num::u7;
+
+/// Ticks in a quarter note.  Divides down to a dotted thirty-second.
+pub const TICKS_PER_QUARTER_NOTE: u32 = 96;
+
+// Notes are let go after this much of their length, so repeated notes are
+// heard as separate notes.
+const GATE_PERCENT: u32 = 90;
+
+const VELOCITY: u8 = 100;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RtttlError {
+    /// There aren't three sections split by ':'
+    MissingSection,
+    /// A default in the middle section can't be read
+    BadDefault,
+    /// A note can't be read, or is off the end of the keyboard
+    BadNote,
+}
+
+///
+/// One note, or a pause if key is None
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RtttlNote {
+    pub key: Option<u8>,
+    /// Length, in TICKS_PER_QUARTER_NOTE ticks
+    pub ticks: u32,
+}
+
+///
+/// A parsed RTTTL tune.  The notes are read as they're played.
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rtttl<'a> {
+    pub name: &'a str,
+    /// Default duration, 4 for a quarter note
+    pub duration: u8,
+    /// Default octave, where octave 4 has middle A (440hz)
+    pub octave: u8,
+    /// Tempo, in quarter notes per minute
+    pub bpm: u32,
+    notes: &'a str,
+}
+
+// Highest octave, for o= and the notes.  B9 is off the top of the keyboard,
+// but C9 to G9 are still on it.
+const MAX_OCTAVE: u32 = 9;
+
+fn is_valid_duration(duration: u32) -> bool {
+    matches!(duration, 1 | 2 | 4 | 8 | 16 | 32)
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    if text.is_empty() || !text.bytes().all(|c| c.is_ascii_digit()) {
+        None
+    } else {
+        text.parse().ok()
+    }
+}
+
+// Take the digits at the start of text, if there are any.  Too many digits
+// for a u32 is u32::MAX, so it's still rejected as too big.
+fn split_number(text: &[u8]) -> (Option<u32>, &[u8]) {
+    let digits = text.iter().take_while(|c| c.is_ascii_digit()).count();
+    let number = text[..digits]
+        .iter()
+        .try_fold(0u32, |n, c| {
+            n.checked_mul(10)?.checked_add((c - b'0') as u32)
+        })
+        .unwrap_or(u32::MAX);
+    match digits {
+        0 => (None, text),
+        _ => (Some(number), &text[digits..]),
+    }
+}
+
+impl<'a> Rtttl<'a> {
+    pub fn parse(text: &'a str) -> Result<Self, RtttlError> {
+        let mut sections = text.splitn(3, ':');
+        let name = sections.next().ok_or(RtttlError::MissingSection)?.trim();
+        let defaults = sections.next().ok_or(RtttlError::MissingSection)?;
+        let notes = sections.next().ok_or(RtttlError::MissingSection)?;
+
+        // The defaults from the RTTTL spec, for any that are left out
+        let mut rval = Self {
+            name,
+            duration: 4,
+            octave: 6,
+            bpm: 63,
+            notes,
+        };
+        for setting in defaults.split(',').map(str::trim) {
+            if setting.is_empty() {
+                continue;
+            }
+            let (key, value) = setting.split_once('=').ok_or(RtttlError::BadDefault)?;
+            let value = parse_number(value.trim()).ok_or(RtttlError::BadDefault)?;
+            match key.trim() {
+                "d" | "D" if is_valid_duration(value) => rval.duration = value as u8,
+                "o" | "O" if value <= MAX_OCTAVE => rval.octave = value as u8,
+                "b" | "B" if value > 0 && value <= 900 => rval.bpm = value,
+                "d" | "D" | "o" | "O" | "b" | "B" => return Err(RtttlError::BadDefault),
+                // Some tunes have extras, like l= for looping
+                _ => {}
+            }
+        }
+
+        // Check every note now, so playing can't fail part way through.
+        for note in notes.split(',') {
+            rval.parse_note(note)?;
+        }
+        Ok(rval)
+    }
+
+    /// Tempo, in microseconds per quarter note like a MIDI tempo event
+    ///
+    pub fn us_per_quarter_note(&self) -> u32 {
+        60_000_000 / self.bpm
+    }
+
+    pub fn notes(&self) -> RtttlNotes<'a> {
+        RtttlNotes {
+            tune: *self,
+            remaining: self.notes.split(','),
+        }
+    }
+
+    // None for an empty note, which a trailing comma leaves
+    fn parse_note(&self, text: &str) -> Result<Option<RtttlNote>, RtttlError> {
+        let text = text.trim().as_bytes();
+        if text.is_empty() {
+            return Ok(None);
+        }
+
+        let (duration, text) = split_number(text);
+        let duration = duration.unwrap_or(self.duration as u32);
+        if !is_valid_duration(duration) {
+            return Err(RtttlError::BadNote);
+        }
+
+        let (letter, mut text) = text.split_first().ok_or(RtttlError::BadNote)?;
+        let semitone = match letter.to_ascii_lowercase() {
+            b'c' => Some(0),
+            b'd' => Some(2),
+            b'e' => Some(4),
+            b'f' => Some(5),
+            b'g' => Some(7),
+            b'a' => Some(9),
+            // h is the German name for b
+            b'b' | b'h' => Some(11),
+            b'p' => None,
+            _ => return Err(RtttlError::BadNote),
+        };
+        let mut sharp = false;
+        if let Some((b'#', rest)) = text.split_first() {
+            sharp = true;
+            text = rest;
+        }
+        // The dot can go before or after the octave.
+        let mut dotted = false;
+        if let Some((b'.', rest)) = text.split_first() {
+            dotted = true;
+            text = rest;
+        }
+        let (octave, mut text) = split_number(text);
+        let octave = octave.unwrap_or(self.octave as u32);
+        if octave > MAX_OCTAVE {
+            return Err(RtttlError::BadNote);
+        }
+        if let Some((b'.', rest)) = text.split_first() {
+            dotted = true;
+            text = rest;
+        }
+        if !text.is_empty() {
+            return Err(RtttlError::BadNote);
+        }
+
+        let key = match semitone {
+            Some(semitone) => {
+                let key = 12 * (octave + 1) + semitone + sharp as u32;
+                if key > 127 {
+                    return Err(RtttlError::BadNote);
+                }
+                Some(key as u8)
+            }
+            None => None,
+        };
+        let ticks = 4 * TICKS_PER_QUARTER_NOTE / duration;
+        let ticks = if dotted { ticks * 3 / 2 } else { ticks };
+        Ok(Some(RtttlNote { key, ticks }))
+    }
+}
+
+///
+/// The notes of a tune, in order
+///
+#[derive(Clone)]
+pub struct RtttlNotes<'a> {
+    tune: Rtttl<'a>,
+    remaining: core::str::Split<'a, char>,
+}
+
+impl Iterator for RtttlNotes<'_> {
+    type Item = RtttlNote;
+
+    fn next(&mut self) -> Option<RtttlNote> {
+        loop {
+            // parse checked them all, so errors can't happen here.
+            if let Ok(Some(note)) = self.tune.parse_note(self.remaining.next()?) {
+                return Some(note);
+            }
+        }
+    }
+}
+
+///
+/// Plays an RTTTL tune with one program
+///
+/// Only one key is down at a time, but notes ring on through their release
+/// while the next ones play, so MAX_NOTES still has to cover the longest
+/// release.
+///
+pub struct RtttlPlayer<
+    'a,
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    const MAX_NOTES: usize,
+    const NO_SCALEDOWN: bool = false,
+> {
+    notes: RtttlNotes<'a>,
+    amp_adder: AmpAdder<P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>,
+    channels: Channels,
+    tempo: MidiTime<P_FREQ, U_FREQ>,
+    skip_count: u32,
+    settings: PlaybackSettings,
+    volume: MasterVolume<P_FREQ>,
+    /// Key that's down, and when to let it go
+    held_key: Option<u8>,
+    note_off_time: u32,
+    next_note_time: u32,
+    notes_still_playing: bool,
+}
+
+impl<
+        'a,
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        const MAX_NOTES: usize,
+        const NO_SCALEDOWN: bool,
+    > RtttlPlayer<'a, P_FREQ, U_FREQ, MAX_NOTES, NO_SCALEDOWN>
+{
+    const SKIP: u32 = P_FREQ / U_FREQ;
+
+    pub fn new_internal(tune: &Rtttl<'a>, divider: i32, settings: PlaybackSettings) -> Self {
+        assert_eq!(0, (P_FREQ % U_FREQ));
+        let mut tempo = MidiTime::new(tune.us_per_quarter_note(), TICKS_PER_QUARTER_NOTE);
+        tempo.set_tempo_percent(settings.tempo_percent);
+
+        Self {
+            notes: tune.notes(),
+            amp_adder: AmpAdder::new(divider),
+            channels: Channels::default(),
+            tempo,
+            skip_count: 0,
+            settings,
+            volume: MasterVolume::new(),
+            held_key: None,
+            note_off_time: 0,
+            next_note_time: 0,
+            notes_still_playing: true,
+        }
+    }
+
+    pub fn get_loudest_sample(tune: &Rtttl<'a>, settings: PlaybackSettings) -> i32 {
+        let mut fast_forward_player =
+            RtttlPlayer::<240, 240, MAX_NOTES, true>::new_internal(tune, 0, settings);
+        let mut loudest: i32 = 0;
+        while fast_forward_player.has_next() {
+            loudest = core::cmp::max(loudest, fast_forward_player.get_next().to_i32().abs());
+        }
+        loudest
+    }
+
+    /// Play tune with program, from NoteEnum
+    ///
+    pub fn new(tune: &Rtttl<'a>, program: u8) -> Self {
+        let mut settings = PlaybackSettings::DEFAULT;
+        settings.channel_programs[0] = program as i32;
+        Self::new_with_settings(tune, settings)
+    }
+
+    /// Play tune with settings.  Set the program with channel_programs[0].
+    ///
+    pub fn new_with_settings(tune: &Rtttl<'a>, settings: PlaybackSettings) -> Self {
+        let loudest = Self::get_loudest_sample(tune, settings);
+        Self::new_internal(tune, loudest / 0x8000 + 1, settings)
+    }
+
+    /// Play new notes with program
+    ///
+    pub fn set_program(&mut self, program: u8) {
+        self.settings.channel_programs[0] = program as i32;
+    }
+
+    /// Move new notes up or down by semitones
+    ///
+    pub fn set_transpose(&mut self, semitones: i8) {
+        self.settings.transpose = semitones;
+    }
+
+    /// Play at tempo_percent of the tune's tempo
+    ///
+    pub fn set_tempo_percent(&mut self, tempo_percent: u32) {
+        self.settings.tempo_percent = tempo_percent;
+        self.tempo.set_tempo_percent(tempo_percent);
+    }
+
+    /// Change the master volume, from 0 to 100.  The change is ramped, so
+    /// it's safe to call while playing.
+    ///
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume.set_volume(volume);
+    }
+
+    pub fn get_volume(&self) -> u8 {
+        self.volume.get_volume()
+    }
+
+    /// Come up from silence to the master volume over time_in_ms
+    ///
+    pub fn fade_in(&mut self, time_in_ms: i32) {
+        self.volume.fade_in(time_in_ms);
+    }
+
+    /// Go down to silence over time_in_ms.  Playback carries on, so check
+    /// is_faded_out to know when it's safe to stop.
+    ///
+    pub fn fade_out(&mut self, time_in_ms: i32) {
+        self.volume.fade_out(time_in_ms);
+    }
+
+    pub fn is_faded_out(&self) -> bool {
+        self.volume.is_silent()
+    }
+
+    fn send(&mut self, message: midly::MidiMessage) {
+        handle_midi_event(
+            &message,
+            0,
+            &mut self.amp_adder,
+            &mut self.channels,
+            &self.settings,
+        );
+    }
+
+    fn release_held_key(&mut self) {
+        if let Some(key) = self.held_key.take() {
+            self.send(midly::MidiMessage::NoteOff {
+                key: u7::new(key),
+                vel: u7::new(0),
+            });
+        }
+    }
+
+    pub fn update(&mut self) {
+        self.tempo.advance_time();
+        let now = self.tempo.get_current_time();
+        if self.held_key.is_some() && now >= self.note_off_time {
+            self.release_held_key();
+        }
+        while self.notes_still_playing && now >= self.next_note_time {
+            match self.notes.next() {
+                Some(note) => {
+                    if let Some(key) = note.key {
+                        self.release_held_key();
+                        self.send(midly::MidiMessage::NoteOn {
+                            key: u7::new(key),
+                            vel: u7::new(VELOCITY),
+                        });
+                        self.held_key = Some(key);
+                        self.note_off_time = self.next_note_time + note.ticks * GATE_PERCENT / 100;
+                    }
+                    self.next_note_time += note.ticks;
+                }
+                None => self.notes_still_playing = false,
+            }
+        }
+        update_glides(&mut self.amp_adder, &mut self.channels);
+        self.amp_adder.update();
+    }
+
+    pub fn get_next(&mut self) -> SoundSampleI32 {
+        if self.skip_count == 0 {
+            self.update();
+        }
+        self.skip_count += 1;
+        if self.skip_count == Self::SKIP {
+            self.skip_count = 0;
+        }
+        self.volume.apply(self.amp_adder.get_next())
+    }
+
+    pub fn get_note_state(&self, note_volume: &mut [u8; 128]) {
+        note_volume.fill(0);
+        self.channels.get_note_state(note_volume);
+    }
+
+    /// True until the last note's time is up
+    ///
+    pub fn has_next(&self) -> bool {
+        self.notes_still_playing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rtttl::*;
+
+    const TWINKLE: &str = "Twinkle:d=4,o=5,b=120:c,c,g,g,a,a,2g,f,f,e,e,d,d,2c";
+
+    fn notes(text: &str) -> Vec<RtttlNote> {
+        Rtttl::parse(text).unwrap().notes().collect()
+    }
+
+    fn note(key: Option<u8>, ticks: u32) -> RtttlNote {
+        RtttlNote { key, ticks }
+    }
+
+    #[test]
+    fn defaults_should_parse() {
+        let tune = Rtttl::parse(TWINKLE).unwrap();
+        assert_eq!("Twinkle", tune.name);
+        assert_eq!((4, 5, 120), (tune.duration, tune.octave, tune.bpm));
+        assert_eq!(500000, tune.us_per_quarter_note());
+        assert_eq!(14, tune.notes().count());
+
+        // Left out, so from the spec
+        let tune = Rtttl::parse("Empty::").unwrap();
+        assert_eq!((4, 6, 63), (tune.duration, tune.octave, tune.bpm));
+        assert_eq!(0, tune.notes().count());
+    }
+
+    #[test]
+    fn notes_should_parse() {
+        assert_eq!(
+            vec![
+                note(Some(72), 96),
+                note(Some(81), 192),
+                note(Some(70), 144),
+                note(Some(71), 72),
+                note(None, 12),
+                note(Some(51), 576),
+                note(Some(83), 96),
+            ],
+            notes("x:d=4,o=5,b=100: c, 2a, a#4., 8B4., 32p, 1d#.3, H")
+        );
+    }
+
+    #[test]
+    fn bad_tunes_should_be_rejected() {
+        assert_eq!(Err(RtttlError::MissingSection), Rtttl::parse("x:d=4"));
+        assert_eq!(Err(RtttlError::BadDefault), Rtttl::parse("x:d=3:c"));
+        assert_eq!(Err(RtttlError::BadDefault), Rtttl::parse("x:b=0:c"));
+        assert_eq!(Err(RtttlError::BadDefault), Rtttl::parse("x:o:c"));
+        assert_eq!(Err(RtttlError::BadNote), Rtttl::parse("x::c,q"));
+        assert_eq!(Err(RtttlError::BadNote), Rtttl::parse("x::c,6c"));
+        assert_eq!(Err(RtttlError::BadNote), Rtttl::parse("x::c,c#x"));
+        assert_eq!(Err(RtttlError::BadNote), Rtttl::parse("x::b9"));
+        assert_eq!(Err(RtttlError::BadNote), Rtttl::parse("x::c10"));
+        assert_eq!(Err(RtttlError::BadNote), Rtttl::parse("x::c4294967295"));
+        assert_eq!(Err(RtttlError::BadNote), Rtttl::parse("x::c99999999999"));
+        assert_eq!(Err(RtttlError::BadNote), Rtttl::parse("x::99999999999c"));
+        // Unknown defaults and trailing commas are fine
+        assert!(Rtttl::parse("x:l=1,d=8,:c,").is_ok());
+    }
+
+    #[test]
+    fn tunes_should_play_for_their_length() {
+        // Two quarter notes and a quarter rest at 120bpm: 1.5 seconds
+        let tune = Rtttl::parse("x:d=4,o=5,b=120:c,e,p").unwrap();
+        let mut player = RtttlPlayer::<24000, 240, 8>::new(&tune, 0);
+        let mut samples = 0;
+        let mut loudest = 0;
+        let mut notes = [0; 128];
+        while player.has_next() {
+            loudest = core::cmp::max(loudest, player.get_next().to_i32().abs());
+            samples += 1;
+            if samples == 15000 {
+                player.get_note_state(&mut notes);
+                assert!(notes[76] > 0);
+                assert_eq!(0, notes[72]);
+            }
+        }
+        assert!((35900..=36100).contains(&samples), "{}", samples);
+        assert!(loudest > 0x1000 && loudest <= 0x8000, "{}", loudest);
+    }
+
+    #[test]
+    fn programs_without_an_instrument_should_still_play() {
+        // Gunshot has no instrument of its own.
+        let tune = Rtttl::parse("x:d=4,o=5,b=120:c").unwrap();
+        let mut player = RtttlPlayer::<24000, 240, 8>::new(&tune, 127);
+        let mut loudest = 0;
+        while player.has_next() {
+            loudest = core::cmp::max(loudest, player.get_next().to_i32().abs());
+        }
+        assert!(loudest > 0x1000, "{}", loudest);
+    }
+}
@@ -0,0 +1,227 @@
+// Sampled instrument data.
+//
+// A sampled instrument is a list of zones.  Each zone covers a range of
+// keys and velocities and plays one recorded sample, which is pitched up or
+// down from the key it was recorded at.  A piano might have a zone every
+// few keys, and two or three velocity layers in each so hard notes use a
+// hard hit.
+//
+// Everything here is meant to be const data, with the sample audio
+// included from flash with include_bytes!, so an instrument costs no RAM.
+//
+
+use crate::midi_notes::detune_cents;
+use crate::midi_notes::midi_note_to_freq;
+
+/// How the sample audio is stored
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SampleFormat {
+    /// Unsigned 8 bit, 0x80 is zero.  What ffmpeg's -f u8 writes.
+    U8,
+    /// Signed 16 bit, little endian.  What ffmpeg's -f s16le writes.
+    I16Le,
+}
+
+/// What a sample does when it gets to its loop end
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LoopMode {
+    /// Play once, to the end of the data
+    None,
+    /// Loop for as long as the note lasts
+    Continuous,
+    /// Loop while the key is held, then play on to the end of the data
+    UntilRelease,
+}
+
+///
+/// One recorded sample
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Sample {
+    pub format: SampleFormat,
+    pub data: &'static [u8],
+    /// Rate it was recorded (or converted) at, in hz
+    pub sample_rate: u32,
+    pub loop_mode: LoopMode,
+    /// Loop points, in samples.  The loop plays loop_start up to, but not
+    /// including, loop_end.
+    pub loop_start: u32,
+    pub loop_end: u32,
+}
+
+impl Sample {
+    /// Length, in samples
+    ///
+    pub const fn len(&self) -> u32 {
+        match self.format {
+            SampleFormat::U8 => self.data.len() as u32,
+            SampleFormat::I16Le => (self.data.len() / 2) as u32,
+        }
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// True if the loop points make sense to loop on
+    ///
+    pub const fn can_loop(&self) -> bool {
+        !matches!(self.loop_mode, LoopMode::None)
+            && self.loop_start < self.loop_end
+            && self.loop_end <= self.len()
+    }
+
+    /// The sample at idx, from -0x8000 to 0x7fff
+    ///
+    #[inline]
+    pub fn at(&self, idx: u32) -> i32 {
+        let idx = idx as usize;
+        match self.format {
+            SampleFormat::U8 => ((self.data[idx] as i32) - 0x80) << 8,
+            SampleFormat::I16Le => {
+                i16::from_le_bytes([self.data[idx * 2], self.data[idx * 2 + 1]]) as i32
+            }
+        }
+    }
+}
+
+///
+/// A range of keys and velocities, and the sample they play
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Zone {
+    pub low_key: u8,
+    pub high_key: u8,
+    pub low_velocity: u8,
+    pub high_velocity: u8,
+    /// Key the sample plays at its own pitch
+    pub root_key: u8,
+    /// Cents to move the pitch by, like a SoundFont's fine tune.  Positive
+    /// values play sharper.
+    pub fine_tune: i16,
+    /// Level, as a percentage
+    pub volume: u8,
+    pub sample: &'static Sample,
+}
+
+impl Zone {
+    pub const fn contains(&self, key: u8, velocity: u8) -> bool {
+        key >= self.low_key
+            && key <= self.high_key
+            && velocity >= self.low_velocity
+            && velocity <= self.high_velocity
+    }
+
+    /// Frequency the sample plays at without resampling, multiplied by
+    /// FREQUENCY_MULTIPLIER.  Tuning the zone sharp means the sample is
+    /// treated as if it were recorded flat.
+    ///
+    pub fn root_frequency(&self) -> u32 {
+        detune_cents(midi_note_to_freq(self.root_key), -(self.fine_tune as i32))
+    }
+}
+
+///
+/// Patch data for a sampled instrument
+///
+pub trait SamplePatch {
+    /// Zones, searched in order.  The first one that covers the key and
+    /// velocity plays.
+    const ZONES: &'static [Zone];
+
+    fn find_zone(key: u8, velocity: u8) -> Option<&'static Zone> {
+        Self::ZONES.iter().find(|zone| zone.contains(key, velocity))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sample_data::*;
+
+    static DATA_U8: [u8; 4] = [0x80, 0xff, 0x00, 0x40];
+    static DATA_I16: [u8; 4] = [0x00, 0x40, 0x00, 0xc0];
+
+    static SAMPLE_U8: Sample = Sample {
+        format: SampleFormat::U8,
+        data: &DATA_U8,
+        sample_rate: 24000,
+        loop_mode: LoopMode::Continuous,
+        loop_start: 1,
+        loop_end: 4,
+    };
+
+    static SAMPLE_I16: Sample = Sample {
+        format: SampleFormat::I16Le,
+        data: &DATA_I16,
+        sample_rate: 24000,
+        loop_mode: LoopMode::Continuous,
+        loop_start: 1,
+        loop_end: 4,
+    };
+
+    struct Layered {}
+
+    impl SamplePatch for Layered {
+        const ZONES: &'static [Zone] = &[
+            Zone {
+                low_key: 0,
+                high_key: 59,
+                low_velocity: 0,
+                high_velocity: 127,
+                root_key: 48,
+                fine_tune: 0,
+                volume: 100,
+                sample: &SAMPLE_U8,
+            },
+            Zone {
+                low_key: 60,
+                high_key: 127,
+                low_velocity: 0,
+                high_velocity: 63,
+                root_key: 72,
+                fine_tune: 0,
+                volume: 100,
+                sample: &SAMPLE_U8,
+            },
+            Zone {
+                low_key: 60,
+                high_key: 127,
+                low_velocity: 64,
+                high_velocity: 127,
+                root_key: 72,
+                fine_tune: 0,
+                volume: 100,
+                sample: &SAMPLE_I16,
+            },
+        ];
+    }
+
+    #[test]
+    fn samples_should_decode() {
+        assert_eq!(4, SAMPLE_U8.len());
+        assert_eq!(0, SAMPLE_U8.at(0));
+        assert_eq!(0x7f00, SAMPLE_U8.at(1));
+        assert_eq!(-0x8000, SAMPLE_U8.at(2));
+        assert_eq!(2, SAMPLE_I16.len());
+        assert_eq!(0x4000, SAMPLE_I16.at(0));
+        assert_eq!(-0x4000, SAMPLE_I16.at(1));
+        // The loop goes past the end of the 16 bit data.
+        assert!(SAMPLE_U8.can_loop());
+        assert!(!SAMPLE_I16.can_loop());
+    }
+
+    #[test]
+    fn zones_should_split_keys_and_velocities() {
+        assert_eq!(48, Layered::find_zone(40, 100).unwrap().root_key);
+        assert_eq!(
+            SampleFormat::U8,
+            Layered::find_zone(70, 20).unwrap().sample.format
+        );
+        assert_eq!(
+            SampleFormat::I16Le,
+            Layered::find_zone(70, 100).unwrap().sample.format
+        );
+    }
+}
@@ -0,0 +1,425 @@
+// Sample player.
+//
+// Plays a recorded sample from a SamplePatch, picking the zone by key and
+// velocity.  Notes other than the zone's root key are played by stepping
+// through the sample faster or slower than one sample per output sample,
+// interpolating linearly between neighbouring samples.  That's cheap, and
+// fine for a few semitones either side of the root; further out it starts
+// to alias (going up) or sound dull (going down), which is why instruments
+// use more than one zone.
+//
+
+use crate::adsr::CoreDahdsr;
+use crate::adsr::EnvelopeCurve;
+use crate::note::SoundSourceNoteInit;
+use crate::sample_data::LoopMode;
+use crate::sample_data::SamplePatch;
+use crate::sample_data::Zone;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::OscillatorInterface;
+use crate::sound_source_core::SoundSourceCore;
+use core::marker::PhantomData;
+
+///
+/// Sample player core
+///
+/// Has no envelope of its own; wrap it in an envelope with SampledInstrument.
+///
+pub struct SamplePlayer<const P_FREQ: u32, const U_FREQ: u32, Patch: SamplePatch> {
+    zone: Option<&'static Zone>,
+    /// Position in the sample, 16.16
+    position: u64,
+    /// Position step per output sample, 16.16
+    position_inc: u32,
+    base_position_inc: u32,
+    looping: bool,
+    finished: bool,
+    max_amplitude: i32,
+    amplitude: i32,
+    _marker: PhantomData<Patch>,
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, Patch: SamplePatch> SamplePlayer<P_FREQ, U_FREQ, Patch> {
+    fn start(&mut self) {
+        self.position = 0;
+        match self.zone {
+            Some(zone) => {
+                self.looping = zone.sample.can_loop();
+                self.finished = zone.sample.is_empty();
+            }
+            None => {
+                self.looping = false;
+                self.finished = true;
+            }
+        }
+    }
+
+    //
+    // Index of the sample after idx, following the loop if there is one.
+    //
+    #[inline]
+    fn next_idx(&self, zone: &Zone, idx: u32) -> Option<u32> {
+        let sample = zone.sample;
+        if self.looping && idx + 1 >= sample.loop_end {
+            Some(sample.loop_start)
+        } else if idx + 1 < sample.len() {
+            Some(idx + 1)
+        } else {
+            None
+        }
+    }
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, Patch: SamplePatch> SoundSourceCore<P_FREQ, U_FREQ>
+    for SamplePlayer<P_FREQ, U_FREQ, Patch>
+{
+    type InitValuesType = SoundSourceNoteInit;
+
+    fn new(init_values: Self::InitValuesType) -> Self {
+        let zone = Patch::find_zone(init_values.key, init_values.velocity);
+        let (position_inc, max_amplitude) = match zone {
+            Some(zone) => {
+                let frequency = init_values.frequency(0) as u64;
+                let inc = (((zone.sample.sample_rate as u64) * frequency) << 16)
+                    / ((P_FREQ as u64) * (zone.root_frequency() as u64));
+                // Half scale, like the oscillators.
+                (inc as u32, 0x4000 * (zone.volume as i32) / 100)
+            }
+            None => (0, 0),
+        };
+        let mut rval = Self {
+            zone,
+            position: 0,
+            position_inc,
+            base_position_inc: position_inc,
+            looping: false,
+            finished: true,
+            max_amplitude,
+            amplitude: max_amplitude,
+            _marker: PhantomData,
+        };
+        rval.start();
+        rval
+    }
+
+    #[inline]
+    fn get_next(&mut self) -> SoundSampleI32 {
+        let zone = match self.zone {
+            Some(zone) if !self.finished => zone,
+            _ => return SoundSampleI32::ZERO,
+        };
+        let idx = (self.position >> 16) as u32;
+        let fraction = (self.position & 0xffff) as i64;
+        let current = zone.sample.at(idx);
+        let interpolated = match self.next_idx(zone, idx) {
+            Some(next_idx) if fraction != 0 => {
+                // A full scale step times the fraction needs 33 bits.
+                let step = (zone.sample.at(next_idx) - current) as i64;
+                current + ((step * fraction) >> 16) as i32
+            }
+            _ => current,
+        };
+
+        self.position += self.position_inc as u64;
+        let sample = zone.sample;
+        if self.looping {
+            let loop_end = (sample.loop_end as u64) << 16;
+            let loop_length = ((sample.loop_end - sample.loop_start) as u64) << 16;
+            while self.position >= loop_end {
+                self.position -= loop_length;
+            }
+        } else if self.position >= (sample.len() as u64) << 16 {
+            self.finished = true;
+        }
+
+        SoundSampleI32::new_i32((interpolated * self.amplitude) >> 15)
+    }
+
+    fn update(&mut self) {}
+
+    fn has_next(&self) -> bool {
+        !self.finished
+    }
+
+    fn trigger_note_off(&mut self) {
+        if let Some(zone) = self.zone {
+            if zone.sample.loop_mode == LoopMode::UntilRelease {
+                self.looping = false;
+            }
+        }
+    }
+
+    fn reset_oscillator(&mut self) {
+        self.position = 0;
+    }
+
+    fn restart(&mut self, _vel: u8) {
+        self.start();
+    }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.position_inc = (((self.base_position_inc as u64) * (ratio as u64)) >> 16) as u32;
+        true
+    }
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, Patch: SamplePatch> OscillatorInterface<P_FREQ, U_FREQ>
+    for SamplePlayer<P_FREQ, U_FREQ, Patch>
+{
+    fn set_amplitude_adjust(&mut self, adjust: SoundSampleI32) {
+        self.amplitude = (self.max_amplitude * adjust.to_i32()) >> 15;
+    }
+}
+
+///
+/// Sampled instrument: a SamplePlayer with a DAHDSR envelope
+///
+/// The envelope follows SoundFont volume envelopes: a linear attack, and a
+/// decay and release that fall evenly in dB.  The note ends when the
+/// release is over, or when a sample that doesn't loop runs out, whichever
+/// comes first.
+///
+pub struct SampledInstrument<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    Patch: SamplePatch,
+    const DELAY: i32,
+    const A: i32,
+    const HOLD: i32,
+    const D: i32,
+    const S: u8,
+    const R: i32,
+> {
+    core: CoreDahdsr<
+        P_FREQ,
+        U_FREQ,
+        DELAY,
+        A,
+        HOLD,
+        D,
+        S,
+        R,
+        { EnvelopeCurve::Linear as usize },
+        { EnvelopeCurve::Exponential as usize },
+        { EnvelopeCurve::Exponential as usize },
+        0,
+        SamplePlayer<P_FREQ, U_FREQ, Patch>,
+    >,
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        Patch: SamplePatch,
+        const DELAY: i32,
+        const A: i32,
+        const HOLD: i32,
+        const D: i32,
+        const S: u8,
+        const R: i32,
+    > SoundSourceCore<P_FREQ, U_FREQ>
+    for SampledInstrument<P_FREQ, U_FREQ, Patch, DELAY, A, HOLD, D, S, R>
+{
+    type InitValuesType = SoundSourceNoteInit;
+
+    fn new(init_values: Self::InitValuesType) -> Self {
+        let velocity = init_values.velocity as i32;
+        Self {
+            core: CoreDahdsr::new((init_values, velocity << 8)),
+        }
+    }
+
+    #[inline]
+    fn get_next(&mut self) -> SoundSampleI32 {
+        self.core.get_next()
+    }
+
+    fn update(&mut self) {
+        self.core.update();
+    }
+
+    fn has_next(&self) -> bool {
+        self.core.has_next() && self.core.source().has_next()
+    }
+
+    fn trigger_note_off(&mut self) {
+        // The envelope doesn't pass note offs on, and the sample needs it
+        // to leave a release loop.
+        self.core.trigger_note_off();
+        self.core.source_mut().trigger_note_off();
+    }
+
+    fn reset_oscillator(&mut self) {
+        self.core.reset_oscillator();
+    }
+
+    fn restart(&mut self, vel: u8) {
+        self.core.restart(vel);
+    }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.core.set_pitch_ratio(ratio)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sample_data::*;
+    use crate::sample_player::*;
+
+    // A square wave, 8 samples a cycle.  At 24khz that's 3000hz.
+    static SQUARE: [u8; 16] = [
+        0xc0, 0xc0, 0xc0, 0xc0, 0x40, 0x40, 0x40, 0x40, 0xc0, 0xc0, 0xc0, 0xc0, 0x40, 0x40, 0x40,
+        0x40,
+    ];
+
+    static LOOPED: Sample = Sample {
+        format: SampleFormat::U8,
+        data: &SQUARE,
+        sample_rate: 24000,
+        loop_mode: LoopMode::Continuous,
+        loop_start: 8,
+        loop_end: 16,
+    };
+
+    static ONE_SHOT: Sample = Sample {
+        format: SampleFormat::U8,
+        data: &SQUARE,
+        sample_rate: 24000,
+        loop_mode: LoopMode::None,
+        loop_start: 0,
+        loop_end: 0,
+    };
+
+    static UNTIL_RELEASE: Sample = Sample {
+        format: SampleFormat::U8,
+        data: &SQUARE,
+        sample_rate: 24000,
+        loop_mode: LoopMode::UntilRelease,
+        loop_start: 0,
+        loop_end: 8,
+    };
+
+    const fn zone(sample: &'static Sample) -> Zone {
+        Zone {
+            low_key: 0,
+            high_key: 127,
+            low_velocity: 0,
+            high_velocity: 127,
+            root_key: 69,
+            fine_tune: 0,
+            volume: 100,
+            sample,
+        }
+    }
+
+    // Full scale steps, as 16 bit samples
+    static EXTREMES: [u8; 8] = [0x00, 0x80, 0xff, 0x7f, 0x00, 0x80, 0xff, 0x7f];
+
+    static STEPS: Sample = Sample {
+        format: SampleFormat::I16Le,
+        data: &EXTREMES,
+        sample_rate: 24000,
+        loop_mode: LoopMode::None,
+        loop_start: 0,
+        loop_end: 0,
+    };
+
+    struct Looped {}
+    impl SamplePatch for Looped {
+        const ZONES: &'static [Zone] = &[zone(&LOOPED)];
+    }
+
+    struct OneShot {}
+    impl SamplePatch for OneShot {
+        const ZONES: &'static [Zone] = &[zone(&ONE_SHOT)];
+    }
+
+    struct UntilRelease {}
+    impl SamplePatch for UntilRelease {
+        const ZONES: &'static [Zone] = &[zone(&UNTIL_RELEASE)];
+    }
+
+    struct Steps {}
+    impl SamplePatch for Steps {
+        const ZONES: &'static [Zone] = &[zone(&STEPS)];
+    }
+
+    fn count_transitions<T: SoundSourceCore<24000, 240>>(source: &mut T, samples: u32) -> u32 {
+        let mut last = source.get_next().to_i32();
+        let mut transitions = 0;
+        for _ in 1..samples {
+            let current = source.get_next().to_i32();
+            if (last > 0) != (current > 0) {
+                transitions += 1;
+            }
+            last = current;
+        }
+        transitions
+    }
+
+    #[test]
+    fn loops_should_play_forever_at_the_keys_pitch() {
+        let mut root =
+            SamplePlayer::<24000, 240, Looped>::new(SoundSourceNoteInit::new(69, 0, 127));
+        let at_root = count_transitions(&mut root, 2400);
+        assert!(root.has_next());
+        let mut octave_down =
+            SamplePlayer::<24000, 240, Looped>::new(SoundSourceNoteInit::new(57, 0, 127));
+        let an_octave_down = count_transitions(&mut octave_down, 2400);
+        assert!((598..=600).contains(&at_root), "{}", at_root);
+        assert!((298..=300).contains(&an_octave_down), "{}", an_octave_down);
+    }
+
+    #[test]
+    fn one_shots_should_end() {
+        let mut player =
+            SamplePlayer::<24000, 240, OneShot>::new(SoundSourceNoteInit::new(69, 0, 127));
+        for _ in 0..16 {
+            assert!(player.has_next());
+            player.get_next();
+        }
+        assert!(!player.has_next());
+        assert_eq!(0, player.get_next().to_i32());
+
+        player.restart(127);
+        assert!(player.has_next());
+    }
+
+    #[test]
+    fn release_should_end_the_loop() {
+        let mut player =
+            SamplePlayer::<24000, 240, UntilRelease>::new(SoundSourceNoteInit::new(69, 0, 127));
+        for _ in 0..100 {
+            player.get_next();
+        }
+        assert!(player.has_next());
+        player.trigger_note_off();
+        for _ in 0..16 {
+            player.get_next();
+        }
+        assert!(!player.has_next());
+    }
+
+    #[test]
+    fn full_scale_steps_should_interpolate() {
+        // Middle C plays the sample at 0.59 speed, so the second output is
+        // most of the way from the bottom to the top.
+        let mut player =
+            SamplePlayer::<24000, 240, Steps>::new(SoundSourceNoteInit::new(60, 0, 127));
+        assert!(player.get_next().to_i32() < 0);
+        assert!(player.get_next().to_i32() > 0);
+    }
+
+    #[test]
+    fn instrument_should_end_with_a_one_shot() {
+        type Hit = SampledInstrument<24000, 240, OneShot, 0, 0, 0, 100, 100, 100>;
+        let mut hit = Hit::new(SoundSourceNoteInit::new(69, 0, 127));
+        hit.update();
+        assert!(hit.get_next().to_i32() > 0);
+        for _ in 0..16 {
+            hit.get_next();
+        }
+        assert!(!hit.has_next());
+    }
+}
@@ -0,0 +1,78 @@
+// Sampled instruments.
+//
+// The instruments sf2-convert has made from SoundFont presets, and the GM
+// programs they play.  They all go through NoteEnum's SampledEnum, so a new
+// one only touches this file: a variant with its match arms, and its
+// program in PROGRAMS and new.
+//
+
+use crate::kalimba::Kalimba;
+use crate::note::SoundSourceNoteInit;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::SoundSourceCore;
+
+/// GM programs with a sampled instrument
+pub const PROGRAMS: [u8; 1] = [
+    // Kalimba
+    108,
+];
+
+pub enum Sampled<const P_FREQ: u32, const U_FREQ: u32> {
+    KalimbaEnum { pcore: Kalimba<P_FREQ, U_FREQ> },
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32> Sampled<P_FREQ, U_FREQ> {
+    /// True if program has a sampled instrument
+    ///
+    pub fn plays(program: u8) -> bool {
+        PROGRAMS.contains(&program)
+    }
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
+    for Sampled<P_FREQ, U_FREQ>
+{
+    type InitValuesType = SoundSourceNoteInit;
+
+    fn new(init_values: Self::InitValuesType) -> Self {
+        assert!(Self::plays(init_values.instrument));
+        let pcore = Kalimba::<P_FREQ, U_FREQ>::new(init_values);
+        Self::KalimbaEnum { pcore }
+    }
+
+    fn get_next(&mut self) -> SoundSampleI32 {
+        match self {
+            Self::KalimbaEnum { pcore } => pcore.get_next(),
+        }
+    }
+
+    fn update(&mut self) {
+        match self {
+            Self::KalimbaEnum { pcore } => pcore.update(),
+        }
+    }
+
+    fn has_next(&self) -> bool {
+        match self {
+            Self::KalimbaEnum { pcore } => pcore.has_next(),
+        }
+    }
+
+    fn trigger_note_off(&mut self) {
+        match self {
+            Self::KalimbaEnum { pcore } => pcore.trigger_note_off(),
+        }
+    }
+
+    fn restart(&mut self, vel: u8) {
+        match self {
+            Self::KalimbaEnum { pcore } => pcore.restart(vel),
+        }
+    }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        match self {
+            Self::KalimbaEnum { pcore } => pcore.set_pitch_ratio(ratio),
+        }
+    }
+}
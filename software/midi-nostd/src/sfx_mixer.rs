@@ -0,0 +1,449 @@
+// Sound effects mixer.
+//
+// Mixes short sound effects (UI clicks, menu blips, a startup jingle) on
+// top of the music.  Effects are played on a small, fixed number of voices;
+// when they're all busy the oldest one is cut off for the new effect.
+//
+// An effect is either an ADPCM clip or a run of square wave tones, which
+// costs no flash beyond the list of notes.  Effects that duck lower the
+// music while they play, so a voice clip can be heard over a loud song.
+//
+// Other tasks ask for effects by pushing SfxCommands on a CommandQueue; the
+// audio task drains it with run_commands between buffers.
+//
+
+use crate::adpcm::AdpcmClip;
+use crate::adpcm::AdpcmPlayer;
+use crate::command_queue::CommandQueue;
+use crate::master_volume::MasterVolume;
+use crate::midi_notes::midi_note_to_freq;
+use crate::midi_notes::FREQUENCY_MULTIPLIER;
+use crate::sound_sample::time_to_ticks;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::SoundSourceCore;
+
+/// One note of a tone effect
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Tone {
+    /// Midi key, or None for a rest
+    pub key: Option<u8>,
+    pub duration_ms: u16,
+}
+
+/// What an effect plays
+///
+#[derive(Clone, Copy, Debug)]
+pub enum EffectSource {
+    Clip(&'static AdpcmClip),
+    /// Square wave notes, each fading out over its length
+    Tones(&'static [Tone]),
+}
+
+///
+/// A sound effect
+///
+#[derive(Clone, Copy, Debug)]
+pub struct SoundEffect {
+    pub source: EffectSource,
+    /// Level, as a percentage
+    pub volume: u8,
+    /// Lower the music while this plays
+    pub ducks: bool,
+}
+
+/// Commands for the mixer, from other tasks
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SfxCommand {
+    /// Play an effect, by its position in the effect list, at a percentage
+    /// of the effect's own level
+    Play {
+        effect: u8,
+        volume: u8,
+    },
+    /// Stop every voice playing an effect
+    Stop {
+        effect: u8,
+    },
+    StopAll,
+    /// Effects level, as a percentage
+    SetVolume(u8),
+    /// Music level while a ducking effect plays, as a percentage
+    SetDucking(u8),
+}
+
+//
+// Plays a list of tones
+//
+struct TonePlayer<const P_FREQ: u32> {
+    tones: &'static [Tone],
+    tone_idx: usize,
+    samples_left: i32,
+    phase: u32,
+    phase_inc: u32,
+    /// Level, 0x8000 for 1, with 8 extra fractional bits
+    level: i32,
+    level_step: i32,
+}
+
+impl<const P_FREQ: u32> TonePlayer<P_FREQ> {
+    const FRACTION_BITS: u32 = 8;
+
+    fn new(tones: &'static [Tone]) -> Self {
+        let mut rval = Self {
+            tones,
+            tone_idx: 0,
+            samples_left: 0,
+            phase: 0,
+            phase_inc: 0,
+            level: 0,
+            level_step: 0,
+        };
+        rval.start_tone();
+        rval
+    }
+
+    fn start_tone(&mut self) {
+        let tone = match self.tones.get(self.tone_idx) {
+            Some(tone) => tone,
+            None => return,
+        };
+        self.samples_left = core::cmp::max(time_to_ticks::<P_FREQ>(tone.duration_ms as i32), 1);
+        match tone.key {
+            Some(key) => {
+                self.phase_inc = (((midi_note_to_freq(key) as u64) << 32)
+                    / ((P_FREQ as u64) * (FREQUENCY_MULTIPLIER as u64)))
+                    as u32;
+                // Half scale, like the oscillators, fading to nothing.
+                self.level = 0x4000 << Self::FRACTION_BITS;
+                self.level_step = self.level / self.samples_left;
+            }
+            None => {
+                self.level = 0;
+                self.level_step = 0;
+            }
+        }
+    }
+
+    fn has_next(&self) -> bool {
+        self.tone_idx < self.tones.len()
+    }
+
+    #[inline]
+    fn get_next(&mut self) -> i32 {
+        if !self.has_next() {
+            return 0;
+        }
+        let level = self.level >> Self::FRACTION_BITS;
+        let sample = if self.phase & 0x80000000 == 0 {
+            level
+        } else {
+            -level
+        };
+        self.phase = self.phase.wrapping_add(self.phase_inc);
+        self.level -= self.level_step;
+        self.samples_left -= 1;
+        if self.samples_left <= 0 {
+            self.tone_idx += 1;
+            self.start_tone();
+        }
+        sample
+    }
+}
+
+enum VoiceSource<const P_FREQ: u32, const U_FREQ: u32> {
+    Idle,
+    Clip(AdpcmPlayer<P_FREQ, U_FREQ>),
+    Tones(TonePlayer<P_FREQ>),
+}
+
+struct Voice<const P_FREQ: u32, const U_FREQ: u32> {
+    source: VoiceSource<P_FREQ, U_FREQ>,
+    effect: u8,
+    /// 0x8000 for 1
+    volume: i32,
+    ducks: bool,
+    /// When it started, to find the oldest voice
+    age: u32,
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32> Voice<P_FREQ, U_FREQ> {
+    const fn new() -> Self {
+        Self {
+            source: VoiceSource::Idle,
+            effect: 0,
+            volume: 0,
+            ducks: false,
+            age: 0,
+        }
+    }
+
+    fn is_playing(&self) -> bool {
+        !matches!(self.source, VoiceSource::Idle)
+    }
+}
+
+///
+/// Music plus NUM_VOICES sound effect voices
+///
+pub struct SfxMixer<const P_FREQ: u32, const U_FREQ: u32, const NUM_VOICES: usize> {
+    effects: &'static [SoundEffect],
+    voices: [Voice<P_FREQ, U_FREQ>; NUM_VOICES],
+    /// Effects level, 0x8000 for 1
+    volume: i32,
+    music_gain: MasterVolume<P_FREQ>,
+    ducking: u8,
+    ducked: bool,
+    next_age: u32,
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, const NUM_VOICES: usize>
+    SfxMixer<P_FREQ, U_FREQ, NUM_VOICES>
+{
+    /// Default music level under a ducking effect, as a percentage
+    pub const DEFAULT_DUCKING: u8 = 40;
+
+    pub const fn new(effects: &'static [SoundEffect]) -> Self {
+        Self {
+            effects,
+            voices: [const { Voice::new() }; NUM_VOICES],
+            volume: 0x8000,
+            music_gain: MasterVolume::new(),
+            ducking: Self::DEFAULT_DUCKING,
+            ducked: false,
+            next_age: 0,
+        }
+    }
+
+    /// Start an effect, at a percentage of its own level.  Returns false if
+    /// there's no such effect.
+    ///
+    pub fn play(&mut self, effect: u8, volume: u8) -> bool {
+        let sound_effect = match self.effects.get(effect as usize) {
+            Some(sound_effect) => sound_effect,
+            None => return false,
+        };
+        let voice_idx = match self.voices.iter().position(|voice| !voice.is_playing()) {
+            Some(voice_idx) => voice_idx,
+            None => match (0..NUM_VOICES).min_by_key(|idx| self.voices[*idx].age) {
+                Some(voice_idx) => voice_idx,
+                None => return false,
+            },
+        };
+        let voice = &mut self.voices[voice_idx];
+        voice.source = match sound_effect.source {
+            EffectSource::Clip(clip) => VoiceSource::Clip(AdpcmPlayer::new(clip)),
+            EffectSource::Tones(tones) => VoiceSource::Tones(TonePlayer::new(tones)),
+        };
+        voice.effect = effect;
+        voice.volume = 0x8000 * (core::cmp::min(sound_effect.volume, 100) as i32) / 100
+            * (core::cmp::min(volume, 100) as i32)
+            / 100;
+        voice.ducks = sound_effect.ducks;
+        voice.age = self.next_age;
+        self.next_age = self.next_age.wrapping_add(1);
+        self.update_ducking();
+        true
+    }
+
+    /// Stop every voice playing an effect
+    ///
+    pub fn stop(&mut self, effect: u8) {
+        for voice in self.voices.iter_mut() {
+            if voice.effect == effect {
+                voice.source = VoiceSource::Idle;
+            }
+        }
+        self.update_ducking();
+    }
+
+    pub fn stop_all(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.source = VoiceSource::Idle;
+        }
+        self.update_ducking();
+    }
+
+    /// Effects level, as a percentage
+    ///
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = 0x8000 * (core::cmp::min(volume, 100) as i32) / 100;
+    }
+
+    /// Music level while a ducking effect plays, as a percentage.  100
+    /// turns ducking off.
+    ///
+    pub fn set_ducking(&mut self, ducking: u8) {
+        self.ducking = core::cmp::min(ducking, 100);
+        if self.ducked {
+            self.music_gain.set_volume(self.ducking);
+        }
+    }
+
+    /// True if any effect is playing
+    ///
+    pub fn is_playing(&self) -> bool {
+        self.voices.iter().any(|voice| voice.is_playing())
+    }
+
+    /// Carry out the commands waiting in a queue
+    ///
+    pub fn run_commands<const N: usize>(&mut self, queue: &CommandQueue<SfxCommand, N>) {
+        while let Some(command) = queue.pop() {
+            match command {
+                SfxCommand::Play { effect, volume } => {
+                    self.play(effect, volume);
+                }
+                SfxCommand::Stop { effect } => self.stop(effect),
+                SfxCommand::StopAll => self.stop_all(),
+                SfxCommand::SetVolume(volume) => self.set_volume(volume),
+                SfxCommand::SetDucking(ducking) => self.set_ducking(ducking),
+            }
+        }
+    }
+
+    fn update_ducking(&mut self) {
+        let should_duck = self
+            .voices
+            .iter()
+            .any(|voice| voice.is_playing() && voice.ducks);
+        if should_duck != self.ducked {
+            self.ducked = should_duck;
+            self.music_gain
+                .set_volume(if should_duck { self.ducking } else { 100 });
+        }
+    }
+
+    ///
+    /// Mix one sample of music with the effects
+    ///
+    #[inline]
+    pub fn mix(&mut self, music: SoundSampleI32) -> SoundSampleI32 {
+        let mut effects = 0;
+        let mut finished = false;
+        for voice in self.voices.iter_mut() {
+            let sample = match &mut voice.source {
+                VoiceSource::Idle => continue,
+                VoiceSource::Clip(player) => {
+                    let sample = player.get_next().to_i32();
+                    finished |= !player.has_next();
+                    sample
+                }
+                VoiceSource::Tones(player) => {
+                    let sample = player.get_next();
+                    finished |= !player.has_next();
+                    sample
+                }
+            };
+            effects += (sample * voice.volume) >> 15;
+        }
+        if finished {
+            for voice in self.voices.iter_mut() {
+                let done = match &voice.source {
+                    VoiceSource::Idle => false,
+                    VoiceSource::Clip(player) => !player.has_next(),
+                    VoiceSource::Tones(player) => !player.has_next(),
+                };
+                if done {
+                    voice.source = VoiceSource::Idle;
+                }
+            }
+            self.update_ducking();
+        }
+        self.music_gain.apply(music) + SoundSampleI32::new_i32((effects * self.volume) >> 15)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sfx_mixer::*;
+
+    static BLIP: [Tone; 1] = [Tone {
+        key: Some(81),
+        duration_ms: 10,
+    }];
+
+    static JINGLE: [Tone; 3] = [
+        Tone {
+            key: Some(72),
+            duration_ms: 10,
+        },
+        Tone {
+            key: None,
+            duration_ms: 10,
+        },
+        Tone {
+            key: Some(79),
+            duration_ms: 10,
+        },
+    ];
+
+    static EFFECTS: [SoundEffect; 2] = [
+        SoundEffect {
+            source: EffectSource::Tones(&BLIP),
+            volume: 100,
+            ducks: false,
+        },
+        SoundEffect {
+            source: EffectSource::Tones(&JINGLE),
+            volume: 100,
+            ducks: true,
+        },
+    ];
+
+    const MUSIC: SoundSampleI32 = SoundSampleI32::new_i32(0x1000);
+
+    #[test]
+    fn effects_should_add_to_the_music_and_end() {
+        let mut mixer = SfxMixer::<24000, 240, 2>::new(&EFFECTS);
+        assert_eq!(0x1000, mixer.mix(MUSIC).to_i32());
+        assert!(mixer.play(0, 100));
+        assert!(!mixer.play(5, 100));
+        // 880hz square wave, starting high
+        assert!(mixer.mix(MUSIC).to_i32() > 0x4000);
+        for _ in 0..240 {
+            mixer.mix(MUSIC);
+        }
+        assert!(!mixer.is_playing());
+        assert_eq!(0x1000, mixer.mix(MUSIC).to_i32());
+    }
+
+    #[test]
+    fn ducking_effects_should_lower_the_music() {
+        let mut mixer = SfxMixer::<24000, 240, 2>::new(&EFFECTS);
+        let queue = CommandQueue::<SfxCommand, 4>::new();
+        queue.push(SfxCommand::SetVolume(0)).unwrap();
+        queue
+            .push(SfxCommand::Play {
+                effect: 1,
+                volume: 100,
+            })
+            .unwrap();
+        mixer.run_commands(&queue);
+        assert!(mixer.is_playing());
+        // 20ms ramp down, with the effects muted
+        for _ in 0..480 {
+            mixer.mix(MUSIC);
+        }
+        assert_eq!(0x1000 * 40 / 100, mixer.mix(MUSIC).to_i32());
+        // The jingle is 30ms, then the music comes back up.
+        for _ in 0..1000 {
+            mixer.mix(MUSIC);
+        }
+        assert!(!mixer.is_playing());
+        assert_eq!(0x1000, mixer.mix(MUSIC).to_i32());
+    }
+
+    #[test]
+    fn the_oldest_voice_should_be_stolen() {
+        let mut mixer = SfxMixer::<24000, 240, 2>::new(&EFFECTS);
+        mixer.play(1, 100);
+        mixer.play(0, 100);
+        mixer.play(0, 100);
+        // The jingle was cut off, so nothing ducks.
+        assert!(!mixer.ducked);
+        mixer.stop(0);
+        assert!(!mixer.is_playing());
+    }
+}
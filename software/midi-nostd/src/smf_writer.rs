@@ -0,0 +1,320 @@
+// Standard MIDI File writer.
+//
+// Writes an SMF into a buffer the caller owns, so it works without an
+// allocator.  Tracks are written one at a time: start one, add its events
+// in time order, end it, then start the next.  The lengths the header and
+// each track start with are filled in as they're ended.
+//
+// Events are written with running status, leaving out the status byte when
+// it's the same as the last event's, which saves about a third of the space
+// on a run of notes.
+//
+
+/// SMF format, from the file header
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SmfFormat {
+    /// One track with every channel in it
+    SingleTrack = 0,
+    /// Tracks that play together, the first usually just for tempo
+    MultiTrack = 1,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SmfError {
+    /// The buffer is too small for the file
+    BufferFull,
+    /// A SingleTrack file can't have a second track
+    TooManyTracks,
+    /// An event came with no track started
+    NoTrack,
+    /// A track was started, or the file finished, with one still open
+    TrackOpen,
+}
+
+///
+/// Writes an SMF one event at a time
+///
+pub struct SmfWriter<'buf> {
+    buffer: &'buf mut [u8],
+    len: usize,
+    format: SmfFormat,
+    num_tracks: u16,
+    /// Where the open track's data starts
+    track_start: Option<usize>,
+    /// Time of the last event in the open track, in ticks
+    last_time: u32,
+    running_status: Option<u8>,
+}
+
+impl<'buf> SmfWriter<'buf> {
+    pub fn new(
+        buffer: &'buf mut [u8],
+        format: SmfFormat,
+        ticks_per_quarter_note: u16,
+    ) -> Result<Self, SmfError> {
+        let mut rval = Self {
+            buffer,
+            len: 0,
+            format,
+            num_tracks: 0,
+            track_start: None,
+            last_time: 0,
+            running_status: None,
+        };
+        rval.put(b"MThd")?;
+        rval.put(&6u32.to_be_bytes())?;
+        rval.put(&(format as u16).to_be_bytes())?;
+        // The track count is filled in by finish.
+        rval.put(&0u16.to_be_bytes())?;
+        // Top bit clear for metrical timing
+        rval.put(&(ticks_per_quarter_note & 0x7fff).to_be_bytes())?;
+        Ok(rval)
+    }
+
+    fn put(&mut self, bytes: &[u8]) -> Result<(), SmfError> {
+        let end = self.len + bytes.len();
+        if end > self.buffer.len() {
+            return Err(SmfError::BufferFull);
+        }
+        self.buffer[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    // Variable length quantity: 7 bits a byte, most significant first,
+    // with the top bit set on all but the last.
+    fn put_variable(&mut self, value: u32) -> Result<(), SmfError> {
+        let mut bytes = [0u8; 5];
+        let mut start = bytes.len() - 1;
+        bytes[start] = (value & 0x7f) as u8;
+        let mut rest = value >> 7;
+        while rest != 0 {
+            start -= 1;
+            bytes[start] = 0x80 | (rest & 0x7f) as u8;
+            rest >>= 7;
+        }
+        self.put(&bytes[start..])
+    }
+
+    fn put_delta(&mut self, time: u32) -> Result<(), SmfError> {
+        if self.track_start.is_none() {
+            return Err(SmfError::NoTrack);
+        }
+        // Events out of order are played as soon as they can be.
+        let delta = time.saturating_sub(self.last_time);
+        self.last_time = core::cmp::max(time, self.last_time);
+        self.put_variable(delta)
+    }
+
+    pub fn start_track(&mut self) -> Result<(), SmfError> {
+        if self.track_start.is_some() {
+            return Err(SmfError::TrackOpen);
+        }
+        if self.format == SmfFormat::SingleTrack && self.num_tracks == 1 {
+            return Err(SmfError::TooManyTracks);
+        }
+        self.put(b"MTrk")?;
+        // The length is filled in by end_track.
+        self.put(&0u32.to_be_bytes())?;
+        self.track_start = Some(self.len);
+        self.last_time = 0;
+        self.running_status = None;
+        Ok(())
+    }
+
+    /// A channel message, at time in ticks from the start of the track
+    ///
+    pub fn midi_event(
+        &mut self,
+        time: u32,
+        channel: u8,
+        message: &midly::MidiMessage,
+    ) -> Result<(), SmfError> {
+        use midly::MidiMessage;
+
+        let (status, data): (u8, [u8; 2]) = match *message {
+            MidiMessage::NoteOff { key, vel } => (0x80, [key.as_int(), vel.as_int()]),
+            MidiMessage::NoteOn { key, vel } => (0x90, [key.as_int(), vel.as_int()]),
+            MidiMessage::Aftertouch { key, vel } => (0xa0, [key.as_int(), vel.as_int()]),
+            MidiMessage::Controller { controller, value } => {
+                (0xb0, [controller.as_int(), value.as_int()])
+            }
+            MidiMessage::ProgramChange { program } => (0xc0, [program.as_int(), 0]),
+            MidiMessage::ChannelAftertouch { vel } => (0xd0, [vel.as_int(), 0]),
+            MidiMessage::PitchBend { bend } => {
+                let value = bend.0.as_int();
+                (0xe0, [(value & 0x7f) as u8, (value >> 7) as u8])
+            }
+        };
+        let data_len = match status {
+            0xc0 | 0xd0 => 1,
+            _ => 2,
+        };
+        let status = status | (channel & 0xf);
+
+        self.put_delta(time)?;
+        if self.running_status != Some(status) {
+            self.put(&[status])?;
+            self.running_status = Some(status);
+        }
+        self.put(&data[..data_len])
+    }
+
+    fn meta_event(&mut self, time: u32, kind: u8, data: &[u8]) -> Result<(), SmfError> {
+        self.put_delta(time)?;
+        self.put(&[0xff, kind])?;
+        self.put_variable(data.len() as u32)?;
+        self.put(data)?;
+        // Meta events cancel running status.
+        self.running_status = None;
+        Ok(())
+    }
+
+    /// Set the tempo, in microseconds per quarter note
+    ///
+    pub fn tempo(&mut self, time: u32, us_per_quarter_note: u32) -> Result<(), SmfError> {
+        let bytes = us_per_quarter_note.min(0xff_ffff).to_be_bytes();
+        self.meta_event(time, 0x51, &bytes[1..4])
+    }
+
+    pub fn track_name(&mut self, time: u32, name: &[u8]) -> Result<(), SmfError> {
+        self.meta_event(time, 0x03, name)
+    }
+
+    /// End the open track at time, in ticks
+    ///
+    pub fn end_track(&mut self, time: u32) -> Result<(), SmfError> {
+        self.meta_event(time, 0x2f, &[])?;
+        let start = self.track_start.take().ok_or(SmfError::NoTrack)?;
+        let track_len = (self.len - start) as u32;
+        self.buffer[start - 4..start].copy_from_slice(&track_len.to_be_bytes());
+        self.num_tracks += 1;
+        Ok(())
+    }
+
+    /// Fill in the track count and return the file
+    ///
+    pub fn finish(self) -> Result<&'buf [u8], SmfError> {
+        if self.track_start.is_some() {
+            return Err(SmfError::TrackOpen);
+        }
+        let buffer = self.buffer;
+        buffer[10..12].copy_from_slice(&self.num_tracks.to_be_bytes());
+        Ok(&buffer[..self.len])
+    }
+
+    /// Bytes written so far
+    ///
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::smf_writer::*;
+    use midly::num::u7;
+    use midly::MidiMessage;
+
+    fn note_on(key: u8) -> MidiMessage {
+        MidiMessage::NoteOn {
+            key: u7::new(key),
+            vel: u7::new(100),
+        }
+    }
+
+    #[test]
+    fn files_should_parse_back() {
+        let mut buffer = [0u8; 256];
+        let mut writer = SmfWriter::new(&mut buffer, SmfFormat::MultiTrack, 96).unwrap();
+        writer.start_track().unwrap();
+        writer.tempo(0, 400000).unwrap();
+        writer.end_track(0).unwrap();
+        writer.start_track().unwrap();
+        writer.track_name(0, b"Lead").unwrap();
+        let program = MidiMessage::ProgramChange {
+            program: u7::new(10),
+        };
+        writer.midi_event(0, 2, &program).unwrap();
+        writer.midi_event(0, 2, &note_on(60)).unwrap();
+        writer.midi_event(200, 2, &note_on(64)).unwrap();
+        writer.end_track(400).unwrap();
+        let file = writer.finish().unwrap();
+
+        let (header, tracks) = midly::parse(file).unwrap();
+        assert_eq!(midly::Format::Parallel, header.format);
+        assert_eq!(midly::Timing::Metrical(96.into()), header.timing);
+        let tracks: Vec<Vec<midly::TrackEvent>> = tracks
+            .map(|track| track.unwrap().map(|e| e.unwrap()).collect())
+            .collect();
+        assert_eq!(2, tracks.len());
+        assert_eq!(
+            midly::TrackEventKind::Meta(midly::MetaMessage::Tempo(400000.into())),
+            tracks[0][0].kind
+        );
+
+        let events: Vec<(u32, midly::TrackEventKind)> = tracks[1]
+            .iter()
+            .map(|event| (event.delta.as_int(), event.kind))
+            .collect();
+        let midi = |message| midly::TrackEventKind::Midi {
+            channel: 2.into(),
+            message,
+        };
+        assert_eq!(
+            vec![
+                (
+                    0,
+                    midly::TrackEventKind::Meta(midly::MetaMessage::TrackName(b"Lead"))
+                ),
+                (0, midi(program)),
+                (0, midi(note_on(60))),
+                (200, midi(note_on(64))),
+                (
+                    200,
+                    midly::TrackEventKind::Meta(midly::MetaMessage::EndOfTrack)
+                ),
+            ],
+            events
+        );
+    }
+
+    #[test]
+    fn running_status_and_deltas_should_pack() {
+        let mut buffer = [0u8; 64];
+        let mut writer = SmfWriter::new(&mut buffer, SmfFormat::SingleTrack, 96).unwrap();
+        writer.start_track().unwrap();
+        writer.midi_event(0, 0, &note_on(60)).unwrap();
+        writer.midi_event(0x4000, 0, &note_on(62)).unwrap();
+        writer.end_track(0x4000).unwrap();
+        assert_eq!(Err(SmfError::TooManyTracks), writer.start_track());
+        let file = writer.finish().unwrap();
+        assert_eq!(
+            &[0x00, 0x90, 60, 100, 0x81, 0x80, 0x00, 62, 100, 0x00, 0xff, 0x2f, 0x00],
+            // After the file and track headers
+            &file[14 + 8..]
+        );
+    }
+
+    #[test]
+    fn small_buffers_should_fill_up() {
+        let mut buffer = [0u8; 27];
+        let mut writer = SmfWriter::new(&mut buffer, SmfFormat::SingleTrack, 96).unwrap();
+        assert_eq!(
+            Err(SmfError::NoTrack),
+            writer.midi_event(0, 0, &note_on(60))
+        );
+        writer.start_track().unwrap();
+        writer.midi_event(0, 0, &note_on(60)).unwrap();
+        assert_eq!(
+            Err(SmfError::BufferFull),
+            writer.midi_event(0, 0, &note_on(62))
+        );
+        assert_eq!(Err(SmfError::TrackOpen), writer.finish().map(|_| ()));
+    }
+}
@@ -38,6 +38,14 @@ pub trait SoundSourceCore<const P_FREQ: u32, const U_FREQ: u32> {
     /// Restart the sound
     ///
     fn restart(self: &mut Self, _vel: u8);
+
+    /// Play at ratio times the note's own frequency, where ratio is a 16.16
+    /// fraction.  Used for portamento.  Returns false if the source can't
+    /// change pitch once it's started.
+    ///
+    fn set_pitch_ratio(&mut self, _ratio: u32) -> bool {
+        false
+    }
 }
 
 pub trait OscillatorInterface<const P_FREQ: u32, const U_FREQ: u32>:
@@ -0,0 +1,891 @@
+// Step sequencer.
+//
+// A loop of sixteen steps on each of four tracks, edited with the badge's
+// four buttons and played through a LiveSynth.  A step is a sixteenth note,
+// so the loop is one bar of 4/4.  Each track plays on its own channel, the
+// track's number, with its own program.
+//
+// It's in pieces so each can be tested on its own and run where it's
+// needed:
+//
+//   Pattern          the notes, programs and tempo
+//   StepClock        when each step starts, at the pattern's tempo
+//   Editor           turns button presses into changes to a pattern
+//   SequencerPlayer  plays a pattern
+//
+// On the badge the editor runs with the menu on core 0 and the player with
+// the audio on core 1.  A pattern is a couple of hundred bytes and changes
+// a button press at a time, so it's sent across whole, as a
+// SequencerCommand, whenever it changes.
+//
+
+use crate::command_queue::CommandQueue;
+use crate::live_synth::LiveSynth;
+use crate::sound_sample::SoundSampleI32;
+
+pub const NUM_STEPS: usize = 16;
+pub const NUM_TRACKS: usize = 4;
+const STEPS_PER_BEAT: u32 = 4;
+
+pub const MIN_BPM: u16 = 40;
+pub const MAX_BPM: u16 = 240;
+pub const DEFAULT_BPM: u16 = 120;
+const BPM_STEP: u16 = 5;
+
+/// Range of keys a step can play, C1 to C7
+pub const LOWEST_KEY: u8 = 24;
+pub const HIGHEST_KEY: u8 = 96;
+const FIRST_KEY: u8 = 60;
+
+pub const DEFAULT_VELOCITY: u8 = 100;
+const VELOCITY_STEP: u8 = 16;
+
+// Notes are let go after this much of a step, so a run of the same note is
+// heard as separate notes.
+const GATE_PERCENT: u32 = 80;
+
+// Four tracks rarely peak together, so the mix is scaled for two.
+const LOUD_NOTES: i32 = 2;
+
+/// A program the sequencer offers, with a name short enough for the
+/// display
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Instrument {
+    pub program: u8,
+    pub name: &'static str,
+}
+
+const fn instrument(program: u8, name: &'static str) -> Instrument {
+    Instrument { program, name }
+}
+
+/// Every sound NoteEnum has, once each
+pub static INSTRUMENTS: [Instrument; 30] = [
+    instrument(0, "Piano"),
+    instrument(4, "E.Piano"),
+    instrument(8, "Celesta"),
+    instrument(9, "Glockenspiel"),
+    instrument(10, "Music Box"),
+    instrument(11, "Vibraphone"),
+    instrument(12, "Marimba"),
+    instrument(13, "Xylophone"),
+    instrument(15, "Dulcimer"),
+    instrument(16, "Organ"),
+    instrument(17, "Perc Organ"),
+    instrument(19, "Church Organ"),
+    instrument(21, "Accordion"),
+    instrument(22, "Harmonica"),
+    instrument(23, "Tango Accord"),
+    instrument(24, "Guitar"),
+    instrument(29, "Overdrive"),
+    instrument(30, "Distortion"),
+    instrument(33, "Bass"),
+    instrument(40, "Violin"),
+    instrument(42, "Cello"),
+    instrument(45, "Pizzicato"),
+    instrument(46, "Harp"),
+    instrument(47, "Timpani"),
+    instrument(52, "Choir"),
+    instrument(60, "French Horn"),
+    instrument(65, "Sax"),
+    instrument(69, "Oboe"),
+    instrument(80, "Square Lead"),
+    instrument(81, "Saw Lead"),
+];
+
+/// Name of the instrument playing program, if the sequencer has one
+///
+pub fn instrument_name(program: u8) -> Option<&'static str> {
+    INSTRUMENTS
+        .iter()
+        .find(|instrument| instrument.program == program)
+        .map(|instrument| instrument.name)
+}
+
+/// Name of key, like C#4, written into buffer.  Middle C (60) is C4.
+///
+pub fn key_name(key: u8, buffer: &mut [u8; 4]) -> &str {
+    const NAMES: [&[u8]; 12] = [
+        b"C", b"C#", b"D", b"D#", b"E", b"F", b"F#", b"G", b"G#", b"A", b"A#", b"B",
+    ];
+    let name = NAMES[(key % 12) as usize];
+    let octave = (key / 12) as i8 - 1;
+    let mut len = name.len();
+    buffer[..len].copy_from_slice(name);
+    if octave < 0 {
+        buffer[len] = b'-';
+        len += 1;
+    }
+    buffer[len] = b'0' + octave.unsigned_abs();
+    len += 1;
+    core::str::from_utf8(&buffer[..len]).unwrap_or("?")
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Step {
+    /// Midi key, or None for a rest
+    pub key: Option<u8>,
+    pub velocity: u8,
+}
+
+impl Step {
+    pub const REST: Self = Self {
+        key: None,
+        velocity: DEFAULT_VELOCITY,
+    };
+
+    pub const fn note(key: u8, velocity: u8) -> Self {
+        Self {
+            key: Some(key),
+            velocity,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Track {
+    pub steps: [Step; NUM_STEPS],
+    pub program: u8,
+}
+
+impl Track {
+    pub const fn new(program: u8) -> Self {
+        Self {
+            steps: [Step::REST; NUM_STEPS],
+            program,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.steps = [Step::REST; NUM_STEPS];
+    }
+}
+
+///
+/// Everything the sequencer plays
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Pattern {
+    pub tracks: [Track; NUM_TRACKS],
+    pub bpm: u16,
+}
+
+impl Pattern {
+    /// Empty tracks, with a lead, a piano, a bass and timpani
+    ///
+    pub const fn new() -> Self {
+        Self {
+            tracks: [
+                Track::new(80),
+                Track::new(0),
+                Track::new(33),
+                Track::new(47),
+            ],
+            bpm: DEFAULT_BPM,
+        }
+    }
+}
+
+impl Default for Pattern {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+///
+/// Counts out steps, a sixteenth note each, at FREQ ticks a second
+///
+pub struct StepClock<const FREQ: u32> {
+    bpm: u16,
+    /// Ticks into the step, scaled so a step is PERIOD whatever the tempo
+    phase: u32,
+    step: usize,
+}
+
+impl<const FREQ: u32> StepClock<FREQ> {
+    const PERIOD: u32 = FREQ * 60;
+
+    /// A clock that starts on step 0 with the next tick
+    ///
+    pub const fn new(bpm: u16) -> Self {
+        Self {
+            bpm,
+            phase: Self::PERIOD,
+            step: NUM_STEPS - 1,
+        }
+    }
+
+    /// Change the tempo.  The step that's playing carries on from where it
+    /// is, so the change doesn't jump.
+    ///
+    pub fn set_bpm(&mut self, bpm: u16) {
+        self.bpm = bpm;
+    }
+
+    /// Start over from step 0 with the next tick
+    ///
+    pub fn restart(&mut self) {
+        self.phase = Self::PERIOD;
+        self.step = NUM_STEPS - 1;
+    }
+
+    /// Move on a tick.  Returns the step that starts on this tick, if one
+    /// does.  Steps land on the nearest tick after they're due, so there's
+    /// no drift, even when a step isn't a whole number of ticks.
+    ///
+    pub fn tick(&mut self) -> Option<usize> {
+        let started = if self.phase >= Self::PERIOD {
+            self.phase -= Self::PERIOD;
+            self.step = (self.step + 1) % NUM_STEPS;
+            Some(self.step)
+        } else {
+            None
+        };
+        self.phase += self.bpm as u32 * STEPS_PER_BEAT;
+        started
+    }
+
+    /// The step playing now
+    ///
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// True once percent of the step is over
+    ///
+    pub fn is_past(&self, percent: u32) -> bool {
+        (self.phase as u64) * 100 >= (Self::PERIOD as u64) * (percent as u64)
+    }
+}
+
+/// The badge's buttons, as the editor sees them
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SeqButton {
+    Back,
+    Up,
+    Down,
+    Action,
+}
+
+/// What up and down change
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EditMode {
+    /// Move the cursor through the steps
+    Navigate,
+    /// The key of the step under the cursor
+    Note,
+    /// The velocity of the step under the cursor.  Turning it all the way
+    /// down makes the step a rest.
+    Velocity,
+    Tempo,
+    /// The program of the cursor's track
+    Instrument,
+}
+
+/// What a button press did
+///
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EditorAction {
+    /// Nothing the player needs to know about
+    None,
+    /// The pattern changed
+    Changed,
+    /// Back was pressed while navigating, so it's time for the options
+    Menu,
+}
+
+///
+/// Edits a pattern with four buttons
+///
+/// Navigating, up and down move the cursor a step at a time, running on
+/// from the end of one track to the start of the next.  Action edits the
+/// step: on a rest it puts down the last key used.  Then up and down move
+/// the key by a semitone, action moves on to the velocity, and action again
+/// goes back to navigating one step on, ready for the next note.  Back
+/// goes back to navigating where the cursor is.
+///
+pub struct Editor {
+    pattern: Pattern,
+    track: usize,
+    step: usize,
+    mode: EditMode,
+    last_key: u8,
+}
+
+impl Editor {
+    pub const fn new(pattern: Pattern) -> Self {
+        Self {
+            pattern,
+            track: 0,
+            step: 0,
+            mode: EditMode::Navigate,
+            last_key: FIRST_KEY,
+        }
+    }
+
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    /// Cursor position, as (track, step)
+    ///
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.track, self.step)
+    }
+
+    pub fn mode(&self) -> EditMode {
+        self.mode
+    }
+
+    /// The step under the cursor
+    ///
+    pub fn current_step(&self) -> Step {
+        self.pattern.tracks[self.track].steps[self.step]
+    }
+
+    fn current_step_mut(&mut self) -> &mut Step {
+        &mut self.pattern.tracks[self.track].steps[self.step]
+    }
+
+    /// Up and down change the tempo until action or back
+    ///
+    pub fn edit_tempo(&mut self) {
+        self.mode = EditMode::Tempo;
+    }
+
+    /// Up and down change the cursor track's program until action or back
+    ///
+    pub fn edit_instrument(&mut self) {
+        self.mode = EditMode::Instrument;
+    }
+
+    /// Make every step on the cursor's track a rest
+    ///
+    pub fn clear_track(&mut self) -> EditorAction {
+        self.pattern.tracks[self.track].clear();
+        EditorAction::Changed
+    }
+
+    pub fn press(&mut self, button: SeqButton) -> EditorAction {
+        match self.mode {
+            EditMode::Navigate => self.navigate(button),
+            EditMode::Note => self.edit_note(button),
+            EditMode::Velocity => self.edit_velocity(button),
+            EditMode::Tempo => self.edit_bpm(button),
+            EditMode::Instrument => self.edit_program(button),
+        }
+    }
+
+    fn move_cursor(&mut self, forward: bool) {
+        const NUM_CELLS: usize = NUM_TRACKS * NUM_STEPS;
+        let cell = self.track * NUM_STEPS + self.step;
+        let cell = if forward {
+            (cell + 1) % NUM_CELLS
+        } else {
+            (cell + NUM_CELLS - 1) % NUM_CELLS
+        };
+        self.track = cell / NUM_STEPS;
+        self.step = cell % NUM_STEPS;
+    }
+
+    fn navigate(&mut self, button: SeqButton) -> EditorAction {
+        match button {
+            SeqButton::Back => return EditorAction::Menu,
+            SeqButton::Up => self.move_cursor(false),
+            SeqButton::Down => self.move_cursor(true),
+            SeqButton::Action => {
+                self.mode = EditMode::Note;
+                if self.current_step().key.is_none() {
+                    *self.current_step_mut() = Step::note(self.last_key, DEFAULT_VELOCITY);
+                    return EditorAction::Changed;
+                }
+            }
+        }
+        EditorAction::None
+    }
+
+    fn edit_note(&mut self, button: SeqButton) -> EditorAction {
+        let Some(key) = self.current_step().key else {
+            self.mode = EditMode::Navigate;
+            return EditorAction::None;
+        };
+        let new_key = match button {
+            SeqButton::Back => {
+                self.mode = EditMode::Navigate;
+                return EditorAction::None;
+            }
+            SeqButton::Action => {
+                self.mode = EditMode::Velocity;
+                return EditorAction::None;
+            }
+            SeqButton::Up => core::cmp::min(key + 1, HIGHEST_KEY),
+            SeqButton::Down => core::cmp::max(key - 1, LOWEST_KEY),
+        };
+        self.last_key = new_key;
+        if new_key == key {
+            return EditorAction::None;
+        }
+        self.current_step_mut().key = Some(new_key);
+        EditorAction::Changed
+    }
+
+    fn edit_velocity(&mut self, button: SeqButton) -> EditorAction {
+        let velocity = self.current_step().velocity;
+        let new_velocity = match button {
+            SeqButton::Back => {
+                self.mode = EditMode::Navigate;
+                return EditorAction::None;
+            }
+            SeqButton::Action => {
+                self.mode = EditMode::Navigate;
+                self.move_cursor(true);
+                return EditorAction::None;
+            }
+            SeqButton::Up => core::cmp::min(velocity.saturating_add(VELOCITY_STEP), 127),
+            SeqButton::Down => velocity.saturating_sub(VELOCITY_STEP),
+        };
+        if new_velocity == velocity {
+            return EditorAction::None;
+        }
+        if new_velocity == 0 {
+            *self.current_step_mut() = Step::REST;
+            self.mode = EditMode::Navigate;
+        } else {
+            self.current_step_mut().velocity = new_velocity;
+        }
+        EditorAction::Changed
+    }
+
+    fn edit_bpm(&mut self, button: SeqButton) -> EditorAction {
+        let bpm = self.pattern.bpm;
+        let new_bpm = match button {
+            SeqButton::Back | SeqButton::Action => {
+                self.mode = EditMode::Navigate;
+                return EditorAction::None;
+            }
+            SeqButton::Up => core::cmp::min(bpm + BPM_STEP, MAX_BPM),
+            SeqButton::Down => core::cmp::max(bpm - BPM_STEP, MIN_BPM),
+        };
+        if new_bpm == bpm {
+            return EditorAction::None;
+        }
+        self.pattern.bpm = new_bpm;
+        EditorAction::Changed
+    }
+
+    fn edit_program(&mut self, button: SeqButton) -> EditorAction {
+        let program = &mut self.pattern.tracks[self.track].program;
+        let idx = INSTRUMENTS
+            .iter()
+            .position(|instrument| instrument.program == *program);
+        let new_idx = match (button, idx) {
+            (SeqButton::Back | SeqButton::Action, _) => {
+                self.mode = EditMode::Navigate;
+                return EditorAction::None;
+            }
+            (_, None) => 0,
+            (SeqButton::Up, Some(idx)) => (idx + INSTRUMENTS.len() - 1) % INSTRUMENTS.len(),
+            (SeqButton::Down, Some(idx)) => (idx + 1) % INSTRUMENTS.len(),
+        };
+        *program = INSTRUMENTS[new_idx].program;
+        EditorAction::Changed
+    }
+}
+
+/// Commands for a SequencerPlayer on another core
+///
+#[derive(Clone, Copy, Debug)]
+pub enum SequencerCommand {
+    /// Play this pattern from now on
+    SetPattern(Pattern),
+    /// Start the loop from step 0
+    Play,
+    /// Stop the loop, letting its notes go
+    Stop,
+    /// Play one step of a track on its own, to hear an edit
+    Preview { track: u8, step: Step },
+}
+
+///
+/// Plays a pattern round and round
+///
+pub struct SequencerPlayer<const P_FREQ: u32, const U_FREQ: u32, const MAX_NOTES: usize> {
+    synth: LiveSynth<P_FREQ, U_FREQ, MAX_NOTES>,
+    pattern: Pattern,
+    clock: StepClock<P_FREQ>,
+    playing: bool,
+    /// Key each track is holding down
+    held_keys: [Option<u8>; NUM_TRACKS],
+    /// A previewed note, as (track, key), and samples until it's let go
+    preview: Option<(u8, u8)>,
+    preview_samples_left: u32,
+}
+
+impl<const P_FREQ: u32, const U_FREQ: u32, const MAX_NOTES: usize>
+    SequencerPlayer<P_FREQ, U_FREQ, MAX_NOTES>
+{
+    // A quarter of a second is long enough to hear the note and short
+    // enough to keep up with the buttons.
+    const PREVIEW_SAMPLES: u32 = P_FREQ / 4;
+
+    pub fn new(pattern: &Pattern) -> Self {
+        let mut rval = Self {
+            synth: LiveSynth::new(LOUD_NOTES),
+            pattern: *pattern,
+            clock: StepClock::new(pattern.bpm),
+            playing: false,
+            held_keys: [None; NUM_TRACKS],
+            preview: None,
+            preview_samples_left: 0,
+        };
+        rval.set_pattern(pattern);
+        rval
+    }
+
+    pub fn pattern(&self) -> &Pattern {
+        &self.pattern
+    }
+
+    /// Play pattern from now on.  The loop carries on from the step it's on.
+    ///
+    pub fn set_pattern(&mut self, pattern: &Pattern) {
+        self.pattern = *pattern;
+        for (channel, track) in pattern.tracks.iter().enumerate() {
+            self.synth.set_program(channel as u8, track.program);
+        }
+        self.clock.set_bpm(pattern.bpm);
+    }
+
+    pub fn play(&mut self) {
+        self.clock.restart();
+        self.playing = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.release_held_keys();
+        self.playing = false;
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// The step playing, if the loop is
+    ///
+    pub fn current_step(&self) -> Option<usize> {
+        self.playing.then(|| self.clock.step())
+    }
+
+    /// Play step on track's channel for a moment
+    ///
+    pub fn preview(&mut self, track: u8, step: Step) {
+        self.release_preview();
+        if let Some(key) = step.key {
+            self.synth.note_on(track, key, step.velocity);
+            self.preview = Some((track, key));
+            self.preview_samples_left = Self::PREVIEW_SAMPLES;
+        }
+    }
+
+    pub fn run_commands<const N: usize>(&mut self, queue: &CommandQueue<SequencerCommand, N>) {
+        while let Some(command) = queue.pop() {
+            match command {
+                SequencerCommand::SetPattern(pattern) => self.set_pattern(&pattern),
+                SequencerCommand::Play => self.play(),
+                SequencerCommand::Stop => self.stop(),
+                SequencerCommand::Preview { track, step } => self.preview(track, step),
+            }
+        }
+    }
+
+    fn release_held_keys(&mut self) {
+        for (channel, held_key) in self.held_keys.iter_mut().enumerate() {
+            if let Some(key) = held_key.take() {
+                self.synth.note_off(channel as u8, key);
+            }
+        }
+    }
+
+    fn release_preview(&mut self) {
+        if let Some((track, key)) = self.preview.take() {
+            self.synth.note_off(track, key);
+        }
+    }
+
+    fn play_step(&mut self, step: usize) {
+        self.release_held_keys();
+        for (channel, track) in self.pattern.tracks.iter().enumerate() {
+            let Step { key, velocity } = track.steps[step];
+            if let Some(key) = key {
+                self.synth.note_on(channel as u8, key, velocity);
+                self.held_keys[channel] = Some(key);
+            }
+        }
+    }
+
+    pub fn get_next(&mut self) -> SoundSampleI32 {
+        if self.playing {
+            if let Some(step) = self.clock.tick() {
+                self.play_step(step);
+            } else if self.clock.is_past(GATE_PERCENT) {
+                self.release_held_keys();
+            }
+        }
+        if self.preview.is_some() {
+            self.preview_samples_left -= 1;
+            if self.preview_samples_left == 0 {
+                self.release_preview();
+            }
+        }
+        self.synth.get_next()
+    }
+
+    pub fn get_note_state(&self, note_volume: &mut [u8; 128]) {
+        self.synth.get_note_state(note_volume);
+    }
+
+    /// True if key is held down on track
+    ///
+    pub fn is_key_down(&self, track: u8, key: u8) -> bool {
+        self.synth.is_key_down(track, key)
+    }
+
+    /// Change the master volume, from 0 to 100.  The change is ramped, so
+    /// it's safe to call while playing.
+    ///
+    pub fn set_volume(&mut self, volume: u8) {
+        self.synth.set_volume(volume);
+    }
+
+    pub fn get_volume(&self) -> u8 {
+        self.synth.get_volume()
+    }
+
+    /// Go down to silence over time_in_ms.  The loop carries on, so check
+    /// is_faded_out to know when it's safe to stop.
+    ///
+    pub fn fade_out(&mut self, time_in_ms: i32) {
+        self.synth.fade_out(time_in_ms);
+    }
+
+    pub fn is_faded_out(&self) -> bool {
+        self.synth.is_faded_out()
+    }
+
+    /// The loop never ends on its own
+    ///
+    pub fn has_next(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::step_sequencer::*;
+
+    // 150 bpm is 2400 ticks a step at 24kHz.
+    const FREQ: u32 = 24000;
+    const STEP: usize = 2400;
+
+    type TestPlayer = SequencerPlayer<FREQ, 1000, 16>;
+
+    #[test]
+    fn steps_should_start_on_time() {
+        let mut clock = StepClock::<1000>::new(120);
+        // 125 ticks a step
+        let starts: Vec<(usize, usize)> = (0..2001)
+            .filter_map(|tick| clock.tick().map(|step| (tick, step)))
+            .collect();
+        assert_eq!(17, starts.len());
+        assert_eq!((0, 0), starts[0]);
+        assert_eq!((125, 1), starts[1]);
+        assert_eq!((1875, 15), starts[15]);
+        assert_eq!((2000, 0), starts[16]);
+
+        // 166.67 ticks a step, which shouldn't drift
+        clock.set_bpm(90);
+        clock.restart();
+        let starts: Vec<usize> = (0..501).filter(|_| clock.tick().is_some()).collect();
+        assert_eq!(vec![0, 167, 334, 500], starts);
+        assert_eq!(3, clock.step());
+        assert!(!clock.is_past(1));
+    }
+
+    #[test]
+    fn notes_should_go_in_with_the_buttons() {
+        let mut editor = Editor::new(Pattern::new());
+        assert_eq!(EditorAction::Menu, editor.press(SeqButton::Back));
+
+        // Put down middle C, move it up two, and turn it down a notch
+        assert_eq!(EditorAction::Changed, editor.press(SeqButton::Action));
+        assert_eq!(EditMode::Note, editor.mode());
+        assert_eq!(Step::note(60, DEFAULT_VELOCITY), editor.current_step());
+        editor.press(SeqButton::Up);
+        editor.press(SeqButton::Up);
+        assert_eq!(EditorAction::None, editor.press(SeqButton::Action));
+        assert_eq!(EditMode::Velocity, editor.mode());
+        assert_eq!(EditorAction::Changed, editor.press(SeqButton::Down));
+        editor.press(SeqButton::Action);
+
+        // On to the next step, where the last key goes down
+        assert_eq!(EditMode::Navigate, editor.mode());
+        assert_eq!((0, 1), editor.cursor());
+        editor.press(SeqButton::Action);
+        assert_eq!(Step::note(62, DEFAULT_VELOCITY), editor.current_step());
+        editor.press(SeqButton::Back);
+
+        let steps = &editor.pattern().tracks[0].steps;
+        assert_eq!(Step::note(62, DEFAULT_VELOCITY - VELOCITY_STEP), steps[0]);
+        assert_eq!(Step::note(62, DEFAULT_VELOCITY), steps[1]);
+        assert_eq!(Step::REST, steps[2]);
+    }
+
+    #[test]
+    fn edits_should_stay_in_range() {
+        let mut editor = Editor::new(Pattern::new());
+
+        // The cursor runs from track to track, and round
+        editor.press(SeqButton::Up);
+        assert_eq!((NUM_TRACKS - 1, NUM_STEPS - 1), editor.cursor());
+        editor.press(SeqButton::Down);
+        editor.press(SeqButton::Down);
+        assert_eq!((0, 1), editor.cursor());
+        for _ in 0..NUM_STEPS {
+            editor.press(SeqButton::Down);
+        }
+        assert_eq!((1, 1), editor.cursor());
+
+        editor.press(SeqButton::Action);
+        for _ in 0..100 {
+            editor.press(SeqButton::Up);
+        }
+        assert_eq!(Some(HIGHEST_KEY), editor.current_step().key);
+        for _ in 0..100 {
+            editor.press(SeqButton::Down);
+        }
+        assert_eq!(Some(LOWEST_KEY), editor.current_step().key);
+
+        // All the way down makes a rest
+        editor.press(SeqButton::Action);
+        editor.press(SeqButton::Up);
+        editor.press(SeqButton::Up);
+        assert_eq!(127, editor.current_step().velocity);
+        for _ in 0..7 {
+            editor.press(SeqButton::Down);
+        }
+        assert_eq!(EditMode::Velocity, editor.mode());
+        editor.press(SeqButton::Down);
+        assert_eq!(Step::REST, editor.current_step());
+        assert_eq!(EditMode::Navigate, editor.mode());
+
+        editor.edit_tempo();
+        for _ in 0..100 {
+            editor.press(SeqButton::Up);
+        }
+        assert_eq!(EditorAction::None, editor.press(SeqButton::Up));
+        assert_eq!(MAX_BPM, editor.pattern().bpm);
+        editor.press(SeqButton::Action);
+        assert_eq!(EditMode::Navigate, editor.mode());
+    }
+
+    #[test]
+    fn instruments_should_cycle_and_play() {
+        let mut editor = Editor::new(Pattern::new());
+        editor.edit_instrument();
+        editor.press(SeqButton::Down);
+        editor.press(SeqButton::Down);
+        // Round from the end of the list
+        assert_eq!(INSTRUMENTS[0].program, editor.pattern().tracks[0].program);
+        editor.press(SeqButton::Up);
+        editor.press(SeqButton::Up);
+        assert_eq!(
+            Some("Square Lead"),
+            instrument_name(editor.pattern().tracks[0].program)
+        );
+
+        // Every one of them has to be a program the synth knows
+        for instrument in INSTRUMENTS.iter() {
+            let mut pattern = Pattern::new();
+            pattern.tracks[0].program = instrument.program;
+            let mut player = TestPlayer::new(&pattern);
+            player.preview(0, Step::note(60, 100));
+            player.get_next();
+        }
+
+        let mut buffer = [0u8; 4];
+        assert_eq!("C4", key_name(60, &mut buffer));
+        assert_eq!("A#7", key_name(106, &mut buffer));
+        assert_eq!("C-1", key_name(0, &mut buffer));
+    }
+
+    #[test]
+    fn the_loop_should_play_the_pattern() {
+        let mut pattern = Pattern::new();
+        pattern.bpm = 150;
+        pattern.tracks[0].steps[0] = Step::note(60, 100);
+        pattern.tracks[2].steps[0] = Step::note(36, 100);
+        pattern.tracks[0].steps[2] = Step::note(64, 100);
+
+        let mut player = TestPlayer::new(&pattern);
+        let run = |player: &mut TestPlayer, samples: usize| {
+            for _ in 0..samples {
+                player.get_next();
+            }
+        };
+
+        assert_eq!(None, player.current_step());
+        player.play();
+        run(&mut player, 1);
+        assert!(player.is_key_down(0, 60) && player.is_key_down(2, 36));
+        assert_eq!(Some(0), player.current_step());
+
+        // Let go at the end of the gate
+        run(&mut player, STEP * 80 / 100);
+        assert!(!player.is_key_down(0, 60));
+        run(&mut player, STEP * 2 - STEP * 80 / 100);
+        assert!(player.is_key_down(0, 64));
+        assert_eq!(Some(2), player.current_step());
+
+        // And round again
+        run(&mut player, STEP * 14);
+        assert!(player.is_key_down(0, 60) && !player.is_key_down(0, 64));
+        assert_eq!(Some(0), player.current_step());
+
+        player.stop();
+        assert!(!player.is_key_down(0, 60) && !player.is_key_down(2, 36));
+        assert_eq!(None, player.current_step());
+        assert!(player.has_next());
+    }
+
+    #[test]
+    fn commands_should_reach_the_player() {
+        let queue = CommandQueue::<SequencerCommand, 4>::new();
+        let mut player = TestPlayer::new(&Pattern::new());
+        let mut pattern = Pattern::new();
+        pattern.tracks[1].steps[0] = Step::note(72, 100);
+        pattern.tracks[1].program = 13;
+        queue.push(SequencerCommand::SetPattern(pattern)).unwrap();
+        queue.push(SequencerCommand::Play).unwrap();
+        player.run_commands(&queue);
+        assert!(player.is_playing());
+        assert_eq!(&pattern, player.pattern());
+
+        queue.push(SequencerCommand::Stop).unwrap();
+        queue
+            .push(SequencerCommand::Preview {
+                track: 3,
+                step: Step::note(48, 100),
+            })
+            .unwrap();
+        player.run_commands(&queue);
+        assert!(!player.is_playing());
+        assert!(player.is_key_down(3, 48));
+        for _ in 0..FREQ / 4 {
+            player.get_next();
+        }
+        assert!(!player.is_key_down(3, 48));
+    }
+}
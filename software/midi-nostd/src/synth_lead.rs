@@ -0,0 +1,34 @@
+use crate::adsr::EnvelopeCurve;
+use crate::instrument_low_pass_filters::GenericLowPassCalculator;
+use crate::instrument_template_basic::InstrumentTemplateBasic;
+use crate::oscillator::OscillatorType;
+use crate::waveshaper::ShapeCurve;
+use crate::waveshaper::Waveshaper;
+
+pub type SynthLead<const P_FREQ: u32, const U_FREQ: u32> = Waveshaper<
+    P_FREQ,
+    U_FREQ,
+    InstrumentTemplateBasic<
+        P_FREQ,
+        U_FREQ,
+        50,                                    // Oscillator 0 pulse width
+        100,                                   // Oscillator 0 volume
+        { OscillatorType::SawTooth as usize }, // Oscillator 0 wave form
+        0,                                     // Oscillator 0 note offset
+        50,                                    // Oscillator 1 pulse width
+        60,                                    // Oscillator 1 volume
+        { OscillatorType::SawTooth as usize }, // Oscillator 1 wave form
+        12,                                    // Oscillator 1 note offset
+        false,                                 // Sync Oscillator 1 to 0
+        10,                                    // A
+        400,                                   // D
+        80,                                    // S
+        200,                                   // R
+        { EnvelopeCurve::Linear as usize },    // D curve
+        { EnvelopeCurve::Linear as usize },    // R curve
+        GenericLowPassCalculator<100, 1000>,
+    >,
+    { ShapeCurve::HardClip as usize }, // Curve
+    300,                               // Drive, percent
+    60,                                // Output, percent
+>;
@@ -0,0 +1,19 @@
+use crate::instrument_low_pass_filters::GenericLowPassCalculator;
+use crate::instrument_template_reed::InstrumentTemplateReed;
+use crate::midi_notes::FREQUENCY_MULTIPLIER;
+use crate::oscillator::OscillatorType;
+
+pub type TangoAccordion<const P_FREQ: u32, const U_FREQ: u32> = InstrumentTemplateReed<
+    P_FREQ,
+    U_FREQ,
+    35,                                    // Reed pulse width
+    { OscillatorType::SawTooth as usize }, // Reed wave form
+    3,                                     // Second reed detune, cents
+    { 4 * FREQUENCY_MULTIPLIER },          // Bellows tremolo frequency
+    3,                                     // Bellows tremolo depth
+    50,                                    // A
+    200,                                   // D
+    85,                                    // S
+    100,                                   // R
+    GenericLowPassCalculator<150, 1200>,
+>;
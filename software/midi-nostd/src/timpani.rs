@@ -0,0 +1,42 @@
+use crate::modal_percussion::ModalPatch;
+use crate::modal_percussion::ModalPercussion;
+use crate::modal_percussion::Mode;
+use crate::modal_percussion::MAX_MODES;
+
+//
+// A kettle drum head.  The kettle pulls the membrane modes into something
+// close to a harmonic series, which is why a timpani has a pitch at all.
+// A hard hit starts a little sharp and settles.
+//
+pub struct TimpaniModes {}
+
+impl ModalPatch for TimpaniModes {
+    const MODES: [Mode; MAX_MODES] = [
+        Mode {
+            ratio: 1000,
+            level: 55,
+            decay: 1500,
+        },
+        Mode {
+            ratio: 1504,
+            level: 25,
+            decay: 900,
+        },
+        Mode {
+            ratio: 1742,
+            level: 10,
+            decay: 600,
+        },
+        Mode {
+            ratio: 2000,
+            level: 10,
+            decay: 500,
+        },
+    ];
+    const PITCH_DROP_CENTS: i32 = 40;
+    const PITCH_DROP_TIME: u32 = 80;
+    const RELEASE: u32 = 600;
+}
+
+pub type Timpani<const P_FREQ: u32, const U_FREQ: u32> =
+    ModalPercussion<P_FREQ, U_FREQ, TimpaniModes>;
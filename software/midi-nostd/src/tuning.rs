@@ -0,0 +1,210 @@
+// Tuning.
+//
+// Every note's pitch starts out as 12 tone equal temperament at A440, from
+// midi_notes.  A Tuning moves each key by some number of cents, which
+// comes from three places:
+//
+// - The reference pitch.  A4 at 415hz for baroque pitch, 442hz for a
+//   bright modern orchestra, or anything else for fine detune.
+// - A temperament, a fixed pattern of offsets for the twelve notes of the
+//   octave, counted from a selectable root.
+// - Per key offsets set by MIDI Tuning Standard single note tuning change
+//   SysEx messages.
+//
+
+use crate::midi_notes::ratio_to_cents;
+use crate::midi_notes::FREQUENCY_MULTIPLIER;
+
+/// Historical temperaments
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(usize)]
+pub enum Temperament {
+    /// 12 tone equal temperament.  No offsets.
+    Equal,
+    /// 5 limit just intonation.  Pure thirds and fifths in the root's key,
+    /// and increasingly sour ones further away.
+    Just,
+    /// Quarter comma meantone.  Pure major thirds, with a wolf fifth
+    /// between the sharp side and the flat side.
+    Meantone,
+    /// Werckmeister III.  Every key is usable, but the ones close to the
+    /// root are sweeter.
+    Werckmeister,
+}
+
+impl Temperament {
+    /// Offset of each note from equal temperament in cents, counted in
+    /// semitones up from the root
+    ///
+    pub const fn offsets(&self) -> [i16; 12] {
+        match self {
+            Self::Equal => [0; 12],
+            Self::Just => [0, 12, 4, 16, -14, -2, -10, 2, 14, -16, -4, -12],
+            Self::Meantone => [0, -24, -7, 10, -14, 3, -21, -3, -17, -10, 7, -17],
+            Self::Werckmeister => [0, -10, -8, -6, -10, -2, -12, -4, -8, -12, -4, -8],
+        }
+    }
+}
+
+///
+/// Per key tuning, in cents away from equal temperament at A440
+///
+#[derive(Clone)]
+pub struct Tuning {
+    reference_cents: i16,
+    temperament: Temperament,
+    root: u8,
+    key_cents: [i16; 128],
+}
+
+impl Tuning {
+    pub const EQUAL: Self = Self {
+        reference_cents: 0,
+        temperament: Temperament::Equal,
+        root: 0,
+        key_cents: [0; 128],
+    };
+
+    /// Tune to a reference pitch for A4, in hz * FREQUENCY_MULTIPLIER
+    ///
+    pub fn set_reference_pitch(&mut self, a4_frequency: u32) {
+        let a440 = 440 * FREQUENCY_MULTIPLIER;
+        let ratio = ((a4_frequency as u64) << 16) / (a440 as u64);
+        self.reference_cents = ratio_to_cents(ratio as u32).clamp(-2400, 2400) as i16;
+    }
+
+    /// Detune everything by cents
+    ///
+    pub fn set_fine_tune(&mut self, cents: i16) {
+        self.reference_cents = cents.clamp(-2400, 2400);
+    }
+
+    /// Use a temperament, counted from root (0 for C, 1 for C#, ...)
+    ///
+    pub fn set_temperament(&mut self, temperament: Temperament, root: u8) {
+        self.temperament = temperament;
+        self.root = root % 12;
+    }
+
+    /// Clear any per key tuning from SysEx
+    ///
+    pub fn reset_keys(&mut self) {
+        self.key_cents = [0; 128];
+    }
+
+    /// How far key is from equal temperament at A440, in cents
+    ///
+    pub fn cents(&self, key: u8) -> i16 {
+        let degree = ((key as usize) + 12 - (self.root as usize)) % 12;
+        self.reference_cents
+            + self.temperament.offsets()[degree]
+            + self.key_cents[(key & 0x7f) as usize]
+    }
+
+    ///
+    /// Handle a SysEx message, as midly hands it over (without the leading
+    /// 0xf0).  Understands the real time and non real time MIDI Tuning
+    /// Standard single note tuning changes, and ignores everything else.
+    ///
+    pub fn handle_sysex(&mut self, data: &[u8]) {
+        // 7f <device> 08 02 <program> <count> ...
+        // 7e <device> 08 07 <bank> <program> <count> ...
+        let changes = match data {
+            [0x7f, _, 0x08, 0x02, _, _count, changes @ ..] => changes,
+            [0x7e, _, 0x08, 0x07, _, _, _count, changes @ ..] => changes,
+            _ => return,
+        };
+        for change in changes.chunks_exact(4) {
+            self.single_note_change(change[0], change[1], change[2], change[3]);
+        }
+    }
+
+    //
+    // Retune key to semitone plus a 14 bit fraction of a semitone.
+    // 7f 7f 7f means leave it alone.
+    //
+    fn single_note_change(&mut self, key: u8, semitone: u8, fraction_msb: u8, fraction_lsb: u8) {
+        if key > 0x7f || (semitone == 0x7f && fraction_msb == 0x7f && fraction_lsb == 0x7f) {
+            return;
+        }
+        let fraction = ((fraction_msb as i32) << 7) | (fraction_lsb as i32);
+        let cents = ((semitone as i32) - (key as i32)) * 100 + ((fraction * 100 + 0x2000) >> 14);
+        self.key_cents[key as usize] = cents as i16;
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Self::EQUAL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tuning::*;
+
+    #[test]
+    fn equal_temperament_at_a440_should_do_nothing() {
+        let tuning = Tuning::default();
+        for key in 0..128 {
+            assert_eq!(0, tuning.cents(key));
+        }
+    }
+
+    #[test]
+    fn reference_pitch_should_move_every_key() {
+        let mut tuning = Tuning::default();
+        tuning.set_reference_pitch(415 * FREQUENCY_MULTIPLIER);
+        assert_eq!(-101, tuning.cents(69));
+        assert_eq!(-101, tuning.cents(20));
+        tuning.set_reference_pitch(442 * FREQUENCY_MULTIPLIER);
+        assert_eq!(8, tuning.cents(60));
+        tuning.set_fine_tune(-3);
+        assert_eq!(-3, tuning.cents(60));
+        tuning.set_fine_tune(i16::MAX);
+        assert_eq!(2400, tuning.cents(60));
+    }
+
+    #[test]
+    fn temperaments_should_follow_the_root() {
+        let mut tuning = Tuning::default();
+        tuning.set_temperament(Temperament::Just, 0);
+        // C major: the E is a pure third, the G a pure fifth.
+        assert_eq!(0, tuning.cents(60));
+        assert_eq!(-14, tuning.cents(64));
+        assert_eq!(2, tuning.cents(67));
+
+        // In D the pattern moves up two semitones.
+        tuning.set_temperament(Temperament::Just, 2);
+        assert_eq!(0, tuning.cents(62));
+        assert_eq!(-14, tuning.cents(66));
+        assert_eq!(-12, tuning.cents(61));
+    }
+
+    #[test]
+    fn sysex_should_retune_single_notes() {
+        let mut tuning = Tuning::default();
+        // Real time: A4 to a quarter tone above, and C4 to C#4.
+        tuning.handle_sysex(&[
+            0x7f, 0x7f, 0x08, 0x02, 0x00, 0x02, 69, 69, 0x40, 0x00, 60, 61, 0x00, 0x00, 0xf7,
+        ]);
+        assert_eq!(50, tuning.cents(69));
+        assert_eq!(100, tuning.cents(60));
+        assert_eq!(0, tuning.cents(61));
+
+        // Non real time, with the no change marker on C4.
+        tuning.handle_sysex(&[
+            0x7e, 0x00, 0x08, 0x07, 0x00, 0x00, 0x02, 69, 68, 0x20, 0x00, 60, 0x7f, 0x7f, 0x7f,
+            0xf7,
+        ]);
+        assert_eq!(-75, tuning.cents(69));
+        assert_eq!(100, tuning.cents(60));
+
+        // Anything else is left alone.
+        tuning.handle_sysex(&[0x7e, 0x7f, 0x09, 0x01, 0xf7]);
+        assert_eq!(100, tuning.cents(60));
+        tuning.reset_keys();
+        assert_eq!(0, tuning.cents(60));
+    }
+}
@@ -0,0 +1,323 @@
+// Velocity response.
+//
+// MIDI velocity is a number from 1 to 127, and by default the synth treats
+// it as a straight volume.  That's rarely what a file (or a listener) wants.
+// Files exported from notation software often squeeze everything into
+// 60 to 100, which sounds flat, and some instruments (organs, most
+// obviously) shouldn't respond to velocity at all.
+//
+
+use crate::instrument_low_pass_filters::FrequencyCalculator;
+use crate::note::SoundSourceNoteInit;
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::OscillatorInterface;
+use crate::sound_source_core::SoundSourceCore;
+use core::marker::PhantomData;
+
+/// Fixed velocity response shapes
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(usize)]
+pub enum VelocityShape {
+    /// Velocity in, velocity out
+    Linear,
+    /// Quiet notes are brought up.  Good for files that play too softly.
+    Soft,
+    /// Quiet notes are pushed down, which spreads out files that use a
+    /// narrow range of loud velocities.
+    Hard,
+}
+
+impl VelocityShape {
+    pub const fn from_usize(usize_value: usize) -> Self {
+        match usize_value {
+            0 => Self::Linear,
+            1 => Self::Soft,
+            2 => Self::Hard,
+            3_usize.. => todo!(),
+        }
+    }
+
+    pub const fn apply(&self, vel: u8) -> u8 {
+        let vel = vel as u32;
+        let shaped = match self {
+            Self::Linear => vel,
+            Self::Soft => isqrt(vel * 127),
+            Self::Hard => vel * vel / 127,
+        };
+        clamp_velocity(shaped)
+    }
+}
+
+/// Velocity curve, applied to every note on by the Midi player
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VelocityCurve {
+    Shape(VelocityShape),
+    /// Every note plays at this velocity
+    Fixed(u8),
+    /// Velocities from low to high are stretched out to cover 1 to 127.
+    /// Anything outside the range is clamped.
+    Expand {
+        low: u8,
+        high: u8,
+    },
+    /// A custom table, indexed by the incoming velocity
+    Table(&'static [u8; 128]),
+}
+
+impl VelocityCurve {
+    pub const LINEAR: Self = Self::Shape(VelocityShape::Linear);
+
+    pub const fn apply(&self, vel: u8) -> u8 {
+        match self {
+            Self::Shape(shape) => shape.apply(vel),
+            Self::Fixed(fixed) => clamp_velocity(*fixed as u32),
+            Self::Expand { low, high } => {
+                let low = *low as u32;
+                let high = *high as u32;
+                let vel = vel as u32;
+                if high <= low {
+                    clamp_velocity(vel)
+                } else if vel <= low {
+                    1
+                } else if vel >= high {
+                    127
+                } else {
+                    clamp_velocity(1 + (vel - low) * 126 / (high - low))
+                }
+            }
+            Self::Table(table) => clamp_velocity(table[(vel & 0x7f) as usize] as u32),
+        }
+    }
+}
+
+impl Default for VelocityCurve {
+    fn default() -> Self {
+        Self::LINEAR
+    }
+}
+
+//
+// A note on with a velocity of 0 is a note off, so a curve should never
+// produce one.
+//
+const fn clamp_velocity(vel: u32) -> u8 {
+    if vel < 1 {
+        1
+    } else if vel > 127 {
+        127
+    } else {
+        vel as u8
+    }
+}
+
+const fn isqrt(value: u32) -> u32 {
+    let mut root = 0;
+    while (root + 1) * (root + 1) <= value {
+        root += 1;
+    }
+    root
+}
+
+///
+/// Per instrument velocity response
+///
+/// Runs the velocity of every note through SHAPE and then scales how much
+/// it matters by SENSITIVITY, a percentage.  At 0 every note plays at full
+/// velocity, at 100 the shaped velocity is used as is.
+///
+pub struct VelocityResponse<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    Source: SoundSourceCore<P_FREQ, U_FREQ, InitValuesType = SoundSourceNoteInit>,
+    const SHAPE: usize,
+    const SENSITIVITY: u8,
+> {
+    source: Source,
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        Source: SoundSourceCore<P_FREQ, U_FREQ, InitValuesType = SoundSourceNoteInit>,
+        const SHAPE: usize,
+        const SENSITIVITY: u8,
+    > VelocityResponse<P_FREQ, U_FREQ, Source, SHAPE, SENSITIVITY>
+{
+    const SHAPE_ENUM: VelocityShape = VelocityShape::from_usize(SHAPE);
+
+    pub const fn respond(vel: u8) -> u8 {
+        let shaped = Self::SHAPE_ENUM.apply(vel) as u32;
+        clamp_velocity(127 - (127 - shaped) * (SENSITIVITY as u32) / 100)
+    }
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        Source: SoundSourceCore<P_FREQ, U_FREQ, InitValuesType = SoundSourceNoteInit>,
+        const SHAPE: usize,
+        const SENSITIVITY: u8,
+    > SoundSourceCore<P_FREQ, U_FREQ>
+    for VelocityResponse<P_FREQ, U_FREQ, Source, SHAPE, SENSITIVITY>
+{
+    type InitValuesType = SoundSourceNoteInit;
+
+    fn new(init_values: Self::InitValuesType) -> Self {
+        let velocity = Self::respond(init_values.velocity);
+        Self {
+            source: Source::new(SoundSourceNoteInit {
+                velocity,
+                ..init_values
+            }),
+        }
+    }
+
+    #[inline]
+    fn get_next(&mut self) -> SoundSampleI32 {
+        self.source.get_next()
+    }
+
+    fn update(&mut self) {
+        self.source.update();
+    }
+
+    fn has_next(&self) -> bool {
+        self.source.has_next()
+    }
+
+    fn trigger_note_off(&mut self) {
+        self.source.trigger_note_off();
+    }
+
+    fn reset_oscillator(&mut self) {
+        self.source.reset_oscillator();
+    }
+
+    fn restart(&mut self, vel: u8) {
+        self.source.restart(Self::respond(vel));
+    }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.source.set_pitch_ratio(ratio)
+    }
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        Source: OscillatorInterface<P_FREQ, U_FREQ, InitValuesType = SoundSourceNoteInit>,
+        const SHAPE: usize,
+        const SENSITIVITY: u8,
+    > OscillatorInterface<P_FREQ, U_FREQ>
+    for VelocityResponse<P_FREQ, U_FREQ, Source, SHAPE, SENSITIVITY>
+{
+    fn set_amplitude_adjust(&mut self, adjust: SoundSampleI32) {
+        self.source.set_amplitude_adjust(adjust);
+    }
+}
+
+///
+/// Routes velocity to a filter's cutoff frequency.
+///
+/// Takes the cutoff from another calculator and scales it down for softer
+/// notes.  DEPTH is a percentage; at 50 the softest note gets half the
+/// cutoff of the hardest.
+///
+pub struct VelocityCutoff<Calculator: FrequencyCalculator, const DEPTH: u32> {
+    _marker: PhantomData<Calculator>,
+}
+
+impl<Calculator: FrequencyCalculator, const DEPTH: u32> FrequencyCalculator
+    for VelocityCutoff<Calculator, DEPTH>
+{
+    fn get_cutoff_frequency(init_values: &SoundSourceNoteInit) -> u32 {
+        let cutoff = Calculator::get_cutoff_frequency(init_values);
+        let velocity = init_values.velocity as u32;
+        cutoff * (100 * 127 - DEPTH * (127 - velocity)) / (100 * 127)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instrument_low_pass_filters::GenericLowPassCalculator;
+    use crate::steady_one::SteadyOne;
+    use crate::velocity::*;
+
+    #[test]
+    fn shapes_should_bend_the_middle_and_keep_the_ends() {
+        for shape in [
+            VelocityShape::Linear,
+            VelocityShape::Soft,
+            VelocityShape::Hard,
+        ] {
+            assert_eq!(127, shape.apply(127));
+            assert!(shape.apply(1) >= 1);
+        }
+        assert_eq!(64, VelocityShape::Linear.apply(64));
+        assert!(VelocityShape::Soft.apply(64) > 80);
+        assert!(VelocityShape::Hard.apply(64) < 40);
+        for vel in 1..127 {
+            assert!(VelocityShape::Soft.apply(vel) <= VelocityShape::Soft.apply(vel + 1));
+            assert!(VelocityShape::Hard.apply(vel) <= VelocityShape::Hard.apply(vel + 1));
+        }
+    }
+
+    #[test]
+    fn curves_should_map_velocities() {
+        assert_eq!(100, VelocityCurve::Fixed(100).apply(3));
+        assert_eq!(1, VelocityCurve::Fixed(0).apply(3));
+
+        let expand = VelocityCurve::Expand { low: 60, high: 100 };
+        assert_eq!(1, expand.apply(40));
+        assert_eq!(64, expand.apply(80));
+        assert_eq!(127, expand.apply(110));
+
+        static TABLE: [u8; 128] = [42; 128];
+        assert_eq!(42, VelocityCurve::Table(&TABLE).apply(99));
+        assert_eq!(99, VelocityCurve::default().apply(99));
+    }
+
+    #[test]
+    fn sensitivity_should_scale_the_response() {
+        type Insensitive = VelocityResponse<
+            1000,
+            1000,
+            SteadyOne<1000, 1000>,
+            { VelocityShape::Linear as usize },
+            0,
+        >;
+        type HalfSensitive = VelocityResponse<
+            1000,
+            1000,
+            SteadyOne<1000, 1000>,
+            { VelocityShape::Linear as usize },
+            50,
+        >;
+        type FullSensitive = VelocityResponse<
+            1000,
+            1000,
+            SteadyOne<1000, 1000>,
+            { VelocityShape::Linear as usize },
+            100,
+        >;
+        assert_eq!(127, Insensitive::respond(1));
+        assert_eq!(127, Insensitive::respond(100));
+        assert_eq!(64, HalfSensitive::respond(1));
+        assert_eq!(27, FullSensitive::respond(27));
+    }
+
+    #[test]
+    fn velocity_should_close_the_filter() {
+        type Cutoff = VelocityCutoff<GenericLowPassCalculator<0, 1000>, 50>;
+        assert_eq!(
+            1000,
+            Cutoff::get_cutoff_frequency(&SoundSourceNoteInit::new(60, 0, 127))
+        );
+        assert_eq!(
+            500,
+            Cutoff::get_cutoff_frequency(&SoundSourceNoteInit::new(60, 0, 0))
+        );
+    }
+}
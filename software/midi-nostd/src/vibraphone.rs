@@ -0,0 +1,35 @@
+use crate::modal_percussion::ModalPatch;
+use crate::modal_percussion::ModalPercussion;
+use crate::modal_percussion::Mode;
+use crate::modal_percussion::MAX_MODES;
+
+//
+// Aluminium bars, tuned so the first two overtones sit two octaves and
+// a bit more than three octaves up.  Long, mellow decay.
+//
+pub struct VibraphoneModes {}
+
+impl ModalPatch for VibraphoneModes {
+    const MODES: [Mode; MAX_MODES] = [
+        Mode {
+            ratio: 1000,
+            level: 70,
+            decay: 3000,
+        },
+        Mode {
+            ratio: 4000,
+            level: 20,
+            decay: 800,
+        },
+        Mode {
+            ratio: 10000,
+            level: 10,
+            decay: 200,
+        },
+        Mode::UNUSED,
+    ];
+    const RELEASE: u32 = 300;
+}
+
+pub type Vibraphone<const P_FREQ: u32, const U_FREQ: u32> =
+    ModalPercussion<P_FREQ, U_FREQ, VibraphoneModes>;
@@ -1,8 +1,8 @@
-use crate::adsr::CoreAdsr;
+use crate::adsr::CoreDahdsr;
+use crate::adsr::EnvelopeCurve;
 use crate::double_oscillator::DoubleOscillator;
 use crate::filter::Filter;
 use crate::lfo_amplitude::LfoAmplitude;
-use crate::midi_notes::midi_note_to_freq;
 use crate::midi_notes::FREQUENCY_MULTIPLIER;
 use crate::note::SoundSourceNoteInit;
 use crate::oscillator::CoreOscillator;
@@ -27,8 +27,24 @@ type ViolinOscillatorLfo<const P_FREQ: u32, const U_FREQ: u32> = LfoAmplitude<
     10,
 >;
 
-type ViolinOscillatorAdsr<const P_FREQ: u32, const U_FREQ: u32> =
-    CoreAdsr<P_FREQ, U_FREQ, 03, 5000, 100, 350, ViolinOscillatorLfo<P_FREQ, U_FREQ>>;
+//
+// Soft notes are bowed in gently, so their attack is up to 150ms longer.
+//
+type ViolinOscillatorAdsr<const P_FREQ: u32, const U_FREQ: u32> = CoreDahdsr<
+    P_FREQ,
+    U_FREQ,
+    0,
+    03,
+    0,
+    5000,
+    100,
+    350,
+    { EnvelopeCurve::Linear as usize },
+    { EnvelopeCurve::Linear as usize },
+    { EnvelopeCurve::Linear as usize },
+    150,
+    ViolinOscillatorLfo<P_FREQ, U_FREQ>,
+>;
 
 type ViolinFiltered<const P_FREQ: u32, const U_FREQ: u32> =
     Filter<P_FREQ, U_FREQ, ViolinOscillatorAdsr<P_FREQ, U_FREQ>>;
@@ -58,8 +74,8 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
     }
 
     fn new(init_values: Self::InitValuesType) -> Self {
-        let frequency_1 = midi_note_to_freq(init_values.key);
-        let frequency_2 = midi_note_to_freq(init_values.key + 6);
+        let frequency_1 = init_values.frequency(0);
+        let frequency_2 = init_values.frequency(6);
         let adsr_init = (init_values.velocity as i32) << 8;
         let core =
             ViolinFiltered::<P_FREQ, U_FREQ>::new((((frequency_1, frequency_2), adsr_init), 1900));
@@ -73,4 +89,8 @@ impl<const P_FREQ: u32, const U_FREQ: u32> SoundSourceCore<P_FREQ, U_FREQ>
     fn restart(self: &mut Self, vel: u8) {
         self.core.restart(vel);
     }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.core.set_pitch_ratio(ratio)
+    }
 }
@@ -0,0 +1,250 @@
+// Waveshaping, for overdriven guitars and anything else that wants some dirt.
+
+use crate::sound_sample::SoundSampleI32;
+use crate::sound_source_core::OscillatorInterface;
+use crate::sound_source_core::SoundSourceCore;
+
+/// Waveshaping curves
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[repr(usize)]
+pub enum ShapeCurve {
+    /// Cubic soft clip.  Rounds the peaks off, warm overdrive.
+    SoftClip,
+    /// Flat tops.  Buzzy, fuzz box like.
+    HardClip,
+    /// Soft clip on the way up, a lower and harder clip on the way down.  The
+    /// asymmetry adds the even harmonics valve amps are known for.
+    Tube,
+}
+
+impl ShapeCurve {
+    const fn from_usize(usize_value: usize) -> Self {
+        match usize_value {
+            0 => Self::SoftClip,
+            1 => Self::HardClip,
+            2 => Self::Tube,
+            3_usize.. => todo!(),
+        }
+    }
+}
+
+//
+// y = 1.5x - 0.5x^3, clamped at +/- 1.  Close to linear around zero, with
+// the slope flattening out smoothly to nothing at full scale.
+//
+#[inline]
+const fn soft_clip(sample: i32) -> i32 {
+    let x = if sample > 0x8000 {
+        0x8000
+    } else if sample < -0x8000 {
+        -0x8000
+    } else {
+        sample
+    };
+    let x_cubed = (((x * x) >> 15) * x) >> 15;
+    (3 * x - x_cubed) / 2
+}
+
+///
+/// Waveshaper
+///
+/// Amplifies the source by DRIVE percent, pushes it through a CURVE and then
+/// scales the result by OUTPUT percent.  The more drive, the more of the
+/// signal ends up on the flat part of the curve, so higher drive means more
+/// distortion and more sustain.  OUTPUT brings the level back down, since
+/// a heavily clipped signal is much louder than a clean one.
+///
+/// Asymmetric curves add a DC offset, which is taken out again by a simple
+/// DC blocking filter.
+///
+pub struct Waveshaper<
+    const P_FREQ: u32,
+    const U_FREQ: u32,
+    Source: SoundSourceCore<P_FREQ, U_FREQ>,
+    const CURVE: usize,
+    const DRIVE: u32,
+    const OUTPUT: u8,
+> {
+    source: Source,
+    last_shaped: i32,
+    last_out: i32,
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        Source: SoundSourceCore<P_FREQ, U_FREQ>,
+        const CURVE: usize,
+        const DRIVE: u32,
+        const OUTPUT: u8,
+    > Waveshaper<P_FREQ, U_FREQ, Source, CURVE, DRIVE, OUTPUT>
+{
+    const CURVE_ENUM: ShapeCurve = ShapeCurve::from_usize(CURVE);
+    const OUTPUT_SCALE: SoundSampleI32 = SoundSampleI32::new_percent(OUTPUT);
+
+    // DC blocker pole, about 0.995.  Corner frequency is around 20hz at 24000hz.
+    const DC_POLE: i32 = 0x7f5c;
+
+    #[inline]
+    fn shape(sample: i32) -> i32 {
+        // Drive can get big, so clamp before the curve gets to square it.
+        let driven = (sample * (DRIVE as i32) / 100).clamp(-0x10000, 0x10000);
+        match Self::CURVE_ENUM {
+            ShapeCurve::SoftClip => soft_clip(driven),
+            ShapeCurve::HardClip => driven.clamp(-0x8000, 0x8000),
+            ShapeCurve::Tube => {
+                if driven >= 0 {
+                    soft_clip(driven)
+                } else {
+                    soft_clip(driven * 2) / 2
+                }
+            }
+        }
+    }
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        Source: SoundSourceCore<P_FREQ, U_FREQ>,
+        const CURVE: usize,
+        const DRIVE: u32,
+        const OUTPUT: u8,
+    > SoundSourceCore<P_FREQ, U_FREQ> for Waveshaper<P_FREQ, U_FREQ, Source, CURVE, DRIVE, OUTPUT>
+{
+    type InitValuesType = Source::InitValuesType;
+
+    fn new(init_values: Self::InitValuesType) -> Self {
+        Self {
+            source: Source::new(init_values),
+            last_shaped: 0,
+            last_out: 0,
+        }
+    }
+
+    #[inline]
+    fn get_next(&mut self) -> SoundSampleI32 {
+        let shaped = Self::shape(self.source.get_next().to_i32());
+        let out = if Self::CURVE_ENUM == ShapeCurve::Tube {
+            let blocked = shaped - self.last_shaped + ((self.last_out * Self::DC_POLE) >> 15);
+            self.last_shaped = shaped;
+            self.last_out = blocked;
+            blocked
+        } else {
+            shaped
+        };
+        SoundSampleI32::new_i32(out) * Self::OUTPUT_SCALE
+    }
+
+    fn update(&mut self) {
+        self.source.update();
+    }
+
+    fn has_next(&self) -> bool {
+        self.source.has_next()
+    }
+
+    fn trigger_note_off(&mut self) {
+        self.source.trigger_note_off();
+    }
+
+    fn reset_oscillator(&mut self) {
+        self.source.reset_oscillator();
+    }
+
+    fn restart(&mut self, vel: u8) {
+        self.source.restart(vel);
+    }
+
+    fn set_pitch_ratio(&mut self, ratio: u32) -> bool {
+        self.source.set_pitch_ratio(ratio)
+    }
+}
+
+impl<
+        const P_FREQ: u32,
+        const U_FREQ: u32,
+        Source: OscillatorInterface<P_FREQ, U_FREQ>,
+        const CURVE: usize,
+        const DRIVE: u32,
+        const OUTPUT: u8,
+    > OscillatorInterface<P_FREQ, U_FREQ>
+    for Waveshaper<P_FREQ, U_FREQ, Source, CURVE, DRIVE, OUTPUT>
+{
+    fn set_amplitude_adjust(&mut self, adjust: SoundSampleI32) {
+        self.source.set_amplitude_adjust(adjust);
+    }
+
+    fn get_table_idx(&self) -> u32 {
+        self.source.get_table_idx()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::note::SoundSourceNoteInit;
+    use crate::oscillator::CoreOscillator;
+    use crate::oscillator::OscillatorType;
+    use crate::steady_one::SteadyOne;
+    use crate::waveshaper::*;
+
+    type Sine = CoreOscillator<24000, 24000, 50, 100, { OscillatorType::Sine as usize }>;
+
+    fn shaped_level<const CURVE: usize, const DRIVE: u32, const OUTPUT: u8>(level: i32) -> i32 {
+        let mut shaper =
+            Waveshaper::<24000, 24000, SteadyOne<24000, 24000>, CURVE, DRIVE, OUTPUT>::new(
+                SoundSourceNoteInit::new(60, 0, 127),
+            );
+        shaper.set_amplitude_adjust(SoundSampleI32::new_i32(level));
+        shaper.get_next().to_i32()
+    }
+
+    #[test]
+    fn hard_clip_should_flatten_the_peaks() {
+        const HARD: usize = ShapeCurve::HardClip as usize;
+        assert_eq!(0x2000, shaped_level::<HARD, 100, 100>(0x2000));
+        assert_eq!(0x4000, shaped_level::<HARD, 200, 100>(0x2000));
+        assert_eq!(0x8000, shaped_level::<HARD, 1000, 100>(0x2000));
+        assert_eq!(-0x8000, shaped_level::<HARD, 1000, 100>(-0x2000));
+        assert_eq!(0x4000, shaped_level::<HARD, 1000, 50>(0x2000));
+    }
+
+    #[test]
+    fn soft_clip_should_be_smooth_and_bounded() {
+        const SOFT: usize = ShapeCurve::SoftClip as usize;
+        // Small signals come through with about the same shape...
+        let quiet = shaped_level::<SOFT, 100, 100>(0x100);
+        assert!((0x170..=0x190).contains(&quiet), "{}", quiet);
+        // ... full scale stays at full scale ...
+        assert_eq!(0x8000, shaped_level::<SOFT, 100, 100>(0x8000));
+        assert_eq!(0x8000, shaped_level::<SOFT, 2000, 100>(0x8000));
+        // ... and the curve never goes backwards.
+        let mut last = -0x8000;
+        for level in (-0x8000..=0x8000).step_by(0x100) {
+            let current = shaped_level::<SOFT, 300, 100>(level);
+            assert!(current >= last);
+            last = current;
+        }
+    }
+
+    #[test]
+    fn tube_should_clip_asymmetrically_without_dc() {
+        const TUBE: usize = ShapeCurve::Tube as usize;
+        assert_eq!(0x8000, shaped_level::<TUBE, 400, 100>(0x4000));
+        assert_eq!(-0x4000, shaped_level::<TUBE, 400, 100>(-0x4000));
+
+        let mut shaper = Waveshaper::<24000, 24000, Sine, TUBE, 400, 100>::new(
+            100 * crate::midi_notes::FREQUENCY_MULTIPLIER,
+        );
+        // Let the DC blocker settle.
+        for _ in 0..24000 {
+            shaper.get_next();
+        }
+        let mut sum: i64 = 0;
+        for _ in 0..2400 {
+            sum += shaper.get_next().to_i32() as i64;
+        }
+        assert!((sum / 2400).abs() < 0x200, "{}", sum / 2400);
+    }
+}
@@ -0,0 +1,35 @@
+use crate::modal_percussion::ModalPatch;
+use crate::modal_percussion::ModalPercussion;
+use crate::modal_percussion::Mode;
+use crate::modal_percussion::MAX_MODES;
+
+//
+// Xylophone bars are tuned 1:3 for a brighter, harder sound than the
+// marimba.  Short.
+//
+pub struct XylophoneModes {}
+
+impl ModalPatch for XylophoneModes {
+    const MODES: [Mode; MAX_MODES] = [
+        Mode {
+            ratio: 1000,
+            level: 60,
+            decay: 300,
+        },
+        Mode {
+            ratio: 3000,
+            level: 30,
+            decay: 80,
+        },
+        Mode {
+            ratio: 6000,
+            level: 10,
+            decay: 30,
+        },
+        Mode::UNUSED,
+    ];
+    const RELEASE: u32 = 150;
+}
+
+pub type Xylophone<const P_FREQ: u32, const U_FREQ: u32> =
+    ModalPercussion<P_FREQ, U_FREQ, XylophoneModes>;
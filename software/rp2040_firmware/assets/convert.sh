@@ -1,2 +1,10 @@
+# 8 bit mono PCM at 24khz, for midi-nostd's sample player.
 ffmpeg -y -i ode.oog -ar 24000 -f u8 -ac 1 ode.bin
 
+# Or decode to 16 bit mono at 24khz, then compress to 4 bit IMA ADPCM for
+# midi-nostd's AdpcmPlayer.  That's half the size of ode.bin.  The output
+# is ignored by git until something plays it; include it as an AdpcmClip
+# at 24000hz.  Add -t SECONDS before ode.raw for a shorter clip.
+ffmpeg -y -i ode.oog -ar 24000 -ac 1 -f s16le ode.raw
+cargo run --release --manifest-path ../../adpcm-encode/Cargo.toml -- --s16le ode.raw ode.adpcm
+rm ode.raw
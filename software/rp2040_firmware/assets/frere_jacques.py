@@ -0,0 +1,111 @@
+#!/usr/bin/env python3
+#
+# Writes frere_jacques.mod, a four voice round of Frere Jacques for the
+# music menu's MOD player:
+#
+#   python3 frere_jacques.py frere_jacques.mod
+#
+# Each voice comes in two bars after the one before and sings the tune
+# twice.  The two instruments are plucks synthesized here, so there's
+# nothing in the file that isn't ours.  They're recorded so that C-2 plays
+# middle C.
+#
+
+import math
+import struct
+import sys
+
+# ProTracker periods for C-1 to B-3, finetune 0
+PERIODS = [
+    856, 808, 762, 720, 678, 640, 604, 570, 538, 508, 480, 453,
+    428, 404, 381, 360, 339, 320, 302, 285, 269, 254, 240, 226,
+    214, 202, 190, 180, 170, 160, 151, 143, 135, 127, 120, 113,
+]
+# Samples a second at C-2: the Amiga's clock over its period
+C2_RATE = 3546895 / 428
+MIDDLE_C = 261.63
+SAMPLE_LENGTH = 6000
+
+ROWS_PER_PATTERN = 64
+# Four rows a beat, two bars between voices
+ROWS_PER_BEAT = 4
+ENTRY_ROWS = 8 * ROWS_PER_BEAT
+
+# The tune, as semitones from C and length in beats
+PHRASES = [
+    [(0, 1), (2, 1), (4, 1), (0, 1)],
+    [(4, 1), (5, 1), (7, 2)],
+    [(7, 0.5), (9, 0.5), (7, 0.5), (5, 0.5), (4, 1), (0, 1)],
+    [(0, 1), (-5, 1), (0, 2)],
+]
+TUNE = [note for phrase in PHRASES for note in phrase * 2]
+
+# Each voice's instrument and octave, in semitones from C-1.  The low G
+# needs the lowest voices to start at C-2.
+VOICES = [(1, 24), (2, 12), (1, 12), (2, 24)]
+
+
+def pluck(partials, decay):
+    samples = []
+    for idx in range(SAMPLE_LENGTH):
+        t = idx / C2_RATE
+        value = sum(
+            level * math.exp(-t * harmonic / decay) * math.sin(2 * math.pi * MIDDLE_C * harmonic * t)
+            for harmonic, level in partials
+        )
+        samples.append(round(100 * value) & 0xFF)
+    return bytes(samples)
+
+
+INSTRUMENTS = [
+    # Bright, like a music box
+    (b"Pluck", pluck([(1, 0.6), (2, 0.25), (3, 0.15)], 0.4), 40),
+    # Soft, nearly a sine
+    (b"Flute", pluck([(1, 0.9), (3, 0.1)], 0.6), 48),
+]
+
+
+def rows():
+    song_rows = (len(VOICES) - 1) * ENTRY_ROWS + 2 * sum(beats for _, beats in TUNE) * ROWS_PER_BEAT
+    num_rows = -(-int(song_rows) // ROWS_PER_PATTERN) * ROWS_PER_PATTERN
+    cells = [[None] * len(VOICES) for _ in range(num_rows)]
+    for channel, (instrument, octave) in enumerate(VOICES):
+        row = channel * ENTRY_ROWS
+        for _ in range(2):
+            for semitones, beats in TUNE:
+                cells[row][channel] = (instrument, PERIODS[octave + semitones])
+                row += int(beats * ROWS_PER_BEAT)
+    return cells
+
+
+def cell_bytes(cell):
+    if cell is None:
+        return bytes(4)
+    instrument, period = cell
+    return bytes([(instrument & 0xF0) | (period >> 8), period & 0xFF, (instrument << 4) & 0xF0, 0])
+
+
+def module():
+    data = b"Frere Jacques".ljust(20, b"\0")
+    for idx in range(31):
+        if idx < len(INSTRUMENTS):
+            name, sample, volume = INSTRUMENTS[idx]
+            # No loop: start 0, one word
+            data += name.ljust(22, b"\0") + struct.pack(">HBBHH", len(sample) // 2, 0, volume, 0, 1)
+        else:
+            data += bytes(22) + struct.pack(">HBBHH", 0, 0, 0, 0, 1)
+
+    cells = rows()
+    num_patterns = len(cells) // ROWS_PER_PATTERN
+    orders = bytes(range(num_patterns)).ljust(128, b"\0")
+    data += bytes([num_patterns, 127]) + orders + b"M.K."
+    for row in cells:
+        data += b"".join(cell_bytes(cell) for cell in row)
+    for _, sample, _ in INSTRUMENTS:
+        data += sample
+    return data
+
+
+if __name__ == "__main__":
+    with open(sys.argv[1], "wb") as out:
+        out.write(module())
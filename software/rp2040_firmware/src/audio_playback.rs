@@ -1,10 +1,122 @@
+use crate::jam::JAM_QUEUE;
+use crate::rhythm;
+use crate::sequencer::NOT_PLAYING;
+use crate::sequencer::PLAYING_STEP;
+use crate::sequencer::SEQUENCER_QUEUE;
+use crate::sound_effects::EFFECTS;
+use crate::sound_effects::SFX_QUEUE;
+use crate::tunes;
+use crate::tunes::Tune;
+use crate::tunes::TUNE_QUEUE;
+use core::sync::atomic::Ordering;
+use midi_nostd::arpeggiator::ArpPlayer;
 use midi_nostd::midi::Midi;
-type NewYearsMidi<'a> = Midi<'a, 20292, { 89 * 3 }, 64, 32>;
+use midi_nostd::protracker::ModPlayer;
+use midi_nostd::rtttl::RtttlPlayer;
+use midi_nostd::sfx_mixer::SfxMixer;
+use midi_nostd::sound_sample::SoundSampleI32;
+use midi_nostd::step_sequencer::SequencerPlayer;
+pub type NewYearsMidi<'a> = Midi<'a, 20292, { 89 * 3 }, 64, 32>;
+pub type NewYearsRtttl<'a> = RtttlPlayer<'a, 20292, { 89 * 3 }, 32>;
+pub type NewYearsMod<'a> = ModPlayer<'a, 20292>;
+pub type NewYearsSequencer = SequencerPlayer<20292, { 89 * 3 }, 32>;
+pub type NewYearsJam = ArpPlayer<20292, { 89 * 3 }, 32>;
+type NewYearsSfx = SfxMixer<20292, { 89 * 3 }, 4>;
+
+// What's playing.  Each player has its own type, so this passes the calls
+// AudioPlayback needs on to whichever one it is.
+pub enum Song<'d> {
+    Midi(&'d mut NewYearsMidi<'d>),
+    Rtttl(&'d mut NewYearsRtttl<'d>),
+    Mod(&'d mut NewYearsMod<'d>),
+    Sequencer(&'d mut NewYearsSequencer),
+    Jam(&'d mut NewYearsJam),
+}
+
+impl<'d> Song<'d> {
+    fn get_next(&mut self) -> SoundSampleI32 {
+        match self {
+            Song::Midi(player) => player.get_next(),
+            Song::Rtttl(player) => player.get_next(),
+            Song::Mod(player) => player.get_next(),
+            Song::Sequencer(player) => player.get_next(),
+            Song::Jam(player) => player.get_next(),
+        }
+    }
+
+    fn has_next(&self) -> bool {
+        match self {
+            Song::Midi(player) => player.has_next(),
+            Song::Rtttl(player) => player.has_next(),
+            Song::Mod(player) => player.has_next(),
+            Song::Sequencer(player) => player.has_next(),
+            Song::Jam(player) => player.has_next(),
+        }
+    }
+
+    fn set_volume(&mut self, volume: u8) {
+        match self {
+            Song::Midi(player) => player.set_volume(volume),
+            Song::Rtttl(player) => player.set_volume(volume),
+            Song::Mod(player) => player.set_volume(volume),
+            Song::Sequencer(player) => player.set_volume(volume),
+            Song::Jam(player) => player.set_volume(volume),
+        }
+    }
+
+    fn fade_out(&mut self, time_in_ms: i32) {
+        match self {
+            Song::Midi(player) => player.fade_out(time_in_ms),
+            Song::Rtttl(player) => player.fade_out(time_in_ms),
+            Song::Mod(player) => player.fade_out(time_in_ms),
+            Song::Sequencer(player) => player.fade_out(time_in_ms),
+            Song::Jam(player) => player.fade_out(time_in_ms),
+        }
+    }
+
+    fn is_faded_out(&self) -> bool {
+        match self {
+            Song::Midi(player) => player.is_faded_out(),
+            Song::Rtttl(player) => player.is_faded_out(),
+            Song::Mod(player) => player.is_faded_out(),
+            Song::Sequencer(player) => player.is_faded_out(),
+            Song::Jam(player) => player.is_faded_out(),
+        }
+    }
+
+    // Where a MIDI song is, for the rhythm game
+    fn current_tick(&self) -> Option<u32> {
+        match self {
+            Song::Midi(player) => Some(player.current_tick()),
+            _ => None,
+        }
+    }
+
+    // Pick up what the sequencer or jam screen's sent, and tell the
+    // sequencer screen where playback is.
+    fn run_commands(&mut self) {
+        match self {
+            Song::Sequencer(player) => {
+                player.run_commands(&SEQUENCER_QUEUE);
+                let step = player.current_step().map_or(NOT_PLAYING, |step| step as u8);
+                PLAYING_STEP.store(step, Ordering::Relaxed);
+            }
+            Song::Jam(player) => player.run_commands(&JAM_QUEUE),
+            _ => {}
+        }
+    }
+}
 
 #[allow(long_running_const_eval)]
 pub struct AudioPlayback<'d> {
-    midi: &'d mut NewYearsMidi<'d>,
+    song: Song<'d>,
+    sfx: NewYearsSfx,
     clear_count: u32,
+    stopping: bool,
+    next_tune: Option<Tune>,
+    volume: u8,
+    // The ticks the last buffer filled starts and ends at, and its length
+    buffer_ticks: Option<(u32, u32, usize)>,
 }
 
 /*
@@ -51,9 +163,28 @@ const fn build_gamma_array_2<const N: usize>() -> [ i32; N] {
 impl<'d>
     AudioPlayback<'d>
 {
-    pub fn new(midi: &'d mut NewYearsMidi<'d>) -> Self {
+    pub fn new(song: Song<'d>) -> Self {
         let clear_count: u32 = 0;
-        Self { midi, clear_count }
+        Self { song, sfx: NewYearsSfx::new(&EFFECTS), clear_count, stopping: false, next_tune: None, volume: 100, buffer_ticks: None }
+    }
+
+    /// Badge volume setting, from 0 to 100
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume;
+        self.song.set_volume(volume);
+    }
+
+    /// Fade the song out rather than cutting it off.  is_done goes true
+    /// once the fade is over.
+    pub fn stop(&mut self, fade_time_in_ms: i32) {
+        self.song.fade_out(fade_time_in_ms);
+        self.stopping = true;
+    }
+
+    /// Tune the menu asked for while this one played, to play once it's
+    /// done
+    pub fn next_tune(&self) -> Option<Tune> {
+        self.next_tune
     }
 
 #[allow(long_running_const_eval)]
@@ -117,10 +248,42 @@ impl<'d>
             }
         }
     }
+    /// The last buffer filled has just been handed to the DMA, so tell the
+    /// rhythm game the ticks it plays.  That's a buffer behind where the
+    /// song's got to.
+    pub fn buffer_started(&self) {
+        if let Some((start_tick, end_tick, entries)) = self.buffer_ticks {
+            // Two samples to an entry
+            let length_us = (entries as u64 * 2 * 1_000_000 / 20292) as u32;
+            rhythm::buffer_started(start_tick, end_tick, length_us);
+        }
+    }
+
     pub fn populate_next_dma_buffer_with_audio(&mut self, buffer: &mut [u32]) {
-        for entry in buffer.iter_mut() {
-            let v0: i32 = self.midi.get_next().to_i32()/4;
-            let v1: i32 = self.midi.get_next().to_i32()/4;
+        let start_tick = self.song.current_tick();
+        for (idx, entry) in buffer.iter_mut().enumerate() {
+            // Pick up effects the menu asked for.  Checking every so often
+            // through the buffer, rather than once at the start, keeps the
+            // clicks in time with the buttons.
+            if idx % 256 == 0 {
+                self.sfx.run_commands(&SFX_QUEUE);
+                // Once it's stopping, commands are for the next song.
+                if !self.stopping {
+                    self.song.run_commands();
+                    // The volume's ramped, so it can change while playing,
+                    // but setting it would undo a fade out.
+                    let volume = tunes::volume();
+                    if volume != self.volume {
+                        self.set_volume(volume);
+                    }
+                }
+                if let Some(tune) = TUNE_QUEUE.pop() {
+                    self.next_tune = Some(tune);
+                    self.stop(300);
+                }
+            }
+            let v0: i32 = self.sfx.mix(self.song.get_next()).to_i32()/4;
+            let v1: i32 = self.sfx.mix(self.song.get_next()).to_i32()/4;
 
             // < 0x0000 - 0x0800     x 4            0x0000 - 0x2000
             //   0x0800 - 0x1000     x 3 + 0x0800   0x2000 - 0x3800
@@ -149,7 +312,9 @@ impl<'d>
                 ((v1_u32 >> 0 ) & 0xff) << 24;
             *entry = output;
 
-            if !self.midi.has_next() {
+            let song_over = !self.song.has_next() || (self.stopping && self.song.is_faded_out());
+            // Don't wait on the sound effects to move on to the next tune.
+            if song_over && (self.next_tune.is_some() || !self.sfx.is_playing()) {
                 self.clear_count = 1;
             }
             /*
@@ -164,6 +329,9 @@ impl<'d>
             //let value_u32: u32 = 0;
 
         }
+        self.buffer_ticks = start_tick
+            .zip(self.song.current_tick())
+            .map(|(start_tick, end_tick)| (start_tick, end_tick, buffer.len()));
     }
 
     pub fn is_done(&self) -> bool {
@@ -63,17 +63,22 @@ impl<'a> Buttons<'a> {
         ];
     }
 
+    /// A button that's gone down since last_state, which is then brought
+    /// up to date.  For screens that have more to do than wait.
+    pub fn new_press(&self, last_state: &mut [bool; 4]) -> Option<Button> {
+        let new_state = self.all_buttons();
+        let pressed = (0..=3).find(|&idx| !last_state[idx] && new_state[idx]);
+        *last_state = new_state;
+        pressed.map(|idx| self.index_to_button(idx))
+    }
+
     pub async fn wait_for_press(&self) -> Button {
         let mut ticker = embassy_time::Ticker::every(embassy_time::Duration::from_millis(100));
         let mut last_state = self.all_buttons();
         loop {
-            let new_state = self.all_buttons();
-            for idx in 0..=3 {
-                if !last_state[idx] && new_state[idx] {
-                    return self.index_to_button(idx);
-                }
+            if let Some(button) = self.new_press(&mut last_state) {
+                return button;
             }
-            last_state = new_state;
             ticker.next().await;
         }
     }
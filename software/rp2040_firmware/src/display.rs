@@ -74,6 +74,42 @@ pub fn draw_text<'a>(display: &mut DisplaySSD<'a>, text: &str, line: i32, bold:
         Text::with_text_style(text, Point::new(0, line), character_style, text_style).draw(display);
 }
 
+/// A line of text for the display, which fits 14 characters.  Write to it
+/// with write!.
+pub struct TextLine {
+    buffer: [u8; 16],
+    len: usize,
+}
+
+impl TextLine {
+    pub fn new() -> Self {
+        Self {
+            buffer: [0; 16],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buffer[..self.len]).unwrap_or("")
+    }
+}
+
+impl Default for TextLine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Write for TextLine {
+    fn write_str(&mut self, text: &str) -> core::fmt::Result {
+        // Anything past the edge of the display is dropped.
+        let len = core::cmp::min(text.len(), self.buffer.len() - self.len);
+        self.buffer[self.len..self.len + len].copy_from_slice(&text.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
 // Some sample code for drawing on the display
 // TODO - this could just be a link to the github example I used.
 
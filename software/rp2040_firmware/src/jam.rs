@@ -0,0 +1,417 @@
+//
+// Jam
+// ===
+//
+// Play along on the badge.  Up, down and action are three keys, C, F and G,
+// which chord memory can turn into chords and the arpeggiator plays out in
+// time, so holding one button is already a tune.  Back opens the options.
+// The arpeggiator and chord memory are in midi-nostd's arpeggiator, where
+// they're tested; this is the buttons, the screen and the options menu.
+//
+// Core 1 plays Tune::Jam with an ArpPlayer.  Keys going up and down, and
+// the settings, go over JAM_QUEUE.  A key change that doesn't fit is tried
+// again on the next tick, so notes can't get stuck.  Only this screen
+// pushes to the queue.  With latch on the arpeggio keeps playing after the
+// screen's left, until another tune is picked.
+//
+// Record in the options keeps a take: the notes the keys play, chords and
+// all, with midi-nostd's MidiRecorder.  Turning it off writes the take as a
+// MIDI file and sends it over the debug probe with defmt, since there's
+// nowhere on the badge to keep it.  The arpeggio isn't in the file; play it
+// back through an arpeggiator to hear it again.
+//
+
+use crate::display;
+use crate::display::DisplaySSD;
+use crate::display::TextLine;
+use crate::menu::run_menu;
+use crate::menu::MenuBinding;
+use crate::tunes;
+use crate::tunes::Tune;
+use crate::DevicesCore0Menu;
+use core::fmt::Write;
+use embassy_time::Duration;
+use embassy_time::Instant;
+use embassy_time::Ticker;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::PrimitiveStyle;
+use embedded_graphics::primitives::Rectangle;
+use midi_nostd::arpeggiator::ArpCommand;
+use midi_nostd::arpeggiator::ArpMode;
+use midi_nostd::arpeggiator::ArpRate;
+use midi_nostd::arpeggiator::ArpSettings;
+use midi_nostd::arpeggiator::Chord;
+use midi_nostd::arpeggiator::LiveSettings;
+use midi_nostd::arpeggiator::MAX_OCTAVES;
+use midi_nostd::command_queue::CommandQueue;
+use midi_nostd::midi_recorder::MidiRecorder;
+use midi_nostd::midi_recorder::RecordedEvent;
+use midi_nostd::smf_writer::SmfFormat;
+use midi_nostd::step_sequencer::INSTRUMENTS;
+use midly::num::u7;
+use midly::MidiMessage;
+use static_cell::StaticCell;
+
+pub static JAM_QUEUE: CommandQueue<ArpCommand, 8> = CommandQueue::new();
+
+// A take, and the MIDI file it's written to.  An event's about 12 bytes
+// and takes at most 9 in the file.
+const TAKE_EVENTS: usize = 512;
+static TAKE: StaticCell<[RecordedEvent; TAKE_EVENTS]> = StaticCell::new();
+static TAKE_FILE: StaticCell<[u8; TAKE_EVENTS * 9 + 64]> = StaticCell::new();
+
+// The keys up, down and action play, and their names
+const KEYS: [(u8, &str); 3] = [(60, "C"), (65, "F"), (67, "G")];
+const VELOCITY: u8 = 100;
+
+const MODES: [(Option<ArpMode>, &str); 5] = [
+    (None, "Off"),
+    (Some(ArpMode::Up), "Up"),
+    (Some(ArpMode::Down), "Down"),
+    (Some(ArpMode::UpDown), "Up Down"),
+    (Some(ArpMode::Random), "Random"),
+];
+const RATES: [(ArpRate, &str); 6] = [
+    (ArpRate::Quarter, "1/4"),
+    (ArpRate::Eighth, "1/8"),
+    (ArpRate::EighthTriplet, "1/8T"),
+    (ArpRate::Sixteenth, "1/16"),
+    (ArpRate::SixteenthTriplet, "1/16T"),
+    (ArpRate::ThirtySecond, "1/32"),
+];
+const CHORDS: [(Option<Chord>, &str); 5] = [
+    (None, "Single"),
+    (Some(Chord::MAJOR), "Major"),
+    (Some(Chord::MINOR), "Minor"),
+    (Some(Chord::SEVENTH), "7th"),
+    (Some(Chord::POWER), "Power"),
+];
+const GATES: [u8; 4] = [25, 50, 75, 100];
+const MIN_BPM: u16 = 60;
+const MAX_BPM: u16 = 200;
+const BPM_STEP: u16 = 20;
+
+// Baselines for two lines of text
+const TOP_LINE: i32 = 13;
+const BOTTOM_LINE: i32 = 27;
+// Width of a character, for lining up the key names
+const CHAR_WIDTH: i32 = 9;
+
+#[derive(Clone)]
+enum OptionsResult {
+    UpMenu,
+    Mode,
+    Rate,
+    Octaves,
+    Gate,
+    Latch,
+    Chord,
+    Tempo,
+    Sound,
+    Record,
+    Exit,
+}
+
+// Next index of a list that's len long, going round to the start
+fn next(idx: usize, len: usize) -> usize {
+    (idx + 1) % len
+}
+
+pub struct Jam {
+    mode: usize,
+    rate: usize,
+    octaves: u8,
+    gate: usize,
+    latch: bool,
+    chord: usize,
+    bpm: u16,
+    instrument: usize,
+    // Keys core 1 has been told are down
+    keys_down: [bool; 3],
+    // Settings core 1 hasn't been sent yet
+    settings_pending: bool,
+    recorder: MidiRecorder<'static>,
+    take_file: &'static mut [u8],
+}
+
+impl Jam {
+    /// There's only room for one.
+    pub fn new() -> Self {
+        Self {
+            mode: 1,
+            rate: 3,
+            octaves: 2,
+            gate: 1,
+            latch: false,
+            chord: 1,
+            bpm: 120,
+            // Square Lead
+            instrument: 28,
+            keys_down: [false; 3],
+            settings_pending: false,
+            recorder: MidiRecorder::new(TAKE.init([RecordedEvent::EMPTY; TAKE_EVENTS])),
+            take_file: TAKE_FILE.init([0; TAKE_EVENTS * 9 + 64]),
+        }
+    }
+
+    fn live_settings(&self) -> LiveSettings {
+        LiveSettings {
+            arp: MODES[self.mode].0.map(|mode| ArpSettings {
+                mode,
+                octaves: self.octaves,
+                rate: RATES[self.rate].0,
+                gate_percent: GATES[self.gate],
+                latch: self.latch,
+            }),
+            chord: CHORDS[self.chord].0,
+            bpm: self.bpm,
+            program: INSTRUMENTS[self.instrument].program,
+        }
+    }
+
+    // Send the settings if they're pending, then bring the keys core 1
+    // knows about up to date with keys.
+    fn sync(&mut self, keys: [bool; 3]) {
+        if self.settings_pending {
+            let command = ArpCommand::Settings(self.live_settings());
+            self.settings_pending = JAM_QUEUE.push(command).is_err();
+            if self.settings_pending {
+                return;
+            }
+            let program = INSTRUMENTS[self.instrument].program;
+            self.record(MidiMessage::ProgramChange {
+                program: u7::new(program),
+            });
+        }
+        for (idx, (key, _)) in KEYS.iter().enumerate() {
+            if keys[idx] == self.keys_down[idx] {
+                continue;
+            }
+            let command = if keys[idx] {
+                ArpCommand::KeyDown {
+                    key: *key,
+                    velocity: VELOCITY,
+                }
+            } else {
+                ArpCommand::KeyUp { key: *key }
+            };
+            if JAM_QUEUE.push(command).is_ok() {
+                self.keys_down[idx] = keys[idx];
+                self.record_key(*key, keys[idx]);
+            }
+        }
+    }
+
+    // Keep message in the take, if there's one going
+    fn record(&mut self, message: MidiMessage) {
+        let now_ms = Instant::now().as_millis() as u32;
+        // A full take keeps what it's got.
+        let _ = self.recorder.record(now_ms, 0, &message);
+    }
+
+    // Keep a key going down or up, with the notes chord memory adds.  The
+    // chord can't change while a key's down, since the options menu lets
+    // go of everything first.
+    fn record_key(&mut self, key: u8, down: bool) {
+        let chord = CHORDS[self.chord].0.unwrap_or(Chord::new(&[0]));
+        for note in chord.keys(key) {
+            let note = u7::new(note);
+            self.record(if down {
+                MidiMessage::NoteOn {
+                    key: note,
+                    vel: u7::new(VELOCITY),
+                }
+            } else {
+                MidiMessage::NoteOff {
+                    key: note,
+                    vel: u7::new(0),
+                }
+            });
+        }
+    }
+
+    // The sound goes in when the options menu sends the settings.
+    fn start_take(&mut self) {
+        self.recorder.start(Instant::now().as_millis() as u32);
+    }
+
+    // Stop the take, and send it over the debug probe as a MIDI file at
+    // the jam's tempo
+    fn stop_take(&mut self) {
+        if !self.recorder.is_recording() {
+            return;
+        }
+        self.recorder.stop(Instant::now().as_millis() as u32);
+        let us_per_quarter_note = 60_000_000 / self.bpm as u32;
+        match self
+            .recorder
+            .write_smf(self.take_file, SmfFormat::SingleTrack, us_per_quarter_note)
+        {
+            Ok(file) => defmt::info!("Jam take, {} bytes: {=[u8]:x}", file.len(), file),
+            Err(_) => defmt::warn!("Jam take didn't fit in the file buffer"),
+        }
+    }
+
+    /// Run the jam screen until the user exits
+    pub async fn run(&mut self, devices: &mut DevicesCore0Menu<'_>) {
+        // A new player starts with the defaults and nothing down.
+        tunes::play(Tune::Jam);
+        self.settings_pending = true;
+        self.keys_down = [false; 3];
+
+        // Quick enough that short taps still play
+        let mut ticker = Ticker::every(Duration::from_millis(10));
+        let mut back_down = true;
+        loop {
+            let buttons = devices.buttons.all_buttons();
+            let keys = [buttons[1], buttons[2], buttons[3]];
+            self.sync(keys);
+            self.draw(&mut devices.display);
+
+            if buttons[0] && !back_down {
+                // Let go of everything while the menu's up
+                self.sync([false; 3]);
+                if !self.run_options(devices).await {
+                    break;
+                }
+            }
+            back_down = buttons[0];
+            ticker.next().await;
+        }
+
+        self.stop_take();
+        // Catch up before leaving, as long as core 1 isn't stuck.
+        for _ in 0..50 {
+            self.sync([false; 3]);
+            if !self.settings_pending && self.keys_down == [false; 3] {
+                break;
+            }
+            ticker.next().await;
+        }
+    }
+
+    // The options menu, which stays up while settings are changed.
+    // Returns false to leave the jam screen.
+    async fn run_options(&mut self, devices: &mut DevicesCore0Menu<'_>) -> bool {
+        let mut pos = 1;
+        loop {
+            let mut mode = TextLine::new();
+            let mut rate = TextLine::new();
+            let mut octaves = TextLine::new();
+            let mut gate = TextLine::new();
+            let mut chord = TextLine::new();
+            let mut tempo = TextLine::new();
+            let _ = write!(mode, "Arp: {}", MODES[self.mode].1);
+            let _ = write!(rate, "Rate: {}", RATES[self.rate].1);
+            let _ = write!(octaves, "Octaves: {}", self.octaves);
+            let _ = write!(gate, "Gate: {}%", GATES[self.gate]);
+            let _ = write!(chord, "Chord: {}", CHORDS[self.chord].1);
+            let _ = write!(tempo, "Tempo: {}", self.bpm);
+
+            let (result, return_pos) = run_menu::<OptionsResult>(
+                &[
+                    MenuBinding::new("Jam", None),
+                    MenuBinding::new(mode.as_str(), Some(OptionsResult::Mode)),
+                    MenuBinding::new(rate.as_str(), Some(OptionsResult::Rate)),
+                    MenuBinding::new(octaves.as_str(), Some(OptionsResult::Octaves)),
+                    MenuBinding::new(gate.as_str(), Some(OptionsResult::Gate)),
+                    MenuBinding::new(
+                        if self.latch {
+                            "Latch: On"
+                        } else {
+                            "Latch: Off"
+                        },
+                        Some(OptionsResult::Latch),
+                    ),
+                    MenuBinding::new(chord.as_str(), Some(OptionsResult::Chord)),
+                    MenuBinding::new(tempo.as_str(), Some(OptionsResult::Tempo)),
+                    MenuBinding::new(
+                        INSTRUMENTS[self.instrument].name,
+                        Some(OptionsResult::Sound),
+                    ),
+                    MenuBinding::new(
+                        if self.recorder.is_recording() {
+                            "Record: On"
+                        } else {
+                            "Record: Off"
+                        },
+                        Some(OptionsResult::Record),
+                    ),
+                    MenuBinding::new("Exit", Some(OptionsResult::Exit)),
+                ],
+                OptionsResult::UpMenu,
+                Some(pos),
+                devices,
+            )
+            .await;
+            pos = return_pos;
+
+            match result {
+                OptionsResult::UpMenu => return true,
+                OptionsResult::Mode => self.mode = next(self.mode, MODES.len()),
+                OptionsResult::Rate => self.rate = next(self.rate, RATES.len()),
+                OptionsResult::Octaves => self.octaves = self.octaves % MAX_OCTAVES + 1,
+                OptionsResult::Gate => self.gate = next(self.gate, GATES.len()),
+                OptionsResult::Latch => self.latch = !self.latch,
+                OptionsResult::Chord => self.chord = next(self.chord, CHORDS.len()),
+                OptionsResult::Tempo => {
+                    self.bpm = if self.bpm + BPM_STEP > MAX_BPM {
+                        MIN_BPM
+                    } else {
+                        self.bpm + BPM_STEP
+                    }
+                }
+                OptionsResult::Sound => self.instrument = next(self.instrument, INSTRUMENTS.len()),
+                OptionsResult::Record => {
+                    if self.recorder.is_recording() {
+                        self.stop_take();
+                    } else {
+                        self.start_take();
+                    }
+                }
+                OptionsResult::Exit => return false,
+            }
+            // Heard straight away if latch is holding notes
+            self.settings_pending = true;
+            self.sync([false; 3]);
+        }
+    }
+
+    fn draw(&self, display: &mut DisplaySSD<'_>) {
+        display.clear(BinaryColor::Off).unwrap();
+        let mut top = TextLine::new();
+        match MODES[self.mode].0 {
+            Some(_) => {
+                let _ = write!(top, "{} {}", MODES[self.mode].1, RATES[self.rate].1);
+            }
+            None => {
+                let _ = write!(top, "{}", INSTRUMENTS[self.instrument].name);
+            }
+        }
+        if self.recorder.is_recording() {
+            let _ = write!(top, " Rec");
+        }
+        display::draw_text(display, top.as_str(), TOP_LINE, false);
+
+        // The key names, three characters apart, with the ones that are
+        // down underlined.
+        let mut bottom = TextLine::new();
+        for (_, name) in KEYS.iter() {
+            let _ = write!(bottom, "{:3}", name);
+        }
+        display::draw_text(display, bottom.as_str(), BOTTOM_LINE, false);
+        let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+        for (idx, down) in self.keys_down.iter().enumerate() {
+            if *down {
+                let _ = Rectangle::new(
+                    Point::new(idx as i32 * 3 * CHAR_WIDTH, BOTTOM_LINE + 3),
+                    Size::new(CHAR_WIDTH as u32, 2),
+                )
+                .into_styled(fill)
+                .draw(display);
+            }
+        }
+        display.flush().unwrap();
+    }
+}
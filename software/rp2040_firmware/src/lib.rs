@@ -26,9 +26,19 @@ pub use devices::DevicesCore1;
 pub mod display;
 pub use display::DisplaySSD;
 
+pub mod jam;
+
 pub mod led_driver;
 
 pub mod menu;
 
+pub mod rhythm;
+
+pub mod sequencer;
+
+pub mod sound_effects;
+
+pub mod tunes;
+
 //mod sound;
 //pub use sound::Sound;
@@ -1,6 +1,7 @@
 #![no_std]
 #![no_main]
 
+use core::fmt::Write;
 use defmt::*;
 use embassy_executor::InterruptExecutor;
 use embassy_executor::{Executor, Spawner};
@@ -12,8 +13,16 @@ use hackernewyears::devices::split_resources_by_core;
 use hackernewyears::devices::Core0ResourcesBacklight;
 use hackernewyears::devices::Core0ResourcesMenu;
 use hackernewyears::devices::Core1Resources;
+use hackernewyears::display::TextLine;
+use hackernewyears::jam::Jam;
 use hackernewyears::led_driver::LedDriver;
 use hackernewyears::menu::MenuBinding;
+use hackernewyears::rhythm;
+use hackernewyears::sequencer::Sequencer;
+use hackernewyears::sound_effects;
+use hackernewyears::sound_effects::Sfx;
+use hackernewyears::tunes;
+use hackernewyears::tunes::Tune;
 use hackernewyears::AnimatingGif;
 use hackernewyears::AnimatingGifs;
 use static_cell::StaticCell;
@@ -67,6 +76,7 @@ async fn main(spawner: Spawner) {
 async fn core0_menu_task(core0_resources_menu: Core0ResourcesMenu) {
     let mut devices = hackernewyears::DevicesCore0Menu::new(core0_resources_menu);
     let animating_gifs = AnimatingGifs::new();
+    sound_effects::play(Sfx::Jingle);
 
     for _ in 0..5 {
         defmt::info!("Animation");
@@ -75,6 +85,8 @@ async fn core0_menu_task(core0_resources_menu: Core0ResourcesMenu) {
             .await;
     }
 
+    let mut sequencer = Sequencer::new();
+    let mut jam = Jam::new();
     let mut current_pos: Option<usize> = None;
     loop {
         #[derive(Clone)]
@@ -83,6 +95,9 @@ async fn core0_menu_task(core0_resources_menu: Core0ResourcesMenu) {
             Eyes,
             Abstract,
             Music,
+            Sequencer,
+            Jam,
+            Rhythm,
         }
 
         let (result, return_pos) = hackernewyears::menu::run_menu::<MainMenuResult>(
@@ -91,6 +106,9 @@ async fn core0_menu_task(core0_resources_menu: Core0ResourcesMenu) {
                 MenuBinding::new("Eyes Animated Gif", Some(MainMenuResult::Eyes)),
                 MenuBinding::new("Abstract", Some(MainMenuResult::Abstract)),
                 MenuBinding::new("Music", Some(MainMenuResult::Music)),
+                MenuBinding::new("Sequencer", Some(MainMenuResult::Sequencer)),
+                MenuBinding::new("Jam", Some(MainMenuResult::Jam)),
+                MenuBinding::new("Rhythm Game", Some(MainMenuResult::Rhythm)),
             ],
             MainMenuResult::UpMenu,
             current_pos,
@@ -112,10 +130,71 @@ async fn core0_menu_task(core0_resources_menu: Core0ResourcesMenu) {
                     .await
             }
             MainMenuResult::Music => {
-                animating_gifs
-                    .animate(AnimatingGif::Abstract, &mut devices)
-                    .await
+                #[derive(Clone)]
+                pub enum MusicMenuResult {
+                    UpMenu,
+                    Play(Tune),
+                    Volume,
+                }
+
+                // The menu stays up while the volume's changed.
+                let mut music_pos: Option<usize> = None;
+                loop {
+                    let mut volume = TextLine::new();
+                    // defmt has a write! too
+                    let _ = core::write!(volume, "Volume: {}%", tunes::volume());
+                    let (result, return_pos) = hackernewyears::menu::run_menu::<MusicMenuResult>(
+                        &[
+                            MenuBinding::new("Music", None),
+                            MenuBinding::new(
+                                "The Entertainer",
+                                Some(MusicMenuResult::Play(Tune::Entertainer)),
+                            ),
+                            MenuBinding::new(
+                                "Auld Lang Syne",
+                                Some(MusicMenuResult::Play(Tune::AuldLangSyne)),
+                            ),
+                            MenuBinding::new(
+                                "Ode to Joy",
+                                Some(MusicMenuResult::Play(Tune::OdeToJoy)),
+                            ),
+                            MenuBinding::new(
+                                "Twinkle Twinkle",
+                                Some(MusicMenuResult::Play(Tune::Twinkle)),
+                            ),
+                            MenuBinding::new(
+                                "Jingle Bells",
+                                Some(MusicMenuResult::Play(Tune::JingleBells)),
+                            ),
+                            MenuBinding::new(
+                                "Frere Jacques",
+                                Some(MusicMenuResult::Play(Tune::FrereJacques)),
+                            ),
+                            MenuBinding::new(volume.as_str(), Some(MusicMenuResult::Volume)),
+                        ],
+                        MusicMenuResult::UpMenu,
+                        music_pos,
+                        &mut devices,
+                    )
+                    .await;
+                    music_pos = Some(return_pos);
+
+                    match result {
+                        MusicMenuResult::UpMenu => break,
+                        MusicMenuResult::Volume => tunes::next_volume(),
+                        MusicMenuResult::Play(tune) => {
+                            tunes::play(tune);
+                            animating_gifs
+                                .animate(AnimatingGif::Abstract, &mut devices)
+                                .await;
+                            break;
+                        }
+                    }
+                }
             }
+            MainMenuResult::Sequencer => sequencer.run(&mut devices).await,
+            MainMenuResult::Jam => jam.run(&mut devices).await,
+            MainMenuResult::Rhythm => rhythm::run(&mut devices).await,
         }
     }
 }
@@ -149,8 +228,9 @@ async fn core1_task(core1_resources: Core1Resources) {
     //
     cortex_m::peripheral::NVIC::mask(embassy_rp::pac::Interrupt::DMA_IRQ_0);
 
+    let mut tune = Tune::Entertainer;
     loop {
-        devices.piosound.play_sound().await;
+        tune = devices.piosound.play_sound(tune).await;
     }
 }
 
@@ -1,4 +1,6 @@
 use crate::display;
+use crate::sound_effects;
+use crate::sound_effects::Sfx;
 use crate::Button;
 use crate::DevicesCore0Menu;
 use embassy_time::Instant;
@@ -111,10 +113,12 @@ pub async fn run_menu<T: Clone>(
         let button = devices.buttons.wait_for_press().await;
         if button == Button::B0 {
             // Exit out of the menu
+            sound_effects::play(Sfx::Back);
             return (up_menu, current_pos);
         }
         if button == Button::B3 && menu_items[current_pos].binding.is_some() {
             // Menu item selected.  Return the binding if it exists.
+            sound_effects::play(Sfx::Select);
             return (
                 menu_items[current_pos].binding.as_ref().unwrap().clone(),
                 current_pos,
@@ -123,11 +127,13 @@ pub async fn run_menu<T: Clone>(
         if button == Button::B2 && current_pos + 1 < max_items {
             // "Down arrow" button.
             current_pos = current_pos + 1;
+            sound_effects::play(Sfx::Click);
             transition_to_new_target_pos(menu_items, devices, current_pos, -1).await;
         }
         if button == Button::B1 && current_pos > 0 {
             // "Up arrow" button.
             current_pos = current_pos - 1;
+            sound_effects::play(Sfx::Click);
             transition_to_new_target_pos(menu_items, devices, current_pos, 1).await;
         }
     }
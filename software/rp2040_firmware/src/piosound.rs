@@ -57,6 +57,19 @@
 //
 
 use crate::audio_playback::AudioPlayback;
+use crate::audio_playback::NewYearsJam;
+use crate::audio_playback::NewYearsMidi;
+use crate::audio_playback::NewYearsMod;
+use crate::audio_playback::NewYearsRtttl;
+use crate::audio_playback::NewYearsSequencer;
+use crate::audio_playback::Song;
+use crate::rhythm;
+use crate::sequencer::NOT_PLAYING;
+use crate::sequencer::PLAYING_STEP;
+use crate::tunes;
+use crate::tunes::Tune;
+use crate::tunes::TuneSource;
+use core::sync::atomic::Ordering;
 use embassy_rp::dma;
 use embassy_rp::dma::Transfer;
 use embassy_rp::gpio;
@@ -67,10 +80,13 @@ use embassy_rp::Peri;
 use embassy_rp::interrupt;
 use fixed::traits::ToFixed;
 use gpio::{Level, Output, Pin};
-use midi_nostd::midi::Midi;
+use midi_nostd::arpeggiator::LiveSettings;
+use midi_nostd::protracker::ModFile;
+use midi_nostd::rtttl::Rtttl;
+use midi_nostd::step_sequencer::Pattern;
 
-// 89 and 3 are factors of 20292.  89*3 has to be a factor of 20292.
-type NewYearsMidi<'a> = Midi<'a, 20292, { 89 * 3 }, 64, 32>;
+// 89 and 3 are factors of 20292.  89*3 has to be a factor of 20292.  The
+// player types are in audio_playback.rs.
 
 // Right noiw the playback time for each buffer is 16384/20292/16 seconds, ~= .05s
 //
@@ -193,20 +209,58 @@ impl<'d> PioSound<'d> {
         }
     }
 
-    pub async fn play_sound(&mut self) {
-        //let (header, tracks) = midly::parse(include_bytes!("../assets/maple.mid"))
-        //let (header, tracks) = midly::parse(include_bytes!("../assets/vivaldi.mid"))
-        let (header, tracks) = midly::parse(include_bytes!("../assets/entertainer.mid"))
-            .expect("It's inlined data, so its expected to parse");
-        let mut midi = NewYearsMidi::new(&header, tracks);
+    /// Play tune until it ends, or until the menu asks for another one.
+    /// Returns the tune to play next: the one asked for, or tune again.
+    pub async fn play_sound(&mut self, tune: Tune) -> Tune {
+        let next_tune = match tune.source() {
+            TuneSource::Midi(data) => {
+                //let (header, tracks) = midly::parse(include_bytes!("../assets/maple.mid"))
+                //let (header, tracks) = midly::parse(include_bytes!("../assets/vivaldi.mid"))
+                let (header, tracks) = midly::parse(data)
+                    .expect("It's inlined data, so its expected to parse");
+                let mut midi = NewYearsMidi::new(&header, tracks);
+                rhythm::song_started();
+                self.play_song(Song::Midi(&mut midi)).await
+            }
+            TuneSource::Rtttl(text, program) => {
+                let rtttl = Rtttl::parse(text)
+                    .expect("It's inlined data, so its expected to parse");
+                let mut player = NewYearsRtttl::new(&rtttl, program);
+                self.play_song(Song::Rtttl(&mut player)).await
+            }
+            TuneSource::Mod(data) => {
+                let module = ModFile::parse(data)
+                    .expect("It's inlined data, so its expected to parse");
+                let mut player = NewYearsMod::new(&module);
+                self.play_song(Song::Mod(&mut player)).await
+            }
+            TuneSource::Sequencer => {
+                // The pattern comes from the sequencer screen.
+                let mut player = NewYearsSequencer::new(&Pattern::new());
+                let next_tune = self.play_song(Song::Sequencer(&mut player)).await;
+                PLAYING_STEP.store(NOT_PLAYING, Ordering::Relaxed);
+                next_tune
+            }
+            TuneSource::Jam => {
+                // The settings and keys come from the jam screen.
+                let mut player = NewYearsJam::new(&LiveSettings::DEFAULT);
+                self.play_song(Song::Jam(&mut player)).await
+            }
+        };
+        next_tune.unwrap_or(tune)
+    }
 
-        let mut playback_state = AudioPlayback::new(&mut midi);
+    async fn play_song(&mut self, song: Song<'_>) -> Option<Tune> {
+        let mut playback_state = AudioPlayback::new(song);
+        // A new player starts at full volume.
+        playback_state.set_volume(tunes::volume());
         let mut buffer_sending: u32 = 0;
 
         while !playback_state.is_done() {
             buffer_sending = 1 - buffer_sending;
             // Start DMA transfer
             let dma_buffer_in_flight = self.send_dma_buffer_to_pio(buffer_sending);
+            playback_state.buffer_started();
             // While the DMA transfer runs, populate the next DMA buffer
             let dma_write_buffer = Self::get_writable_dma_buffer(1 - buffer_sending);
             playback_state.populate_next_dma_buffer_with_audio(dma_write_buffer);
@@ -215,5 +269,6 @@ impl<'d> PioSound<'d> {
             dma_buffer_in_flight.await;
         }
         self.set_level(0x80);
+        playback_state.next_tune()
     }
 }
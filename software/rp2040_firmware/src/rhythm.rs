@@ -0,0 +1,287 @@
+//
+// Rhythm game
+// ===========
+//
+// Notes from one channel of a MIDI song scroll in from the right in three
+// lanes, high notes on top, and are pressed as they reach the line on the
+// left: up for the top lane, action for the middle and down for the
+// bottom.  Back quits.  The chart, the lanes and the judging are in
+// midi-nostd's rhythm_game, where they're tested; this is the drawing, the
+// buttons and keeping up with the song.
+//
+// The song plays on core 1 as an ordinary tune.  Core 1 fills a DMA buffer
+// while the one before it plays, so where it's got to is most of a second
+// ahead of what's heard.  Instead, as it hands each buffer to the DMA, it
+// publishes the ticks the buffer starts and ends at and when it started
+// playing, and song_tick works out how far through it the speaker is.  It
+// also counts the MIDI songs it's started in SONG_STARTS.  Core 0 reads the
+// same file from flash for the chart, so that tick is all it needs.  The
+// game ends when the chart runs out, or when SONG_STARTS moves on because
+// the song's started over.
+//
+
+use crate::display;
+use crate::display::DisplaySSD;
+use crate::display::TextLine;
+use crate::menu::run_menu;
+use crate::menu::MenuBinding;
+use crate::tunes;
+use crate::tunes::Tune;
+use crate::tunes::TuneSource;
+use crate::DevicesCore0Menu;
+use core::fmt::Write;
+use core::sync::atomic::fence;
+use core::sync::atomic::AtomicU32;
+use core::sync::atomic::Ordering;
+use embassy_time::Duration;
+use embassy_time::Instant;
+use embassy_time::Ticker;
+use embedded_graphics::mono_font::ascii::FONT_6X10;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::PrimitiveStyle;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::Text;
+use midi_nostd::rhythm_game::Chart;
+use midi_nostd::rhythm_game::Judgement;
+use midi_nostd::rhythm_game::Lane;
+use midi_nostd::rhythm_game::RhythmGame;
+
+/// Tick the buffer that's playing starts at
+static BUFFER_START_TICK: AtomicU32 = AtomicU32::new(0);
+/// Tick the buffer that's playing ends at
+static BUFFER_END_TICK: AtomicU32 = AtomicU32::new(0);
+/// When that buffer started playing, in microseconds.  It wraps.
+static BUFFER_STARTED_AT: AtomicU32 = AtomicU32::new(0);
+/// How long that buffer plays for, in microseconds
+static BUFFER_LENGTH: AtomicU32 = AtomicU32::new(0);
+/// Odd while core 1 is changing the four above
+static BUFFER_SEQUENCE: AtomicU32 = AtomicU32::new(0);
+/// MIDI songs core 1 has started.  Only core 1 changes it.
+pub static SONG_STARTS: AtomicU32 = AtomicU32::new(0);
+
+/// Core 1 has handed the DMA a buffer of the MIDI song that plays from
+/// start_tick to end_tick over length_us
+pub fn buffer_started(start_tick: u32, end_tick: u32, length_us: u32) {
+    // There's no fetch_add on the RP2040, but there's only one writer.
+    let sequence = BUFFER_SEQUENCE.load(Ordering::Relaxed);
+    BUFFER_SEQUENCE.store(sequence.wrapping_add(1), Ordering::Relaxed);
+    fence(Ordering::Release);
+    BUFFER_START_TICK.store(start_tick, Ordering::Relaxed);
+    BUFFER_END_TICK.store(end_tick, Ordering::Relaxed);
+    BUFFER_STARTED_AT.store(Instant::now().as_micros() as u32, Ordering::Relaxed);
+    BUFFER_LENGTH.store(length_us, Ordering::Relaxed);
+    BUFFER_SEQUENCE.store(sequence.wrapping_add(2), Ordering::Release);
+}
+
+/// Where the speaker is in the MIDI song core 1's playing, in ticks
+pub fn song_tick() -> u32 {
+    loop {
+        let sequence = BUFFER_SEQUENCE.load(Ordering::Acquire);
+        if sequence % 2 == 1 {
+            // Core 1's only a few stores from done.
+            continue;
+        }
+        let start_tick = BUFFER_START_TICK.load(Ordering::Relaxed);
+        let end_tick = BUFFER_END_TICK.load(Ordering::Relaxed);
+        let started_at = BUFFER_STARTED_AT.load(Ordering::Relaxed);
+        let length = BUFFER_LENGTH.load(Ordering::Relaxed);
+        fence(Ordering::Acquire);
+        if BUFFER_SEQUENCE.load(Ordering::Relaxed) != sequence {
+            continue;
+        }
+        // Tempo changes inside a buffer are rare enough to go straight
+        // across.  Past the end, the next buffer's late, so wait for it.
+        let played = (Instant::now().as_micros() as u32).wrapping_sub(started_at);
+        let played = core::cmp::min(played, length);
+        let ticks = end_tick.saturating_sub(start_tick);
+        let into = u64::from(ticks) * u64::from(played) / u64::from(core::cmp::max(length, 1));
+        return start_tick + into as u32;
+    }
+}
+
+/// Core 1 is starting a MIDI song
+pub fn song_started() {
+    // Nothing's heard until its first buffer starts.
+    buffer_started(0, 0, 0);
+    let starts = SONG_STARTS.load(Ordering::Relaxed);
+    SONG_STARTS.store(starts.wrapping_add(1), Ordering::Release);
+}
+
+// A song to play along with, and the channel the notes come from
+struct GameSong {
+    name: &'static str,
+    tune: Tune,
+    channel: u8,
+}
+
+const SONGS: [GameSong; 1] = [GameSong {
+    name: "The Entertainer",
+    tune: Tune::Entertainer,
+    // The right hand
+    channel: 0,
+}];
+
+// Same as NewYearsMidi
+const MAX_TRACKS: usize = 32;
+
+// The play field, left of the score
+const FIELD_WIDTH: i32 = 84;
+// Where notes are due
+const HIT_X: i32 = 8;
+const LANE_HEIGHT: i32 = 11;
+const NOTE_WIDTH: u32 = 5;
+const NOTE_HEIGHT: u32 = 8;
+// Baselines for three lines of small text, and two of large
+const SMALL_LINES: [i32; 3] = [8, 19, 30];
+const TOP_LINE: i32 = 13;
+const BOTTOM_LINE: i32 = 29;
+
+/// Pick a song and play it until it ends or the user quits
+pub async fn run(devices: &mut DevicesCore0Menu<'_>) {
+    #[derive(Clone)]
+    enum SongResult {
+        UpMenu,
+        Play(usize),
+    }
+
+    let (result, _) = run_menu::<SongResult>(
+        &[
+            MenuBinding::new("Rhythm", None),
+            MenuBinding::new(SONGS[0].name, Some(SongResult::Play(0))),
+        ],
+        SongResult::UpMenu,
+        Some(1),
+        devices,
+    )
+    .await;
+    if let SongResult::Play(idx) = result {
+        play(&SONGS[idx], devices).await;
+    }
+}
+
+async fn play(song: &GameSong, devices: &mut DevicesCore0Menu<'_>) {
+    let TuneSource::Midi(data) = song.tune.source() else {
+        return;
+    };
+    let (header, tracks) = midly::parse(data).expect("It's inlined data, so its expected to parse");
+    let mut chart = Chart::<MAX_TRACKS>::new(&header, tracks, song.channel);
+    let mut game = RhythmGame::new(chart.ticks_per_quarter_note());
+
+    // Wait for core 1 to start the song from the top.
+    let starts = SONG_STARTS.load(Ordering::Acquire);
+    tunes::play(song.tune);
+    let mut ticker = Ticker::every(Duration::from_millis(10));
+    let mut last_state = devices.buttons.all_buttons();
+    devices.display.clear(BinaryColor::Off).unwrap();
+    display::draw_text(&mut devices.display, "Get ready", TOP_LINE, true);
+    display::draw_text(&mut devices.display, song.name, BOTTOM_LINE, false);
+    devices.display.flush().unwrap();
+    while SONG_STARTS.load(Ordering::Acquire) == starts {
+        if devices.buttons.new_press(&mut last_state).is_some() {
+            return;
+        }
+        ticker.next().await;
+    }
+
+    let starts = SONG_STARTS.load(Ordering::Acquire);
+    loop {
+        if SONG_STARTS.load(Ordering::Acquire) != starts {
+            break;
+        }
+        let now = song_tick();
+        game.update(&mut chart, now);
+        if game.is_finished(&chart) {
+            break;
+        }
+
+        // Every button that's gone down, in case two went together
+        let state = devices.buttons.all_buttons();
+        let pressed = [0, 1, 2, 3].map(|idx| state[idx] && !last_state[idx]);
+        last_state = state;
+        if pressed[0] {
+            // The song plays on.
+            return;
+        }
+        for (idx, lane) in [(1, Lane::High), (3, Lane::Middle), (2, Lane::Low)] {
+            if pressed[idx] {
+                game.press(lane, now);
+            }
+        }
+
+        draw(&mut devices.display, &game, now);
+        ticker.next().await;
+    }
+
+    draw_results(&mut devices.display, &game, chart.num_notes());
+    devices.buttons.wait_for_press().await;
+}
+
+fn draw(display: &mut DisplaySSD<'_>, game: &RhythmGame, now: u32) {
+    display.clear(BinaryColor::Off).unwrap();
+    let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+    let small = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+
+    // The button for each lane, the line to hit them on, and dots
+    // between the lanes
+    for (lane, label) in Lane::ALL.iter().zip(["^", "o", "v"]) {
+        let y = lane.index() as i32 * LANE_HEIGHT;
+        let _ = Text::new(label, Point::new(0, y + 8), small).draw(display);
+    }
+    let _ = Rectangle::new(Point::new(HIT_X, 0), Size::new(1, 32))
+        .into_styled(fill)
+        .draw(display);
+    let dots = (HIT_X..FIELD_WIDTH).step_by(4).flat_map(|x| {
+        [1, 2].map(|lane| Pixel(Point::new(x, lane * LANE_HEIGHT - 1), BinaryColor::On))
+    });
+    let _ = display.draw_iter(dots);
+
+    for note in game.notes() {
+        let x = HIT_X + game.position(note, now, FIELD_WIDTH - HIT_X);
+        let y = note.lane.index() as i32 * LANE_HEIGHT + 1;
+        let _ = Rectangle::new(
+            Point::new(x - NOTE_WIDTH as i32 / 2, y),
+            Size::new(NOTE_WIDTH, NOTE_HEIGHT),
+        )
+        .into_styled(fill)
+        .draw(display);
+    }
+    // Notes coming in or going out don't go over the score.
+    let _ = Rectangle::new(
+        Point::new(FIELD_WIDTH, 0),
+        Size::new(128 - FIELD_WIDTH as u32, 32),
+    )
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+    .draw(display);
+
+    let mut score = TextLine::new();
+    let mut multiplier = TextLine::new();
+    let _ = write!(score, "{}", game.score());
+    let _ = write!(multiplier, "x{}", game.multiplier());
+    let judgement = match game.last_judgement() {
+        Some(Judgement::Perfect) => "Perfect",
+        Some(Judgement::Good) => "Good",
+        Some(Judgement::Miss) => "Miss",
+        None => "",
+    };
+    for (text, y) in [score.as_str(), multiplier.as_str(), judgement]
+        .iter()
+        .zip(SMALL_LINES)
+    {
+        let _ = Text::new(text, Point::new(FIELD_WIDTH + 2, y), small).draw(display);
+    }
+    display.flush().unwrap();
+}
+
+fn draw_results(display: &mut DisplaySSD<'_>, game: &RhythmGame, num_notes: usize) {
+    let mut top = TextLine::new();
+    let mut bottom = TextLine::new();
+    let hits = game.count(Judgement::Perfect) + game.count(Judgement::Good);
+    let _ = write!(top, "Score {}", game.score());
+    let _ = write!(bottom, "Hits {}/{}", hits, num_notes);
+    display.clear(BinaryColor::Off).unwrap();
+    display::draw_text(display, top.as_str(), TOP_LINE, true);
+    display::draw_text(display, bottom.as_str(), BOTTOM_LINE, false);
+    display.flush().unwrap();
+}
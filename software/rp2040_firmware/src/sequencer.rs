@@ -0,0 +1,292 @@
+//
+// Step sequencer
+// ==============
+//
+// The sequencer screen.  The pattern, the clock and what each button does
+// are in midi-nostd's step_sequencer, where they're tested; this is the
+// drawing, the options menu and getting the pattern over to core 1.
+//
+// The grid fills the display: four tracks of sixteen 8x8 cells.  A note is
+// a bar as tall as it's loud, a rest is a dot (a bigger one on the beat),
+// the cursor is a box and the step that's playing has a dotted line down
+// its right hand side.
+//
+// Core 1 plays Tune::Sequencer with a SequencerPlayer.  Every change to the
+// pattern goes over SEQUENCER_QUEUE whole, and core 1 puts the step it's
+// playing in PLAYING_STEP for the playhead.  Only this screen pushes to the
+// queue.  The loop keeps playing after the screen's left, until another
+// tune is picked.
+//
+
+use crate::display;
+use crate::display::DisplaySSD;
+use crate::display::TextLine;
+use crate::menu::run_menu;
+use crate::menu::MenuBinding;
+use crate::tunes;
+use crate::tunes::Tune;
+use crate::Button;
+use crate::DevicesCore0Menu;
+use core::fmt::Write;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
+use embassy_time::Duration;
+use embassy_time::Ticker;
+use embedded_graphics::pixelcolor::BinaryColor;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::PrimitiveStyle;
+use embedded_graphics::primitives::Rectangle;
+use midi_nostd::command_queue::CommandQueue;
+use midi_nostd::step_sequencer::instrument_name;
+use midi_nostd::step_sequencer::key_name;
+use midi_nostd::step_sequencer::EditMode;
+use midi_nostd::step_sequencer::Editor;
+use midi_nostd::step_sequencer::EditorAction;
+use midi_nostd::step_sequencer::Pattern;
+use midi_nostd::step_sequencer::SeqButton;
+use midi_nostd::step_sequencer::SequencerCommand;
+use midi_nostd::step_sequencer::NUM_STEPS;
+use midi_nostd::step_sequencer::NUM_TRACKS;
+
+pub static SEQUENCER_QUEUE: CommandQueue<SequencerCommand, 4> = CommandQueue::new();
+
+/// Step core 1 is playing, or NOT_PLAYING
+pub static PLAYING_STEP: AtomicU8 = AtomicU8::new(NOT_PLAYING);
+pub const NOT_PLAYING: u8 = 0xff;
+
+const CELL_SIZE: i32 = 8;
+// Baselines for two lines of text
+const TOP_LINE: i32 = 13;
+const BOTTOM_LINE: i32 = 29;
+
+#[derive(Clone)]
+enum OptionsResult {
+    UpMenu,
+    PlayStop,
+    Tempo,
+    Instrument,
+    ClearTrack,
+    Exit,
+}
+
+pub struct Sequencer {
+    editor: Editor,
+    playing: bool,
+    // Changes core 1 hasn't been sent yet.  The queue's only full while
+    // core 1 is switching songs, so they're tried again as the screen's
+    // redrawn rather than waited on.
+    pattern_pending: bool,
+    playing_pending: bool,
+}
+
+impl Sequencer {
+    pub const fn new() -> Self {
+        Self {
+            editor: Editor::new(Pattern::new()),
+            playing: false,
+            pattern_pending: false,
+            playing_pending: false,
+        }
+    }
+
+    // Send what's pending, pattern first so a new player has notes before
+    // it starts.
+    fn sync(&mut self) {
+        if self.pattern_pending {
+            let command = SequencerCommand::SetPattern(*self.editor.pattern());
+            self.pattern_pending = SEQUENCER_QUEUE.push(command).is_err();
+        }
+        if self.playing_pending && !self.pattern_pending {
+            let command = if self.playing {
+                SequencerCommand::Play
+            } else {
+                SequencerCommand::Stop
+            };
+            self.playing_pending = SEQUENCER_QUEUE.push(command).is_err();
+        }
+    }
+
+    /// Let the user hear the step they're changing, if the loop isn't
+    /// already playing it.  A preview that doesn't fit is dropped.
+    fn preview(&self) {
+        let (track, _) = self.editor.cursor();
+        if !self.playing {
+            let _ = SEQUENCER_QUEUE.push(SequencerCommand::Preview {
+                track: track as u8,
+                step: self.editor.current_step(),
+            });
+        }
+    }
+
+    /// Run the sequencer screen until the user exits
+    pub async fn run(&mut self, devices: &mut DevicesCore0Menu<'_>) {
+        // A new player starts empty and stopped, so tell it everything.
+        tunes::play(Tune::Sequencer);
+        self.pattern_pending = true;
+        self.playing_pending = self.playing;
+
+        // Fast enough for the playhead at 240bpm
+        let mut ticker = Ticker::every(Duration::from_millis(20));
+        let mut last_state = devices.buttons.all_buttons();
+        loop {
+            self.sync();
+            self.draw(&mut devices.display);
+
+            let Some(button) = devices.buttons.new_press(&mut last_state) else {
+                ticker.next().await;
+                continue;
+            };
+            let button = match button {
+                Button::B0 => SeqButton::Back,
+                Button::B1 => SeqButton::Up,
+                Button::B2 => SeqButton::Down,
+                Button::B3 => SeqButton::Action,
+            };
+            match self.editor.press(button) {
+                EditorAction::None => {}
+                EditorAction::Changed => {
+                    self.pattern_pending = true;
+                    self.sync();
+                    if matches!(self.editor.mode(), EditMode::Note | EditMode::Velocity) {
+                        self.preview();
+                    }
+                }
+                EditorAction::Menu => {
+                    if !self.run_options(devices).await {
+                        break;
+                    }
+                    last_state = devices.buttons.all_buttons();
+                }
+            }
+        }
+
+        // Catch up before leaving, as long as core 1 isn't stuck.
+        for _ in 0..50 {
+            self.sync();
+            if !self.pattern_pending && !self.playing_pending {
+                break;
+            }
+            ticker.next().await;
+        }
+    }
+
+    // The options menu.  Returns false to leave the sequencer.
+    async fn run_options(&mut self, devices: &mut DevicesCore0Menu<'_>) -> bool {
+        let (result, _) = run_menu::<OptionsResult>(
+            &[
+                MenuBinding::new("Sequencer", None),
+                MenuBinding::new(
+                    if self.playing { "Stop" } else { "Play" },
+                    Some(OptionsResult::PlayStop),
+                ),
+                MenuBinding::new("Tempo", Some(OptionsResult::Tempo)),
+                MenuBinding::new("Instrument", Some(OptionsResult::Instrument)),
+                MenuBinding::new("Clear Track", Some(OptionsResult::ClearTrack)),
+                MenuBinding::new("Exit", Some(OptionsResult::Exit)),
+            ],
+            OptionsResult::UpMenu,
+            Some(1),
+            devices,
+        )
+        .await;
+
+        match result {
+            OptionsResult::UpMenu => {}
+            OptionsResult::PlayStop => {
+                self.playing = !self.playing;
+                self.playing_pending = true;
+            }
+            OptionsResult::Tempo => self.editor.edit_tempo(),
+            OptionsResult::Instrument => self.editor.edit_instrument(),
+            OptionsResult::ClearTrack => {
+                self.editor.clear_track();
+                self.pattern_pending = true;
+            }
+            OptionsResult::Exit => return false,
+        }
+        true
+    }
+
+    fn draw(&self, display: &mut DisplaySSD<'_>) {
+        display.clear(BinaryColor::Off).unwrap();
+        let mut top = TextLine::new();
+        let mut bottom = TextLine::new();
+        let step = self.editor.current_step();
+        let (track, _) = self.editor.cursor();
+        let mut name_buffer = [0u8; 4];
+        match self.editor.mode() {
+            EditMode::Navigate => self.draw_grid(display),
+            EditMode::Note | EditMode::Velocity => {
+                let key = step
+                    .key
+                    .map_or("Rest", |key| key_name(key, &mut name_buffer));
+                let _ = write!(top, "Note {}", key);
+                let _ = write!(bottom, "Vel {}", step.velocity);
+                let note_mode = self.editor.mode() == EditMode::Note;
+                display::draw_text(display, top.as_str(), TOP_LINE, note_mode);
+                display::draw_text(display, bottom.as_str(), BOTTOM_LINE, !note_mode);
+            }
+            EditMode::Tempo => {
+                let _ = write!(bottom, "{} BPM", self.editor.pattern().bpm);
+                display::draw_text(display, "Tempo", TOP_LINE, false);
+                display::draw_text(display, bottom.as_str(), BOTTOM_LINE, true);
+            }
+            EditMode::Instrument => {
+                let program = self.editor.pattern().tracks[track].program;
+                let _ = write!(top, "Track {}", track + 1);
+                display::draw_text(display, top.as_str(), TOP_LINE, false);
+                display::draw_text(
+                    display,
+                    instrument_name(program).unwrap_or("?"),
+                    BOTTOM_LINE,
+                    true,
+                );
+            }
+        }
+        display.flush().unwrap();
+    }
+
+    fn draw_grid(&self, display: &mut DisplaySSD<'_>) {
+        let fill = PrimitiveStyle::with_fill(BinaryColor::On);
+        let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+
+        for (track_idx, track) in self.editor.pattern().tracks.iter().enumerate() {
+            for (step_idx, step) in track.steps.iter().enumerate() {
+                let corner = Point::new(step_idx as i32, track_idx as i32) * CELL_SIZE;
+                let cell = match step.key {
+                    Some(_) => {
+                        // 2 to 6 pixels tall, sitting on the bottom of the cell
+                        let height = 2 + (step.velocity as u32) * 4 / 127;
+                        Rectangle::new(
+                            corner + Point::new(1, 7 - height as i32),
+                            Size::new(5, height),
+                        )
+                    }
+                    None if step_idx % 4 == 0 => {
+                        Rectangle::new(corner + Point::new(3, 3), Size::new(2, 2))
+                    }
+                    None => Rectangle::new(corner + Point::new(3, 4), Size::new(1, 1)),
+                };
+                let _ = cell.into_styled(fill).draw(display);
+            }
+        }
+
+        let (track, step) = self.editor.cursor();
+        let _ = Rectangle::new(
+            Point::new(step as i32, track as i32) * CELL_SIZE,
+            Size::new_equal(CELL_SIZE as u32),
+        )
+        .into_styled(outline)
+        .draw(display);
+
+        let playing_step = PLAYING_STEP.load(Ordering::Relaxed);
+        if (playing_step as usize) < NUM_STEPS {
+            let x = (playing_step as i32 + 1) * CELL_SIZE - 1;
+            let height = NUM_TRACKS as i32 * CELL_SIZE;
+            let dots = (0..height)
+                .step_by(2)
+                .map(|y| Pixel(Point::new(x, y), BinaryColor::On));
+            let _ = display.draw_iter(dots);
+        }
+    }
+}
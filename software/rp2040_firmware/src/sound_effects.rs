@@ -0,0 +1,74 @@
+//
+// Sound effects
+// =============
+//
+// UI sounds, mixed over the music by midi-nostd's SfxMixer in
+// audio_playback.rs.  The menu runs on core 0 and the audio on core 1, so
+// effects are asked for through SFX_QUEUE, which core 1 drains before each
+// DMA buffer.
+//
+// The queue only takes one producer.  Right now that's the menu task; any
+// other task that wants to play effects has to go through it.
+//
+
+use midi_nostd::command_queue::CommandQueue;
+use midi_nostd::sfx_mixer::EffectSource;
+use midi_nostd::sfx_mixer::SfxCommand;
+use midi_nostd::sfx_mixer::SoundEffect;
+use midi_nostd::sfx_mixer::Tone;
+
+pub static SFX_QUEUE: CommandQueue<SfxCommand, 16> = CommandQueue::new();
+
+// Positions in EFFECTS
+#[derive(Clone, Copy)]
+pub enum Sfx {
+    Click = 0,
+    Select = 1,
+    Back = 2,
+    Jingle = 3,
+}
+
+const fn tone(key: u8, duration_ms: u16) -> Tone {
+    Tone {
+        key: Some(key),
+        duration_ms,
+    }
+}
+
+static CLICK: [Tone; 1] = [tone(96, 15)];
+static SELECT: [Tone; 2] = [tone(84, 40), tone(91, 60)];
+static BACK: [Tone; 2] = [tone(91, 40), tone(84, 60)];
+// C E G C, happy new year
+static JINGLE: [Tone; 4] = [tone(72, 120), tone(76, 120), tone(79, 120), tone(84, 400)];
+
+pub static EFFECTS: [SoundEffect; 4] = [
+    SoundEffect {
+        source: EffectSource::Tones(&CLICK),
+        volume: 30,
+        ducks: false,
+    },
+    SoundEffect {
+        source: EffectSource::Tones(&SELECT),
+        volume: 40,
+        ducks: false,
+    },
+    SoundEffect {
+        source: EffectSource::Tones(&BACK),
+        volume: 40,
+        ducks: false,
+    },
+    SoundEffect {
+        source: EffectSource::Tones(&JINGLE),
+        volume: 60,
+        ducks: true,
+    },
+];
+
+/// Play an effect over the music.  If the queue's full the effect is
+/// dropped; it's only a click.
+pub fn play(effect: Sfx) {
+    let _ = SFX_QUEUE.push(SfxCommand::Play {
+        effect: effect as u8,
+        volume: 100,
+    });
+}
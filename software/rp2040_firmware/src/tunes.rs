@@ -0,0 +1,107 @@
+//
+// Tunes
+// =====
+//
+// Everything the music menu can play.  MIDI and four channel ProTracker MOD
+// files are included from assets; RTTTL tunes are one line strings.  Adding
+// one is a line here, a variant in Tune and a menu entry in main.rs.  Please
+// only add tunes that are out of copyright, or that you wrote.  Keep an eye
+// on MOD sizes; samples add up fast, and the flash is 2MB.
+//
+// The menu runs on core 0 and playback on core 1, so tunes are asked for
+// through TUNE_QUEUE.  AudioPlayback fades out whatever is playing when a
+// request comes in, and PioSound then starts the new tune.  Like SFX_QUEUE,
+// it only takes one producer, the menu task.
+//
+// The volume setting is a single value, so it goes in VOLUME instead.  The
+// menu sets it; AudioPlayback gives it to each tune as it starts, and
+// checks it while the tune plays.
+//
+
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
+use midi_nostd::command_queue::CommandQueue;
+
+pub static TUNE_QUEUE: CommandQueue<Tune, 4> = CommandQueue::new();
+/// Volume tunes play at, from 0 to 100.  Only the menu task changes it.
+pub static VOLUME: AtomicU8 = AtomicU8::new(100);
+
+// Volumes the menu steps through
+const VOLUME_STEPS: [u8; 4] = [25, 50, 75, 100];
+
+#[derive(Clone, Copy)]
+pub enum Tune {
+    Entertainer,
+    OdeToJoy,
+    Twinkle,
+    AuldLangSyne,
+    JingleBells,
+    FrereJacques,
+    /// The step sequencer's loop, from sequencer.rs
+    Sequencer,
+    /// The buttons, played through the arpeggiator, from jam.rs
+    Jam,
+}
+
+pub enum TuneSource {
+    Midi(&'static [u8]),
+    /// RTTTL text, and the program to play it with
+    Rtttl(&'static str, u8),
+    Mod(&'static [u8]),
+    /// Played live, with no data of its own
+    Sequencer,
+    Jam,
+}
+
+impl Tune {
+    pub const fn source(self) -> TuneSource {
+        match self {
+            Tune::Entertainer => TuneSource::Midi(include_bytes!("../assets/entertainer.mid")),
+            Tune::OdeToJoy => TuneSource::Rtttl(
+                "OdeToJoy:d=4,o=5,b=140:e,e,f,g,g,f,e,d,c,c,d,e,e.,8d,2d,e,e,f,g,g,f,e,d,c,c,d,e,d.,8c,2c",
+                19,
+            ),
+            Tune::Twinkle => TuneSource::Rtttl(
+                "Twinkle:d=4,o=5,b=120:c,c,g,g,a,a,2g,f,f,e,e,d,d,2c,g,g,f,f,e,e,2d,g,g,f,f,e,e,2d,c,c,g,g,a,a,2g,f,f,e,e,d,d,2c",
+                10,
+            ),
+            Tune::AuldLangSyne => TuneSource::Rtttl(
+                "AuldLangSyne:d=4,o=5,b=100:g4,c.,8c,c,e,d.,8c,d,e,c.,8c,e,g,2a.,a,g.,8e,e,c,d.,8c,d,8e,8d,c.,8a4,a4,g4,2c.",
+                21,
+            ),
+            Tune::JingleBells => TuneSource::Rtttl(
+                "JingleBells:d=8,o=5,b=112:e,e,4e,e,e,4e,e,g,c.,16d,2e,f,f,f.,16f,f,e,e,16e,16e,e,d,d,e,4d,4g",
+                9,
+            ),
+            // A round, made by assets/frere_jacques.py
+            Tune::FrereJacques => {
+                TuneSource::Mod(include_bytes!("../assets/frere_jacques.mod"))
+            }
+            Tune::Sequencer => TuneSource::Sequencer,
+            Tune::Jam => TuneSource::Jam,
+        }
+    }
+}
+
+/// Fade out what's playing and play tune.  Menu task only.
+pub fn play(tune: Tune) {
+    // If core 1 is that far behind, a dropped request won't be missed.
+    let _ = TUNE_QUEUE.push(tune);
+}
+
+/// The volume setting, from 0 to 100
+pub fn volume() -> u8 {
+    VOLUME.load(Ordering::Relaxed)
+}
+
+/// Step the volume up, going round to the quietest after the loudest.
+/// Menu task only.
+pub fn next_volume() {
+    let current = volume();
+    let next = VOLUME_STEPS
+        .iter()
+        .copied()
+        .find(|step| *step > current)
+        .unwrap_or(VOLUME_STEPS[0]);
+    VOLUME.store(next, Ordering::Relaxed);
+}
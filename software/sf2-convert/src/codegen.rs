@@ -0,0 +1,311 @@
+// Rust output.
+//
+// Writes a preset out as a module for midi-nostd: one .bin file per sample,
+// pulled in with include_bytes! so it stays in flash, the zone table as a
+// SamplePatch, and a SampledInstrument type alias to play it with.
+//
+// SampledInstrument takes its envelope as const generics, so the whole
+// instrument gets one envelope.  It's taken from the zone that plays
+// middle C, which is usually representative.
+//
+
+use crate::resample::resample;
+use crate::resample::to_i16_le;
+use crate::resample::to_u8;
+use crate::sf2::Sf2;
+use crate::zones::Envelope;
+use crate::zones::LoopKind;
+use crate::zones::ResolvedZone;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputFormat {
+    U8,
+    I16Le,
+}
+
+pub struct Options {
+    pub sample_rate: u32,
+    pub format: OutputFormat,
+    /// Name of the instrument type, like Piano
+    pub type_name: String,
+    /// Start of the .bin file names, like piano
+    pub file_stem: String,
+    /// Where the preset came from, for the header comment
+    pub source: String,
+}
+
+pub struct Output {
+    pub module: String,
+    /// File names and contents of the sample data
+    pub files: Vec<(String, Vec<u8>)>,
+    /// Envelopes that differ from the one the instrument uses
+    pub dropped_envelopes: usize,
+}
+
+impl Output {
+    /// Flash the sample data takes, in bytes
+    ///
+    pub fn sample_bytes(&self) -> usize {
+        self.files.iter().map(|(_, data)| data.len()).sum()
+    }
+}
+
+/// CamelCase, for type names
+///
+pub fn type_name(name: &str) -> String {
+    let mut rval: String = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().unwrap().to_ascii_uppercase();
+            std::iter::once(first)
+                .chain(chars.map(|c| c.to_ascii_lowercase()))
+                .collect::<String>()
+        })
+        .collect();
+    if !rval.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        rval.insert_str(0, "Sf");
+    }
+    rval
+}
+
+/// snake_case, for file names
+///
+pub fn file_stem(name: &str) -> String {
+    let mut rval = String::new();
+    for (idx, c) in type_name(name).chars().enumerate() {
+        if c.is_ascii_uppercase() && idx > 0 {
+            rval.push('_');
+        }
+        rval.push(c.to_ascii_lowercase());
+    }
+    rval
+}
+
+fn loop_mode(loop_kind: LoopKind) -> &'static str {
+    match loop_kind {
+        LoopKind::None => "LoopMode::None",
+        LoopKind::Continuous => "LoopMode::Continuous",
+        LoopKind::UntilRelease => "LoopMode::UntilRelease",
+    }
+}
+
+//
+// The envelope from the zone that plays middle C at a medium velocity, or
+// the first zone if none does.
+//
+fn instrument_envelope(zones: &[ResolvedZone]) -> Envelope {
+    zones
+        .iter()
+        .find(|zone| (zone.low_key..=zone.high_key).contains(&60))
+        .or(zones.first())
+        .map(|zone| zone.envelope)
+        .unwrap_or(Envelope {
+            delay_ms: 0,
+            attack_ms: 0,
+            hold_ms: 0,
+            decay_ms: 0,
+            sustain_percent: 100,
+            release_ms: 0,
+        })
+}
+
+///
+/// Convert a preset's zones to a module and its sample files
+///
+pub fn generate(sf2: &Sf2, zones: &[ResolvedZone], options: &Options) -> Output {
+    let format = match options.format {
+        OutputFormat::U8 => "SampleFormat::U8",
+        OutputFormat::I16Le => "SampleFormat::I16Le",
+    };
+
+    // Zones often share a sample, so each one is converted once.
+    let mut sample_ids = HashMap::new();
+    let mut files = Vec::new();
+    let mut samples = String::new();
+    let mut zone_table = String::new();
+    for zone in zones {
+        let key = (
+            zone.start,
+            zone.end,
+            zone.loop_start,
+            zone.loop_end,
+            zone.loop_kind,
+            zone.sample_rate,
+        );
+        let sample_id = match sample_ids.get(&key) {
+            Some(sample_id) => *sample_id,
+            None => {
+                let sample_id = sample_ids.len();
+                sample_ids.insert(key, sample_id);
+
+                let loop_range = match zone.loop_kind {
+                    LoopKind::None => None,
+                    _ => Some((zone.loop_start - zone.start, zone.loop_end - zone.start)),
+                };
+                let (data, loop_range) = resample(
+                    &sf2.sample_data[zone.start..zone.end],
+                    loop_range,
+                    zone.sample_rate,
+                    options.sample_rate,
+                );
+                let sample_rate = zone.sample_rate.min(options.sample_rate);
+                let (loop_start, loop_end) = loop_range.unwrap_or((0, 0));
+                let file_name = format!("{}_{}.bin", options.file_stem, sample_id);
+                writeln!(
+                    samples,
+                    "static SAMPLE_{}: Sample = Sample {{\n    \
+                     format: {},\n    \
+                     data: include_bytes!(\"{}\"),\n    \
+                     sample_rate: {},\n    \
+                     loop_mode: {},\n    \
+                     loop_start: {},\n    \
+                     loop_end: {},\n\
+                     }};\n",
+                    sample_id,
+                    format,
+                    file_name,
+                    sample_rate,
+                    loop_mode(zone.loop_kind),
+                    loop_start,
+                    loop_end
+                )
+                .unwrap();
+                let bytes = match options.format {
+                    OutputFormat::U8 => to_u8(&data),
+                    OutputFormat::I16Le => to_i16_le(&data),
+                };
+                files.push((file_name, bytes));
+                sample_id
+            }
+        };
+
+        writeln!(
+            zone_table,
+            "        Zone {{\n            \
+             low_key: {},\n            \
+             high_key: {},\n            \
+             low_velocity: {},\n            \
+             high_velocity: {},\n            \
+             root_key: {},\n            \
+             fine_tune: {},\n            \
+             volume: {},\n            \
+             sample: &SAMPLE_{},\n        \
+             }},",
+            zone.low_key,
+            zone.high_key,
+            zone.low_velocity,
+            zone.high_velocity,
+            zone.root_key,
+            zone.fine_tune,
+            zone.volume_percent,
+            sample_id
+        )
+        .unwrap();
+    }
+
+    let envelope = instrument_envelope(zones);
+    let dropped_envelopes = zones
+        .iter()
+        .filter(|zone| zone.envelope != envelope)
+        .count();
+
+    let name = &options.type_name;
+    let mut module = String::new();
+    writeln!(
+        module,
+        "// Generated by sf2-convert from {}.\n\
+         // Run sf2-convert again instead of editing.\n\
+         //\n\
+         \n\
+         use crate::sample_data::LoopMode;\n\
+         use crate::sample_data::Sample;\n\
+         use crate::sample_data::SampleFormat;\n\
+         use crate::sample_data::SamplePatch;\n\
+         use crate::sample_data::Zone;\n\
+         use crate::sample_player::SampledInstrument;\n",
+        options.source
+    )
+    .unwrap();
+    module.push_str(&samples);
+    writeln!(
+        module,
+        "pub struct {name}Zones {{}}\n\
+         \n\
+         impl SamplePatch for {name}Zones {{\n    \
+         const ZONES: &'static [Zone] = &[\n\
+         {zone_table}    \
+         ];\n\
+         }}\n\
+         \n\
+         pub type {name}<const P_FREQ: u32, const U_FREQ: u32> = SampledInstrument<\n    \
+         P_FREQ,\n    \
+         U_FREQ,\n    \
+         {name}Zones,\n    \
+         {:<10} // Delay\n    \
+         {:<10} // A\n    \
+         {:<10} // Hold\n    \
+         {:<10} // D\n    \
+         {:<10} // S\n    \
+         {:<10} // R\n\
+         >;",
+        format!("{},", envelope.delay_ms),
+        format!("{},", envelope.attack_ms),
+        format!("{},", envelope.hold_ms),
+        format!("{},", envelope.decay_ms),
+        format!("{},", envelope.sustain_percent),
+        format!("{},", envelope.release_ms),
+    )
+    .unwrap();
+
+    Output {
+        module,
+        files,
+        dropped_envelopes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codegen::*;
+    use crate::sf2::tests::test_sf2;
+    use crate::zones::resolve;
+
+    #[test]
+    fn names_should_be_rust_names() {
+        assert_eq!("GrandPiano", type_name("Grand Piano"));
+        assert_eq!("Sf808Drums", type_name("808 drums"));
+        assert_eq!("grand_piano", file_stem("Grand Piano"));
+    }
+
+    #[test]
+    fn shared_samples_should_be_written_once() {
+        let sf2 = Sf2::parse(&test_sf2(48000, &[0x100; 1000])).unwrap();
+        let zones = resolve(&sf2, sf2.find_preset(0, 5).unwrap()).unwrap();
+        let options = Options {
+            sample_rate: 24000,
+            format: OutputFormat::U8,
+            type_name: "Test".to_string(),
+            file_stem: "test".to_string(),
+            source: "test.sf2".to_string(),
+        };
+        let output = generate(&sf2, &zones, &options);
+        // The zones loop differently, so they get a sample each.
+        assert_eq!(2, output.files.len());
+        assert_eq!(1000, output.sample_bytes());
+        assert!(output
+            .module
+            .contains("data: include_bytes!(\"test_0.bin\")"));
+        assert!(output.module.contains("loop_start: 1,"));
+        assert!(output.module.contains("loop_end: 499,"));
+        assert!(output.module.contains("fine_tune: 1200,"));
+        assert!(output
+            .module
+            .contains("pub type Test<const P_FREQ: u32, const U_FREQ: u32> = SampledInstrument<"));
+        assert!(output.module.contains("100,       // R"));
+        assert_eq!(0, output.dropped_envelopes);
+    }
+}
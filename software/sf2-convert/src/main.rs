@@ -0,0 +1,193 @@
+// SoundFont 2 to midi-nostd converter.
+//
+// Converts presets from an SF2 file into sampled instruments that
+// midi-nostd can play from flash:
+//
+//   sf2-convert FILE.sf2 --list
+//   sf2-convert FILE.sf2 --preset 0:0 [--rate 24000] [--bits 8|16]
+//       [--name Piano] [--out DIR] [--max-bytes N]
+//
+// --preset takes bank:program.  Output goes into DIR (default .) as NAME.rs
+// plus a .bin per sample.  --max-bytes is the flash budget for the sample
+// data; going over it is an error, so a build script can catch a preset that
+// won't fit.  8 bit samples are half the size and noisier.
+//
+// To play the result, copy the files into midi-nostd/src, add the module to
+// lib.rs, then add a variant for the type alias to midi-nostd's sampled.rs,
+// with its match arms and GM program, like the Kalimba.  Note::new plays
+// those programs through NoteEnum's SampledEnum.  The Kalimba comes from
+// dev_scripts/kalimba_sf2.py.
+//
+// Only the parts of SF2 that map onto SampledInstrument are converted: key
+// and velocity zones, sample data and loops, tuning, attenuation and the
+// volume envelope.  Filters, modulation envelopes, LFOs and modulators are
+// dropped.
+//
+
+mod codegen;
+mod resample;
+mod sf2;
+mod zones;
+
+use crate::codegen::generate;
+use crate::codegen::Options;
+use crate::codegen::OutputFormat;
+use crate::sf2::Sf2;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: sf2-convert FILE.sf2 (--list | --preset BANK:PROGRAM [--rate HZ] \
+                     [--bits 8|16] [--name NAME] [--out DIR] [--max-bytes N])";
+
+struct Args {
+    file: PathBuf,
+    list: bool,
+    preset: Option<(u16, u16)>,
+    rate: u32,
+    bits: u32,
+    name: Option<String>,
+    out: PathBuf,
+    max_bytes: Option<usize>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        file: PathBuf::new(),
+        list: false,
+        preset: None,
+        rate: 24000,
+        bits: 8,
+        name: None,
+        out: PathBuf::from("."),
+        max_bytes: None,
+    };
+    let mut file = None;
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        let mut value = || argv.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--list" => args.list = true,
+            "--preset" => {
+                let value = value()?;
+                let (bank, program) = value.split_once(':').ok_or("--preset is BANK:PROGRAM")?;
+                args.preset = Some((
+                    bank.parse().map_err(|_| "bad bank")?,
+                    program.parse().map_err(|_| "bad program")?,
+                ));
+            }
+            "--rate" => args.rate = value()?.parse().map_err(|_| "bad rate")?,
+            "--bits" => {
+                args.bits = value()?.parse().map_err(|_| "bad bits")?;
+                if args.bits != 8 && args.bits != 16 {
+                    return Err("--bits is 8 or 16".to_string());
+                }
+            }
+            "--name" => args.name = Some(value()?),
+            "--out" => args.out = PathBuf::from(value()?),
+            "--max-bytes" => args.max_bytes = Some(value()?.parse().map_err(|_| "bad size")?),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => file = Some(PathBuf::from(arg)),
+        }
+    }
+    args.file = file.ok_or("no SF2 file given")?;
+    if !args.list && args.preset.is_none() {
+        return Err("give --list or --preset".to_string());
+    }
+    Ok(args)
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let data = std::fs::read(&args.file)
+        .map_err(|e| format!("can't read {}: {}", args.file.display(), e))?;
+    let sf2 = Sf2::parse(&data).map_err(|e| format!("{}: {}", args.file.display(), e))?;
+
+    if args.list {
+        for preset in &sf2.presets {
+            println!(
+                "{:>3}:{:<3} {}",
+                preset.bank, preset.program, preset.zones.name
+            );
+        }
+        return Ok(());
+    }
+
+    let (bank, program) = args.preset.unwrap();
+    let preset = sf2
+        .find_preset(bank, program)
+        .ok_or(format!("no preset {}:{}", bank, program))?;
+    let zones = zones::resolve(&sf2, preset).map_err(|e| e.to_string())?;
+    if zones.is_empty() {
+        return Err(format!("preset {} has no zones", preset.zones.name));
+    }
+
+    let name = args.name.as_deref().unwrap_or(&preset.zones.name);
+    let options = Options {
+        sample_rate: args.rate,
+        format: if args.bits == 8 {
+            OutputFormat::U8
+        } else {
+            OutputFormat::I16Le
+        },
+        type_name: codegen::type_name(name),
+        file_stem: codegen::file_stem(name),
+        source: format!(
+            "{}, preset \"{}\" ({}:{})",
+            args.file
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default(),
+            preset.zones.name,
+            bank,
+            program
+        ),
+    };
+    let output = generate(&sf2, &zones, &options);
+
+    println!(
+        "{}: {} zones, {} samples, {} bytes of sample data",
+        options.type_name,
+        zones.len(),
+        output.files.len(),
+        output.sample_bytes()
+    );
+    if output.dropped_envelopes > 0 {
+        println!(
+            "{} zones have their own envelope; they'll use the instrument's",
+            output.dropped_envelopes
+        );
+    }
+    if let Some(max_bytes) = args.max_bytes {
+        if output.sample_bytes() > max_bytes {
+            return Err(format!(
+                "{} bytes is over the {} byte budget; try --bits 8, a lower --rate, or a smaller preset",
+                output.sample_bytes(),
+                max_bytes
+            ));
+        }
+    }
+
+    std::fs::create_dir_all(&args.out).map_err(|e| e.to_string())?;
+    let write = |file_name: &str, data: &[u8]| {
+        let path = args.out.join(file_name);
+        std::fs::write(&path, data).map_err(|e| format!("can't write {}: {}", path.display(), e))
+    };
+    for (file_name, data) in &output.files {
+        write(file_name, data)?;
+    }
+    write(
+        &format!("{}.rs", options.file_stem),
+        output.module.as_bytes(),
+    )
+}
+
+fn main() -> ExitCode {
+    let result = parse_args().and_then(run);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("{}", USAGE);
+            ExitCode::FAILURE
+        }
+    }
+}
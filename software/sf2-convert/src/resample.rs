@@ -0,0 +1,153 @@
+// Sample rate conversion.
+//
+// SoundFont samples are usually 44.1 or 48khz, which is more than the
+// RP2040 plays at and more than its flash can hold.  Samples are converted
+// down to the playback rate with a windowed sinc filter, which cuts off
+// what the lower rate can't carry instead of letting it alias.
+//
+// Looped samples are read around their loop, so the filter sees the audio
+// that will actually follow the loop end and the seam stays smooth.
+//
+
+use std::f64::consts::PI;
+
+// Filter half width, in output samples.  More is sharper and slower.
+const HALF_WIDTH: f64 = 16.0;
+
+// Cutoff, as a fraction of the output's Nyquist frequency.  A little under
+// 1 leaves room for the filter's transition band.
+const CUTOFF: f64 = 0.9;
+
+//
+// Input sample i, following the loop if there is one and silent outside
+// the data otherwise.
+//
+fn input_at(input: &[i16], loop_range: Option<(usize, usize)>, i: i64) -> f64 {
+    if i < 0 {
+        return 0.0;
+    }
+    let mut i = i as usize;
+    if let Some((loop_start, loop_end)) = loop_range {
+        if i >= loop_end {
+            i = loop_start + (i - loop_end) % (loop_end - loop_start);
+        }
+    }
+    input.get(i).map(|sample| *sample as f64).unwrap_or(0.0)
+}
+
+fn blackman(x: f64) -> f64 {
+    // x from -1 to 1
+    0.42 + 0.5 * (PI * x).cos() + 0.08 * (2.0 * PI * x).cos()
+}
+
+///
+/// Convert input from from_rate to to_rate.  Returns the new samples and the
+/// loop points moved to match.  Samples already at or below to_rate are
+/// left alone; the player pitches them up as it goes.
+///
+pub fn resample(
+    input: &[i16],
+    loop_range: Option<(usize, usize)>,
+    from_rate: u32,
+    to_rate: u32,
+) -> (Vec<i16>, Option<(usize, usize)>) {
+    if from_rate <= to_rate || input.is_empty() {
+        return (input.to_vec(), loop_range);
+    }
+    let ratio = to_rate as f64 / from_rate as f64;
+    let scale = |position: usize| (position as f64 * ratio).round() as usize;
+    let output_len = ((input.len() as u64 * to_rate as u64).div_ceil(from_rate as u64)) as usize;
+    let new_loop = loop_range.map(|(start, end)| {
+        let new_start = scale(start);
+        (new_start, scale(end).max(new_start + 1).min(output_len))
+    });
+
+    // Cutoff in cycles per input sample
+    let cutoff = CUTOFF * ratio / 2.0;
+    let half_width = HALF_WIDTH / ratio;
+
+    let output = (0..output_len)
+        .map(|n| {
+            let t = n as f64 / ratio;
+            let first = (t - half_width).ceil() as i64;
+            let last = (t + half_width).floor() as i64;
+            let mut sum = 0.0;
+            for i in first..=last {
+                let x = i as f64 - t;
+                let sinc = if x == 0.0 {
+                    2.0 * cutoff
+                } else {
+                    (2.0 * PI * cutoff * x).sin() / (PI * x)
+                };
+                sum += input_at(input, loop_range, i) * sinc * blackman(x / half_width);
+            }
+            sum.round().clamp(i16::MIN as f64, i16::MAX as f64) as i16
+        })
+        .collect();
+    (output, new_loop)
+}
+
+/// Unsigned 8 bit, as SampleFormat::U8 plays it
+///
+pub fn to_u8(samples: &[i16]) -> Vec<u8> {
+    samples
+        .iter()
+        .map(|sample| (((*sample as i32 + 0x80) >> 8) + 0x80).clamp(0, 0xff) as u8)
+        .collect()
+}
+
+/// Signed 16 bit little endian, as SampleFormat::I16Le plays it
+///
+pub fn to_i16_le(samples: &[i16]) -> Vec<u8> {
+    samples
+        .iter()
+        .flat_map(|sample| sample.to_le_bytes())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::resample::*;
+
+    fn sine(frequency: f64, rate: u32, len: usize) -> Vec<i16> {
+        (0..len)
+            .map(|n| (10000.0 * (2.0 * PI * frequency * n as f64 / rate as f64).sin()) as i16)
+            .collect()
+    }
+
+    fn peak(samples: &[i16]) -> i16 {
+        samples.iter().map(|sample| sample.abs()).max().unwrap()
+    }
+
+    #[test]
+    fn low_tones_should_pass_and_high_tones_should_not() {
+        let (low, _) = resample(&sine(1000.0, 48000, 4800), None, 48000, 24000);
+        assert_eq!(2400, low.len());
+        // Away from the ends, where the filter runs off the data
+        assert!((9800..=10200).contains(&peak(&low[100..2300])));
+
+        // 15khz would alias down to 9khz at 24khz.
+        let (high, _) = resample(&sine(15000.0, 48000, 4800), None, 48000, 24000);
+        assert!(peak(&high[100..2300]) < 100, "{}", peak(&high[100..2300]));
+    }
+
+    #[test]
+    fn loop_points_should_scale() {
+        let (output, new_loop) = resample(&[0; 441], Some((100, 400)), 44100, 24000);
+        assert_eq!(240, output.len());
+        assert_eq!(Some((54, 218)), new_loop);
+        // Nothing to do
+        let (output, new_loop) = resample(&[0; 100], Some((10, 90)), 22050, 24000);
+        assert_eq!(100, output.len());
+        assert_eq!(Some((10, 90)), new_loop);
+    }
+
+    #[test]
+    fn output_formats_should_match_the_player() {
+        assert_eq!(
+            vec![0x80, 0xff, 0x00, 0xc0],
+            to_u8(&[0, 0x7fff, -0x8000, 0x4000])
+        );
+        assert_eq!(vec![0x00, 0x40, 0x00, 0xc0], to_i16_le(&[0x4000, -0x4000]));
+    }
+}
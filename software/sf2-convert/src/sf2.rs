@@ -0,0 +1,513 @@
+// SoundFont 2 reader.
+//
+// Reads the parts of an SF2 file the converter needs: the 16 bit sample
+// data, the sample headers, and the preset and instrument zones with their
+// generators.  Modulators are skipped; they don't map onto anything the
+// synth can do.
+//
+// An SF2 file is a RIFF file:
+//
+//   RIFF sfbk
+//     LIST INFO  (names, ignored)
+//     LIST sdta
+//       smpl     16 bit mono sample data, every sample back to back
+//     LIST pdta
+//       phdr pbag pmod pgen   presets, their zones, and generators
+//       inst ibag imod igen   instruments, their zones, and generators
+//       shdr                  sample headers
+//
+
+use std::fmt;
+
+/// Generator numbers from the SF2 spec
+pub mod gen {
+    pub const START_ADDRS_OFFSET: u16 = 0;
+    pub const END_ADDRS_OFFSET: u16 = 1;
+    pub const STARTLOOP_ADDRS_OFFSET: u16 = 2;
+    pub const ENDLOOP_ADDRS_OFFSET: u16 = 3;
+    pub const START_ADDRS_COARSE_OFFSET: u16 = 4;
+    pub const END_ADDRS_COARSE_OFFSET: u16 = 12;
+    pub const DELAY_VOL_ENV: u16 = 33;
+    pub const ATTACK_VOL_ENV: u16 = 34;
+    pub const HOLD_VOL_ENV: u16 = 35;
+    pub const DECAY_VOL_ENV: u16 = 36;
+    pub const SUSTAIN_VOL_ENV: u16 = 37;
+    pub const RELEASE_VOL_ENV: u16 = 38;
+    pub const INSTRUMENT: u16 = 41;
+    pub const KEY_RANGE: u16 = 43;
+    pub const VEL_RANGE: u16 = 44;
+    pub const STARTLOOP_ADDRS_COARSE_OFFSET: u16 = 45;
+    pub const INITIAL_ATTENUATION: u16 = 48;
+    pub const ENDLOOP_ADDRS_COARSE_OFFSET: u16 = 50;
+    pub const COARSE_TUNE: u16 = 51;
+    pub const FINE_TUNE: u16 = 52;
+    pub const SAMPLE_ID: u16 = 53;
+    pub const SAMPLE_MODES: u16 = 54;
+    pub const OVERRIDING_ROOT_KEY: u16 = 58;
+    pub const COUNT: usize = 61;
+}
+
+#[derive(Debug)]
+pub struct Error(pub String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn error<T>(message: &str) -> Result<T, Error> {
+    Err(Error(message.to_string()))
+}
+
+/// Generators set in one zone, by generator number
+///
+#[derive(Clone, Debug, Default)]
+pub struct Generators {
+    values: Vec<Option<u16>>,
+}
+
+impl Generators {
+    fn new() -> Self {
+        Self {
+            values: vec![None; gen::COUNT],
+        }
+    }
+
+    fn set(&mut self, oper: u16, amount: u16) {
+        if (oper as usize) < gen::COUNT {
+            self.values[oper as usize] = Some(amount);
+        }
+    }
+
+    pub fn get(&self, oper: u16) -> Option<u16> {
+        self.values.get(oper as usize).copied().flatten()
+    }
+
+    pub fn get_i16(&self, oper: u16) -> Option<i16> {
+        self.get(oper).map(|amount| amount as i16)
+    }
+
+    /// A key or velocity range, low and high
+    ///
+    pub fn get_range(&self, oper: u16) -> Option<(u8, u8)> {
+        self.get(oper)
+            .map(|amount| ((amount & 0xff) as u8, (amount >> 8) as u8))
+    }
+
+    /// Generators from self, falling back to global for anything not set
+    ///
+    pub fn with_global(&self, global: Option<&Generators>) -> Generators {
+        let mut merged = self.clone();
+        if let Some(global) = global {
+            for (value, global_value) in merged.values.iter_mut().zip(global.values.iter()) {
+                if value.is_none() {
+                    *value = *global_value;
+                }
+            }
+        }
+        merged
+    }
+}
+
+/// A preset or instrument: a name, an optional global zone, and zones
+///
+#[derive(Debug)]
+pub struct ZoneList {
+    pub name: String,
+    pub global: Option<Generators>,
+    pub zones: Vec<Generators>,
+}
+
+#[derive(Debug)]
+pub struct Preset {
+    pub bank: u16,
+    pub program: u16,
+    pub zones: ZoneList,
+}
+
+#[derive(Clone, Debug)]
+pub struct SampleHeader {
+    pub start: u32,
+    pub end: u32,
+    pub loop_start: u32,
+    pub loop_end: u32,
+    pub sample_rate: u32,
+    pub original_pitch: u8,
+    pub pitch_correction: i8,
+}
+
+#[derive(Debug)]
+pub struct Sf2 {
+    pub presets: Vec<Preset>,
+    pub instruments: Vec<ZoneList>,
+    pub samples: Vec<SampleHeader>,
+    pub sample_data: Vec<i16>,
+}
+
+// A chunk's id and contents
+type Chunk<'a> = (&'a [u8], &'a [u8]);
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn u16(&self, at: usize) -> u16 {
+        u16::from_le_bytes([self.data[at], self.data[at + 1]])
+    }
+
+    fn u32(&self, at: usize) -> u32 {
+        u32::from_le_bytes([
+            self.data[at],
+            self.data[at + 1],
+            self.data[at + 2],
+            self.data[at + 3],
+        ])
+    }
+
+    fn name(&self, at: usize) -> String {
+        let raw = &self.data[at..at + 20];
+        let end = raw.iter().position(|c| *c == 0).unwrap_or(20);
+        String::from_utf8_lossy(&raw[..end]).trim().to_string()
+    }
+
+    //
+    // The chunks inside data, as (id, contents) pairs.
+    //
+    fn chunks(&self) -> Result<Vec<Chunk<'a>>, Error> {
+        let mut chunks = Vec::new();
+        let mut at = 0;
+        while at + 8 <= self.data.len() {
+            let id = &self.data[at..at + 4];
+            let size = self.u32(at + 4) as usize;
+            let start = at + 8;
+            if start + size > self.data.len() {
+                return error("chunk runs off the end of the file");
+            }
+            chunks.push((id, &self.data[start..start + size]));
+            // Chunks are padded to an even length.
+            at = start + size + (size & 1);
+        }
+        Ok(chunks)
+    }
+}
+
+//
+// The contents of each LIST chunk of a given type
+//
+fn find_list<'a>(chunks: &[Chunk<'a>], list_type: &[u8]) -> Option<&'a [u8]> {
+    chunks
+        .iter()
+        .find(|(id, data)| *id == b"LIST" && data.len() >= 4 && &data[0..4] == list_type)
+        .map(|(_, data)| &data[4..])
+}
+
+fn find_chunk<'a>(chunks: &[Chunk<'a>], chunk_id: &[u8]) -> Result<&'a [u8], Error> {
+    match chunks.iter().find(|(id, _)| *id == chunk_id) {
+        Some((_, data)) => Ok(data),
+        None => error(&format!(
+            "missing {} chunk",
+            String::from_utf8_lossy(chunk_id)
+        )),
+    }
+}
+
+//
+// Turn the header, bag and generator chunks into zone lists.  Each header
+// record points at its first bag, and each bag at its first generator; the
+// last record is a terminator that only marks where the one before ends.
+//
+fn read_zone_lists(
+    headers: &[u8],
+    header_size: usize,
+    bag_idx_offset: usize,
+    bags: &[u8],
+    gens: &[u8],
+    zone_link: u16,
+) -> Result<Vec<(usize, ZoneList)>, Error> {
+    let header_reader = Reader { data: headers };
+    let bag_reader = Reader { data: bags };
+    let gen_reader = Reader { data: gens };
+    let num_headers = headers.len() / header_size;
+    let num_bags = bags.len() / 4;
+    let num_gens = gens.len() / 4;
+    if num_headers < 1 || num_bags < 1 {
+        return error("empty preset or instrument list");
+    }
+
+    let mut lists = Vec::new();
+    for header in 0..num_headers - 1 {
+        let at = header * header_size;
+        let first_bag = header_reader.u16(at + bag_idx_offset) as usize;
+        let end_bag = header_reader.u16(at + header_size + bag_idx_offset) as usize;
+        if first_bag > end_bag || end_bag >= num_bags {
+            return error("bad bag index");
+        }
+        let mut global = None;
+        let mut zones = Vec::new();
+        for bag in first_bag..end_bag {
+            let first_gen = bag_reader.u16(bag * 4) as usize;
+            let end_gen = bag_reader.u16(bag * 4 + 4) as usize;
+            if first_gen > end_gen || end_gen > num_gens {
+                return error("bad generator index");
+            }
+            let mut generators = Generators::new();
+            for generator in first_gen..end_gen {
+                generators.set(
+                    gen_reader.u16(generator * 4),
+                    gen_reader.u16(generator * 4 + 2),
+                );
+            }
+            // A first zone that doesn't link to anything is the global zone.
+            if bag == first_bag && generators.get(zone_link).is_none() {
+                global = Some(generators);
+            } else if generators.get(zone_link).is_some() {
+                zones.push(generators);
+            }
+        }
+        lists.push((
+            at,
+            ZoneList {
+                name: header_reader.name(at),
+                global,
+                zones,
+            },
+        ));
+    }
+    Ok(lists)
+}
+
+impl Sf2 {
+    pub fn parse(file: &[u8]) -> Result<Self, Error> {
+        let file_reader = Reader { data: file };
+        let riff = file_reader.chunks()?;
+        let sfbk = find_chunk(&riff, b"RIFF")?;
+        if sfbk.len() < 4 || &sfbk[0..4] != b"sfbk" {
+            return error("not a SoundFont");
+        }
+        let top = Reader { data: &sfbk[4..] }.chunks()?;
+
+        let sdta = match find_list(&top, b"sdta") {
+            Some(sdta) => Reader { data: sdta }.chunks()?,
+            None => return error("missing sdta list"),
+        };
+        let smpl = find_chunk(&sdta, b"smpl")?;
+        let sample_data = smpl
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+
+        let pdta = match find_list(&top, b"pdta") {
+            Some(pdta) => Reader { data: pdta }.chunks()?,
+            None => return error("missing pdta list"),
+        };
+
+        let phdr = find_chunk(&pdta, b"phdr")?;
+        let phdr_reader = Reader { data: phdr };
+        let presets = read_zone_lists(
+            phdr,
+            38,
+            24,
+            find_chunk(&pdta, b"pbag")?,
+            find_chunk(&pdta, b"pgen")?,
+            gen::INSTRUMENT,
+        )?
+        .into_iter()
+        .map(|(at, zones)| Preset {
+            program: phdr_reader.u16(at + 20),
+            bank: phdr_reader.u16(at + 22),
+            zones,
+        })
+        .collect();
+
+        let instruments = read_zone_lists(
+            find_chunk(&pdta, b"inst")?,
+            22,
+            20,
+            find_chunk(&pdta, b"ibag")?,
+            find_chunk(&pdta, b"igen")?,
+            gen::SAMPLE_ID,
+        )?
+        .into_iter()
+        .map(|(_, zones)| zones)
+        .collect();
+
+        let shdr = find_chunk(&pdta, b"shdr")?;
+        let shdr_reader = Reader { data: shdr };
+        let num_samples = (shdr.len() / 46).saturating_sub(1);
+        let samples = (0..num_samples)
+            .map(|sample| {
+                let at = sample * 46;
+                SampleHeader {
+                    start: shdr_reader.u32(at + 20),
+                    end: shdr_reader.u32(at + 24),
+                    loop_start: shdr_reader.u32(at + 28),
+                    loop_end: shdr_reader.u32(at + 32),
+                    sample_rate: shdr_reader.u32(at + 36),
+                    original_pitch: shdr[at + 40],
+                    pitch_correction: shdr[at + 41] as i8,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            presets,
+            instruments,
+            samples,
+            sample_data,
+        })
+    }
+
+    pub fn find_preset(&self, bank: u16, program: u16) -> Option<&Preset> {
+        self.presets
+            .iter()
+            .find(|preset| preset.bank == bank && preset.program == program)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use crate::sf2::*;
+
+    fn chunk(id: &[u8], contents: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((contents.len() as u32).to_le_bytes());
+        chunk.extend(contents);
+        if contents.len() & 1 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn list(list_type: &[u8], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut contents = list_type.to_vec();
+        for sub_chunk in chunks {
+            contents.extend(sub_chunk);
+        }
+        chunk(b"LIST", &contents)
+    }
+
+    fn name(name: &str) -> Vec<u8> {
+        let mut raw = name.as_bytes().to_vec();
+        raw.resize(20, 0);
+        raw
+    }
+
+    fn records(records: &[&[u16]]) -> Vec<u8> {
+        records
+            .iter()
+            .flat_map(|record| record.iter().flat_map(|value| value.to_le_bytes()))
+            .collect()
+    }
+
+    ///
+    /// A SoundFont with one preset (bank 0, program 5), one instrument with
+    /// a global zone and two key split zones, and one looped sample.
+    ///
+    pub fn test_sf2(sample_rate: u32, samples: &[i16]) -> Vec<u8> {
+        let smpl: Vec<u8> = samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+
+        let mut phdr = Vec::new();
+        for (preset_name, program, bag) in [("Test", 5u16, 0u16), ("EOP", 0, 1)] {
+            phdr.extend(name(preset_name));
+            phdr.extend(records(&[&[program, 0, bag]]));
+            phdr.extend([0u8; 12]);
+        }
+        let pbag = records(&[&[0, 0], &[1, 0]]);
+        let pgen = records(&[&[gen::INSTRUMENT, 0], &[0, 0]]);
+
+        let mut inst = Vec::new();
+        for (inst_name, bag) in [("Test inst", 0u16), ("EOI", 3)] {
+            inst.extend(name(inst_name));
+            inst.extend(records(&[&[bag]]));
+        }
+        let ibag = records(&[&[0, 0], &[1, 0], &[4, 0], &[7, 0]]);
+        let igen = records(&[
+            // Global: a 100ms release
+            &[gen::RELEASE_VOL_ENV, (-3986i16) as u16],
+            // Low keys, looped
+            &[gen::KEY_RANGE, 59 << 8],
+            &[gen::SAMPLE_MODES, 1],
+            &[gen::SAMPLE_ID, 0],
+            // High keys, an octave up
+            &[gen::KEY_RANGE, (127 << 8) | 60],
+            &[gen::COARSE_TUNE, 12],
+            &[gen::SAMPLE_ID, 0],
+            &[0, 0],
+        ]);
+
+        let mut shdr = Vec::new();
+        shdr.extend(name("Test sample"));
+        for value in [
+            0,
+            samples.len() as u32,
+            2,
+            samples.len() as u32 - 2,
+            sample_rate,
+        ] {
+            shdr.extend(value.to_le_bytes());
+        }
+        shdr.extend([60u8, 0, 0, 0, 1, 0]);
+        shdr.extend(name("EOS"));
+        shdr.extend([0u8; 26]);
+
+        let sfbk = [
+            b"sfbk".to_vec(),
+            list(b"INFO", &[chunk(b"INAM", b"Test\0\0")]),
+            list(b"sdta", &[chunk(b"smpl", &smpl)]),
+            list(
+                b"pdta",
+                &[
+                    chunk(b"phdr", &phdr),
+                    chunk(b"pbag", &pbag),
+                    chunk(b"pmod", &[0; 10]),
+                    chunk(b"pgen", &pgen),
+                    chunk(b"inst", &inst),
+                    chunk(b"ibag", &ibag),
+                    chunk(b"imod", &[0; 10]),
+                    chunk(b"igen", &igen),
+                    chunk(b"shdr", &shdr),
+                ],
+            ),
+        ]
+        .concat();
+        chunk(b"RIFF", &sfbk)
+    }
+
+    #[test]
+    fn presets_instruments_and_samples_should_parse() {
+        let sf2 = Sf2::parse(&test_sf2(44100, &[0; 100])).unwrap();
+        let preset = sf2.find_preset(0, 5).unwrap();
+        assert_eq!("Test", preset.zones.name);
+        assert_eq!(1, preset.zones.zones.len());
+
+        let instrument = &sf2.instruments[0];
+        assert_eq!("Test inst", instrument.name);
+        assert_eq!(
+            Some(-3986),
+            instrument
+                .global
+                .as_ref()
+                .unwrap()
+                .get_i16(gen::RELEASE_VOL_ENV)
+        );
+        assert_eq!(2, instrument.zones.len());
+        assert_eq!(Some((0, 59)), instrument.zones[0].get_range(gen::KEY_RANGE));
+        assert_eq!(
+            Some((60, 127)),
+            instrument.zones[1].get_range(gen::KEY_RANGE)
+        );
+
+        assert_eq!(1, sf2.samples.len());
+        assert_eq!(44100, sf2.samples[0].sample_rate);
+        assert_eq!(98, sf2.samples[0].loop_end);
+        assert_eq!(100, sf2.sample_data.len());
+    }
+
+    #[test]
+    fn other_files_should_be_rejected() {
+        assert!(Sf2::parse(b"RIFF\x04\x00\x00\x00WAVE").is_err());
+        assert!(Sf2::parse(b"").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+// Preset zone resolution.
+//
+// A SoundFont preset doesn't say directly what plays.  Each preset zone
+// picks an instrument and can narrow its key and velocity ranges and nudge
+// its generators; each instrument zone picks a sample and sets the
+// generators for it.  Both levels can have a global zone that fills in
+// generators the other zones leave out.
+//
+// This flattens all that into one list of zones, each with a key range,
+// velocity range, a slice of the sample data and the settings it plays
+// with.  Instrument generators are absolute; preset generators add to them.
+//
+
+use crate::sf2::gen;
+use crate::sf2::Error;
+use crate::sf2::Generators;
+use crate::sf2::Preset;
+use crate::sf2::Sf2;
+
+/// What a zone does at its loop end, as in sampleModes
+///
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum LoopKind {
+    None,
+    Continuous,
+    UntilRelease,
+}
+
+/// Volume envelope, in the units SampledInstrument takes
+///
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Envelope {
+    pub delay_ms: i32,
+    pub attack_ms: i32,
+    pub hold_ms: i32,
+    pub decay_ms: i32,
+    pub sustain_percent: u8,
+    pub release_ms: i32,
+}
+
+/// One zone of a preset, with everything resolved
+///
+#[derive(Clone, Debug)]
+pub struct ResolvedZone {
+    pub low_key: u8,
+    pub high_key: u8,
+    pub low_velocity: u8,
+    pub high_velocity: u8,
+    /// Sample data, as indexes into Sf2::sample_data
+    pub start: usize,
+    pub end: usize,
+    /// Loop points, as indexes into Sf2::sample_data
+    pub loop_start: usize,
+    pub loop_end: usize,
+    pub loop_kind: LoopKind,
+    pub sample_rate: u32,
+    pub root_key: u8,
+    /// In cents, positive for sharper
+    pub fine_tune: i16,
+    pub volume_percent: u8,
+    pub envelope: Envelope,
+}
+
+// Longest envelope stage kept, in ms.  SoundFonts allow 100 seconds.
+const MAX_ENVELOPE_MS: i32 = 20000;
+
+/// Timecents (1200 per doubling, 0 is one second) to ms
+///
+pub fn timecents_to_ms(timecents: i32) -> i32 {
+    if timecents <= -12000 {
+        // The spec's default, which means "instant"
+        0
+    } else {
+        let ms = 1000.0 * 2f64.powf(timecents as f64 / 1200.0);
+        (ms.round() as i32).min(MAX_ENVELOPE_MS)
+    }
+}
+
+/// Centibels of attenuation to a percentage of full level
+///
+pub fn centibels_to_percent(centibels: i32) -> u8 {
+    let level = 100.0 * 10f64.powf(-(centibels.max(0) as f64) / 200.0);
+    level.round().clamp(0.0, 100.0) as u8
+}
+
+//
+// Instrument generators, plus whatever the preset adds
+//
+struct Combined<'a> {
+    instrument: &'a Generators,
+    preset: &'a Generators,
+}
+
+impl Combined<'_> {
+    fn sum(&self, oper: u16, default: i16) -> i32 {
+        self.instrument.get_i16(oper).unwrap_or(default) as i32
+            + self.preset.get_i16(oper).unwrap_or(0) as i32
+    }
+
+    // Instrument level only; the spec doesn't allow these in presets.
+    fn offset(&self, fine: u16, coarse: u16) -> i64 {
+        self.instrument.get_i16(fine).unwrap_or(0) as i64
+            + 32768 * self.instrument.get_i16(coarse).unwrap_or(0) as i64
+    }
+
+    fn range(&self, oper: u16) -> Option<(u8, u8)> {
+        let (instrument_low, instrument_high) = self.instrument.get_range(oper).unwrap_or((0, 127));
+        let (preset_low, preset_high) = self.preset.get_range(oper).unwrap_or((0, 127));
+        let low = instrument_low.max(preset_low);
+        let high = instrument_high.min(preset_high).min(127);
+        if low <= high {
+            Some((low, high))
+        } else {
+            None
+        }
+    }
+}
+
+fn clamp_index(index: i64, data_len: usize) -> usize {
+    index.clamp(0, data_len as i64) as usize
+}
+
+fn resolve_zone(
+    sf2: &Sf2,
+    combined: &Combined,
+    sample_id: usize,
+) -> Result<Option<ResolvedZone>, Error> {
+    let (low_key, high_key) = match combined.range(gen::KEY_RANGE) {
+        Some(range) => range,
+        None => return Ok(None),
+    };
+    let (low_velocity, high_velocity) = match combined.range(gen::VEL_RANGE) {
+        Some(range) => range,
+        None => return Ok(None),
+    };
+    let header = match sf2.samples.get(sample_id) {
+        Some(header) => header,
+        None => return Err(Error(format!("zone uses missing sample {}", sample_id))),
+    };
+
+    let data_len = sf2.sample_data.len();
+    let start = clamp_index(
+        header.start as i64
+            + combined.offset(gen::START_ADDRS_OFFSET, gen::START_ADDRS_COARSE_OFFSET),
+        data_len,
+    );
+    let end = clamp_index(
+        header.end as i64 + combined.offset(gen::END_ADDRS_OFFSET, gen::END_ADDRS_COARSE_OFFSET),
+        data_len,
+    )
+    .max(start);
+    let loop_start = clamp_index(
+        header.loop_start as i64
+            + combined.offset(
+                gen::STARTLOOP_ADDRS_OFFSET,
+                gen::STARTLOOP_ADDRS_COARSE_OFFSET,
+            ),
+        data_len,
+    );
+    let loop_end = clamp_index(
+        header.loop_end as i64
+            + combined.offset(gen::ENDLOOP_ADDRS_OFFSET, gen::ENDLOOP_ADDRS_COARSE_OFFSET),
+        data_len,
+    );
+
+    let loop_kind = match combined.instrument.get(gen::SAMPLE_MODES).unwrap_or(0) & 3 {
+        1 => LoopKind::Continuous,
+        3 => LoopKind::UntilRelease,
+        _ => LoopKind::None,
+    };
+    // A loop that isn't inside the sample can't be played.
+    let loop_kind = if loop_start < loop_end && loop_start >= start && loop_end <= end {
+        loop_kind
+    } else {
+        LoopKind::None
+    };
+
+    let root_key = match combined.instrument.get_i16(gen::OVERRIDING_ROOT_KEY) {
+        Some(key) if (0..=127).contains(&key) => key as u8,
+        _ if header.original_pitch <= 127 => header.original_pitch,
+        _ => 60,
+    };
+    let fine_tune = 100 * combined.sum(gen::COARSE_TUNE, 0)
+        + combined.sum(gen::FINE_TUNE, 0)
+        + header.pitch_correction as i32;
+
+    Ok(Some(ResolvedZone {
+        low_key,
+        high_key,
+        low_velocity,
+        high_velocity,
+        start,
+        end,
+        loop_start,
+        loop_end,
+        loop_kind,
+        sample_rate: header.sample_rate,
+        root_key,
+        fine_tune: fine_tune.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+        volume_percent: centibels_to_percent(combined.sum(gen::INITIAL_ATTENUATION, 0)),
+        envelope: Envelope {
+            delay_ms: timecents_to_ms(combined.sum(gen::DELAY_VOL_ENV, -12000)),
+            attack_ms: timecents_to_ms(combined.sum(gen::ATTACK_VOL_ENV, -12000)),
+            hold_ms: timecents_to_ms(combined.sum(gen::HOLD_VOL_ENV, -12000)),
+            decay_ms: timecents_to_ms(combined.sum(gen::DECAY_VOL_ENV, -12000)),
+            sustain_percent: centibels_to_percent(combined.sum(gen::SUSTAIN_VOL_ENV, 0)),
+            release_ms: timecents_to_ms(combined.sum(gen::RELEASE_VOL_ENV, -12000)),
+        },
+    }))
+}
+
+///
+/// Every zone of a preset, in the order the synth should search them
+///
+pub fn resolve(sf2: &Sf2, preset: &Preset) -> Result<Vec<ResolvedZone>, Error> {
+    let mut resolved = Vec::new();
+    for preset_zone in &preset.zones.zones {
+        let preset_gens = preset_zone.with_global(preset.zones.global.as_ref());
+        let instrument_id = preset_gens.get(gen::INSTRUMENT).unwrap_or(0) as usize;
+        let instrument = match sf2.instruments.get(instrument_id) {
+            Some(instrument) => instrument,
+            None => {
+                return Err(Error(format!(
+                    "preset {} uses missing instrument {}",
+                    preset.zones.name, instrument_id
+                )))
+            }
+        };
+        for instrument_zone in &instrument.zones {
+            let instrument_gens = instrument_zone.with_global(instrument.global.as_ref());
+            let combined = Combined {
+                instrument: &instrument_gens,
+                preset: &preset_gens,
+            };
+            let sample_id = instrument_gens.get(gen::SAMPLE_ID).unwrap_or(0) as usize;
+            if let Some(zone) = resolve_zone(sf2, &combined, sample_id)? {
+                resolved.push(zone);
+            }
+        }
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sf2::tests::test_sf2;
+    use crate::zones::*;
+
+    #[test]
+    fn units_should_convert() {
+        assert_eq!(0, timecents_to_ms(-12000));
+        assert_eq!(1000, timecents_to_ms(0));
+        assert_eq!(2000, timecents_to_ms(1200));
+        assert_eq!(100, timecents_to_ms(-3986));
+        assert_eq!(MAX_ENVELOPE_MS, timecents_to_ms(8000));
+        assert_eq!(100, centibels_to_percent(0));
+        assert_eq!(50, centibels_to_percent(60));
+        assert_eq!(0, centibels_to_percent(1440));
+    }
+
+    #[test]
+    fn preset_zones_should_resolve() {
+        let sf2 = Sf2::parse(&test_sf2(44100, &[0; 100])).unwrap();
+        let zones = resolve(&sf2, sf2.find_preset(0, 5).unwrap()).unwrap();
+        assert_eq!(2, zones.len());
+
+        assert_eq!((0, 59), (zones[0].low_key, zones[0].high_key));
+        assert_eq!(LoopKind::Continuous, zones[0].loop_kind);
+        assert_eq!((2, 98), (zones[0].loop_start, zones[0].loop_end));
+        assert_eq!(0, zones[0].fine_tune);
+        // From the instrument's global zone
+        assert_eq!(100, zones[0].envelope.release_ms);
+        assert_eq!(100, zones[0].envelope.sustain_percent);
+
+        assert_eq!((60, 127), (zones[1].low_key, zones[1].high_key));
+        assert_eq!(LoopKind::None, zones[1].loop_kind);
+        assert_eq!(1200, zones[1].fine_tune);
+        assert_eq!(60, zones[1].root_key);
+        assert_eq!(100, zones[1].envelope.release_ms);
+    }
+}